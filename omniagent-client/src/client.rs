@@ -0,0 +1,812 @@
+use reqwest::{Method, RequestBuilder, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::access::{AccessError, ApiKeyInfo, ApiKeyRequest, MintedApiKey, ReadOnlyRequest, ReadOnlyStatus};
+use crate::models::apply::{ApplyReport, ManifestResource};
+use crate::models::bandwidth::BandwidthLimit;
+use crate::models::capture::CaptureRequest;
+use crate::models::checks::{CheckHistory, CheckRequest, CheckStatus, SyntheticCheck};
+use crate::models::cpi::{CpiExecRequest, CpiExecResult, CpiExecution, CpiProvider, CpiRegistryStatus};
+use crate::models::diagnostics::DiagnosticsReport;
+use crate::models::disk::DiskStatus;
+use crate::models::flags::{FeatureFlag, FeatureFlagRequest, FlagEvaluation};
+use crate::models::ha::LeaderStatus;
+use crate::models::host::{ShutdownHostRequest, ShutdownReport};
+use crate::models::housekeeping::HousekeepingStatus;
+use crate::models::images::{ImageMetadata, PinnedImages, PreloadJob, PreloadRequest};
+use crate::models::instances::{
+    AgentInfo, AppInstance, AppInstanceRequest, DockerDaemonInfo, HealthStatus, InstanceLogs,
+    Activation, ExecRequest, ExecResize, ExecSession, NetworkCreateRequest, NetworkEndpointConfig, NetworkInfo, StdinWrite, VolumeCreateRequest, VolumeInfo,
+};
+use crate::models::limits::LimitSaturation;
+use crate::models::lint::LintReport;
+use crate::models::logs::{LogHealthStatus, LogParsing};
+use crate::models::maintenance::{MaintenanceWindow, MaintenanceWindowRequest};
+use crate::models::mesh::{MeshPeer, MeshStatus};
+use crate::models::nettest::{NetTestReport, NetTestRequest};
+use crate::models::node::{NodeLabels, NodeTaints};
+use crate::models::plugins::PluginInfo;
+use crate::models::rbac::{Role, RoleBinding, RoleBindingRequest, RoleRequest};
+use crate::models::registry_cache::RegistryCacheStatus;
+use crate::models::seccomp::SeccompProfileSummary;
+use crate::models::share::{ShareRequest, SharedLink};
+use crate::models::state::{InstanceSummary, StateDelta, StateDigest};
+use crate::models::usage::UsageRecord;
+use crate::models::views::{SavedView, SavedViewRequest};
+use crate::models::WithWarnings;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid agent URL: {0}")]
+    InvalidUrl(String),
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// A mutation was refused, e.g. `agent_read_only`, `not_leader` or `disk_pressure`
+    #[error("agent refused the request with {status}: {error}")]
+    Refused { status: StatusCode, error: AccessError },
+    #[error("agent returned {status}: {body}")]
+    Status { status: StatusCode, body: String },
+    /// Handlers report failures as a plain-text message instead of the expected JSON
+    #[error("{0}")]
+    Agent(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Async client for one agent
+#[derive(Debug, Clone)]
+pub struct Client {
+    base: Url,
+    http: reqwest::Client,
+    api_key: Option<String>,
+}
+
+impl Client {
+    /// `base_url` is the agent's root, e.g. `http://10.0.0.5:8000`
+    pub fn new(base_url: &str) -> Result<Self> {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Uses a preconfigured reqwest client, e.g. with TLS settings or timeouts
+    pub fn with_http_client(base_url: &str, http: reqwest::Client) -> Result<Self> {
+        let base = Url::parse(base_url).map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        if base.cannot_be_a_base() {
+            return Err(Error::InvalidUrl(base_url.to_string()));
+        }
+        Ok(Client { base, http, api_key: None })
+    }
+
+    /// Sends `key` in the `X-API-Key` header, which mutations, logs, exec and port forwarding
+    /// need once the agent has any API keys
+    pub fn with_api_key(mut self, key: &str) -> Self {
+        self.api_key = Some(key.to_string());
+        self
+    }
+
+    /// Builds a URL from path segments, percent-encoding each one (image names contain `/`)
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("checked in Client::new")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let request = self.http.request(method, self.url(segments));
+        match &self.api_key {
+            Some(key) => request.header("X-API-Key", key),
+            None => request,
+        }
+    }
+
+    async fn read(request: RequestBuilder) -> Result<(StatusCode, String)> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status.is_success() {
+            return Ok((status, body));
+        }
+        Err(Self::failure(status, body))
+    }
+
+    fn failure(status: StatusCode, body: String) -> Error {
+        match serde_json::from_str::<AccessError>(&body) {
+            Ok(error) => Error::Refused { status, error },
+            Err(_) => Error::Status { status, body },
+        }
+    }
+
+    async fn json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+        let (_, body) = Self::read(request).await?;
+        serde_json::from_str(&body).map_err(|_| Error::Agent(body))
+    }
+
+    /// For routes answering `404` when the resource doesn't exist
+    async fn optional<T: DeserializeOwned>(request: RequestBuilder) -> Result<Option<T>> {
+        match Self::json(request).await {
+            Err(Error::Status { status: StatusCode::NOT_FOUND, .. }) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    /// For routes streaming server-sent events
+    async fn events(request: RequestBuilder) -> Result<DockerEvents> {
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Self::failure(status, response.text().await?));
+        }
+        Ok(DockerEvents { response, buffer: String::new() })
+    }
+
+    /// For routes answering with a plain-text message. Their failures are plain text too,
+    /// so the message must be read to tell them apart.
+    async fn text(request: RequestBuilder) -> Result<String> {
+        Self::read(request).await.map(|(_, body)| body)
+    }
+
+    fn get(&self, segments: &[&str]) -> RequestBuilder {
+        self.request(Method::GET, segments)
+    }
+
+    fn put(&self, segments: &[&str]) -> RequestBuilder {
+        self.request(Method::PUT, segments)
+    }
+
+    fn send_json<B: Serialize + ?Sized>(&self, method: Method, segments: &[&str], body: &B) -> RequestBuilder {
+        self.request(method, segments).json(body)
+    }
+
+    // Instances
+
+    pub async fn list_instances(&self) -> Result<Vec<AppInstance>> {
+        Self::json(self.get(&["instances"])).await
+    }
+
+    /// Instances matching a saved view
+    pub async fn list_instances_in_view(&self, view: &str) -> Result<Vec<AppInstance>> {
+        Self::json(self.get(&["instances"]).query(&[("view", view)])).await
+    }
+
+    pub async fn get_instance(&self, id: &str) -> Result<Option<AppInstance>> {
+        Self::optional(self.get(&["instances", id])).await
+    }
+
+    pub async fn create_instance(&self, request: &AppInstanceRequest) -> Result<WithWarnings<AppInstance>> {
+        Self::json(self.send_json(Method::POST, &["instances"], request)).await
+    }
+
+    pub async fn update_instance(&self, id: &str, request: &AppInstanceRequest) -> Result<WithWarnings<AppInstance>> {
+        Self::json(self.send_json(Method::PATCH, &["instances", id], request)).await
+    }
+
+    /// Checks a spec against best-practice rules without deploying it. `fail_on` is the
+    /// lowest severity that fails the report: `info`, `warning` or `error` (the default).
+    pub async fn lint_spec(&self, request: &AppInstanceRequest, fail_on: Option<&str>) -> Result<LintReport> {
+        let mut request = self.send_json(Method::POST, &["lint"], request);
+        if let Some(fail_on) = fail_on {
+            request = request.query(&[("fail_on", fail_on)]);
+        }
+        Self::json(request).await
+    }
+
+    pub async fn delete_instance(&self, id: &str) -> Result<String> {
+        Self::text(self.request(Method::DELETE, &["instances", id])).await
+    }
+
+    pub async fn start_instance(&self, id: &str) -> Result<AppInstance> {
+        Self::json(self.put(&["instances", id, "start"])).await
+    }
+
+    /// Starts an instance created with `standby`
+    pub async fn activate_instance(&self, id: &str) -> Result<Activation> {
+        Self::json(self.request(Method::POST, &["instances", id, "activate"])).await
+    }
+
+    pub async fn stop_instance(&self, id: &str) -> Result<AppInstance> {
+        Self::json(self.put(&["instances", id, "stop"])).await
+    }
+
+    pub async fn restart_instance(&self, id: &str) -> Result<AppInstance> {
+        Self::json(self.put(&["instances", id, "restart"])).await
+    }
+
+    pub async fn pause_instance(&self, id: &str) -> Result<String> {
+        Self::text(self.put(&["instances", id, "pause"])).await
+    }
+
+    pub async fn unpause_instance(&self, id: &str) -> Result<String> {
+        Self::text(self.put(&["instances", id, "unpause"])).await
+    }
+
+    /// `stream` is `stdout`, `stderr` or `all`; `since` is a Unix timestamp
+    pub async fn get_instance_logs(&self, id: &str, stream: Option<&str>, tail: Option<usize>, since: Option<i64>) -> Result<InstanceLogs> {
+        let mut request = self.get(&["instances", id, "logs"]);
+        if let Some(stream) = stream {
+            request = request.query(&[("stream", stream)]);
+        }
+        if let Some(tail) = tail {
+            request = request.query(&[("tail", tail)]);
+        }
+        if let Some(since) = since {
+            request = request.query(&[("since", since)]);
+        }
+        Self::json(request).await
+    }
+
+    /// Docker's raw stats document
+    pub async fn get_instance_stats(&self, id: &str) -> Result<Value> {
+        Self::json(self.get(&["instances", id, "stats"])).await
+    }
+
+    /// Mints an expiring link to the instance's logs or an exec session, usable without
+    /// credentials
+    pub async fn share_instance(&self, id: &str, request: &ShareRequest) -> Result<SharedLink> {
+        Self::json(self.send_json(Method::POST, &["instances", id, "share"], request)).await
+    }
+
+    /// Logs through a shared link's token
+    pub async fn get_shared_logs(&self, token: &str, tail: Option<usize>) -> Result<InstanceLogs> {
+        let mut request = self.get(&["shared", token, "logs"]);
+        if let Some(tail) = tail {
+            request = request.query(&[("tail", tail)]);
+        }
+        Self::json(request).await
+    }
+
+    /// Runs a packet capture in the instance's network namespace and returns the pcap file.
+    /// Needs the agent's admin token.
+    pub async fn capture_instance(&self, id: &str, admin_token: &str, request: &CaptureRequest) -> Result<Vec<u8>> {
+        let response = self.send_json(Method::POST, &["instances", id, "capture"], request)
+            .bearer_auth(admin_token)
+            .send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(match serde_json::from_str::<AccessError>(&body) {
+                Ok(error) => Error::Refused { status, error },
+                Err(_) => Error::Status { status, body },
+            });
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// DNS, TCP and HTTP checks against `request.target` from inside the instance
+    pub async fn test_instance_network(&self, id: &str, request: &NetTestRequest) -> Result<NetTestReport> {
+        Self::json(self.send_json(Method::POST, &["instances", id, "nettest"], request)).await
+    }
+
+    /// Docker's raw inspect document
+    pub async fn inspect_instance(&self, id: &str) -> Result<Value> {
+        Self::json(self.get(&["instances", id, "inspect"])).await
+    }
+
+    pub async fn write_instance_stdin(&self, id: &str, input: Vec<u8>) -> Result<StdinWrite> {
+        Self::json(self.request(Method::POST, &["instances", id, "stdin"]).body(input)).await
+    }
+
+    /// Creates an exec; attach to it with a WebSocket to `/instances/<id>/exec/<exec_id>`,
+    /// sending the same `X-API-Key` header
+    pub async fn create_exec(&self, id: &str, request: &ExecRequest) -> Result<ExecSession> {
+        Self::json(self.send_json(Method::POST, &["instances", id, "exec"], request)).await
+    }
+
+    pub async fn resize_exec(&self, id: &str, exec_id: &str, size: &ExecResize) -> Result<String> {
+        Self::text(self.send_json(Method::PUT, &["instances", id, "exec", exec_id, "resize"], size)).await
+    }
+
+    /// Keeps the last `limit` matching lines. `level` and `fields` (`key` or `key=value`)
+    /// need the instance to have log parsing rules.
+    pub async fn search_instance_logs(&self, id: &str, q: Option<&str>, level: Option<&str>, fields: &[&str], limit: Option<usize>) -> Result<InstanceLogs> {
+        let mut request = self.get(&["instances", id, "logs", "search"]);
+        if let Some(q) = q {
+            request = request.query(&[("q", q)]);
+        }
+        if let Some(level) = level {
+            request = request.query(&[("level", level)]);
+        }
+        for field in fields {
+            request = request.query(&[("field", field)]);
+        }
+        if let Some(limit) = limit {
+            request = request.query(&[("limit", limit)]);
+        }
+        Self::json(request).await
+    }
+
+    /// `None` unless the instance sets `log_health` and is running
+    pub async fn get_log_health(&self, id: &str) -> Result<Option<LogHealthStatus>> {
+        Self::optional(self.get(&["instances", id, "log-health"])).await
+    }
+
+    /// `None` clears the rules
+    pub async fn set_log_parsing(&self, id: &str, parsing: Option<&LogParsing>) -> Result<Option<LogParsing>> {
+        Self::json(self.send_json(Method::PUT, &["instances", id, "log-parsing"], &parsing)).await
+    }
+
+    pub async fn set_instance_bandwidth(&self, id: &str, limit: &BandwidthLimit) -> Result<BandwidthLimit> {
+        Self::json(self.send_json(Method::PUT, &["instances", id, "bandwidth"], limit)).await
+    }
+
+    /// Returns Docker's endpoint settings for the new attachment
+    pub async fn connect_instance_to_network(&self, id: &str, network_id: &str, endpoint: Option<&NetworkEndpointConfig>) -> Result<Value> {
+        let request = match endpoint {
+            Some(endpoint) => self.send_json(Method::PUT, &["instances", id, "connect", network_id], endpoint),
+            None => self.put(&["instances", id, "connect", network_id]),
+        };
+        Self::json(request).await
+    }
+
+    pub async fn disconnect_instance_from_network(&self, id: &str, network_id: &str) -> Result<String> {
+        Self::text(self.put(&["instances", id, "disconnect", network_id])).await
+    }
+
+    // Images
+
+    pub async fn list_images(&self) -> Result<Vec<String>> {
+        Self::json(self.get(&["images"])).await
+    }
+
+    pub async fn preload_images(&self, request: &PreloadRequest) -> Result<PreloadJob> {
+        Self::json(self.send_json(Method::POST, &["images", "preload"], request)).await
+    }
+
+    pub async fn list_preload_jobs(&self) -> Result<Vec<PreloadJob>> {
+        Self::json(self.get(&["images", "preload"])).await
+    }
+
+    pub async fn get_preload_job(&self, job_id: &str) -> Result<Option<PreloadJob>> {
+        Self::optional(self.get(&["images", "preload", job_id])).await
+    }
+
+    pub async fn get_image_metadata(&self, image: &str) -> Result<ImageMetadata> {
+        Self::json(self.get(&["images", image, "metadata"])).await
+    }
+
+    pub async fn list_pinned_images(&self) -> Result<PinnedImages> {
+        Self::json(self.get(&["images", "pinned"])).await
+    }
+
+    pub async fn set_pinned_images(&self, pinned: &PinnedImages) -> Result<PinnedImages> {
+        Self::json(self.send_json(Method::PUT, &["images", "pinned"], pinned)).await
+    }
+
+    /// Uploads a tar build context and streams Docker's build messages; a failed build ends
+    /// with `Error::Agent`
+    pub async fn build_image(&self, tag: &str, context: Vec<u8>, build_args: &[(&str, &str)]) -> Result<DockerEvents> {
+        let mut request = self.request(Method::POST, &["images", "build"])
+            .query(&[("tag", tag)])
+            .body(context);
+        for (key, value) in build_args {
+            request = request.query(&[("buildarg", format!("{}={}", key, value))]);
+        }
+        Self::events(request).await
+    }
+
+    // Volumes and networks
+
+    pub async fn list_volumes(&self) -> Result<Vec<VolumeInfo>> {
+        Self::json(self.get(&["volumes"])).await
+    }
+
+    pub async fn create_volume(&self, request: &VolumeCreateRequest) -> Result<VolumeInfo> {
+        Self::json(self.send_json(Method::POST, &["volumes"], request)).await
+    }
+
+    pub async fn delete_volume(&self, name: &str) -> Result<String> {
+        Self::text(self.request(Method::DELETE, &["volumes", name])).await
+    }
+
+    pub async fn list_networks(&self) -> Result<Vec<NetworkInfo>> {
+        Self::json(self.get(&["networks"])).await
+    }
+
+    pub async fn create_network(&self, request: &NetworkCreateRequest) -> Result<NetworkInfo> {
+        Self::json(self.send_json(Method::POST, &["networks"], request)).await
+    }
+
+    pub async fn delete_network(&self, id: &str) -> Result<String> {
+        Self::text(self.request(Method::DELETE, &["networks", id])).await
+    }
+
+    // Bundles
+
+    /// Applies resources in dependency order; the report lists each one's outcome
+    pub async fn apply(&self, resources: &[ManifestResource]) -> Result<ApplyReport> {
+        Self::json(self.send_json(Method::POST, &["apply"], resources)).await
+    }
+
+    /// Applies a multi-document YAML or JSON bundle as written
+    pub async fn apply_bundle(&self, bundle: &str) -> Result<ApplyReport> {
+        Self::json(self.request(Method::POST, &["apply"]).body(bundle.to_string())).await
+    }
+
+    // Agent
+
+    /// Answers with a body even while the Docker daemon is unavailable (status 503)
+    pub async fn health_check(&self) -> Result<HealthStatus> {
+        let response = self.get(&["health"]).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        serde_json::from_str(&body).map_err(|_| Error::Status { status, body })
+    }
+
+    pub async fn get_agent_info(&self) -> Result<AgentInfo> {
+        Self::json(self.get(&["agent", "info"])).await
+    }
+
+    pub async fn get_docker_info(&self) -> Result<DockerDaemonInfo> {
+        Self::json(self.get(&["agent", "docker"])).await
+    }
+
+    pub async fn get_read_only(&self) -> Result<ReadOnlyStatus> {
+        Self::json(self.get(&["agent", "read-only"])).await
+    }
+
+    pub async fn set_read_only(&self, admin_token: &str, request: &ReadOnlyRequest) -> Result<ReadOnlyStatus> {
+        Self::json(self.send_json(Method::PUT, &["agent", "read-only"], request).bearer_auth(admin_token)).await
+    }
+
+    // API keys, managed with the agent's admin token
+
+    pub async fn list_api_keys(&self, admin_token: &str) -> Result<Vec<ApiKeyInfo>> {
+        Self::json(self.get(&["agent", "api-keys"]).bearer_auth(admin_token)).await
+    }
+
+    pub async fn mint_api_key(&self, admin_token: &str, request: &ApiKeyRequest) -> Result<MintedApiKey> {
+        Self::json(self.send_json(Method::POST, &["agent", "api-keys"], request).bearer_auth(admin_token)).await
+    }
+
+    /// Issues a new key under the same ID; the old key stops working immediately
+    pub async fn rotate_api_key(&self, admin_token: &str, id: &str) -> Result<MintedApiKey> {
+        Self::json(self.request(Method::POST, &["agent", "api-keys", id, "rotate"]).bearer_auth(admin_token)).await
+    }
+
+    pub async fn revoke_api_key(&self, admin_token: &str, id: &str) -> Result<ApiKeyInfo> {
+        Self::json(self.request(Method::DELETE, &["agent", "api-keys", id]).bearer_auth(admin_token)).await
+    }
+
+    // Cloud provider interfaces
+
+    pub async fn list_cpis(&self) -> Result<CpiRegistryStatus> {
+        Self::json(self.get(&["cpis"])).await
+    }
+
+    pub async fn get_cpi(&self, name: &str) -> Result<Option<CpiProvider>> {
+        Self::optional(self.get(&["cpis", name])).await
+    }
+
+    /// Rescans the agent's CPI directory. Needs the agent's admin token.
+    pub async fn reload_cpis(&self, admin_token: &str) -> Result<CpiRegistryStatus> {
+        Self::json(self.request(Method::POST, &["cpis", "reload"]).bearer_auth(admin_token)).await
+    }
+
+    /// Runs one action of the named provider, waiting for it to finish. Needs the agent's
+    /// admin token.
+    pub async fn execute_cpi_action(&self, admin_token: &str, provider: &str, action: &str, request: &CpiExecRequest) -> Result<CpiExecResult> {
+        Self::json(self.send_json(Method::POST, &["cpis", provider, "actions", action], request).bearer_auth(admin_token)).await
+    }
+
+    pub async fn list_cpi_executions(&self, admin_token: &str) -> Result<Vec<CpiExecution>> {
+        Self::json(self.get(&["cpis", "executions"]).bearer_auth(admin_token)).await
+    }
+
+    /// Kills a running CPI action; its caller gets a `cancelled` result
+    pub async fn cancel_cpi_execution(&self, admin_token: &str, id: &str) -> Result<CpiExecution> {
+        Self::json(self.request(Method::DELETE, &["cpis", "executions", id]).bearer_auth(admin_token)).await
+    }
+
+    // Role-based access control, managed with the agent's admin token
+
+    pub async fn list_roles(&self, admin_token: &str) -> Result<Vec<Role>> {
+        Self::json(self.get(&["rbac", "roles"]).bearer_auth(admin_token)).await
+    }
+
+    pub async fn put_role(&self, admin_token: &str, name: &str, request: &RoleRequest) -> Result<Role> {
+        Self::json(self.send_json(Method::PUT, &["rbac", "roles", name], request).bearer_auth(admin_token)).await
+    }
+
+    pub async fn delete_role(&self, admin_token: &str, name: &str) -> Result<Role> {
+        Self::json(self.request(Method::DELETE, &["rbac", "roles", name]).bearer_auth(admin_token)).await
+    }
+
+    pub async fn list_role_bindings(&self, admin_token: &str) -> Result<Vec<RoleBinding>> {
+        Self::json(self.get(&["rbac", "bindings"]).bearer_auth(admin_token)).await
+    }
+
+    pub async fn put_role_binding(&self, admin_token: &str, name: &str, request: &RoleBindingRequest) -> Result<RoleBinding> {
+        Self::json(self.send_json(Method::PUT, &["rbac", "bindings", name], request).bearer_auth(admin_token)).await
+    }
+
+    pub async fn delete_role_binding(&self, admin_token: &str, name: &str) -> Result<RoleBinding> {
+        Self::json(self.request(Method::DELETE, &["rbac", "bindings", name]).bearer_auth(admin_token)).await
+    }
+
+    pub async fn get_leader_status(&self) -> Result<LeaderStatus> {
+        Self::json(self.get(&["agent", "leader"])).await
+    }
+
+    pub async fn get_disk_status(&self) -> Result<DiskStatus> {
+        Self::json(self.get(&["agent", "disk"])).await
+    }
+
+    /// Prometheus text exposition of the instances' scraped metrics
+    pub async fn get_metrics(&self) -> Result<String> {
+        Self::text(self.get(&["metrics"])).await
+    }
+
+    pub async fn get_housekeeping_status(&self) -> Result<HousekeepingStatus> {
+        Self::json(self.get(&["agent", "maintenance", "status"])).await
+    }
+
+    pub async fn get_concurrency(&self) -> Result<Vec<LimitSaturation>> {
+        Self::json(self.get(&["agent", "concurrency"])).await
+    }
+
+    pub async fn get_diagnostics(&self) -> Result<DiagnosticsReport> {
+        Self::json(self.get(&["agent", "diagnostics"])).await
+    }
+
+    /// The support bundle as a `.tar.gz` archive
+    pub async fn get_diagnostics_bundle(&self) -> Result<Vec<u8>> {
+        let response = self.get(&["agent", "diagnostics", "bundle"]).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Status { status, body: response.text().await? });
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    pub async fn shutdown_host(&self, request: Option<&ShutdownHostRequest>) -> Result<ShutdownReport> {
+        let request = match request {
+            Some(request) => self.send_json(Method::POST, &["agent", "shutdown-host"], request),
+            None => self.request(Method::POST, &["agent", "shutdown-host"]),
+        };
+        Self::json(request).await
+    }
+
+    pub async fn get_node_labels(&self) -> Result<NodeLabels> {
+        Self::json(self.get(&["agent", "labels"])).await
+    }
+
+    pub async fn set_node_labels(&self, labels: &NodeLabels) -> Result<NodeLabels> {
+        Self::json(self.send_json(Method::PUT, &["agent", "labels"], labels)).await
+    }
+
+    pub async fn get_node_taints(&self) -> Result<NodeTaints> {
+        Self::json(self.get(&["agent", "taints"])).await
+    }
+
+    pub async fn set_node_taints(&self, taints: &NodeTaints) -> Result<NodeTaints> {
+        Self::json(self.send_json(Method::PUT, &["agent", "taints"], taints)).await
+    }
+
+    // Maintenance windows
+
+    pub async fn list_maintenance_windows(&self) -> Result<Vec<MaintenanceWindow>> {
+        Self::json(self.get(&["agent", "maintenance-windows"])).await
+    }
+
+    pub async fn get_maintenance_window(&self, id: &str) -> Result<Option<MaintenanceWindow>> {
+        Self::optional(self.get(&["agent", "maintenance-windows", id])).await
+    }
+
+    pub async fn create_maintenance_window(&self, request: &MaintenanceWindowRequest) -> Result<MaintenanceWindow> {
+        Self::json(self.send_json(Method::POST, &["agent", "maintenance-windows"], request)).await
+    }
+
+    pub async fn update_maintenance_window(&self, id: &str, request: &MaintenanceWindowRequest) -> Result<MaintenanceWindow> {
+        Self::json(self.send_json(Method::PUT, &["agent", "maintenance-windows", id], request)).await
+    }
+
+    pub async fn delete_maintenance_window(&self, id: &str) -> Result<String> {
+        Self::text(self.request(Method::DELETE, &["agent", "maintenance-windows", id])).await
+    }
+
+    // Registry cache, mesh and seccomp profiles
+
+    pub async fn list_plugins(&self) -> Result<Vec<PluginInfo>> {
+        Self::json(self.get(&["plugins"])).await
+    }
+
+    pub async fn get_registry_cache_status(&self) -> Result<RegistryCacheStatus> {
+        Self::json(self.get(&["registry-cache"])).await
+    }
+
+    pub async fn get_mesh_status(&self) -> Result<MeshStatus> {
+        Self::json(self.get(&["mesh"])).await
+    }
+
+    pub async fn set_mesh_peers(&self, peers: &[MeshPeer]) -> Result<MeshStatus> {
+        Self::json(self.send_json(Method::PUT, &["mesh", "peers"], peers)).await
+    }
+
+    pub async fn list_seccomp_profiles(&self) -> Result<Vec<SeccompProfileSummary>> {
+        Self::json(self.get(&["profiles", "seccomp"])).await
+    }
+
+    pub async fn get_seccomp_profile(&self, name: &str) -> Result<Option<Value>> {
+        Self::optional(self.get(&["profiles", "seccomp", name])).await
+    }
+
+    pub async fn put_seccomp_profile(&self, name: &str, profile: &Value) -> Result<SeccompProfileSummary> {
+        Self::json(self.send_json(Method::PUT, &["profiles", "seccomp", name], profile)).await
+    }
+
+    pub async fn delete_seccomp_profile(&self, name: &str) -> Result<SeccompProfileSummary> {
+        Self::json(self.request(Method::DELETE, &["profiles", "seccomp", name])).await
+    }
+
+    // State synchronisation
+
+    pub async fn get_state_delta(&self, since: Option<u64>) -> Result<StateDelta> {
+        let mut request = self.get(&["state", "delta"]);
+        if let Some(since) = since {
+            request = request.query(&[("since", since)]);
+        }
+        Self::json(request).await
+    }
+
+    pub async fn get_state_digest(&self) -> Result<StateDigest> {
+        Self::json(self.get(&["state", "digest"])).await
+    }
+
+    /// Tracked instances matching a query such as `status=running label.team=payments`
+    pub async fn search_instances(&self, query: &str) -> Result<Vec<InstanceSummary>> {
+        Self::json(self.get(&["instances", "search"]).query(&[("q", query)])).await
+    }
+
+    // Synthetic checks
+
+    pub async fn list_checks(&self) -> Result<Vec<SyntheticCheck>> {
+        Self::json(self.get(&["checks"])).await
+    }
+
+    pub async fn list_check_statuses(&self) -> Result<Vec<CheckStatus>> {
+        Self::json(self.get(&["checks", "status"])).await
+    }
+
+    pub async fn get_check(&self, name: &str) -> Result<Option<SyntheticCheck>> {
+        Self::optional(self.get(&["checks", name])).await
+    }
+
+    /// Creates or replaces a check; a replaced check starts over with an unknown state
+    pub async fn put_check(&self, name: &str, request: &CheckRequest) -> Result<SyntheticCheck> {
+        Self::json(self.send_json(Method::PUT, &["checks", name], request)).await
+    }
+
+    pub async fn delete_check(&self, name: &str) -> Result<SyntheticCheck> {
+        Self::json(self.request(Method::DELETE, &["checks", name])).await
+    }
+
+    /// A check's ups and downs over `from`..`to` (RFC 3339, default the last 24 hours)
+    pub async fn get_check_history(&self, name: &str, from: Option<&str>, to: Option<&str>) -> Result<Option<CheckHistory>> {
+        let mut request = self.get(&["checks", name, "history"]);
+        for (param, value) in [("from", from), ("to", to)] {
+            if let Some(value) = value {
+                request = request.query(&[(param, value)]);
+            }
+        }
+        Self::optional(request).await
+    }
+
+    // Feature flags, set with the agent's admin token
+
+    pub async fn list_flags(&self) -> Result<Vec<FeatureFlag>> {
+        Self::json(self.get(&["flags"])).await
+    }
+
+    pub async fn get_flag(&self, name: &str) -> Result<Option<FeatureFlag>> {
+        Self::optional(self.get(&["flags", name])).await
+    }
+
+    /// Whether a flag is on for the agent in `namespace` (default `default`)
+    pub async fn evaluate_flag(&self, name: &str, namespace: Option<&str>) -> Result<FlagEvaluation> {
+        let mut request = self.get(&["flags", name, "evaluate"]);
+        if let Some(namespace) = namespace {
+            request = request.query(&[("namespace", namespace)]);
+        }
+        Self::json(request).await
+    }
+
+    /// Sets a flag, overriding the agent's config file until it is deleted
+    pub async fn put_flag(&self, admin_token: &str, name: &str, request: &FeatureFlagRequest) -> Result<FeatureFlag> {
+        Self::json(self.send_json(Method::PUT, &["flags", name], request).bearer_auth(admin_token)).await
+    }
+
+    /// Removes a flag set through the API; returns the flag now in effect, if any
+    pub async fn delete_flag(&self, admin_token: &str, name: &str) -> Result<Option<FeatureFlag>> {
+        Self::json(self.request(Method::DELETE, &["flags", name]).bearer_auth(admin_token)).await
+    }
+
+    // Saved views
+
+    pub async fn list_views(&self) -> Result<Vec<SavedView>> {
+        Self::json(self.get(&["views"])).await
+    }
+
+    pub async fn get_view(&self, name: &str) -> Result<Option<SavedView>> {
+        Self::optional(self.get(&["views", name])).await
+    }
+
+    /// Creates or replaces a view; the agent rejects queries it can't parse
+    pub async fn put_view(&self, name: &str, request: &SavedViewRequest) -> Result<SavedView> {
+        Self::json(self.send_json(Method::PUT, &["views", name], request)).await
+    }
+
+    pub async fn delete_view(&self, name: &str) -> Result<SavedView> {
+        Self::json(self.request(Method::DELETE, &["views", name])).await
+    }
+
+    // Usage metering
+
+    /// Usage records overlapping `from`..`to`, both RFC 3339 timestamps
+    pub async fn get_usage(&self, from: Option<&str>, to: Option<&str>) -> Result<Vec<UsageRecord>> {
+        let mut request = self.get(&["usage"]);
+        for (name, value) in [("from", from), ("to", to)] {
+            if let Some(value) = value {
+                request = request.query(&[(name, value)]);
+            }
+        }
+        Self::json(request).await
+    }
+
+    /// Follows Docker events, optionally only the `events` actions (e.g. `start`) of the given
+    /// `containers`. Port forwarding and the event WebSocket need a WebSocket client and
+    /// aren't covered here.
+    pub async fn stream_events(&self, events: &[&str], containers: &[&str]) -> Result<DockerEvents> {
+        let mut request = self.get(&["events"]);
+        if !events.is_empty() {
+            request = request.query(&[("event", events.join(","))]);
+        }
+        if !containers.is_empty() {
+            request = request.query(&[("container", containers.join(","))]);
+        }
+        Self::events(request).await
+    }
+}
+
+/// Docker messages from `Client::stream_events` or `Client::build_image`, read one
+/// server-sent event at a time
+#[derive(Debug)]
+pub struct DockerEvents {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl DockerEvents {
+    /// The next event as Docker's JSON message, or `None` once the stream ends
+    pub async fn next(&mut self) -> Result<Option<Value>> {
+        loop {
+            while let Some(end) = self.buffer.find("\n\n") {
+                let block: String = self.buffer.drain(..end + 2).collect();
+                let mut kind = None;
+                let mut data = Vec::new();
+                for line in block.lines() {
+                    if let Some(value) = line.strip_prefix("event:") {
+                        kind = Some(value.trim().to_string());
+                    } else if let Some(value) = line.strip_prefix("data:") {
+                        data.push(value.strip_prefix(' ').unwrap_or(value));
+                    }
+                }
+                if data.is_empty() {
+                    continue;
+                }
+                let data = data.join("\n");
+                if kind.as_deref() == Some("error") {
+                    return Err(Error::Agent(data));
+                }
+                return serde_json::from_str(&data).map(Some).map_err(|_| Error::Agent(data));
+            }
+
+            match self.response.chunk().await? {
+                Some(chunk) => self.buffer.push_str(&String::from_utf8_lossy(&chunk)),
+                None => return Ok(None),
+            }
+        }
+    }
+}
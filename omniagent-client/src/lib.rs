@@ -0,0 +1,7 @@
+//! Request and response types for the OmniAgent HTTP API, shared by the agent itself, the
+//! orchestrator and the CLI, plus an async client covering every endpoint.
+
+pub mod models;
+mod client;
+
+pub use client::{Client, DockerEvents, Error, Result};
@@ -0,0 +1,58 @@
+//! Access control: read-only mode, API keys and the error body of rejected mutations
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessError {
+    /// Stable machine-readable reason, e.g. `agent_read_only`
+    pub code: String,
+    pub error: String,
+}
+
+impl AccessError {
+    pub fn new(code: &str, error: &str) -> Self {
+        AccessError { code: code.to_string(), error: error.to_string() }
+    }
+}
+
+impl std::fmt::Display for AccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.error, self.code)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadOnlyStatus {
+    pub enabled: bool,
+    /// True when set through `OMNI_READ_ONLY`, which the API cannot override
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadOnlyRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: Option<String>,
+    /// Leading characters of the key, to tell keys apart without revealing them
+    pub prefix: String,
+    pub created_at: String,
+    pub rotated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApiKeyRequest {
+    pub name: Option<String>,
+}
+
+/// A newly minted or rotated key. The agent keeps only its hash, so this is the only time
+/// the key is shown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintedApiKey {
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+    pub key: String,
+}
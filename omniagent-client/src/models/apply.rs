@@ -0,0 +1,41 @@
+//! Applying a bundle of resources in one request
+
+use serde::{Deserialize, Serialize};
+
+use super::instances::{AppInstanceRequest, NetworkCreateRequest, VolumeCreateRequest};
+
+/// One document of a bundle, e.g. `{"kind": "volume", "spec": {"name": "data"}}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "spec", rename_all = "snake_case")]
+pub enum ManifestResource {
+    Instance(Box<AppInstanceRequest>),
+    Volume(VolumeCreateRequest),
+    Network(NetworkCreateRequest),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyAction {
+    Created,
+    Updated,
+    Unchanged,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplyResult {
+    pub kind: String,
+    pub name: String,
+    pub action: ApplyAction,
+    /// Container, volume or network ID once applied
+    pub id: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Results in the order resources were applied: networks, volumes, then instances after
+/// the instances they depend on
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyReport {
+    pub results: Vec<ApplyResult>,
+    pub failed: usize,
+}
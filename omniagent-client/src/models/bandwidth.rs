@@ -0,0 +1,11 @@
+//! Per-instance network rate limits
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BandwidthLimit {
+    /// Traffic leaving the instance, in kbit/s
+    pub egress_kbit: Option<u64>,
+    /// Traffic reaching the instance, in kbit/s
+    pub ingress_kbit: Option<u64>,
+}
@@ -0,0 +1,15 @@
+//! Packet captures in an instance's network namespace
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CaptureRequest {
+    /// Seconds to capture for; the agent caps this at `OMNI_CAPTURE_MAX_SECS`
+    pub duration_secs: Option<u64>,
+    /// Stops once the capture reaches this size; capped at `OMNI_CAPTURE_MAX_BYTES`
+    pub max_bytes: Option<u64>,
+    /// Interface inside the container, default `any`
+    pub interface: Option<String>,
+    /// tcpdump filter expression, e.g. `tcp port 5432`
+    pub filter: Option<String>,
+}
@@ -0,0 +1,94 @@
+//! Synthetic checks: HTTP, TCP and ICMP probes the agent runs on a schedule
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckKind {
+    Http,
+    Tcp,
+    Icmp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckRequest {
+    pub kind: CheckKind,
+    /// A URL for `http`, `host:port` for `tcp` and a host for `icmp`. `{instance}` stands for
+    /// the address of `instance`, e.g. `http://{instance}:8080/healthz`.
+    pub target: String,
+    /// Instance ID or name whose container address replaces `{instance}`
+    #[serde(default)]
+    pub instance: Option<String>,
+    /// Seconds between probes (default 60, minimum 10)
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    /// Seconds before a probe fails (default 5), at most the interval
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Status an `http` check expects; any 2xx when unset
+    #[serde(default)]
+    pub expected_status: Option<u16>,
+    /// Consecutive failed probes before the check is down (default 1)
+    #[serde(default)]
+    pub failure_threshold: Option<u32>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyntheticCheck {
+    pub name: String,
+    pub kind: CheckKind,
+    pub target: String,
+    pub instance: Option<String>,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+    pub expected_status: Option<u16>,
+    pub failure_threshold: u32,
+    pub description: Option<String>,
+    pub updated_at: String,
+}
+
+/// Outcome of one probe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub up: bool,
+    pub latency_ms: Option<f64>,
+    /// HTTP status of an `http` probe
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckStatus {
+    pub name: String,
+    /// None until the first probe finishes
+    pub up: Option<bool>,
+    /// When `up` last changed
+    pub since: Option<String>,
+    pub consecutive_failures: u32,
+    pub last_result: Option<CheckResult>,
+}
+
+/// A check going up or down, as recorded in its history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckTransition {
+    pub check: String,
+    pub up: bool,
+    /// The failing probe's error when the check went down
+    pub error: Option<String>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckHistory {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    /// Share of the window the check was up, over the part of it with a known state
+    pub uptime_percent: Option<f64>,
+    pub transitions: Vec<CheckTransition>,
+    /// Latest probes since the agent started, oldest first
+    pub recent: Vec<CheckResult>,
+}
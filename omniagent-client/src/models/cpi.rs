@@ -0,0 +1,79 @@
+//! Cloud provider interfaces: JSON-described command sets the agent can run by name
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpiAction {
+    pub name: String,
+    /// Parameters the command needs; ones with a default setting may be omitted
+    pub params: Vec<String>,
+    /// Overrides the agent's default timeout for this action
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpiProvider {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub version: Option<String>,
+    /// File the provider was loaded from
+    pub file: String,
+    pub default_settings: HashMap<String, Value>,
+    pub actions: Vec<CpiAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpiLoadError {
+    pub file: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpiRegistryStatus {
+    pub dir: String,
+    pub providers: Vec<CpiProvider>,
+    /// Files that failed to load or validate
+    pub errors: Vec<CpiLoadError>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CpiExecRequest {
+    /// Values for the action's parameters, overriding the provider's default settings. Names
+    /// the action doesn't declare are rejected.
+    #[serde(default)]
+    pub params: HashMap<String, Value>,
+    /// Overrides the action's timeout
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// ID to give the run, so it can be cancelled while this request waits; generated if unset
+    #[serde(default)]
+    pub execution_id: Option<String>,
+}
+
+/// A CPI action that is still running
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpiExecution {
+    pub id: String,
+    pub provider: String,
+    pub action: String,
+    pub started_at: String,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpiExecResult {
+    pub id: String,
+    pub provider: String,
+    pub action: String,
+    /// `exited`, `timed_out` or `cancelled`; the command is killed in the latter two
+    pub status: String,
+    /// None unless the command exited
+    pub exit_code: Option<i32>,
+    /// The first MiB of each stream; the rest is dropped
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+}
@@ -0,0 +1,41 @@
+//! Self-test results
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    pub agent_id: String,
+    pub generated_at: String,
+    /// Worst status across all checks
+    pub overall: CheckStatus,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+/// The agent clock's offset from a reference clock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockSkew {
+    /// `ntp` or `orchestrator`
+    pub source: String,
+    /// The NTP server or orchestrator URL compared against
+    pub reference: String,
+    /// Agent time minus reference time
+    pub skew_secs: f64,
+    pub measured_at: String,
+}
@@ -0,0 +1,35 @@
+//! Disk pressure monitoring
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskStatus {
+    pub threshold_percent: f64,
+    /// Usage of the fullest watched volume
+    pub used_percent: f64,
+    pub under_pressure: bool,
+    pub last_cleanup: Option<CleanupReport>,
+    /// Volumes holding Docker's data root and the agent's state directory
+    #[serde(default)]
+    pub volumes: Vec<VolumeStatus>,
+}
+
+/// A watched volume; `paths` lists the watched directories it holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeStatus {
+    pub mount: String,
+    pub paths: Vec<String>,
+    pub used_percent: f64,
+    pub total: u64,
+    pub available: u64,
+    pub under_pressure: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub images_removed: Vec<String>,
+    pub containers_removed: Vec<String>,
+    pub volumes_removed: Vec<String>,
+    pub space_reclaimed: u64,
+    pub finished_at: String,
+}
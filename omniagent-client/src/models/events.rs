@@ -0,0 +1,61 @@
+//! Events published over WebSockets, MQTT, telemetry and external publishers
+
+use serde::{Deserialize, Serialize};
+
+/// Events produced by the agent for external consumers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// A container lifecycle transition reported by Docker (create, start, die, destroy, ...)
+    Lifecycle {
+        instance_id: String,
+        name: String,
+        action: String,
+        exit_code: Option<i64>,
+        /// Last lines of output, captured when the container crashed or was OOM-killed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        log_tail: Option<Vec<String>>,
+        timestamp: String,
+    },
+    Alert {
+        severity: String,
+        source: String,
+        message: String,
+        timestamp: String,
+    },
+    /// Docker daemon connectivity changed (`ready` or `unavailable`)
+    Health {
+        status: String,
+        detail: Option<String>,
+        timestamp: String,
+    },
+    /// A lower-priority instance was stopped to relieve memory pressure or make room for a create
+    Preemption {
+        instance_id: String,
+        name: String,
+        priority: i32,
+        reason: String,
+        timestamp: String,
+    },
+    /// Periodic snapshot of host and instance counts
+    MetricsSummary {
+        instances_total: usize,
+        instances_running: usize,
+        memory_available: u64,
+        load_average: f64,
+        timestamp: String,
+    },
+}
+
+impl AgentEvent {
+    /// Short topic suffix used by publishers, e.g. `lifecycle`
+    pub fn topic(&self) -> &'static str {
+        match self {
+            AgentEvent::Lifecycle { .. } => "lifecycle",
+            AgentEvent::Alert { .. } => "alerts",
+            AgentEvent::Health { .. } => "health",
+            AgentEvent::Preemption { .. } => "preemptions",
+            AgentEvent::MetricsSummary { .. } => "metrics",
+        }
+    }
+}
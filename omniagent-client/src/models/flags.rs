@@ -0,0 +1,42 @@
+//! Feature flags gating experimental agent behaviors, so they can be rolled out across a
+//! fleet in stages
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeatureFlagRequest {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Namespaces the flag applies in; empty means all of them
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    /// Share of agents (0-100) the flag is on for, each placed by a stable hash of its
+    /// hostname and the flag name; unset means every agent
+    #[serde(default)]
+    pub percentage: Option<u8>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    pub namespaces: Vec<String>,
+    pub percentage: Option<u8>,
+    pub description: Option<String>,
+    /// `default` for a known flag nobody has set, `config` for one from the config file,
+    /// `api` for one set through the API
+    pub source: String,
+    /// Whether the flag is on for this agent: enabled, and this agent is within `percentage`
+    pub active: bool,
+    /// When the flag was last set through the API
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagEvaluation {
+    pub name: String,
+    pub namespace: String,
+    pub enabled: bool,
+}
@@ -0,0 +1,12 @@
+//! Leader election between agents sharing a state store
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderStatus {
+    /// False when the agent runs standalone and is always the leader
+    pub ha_enabled: bool,
+    pub group: Option<String>,
+    pub holder: String,
+    pub is_leader: bool,
+}
@@ -0,0 +1,29 @@
+//! Host shutdown
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShutdownHostRequest {
+    /// Power off the host once instances are stopped (defaults to false)
+    pub power_off: Option<bool>,
+    /// Grace period for instances without their own, in seconds
+    pub default_grace_period: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoppedInstance {
+    pub id: String,
+    pub name: String,
+    pub grace_period: i64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    pub agent_id: String,
+    /// Instances in the order they were stopped
+    pub stopped: Vec<StoppedInstance>,
+    pub orchestrator_notified: bool,
+    pub powering_off: bool,
+    pub timestamp: String,
+}
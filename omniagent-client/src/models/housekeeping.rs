@@ -0,0 +1,33 @@
+//! Background maintenance of the state store
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HousekeepingStatus {
+    pub backend: String,
+    pub interval_secs: u64,
+    /// Orphan cleanup only runs on the leader, since followers share its records
+    pub is_leader: bool,
+    pub last_run: Option<HousekeepingReport>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HousekeepingReport {
+    pub started_at: String,
+    pub finished_at: String,
+    /// What compaction did, or why it failed
+    pub compaction: String,
+    /// Container IDs whose instance and spec records were removed
+    pub orphans_removed: Vec<String>,
+    /// Usage records past `OMNI_USAGE_RETENTION_DAYS` or merged into their day's total
+    #[serde(default)]
+    pub usage_records_pruned: usize,
+    /// Audit records past `OMNI_AUDIT_RETENTION_DAYS`
+    #[serde(default)]
+    pub audit_records_pruned: usize,
+    /// Check transitions past `OMNI_CHECK_RETENTION_DAYS`
+    #[serde(default)]
+    pub check_history_pruned: usize,
+    /// Unreadable records and mismatches between collections
+    pub integrity_errors: Vec<String>,
+}
@@ -0,0 +1,52 @@
+//! Image preloading, pinning and metadata
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreloadRequest {
+    pub images: Vec<String>,
+    /// Also add the images to the pinned list so GC never removes them
+    pub pin: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreloadJob {
+    pub id: String,
+    pub status: String,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+    pub images: Vec<ImagePullProgress>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImagePullProgress {
+    pub image: String,
+    pub status: String,
+    /// Download progress across all layers, 0-100
+    pub percent: f64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinnedImages {
+    pub images: Vec<String>,
+}
+
+/// Defaults baked into an image, used to pre-populate instance specs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    pub image: String,
+    /// `local` when read from the daemon, `registry` when fetched without pulling
+    pub source: String,
+    pub digest: Option<String>,
+    /// e.g. `80/tcp`
+    pub exposed_ports: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub entrypoint: Option<Vec<String>>,
+    pub cmd: Option<Vec<String>>,
+    pub volumes: Vec<String>,
+    pub labels: HashMap<String, String>,
+    pub working_dir: Option<String>,
+    pub user: Option<String>,
+}
@@ -0,0 +1,464 @@
+//! Instances, volumes, networks and agent information
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use super::bandwidth::BandwidthLimit;
+use super::flags::FeatureFlag;
+use super::logs::{LogHealth, LogParsing};
+use super::metrics::MetricsScrape;
+use super::node::{Constraints, Taint, Toleration};
+use super::userns::UsernsInfo;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppInstance {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub status: String,
+    pub created_at: String,
+    pub ports: Vec<PortMapping>,
+    pub environment: HashMap<String, String>,
+    pub volumes: Vec<VolumeMapping>,
+    pub agent_id: String,
+    /// Digest the instance was deployed from, e.g. `nginx@sha256:...`
+    pub image_digest: Option<String>,
+    /// Limits Docker enforces, where the agent inspected the container (not in listings)
+    pub limits: Option<ResourceLimits>,
+}
+
+/// CPU and memory limits of an instance; unset fields are unlimited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// CPU cores, e.g. `0.5`
+    pub cpu_limit: Option<f64>,
+    /// Memory in bytes
+    pub memory_limit: Option<i64>,
+    /// Memory plus swap in bytes, or -1 for unlimited swap
+    pub memory_swap: Option<i64>,
+    /// Relative CPU weight against other containers (default 1024)
+    pub cpu_shares: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortMapping {
+    pub host_port: u16,
+    pub container_port: u16,
+    pub protocol: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeMapping {
+    pub host_path: String,
+    pub container_path: String,
+}
+
+/// Endpoint options used when attaching an instance to a network
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkEndpointConfig {
+    pub aliases: Option<Vec<String>>,
+    pub ipv4_address: Option<String>,
+    pub ipv6_address: Option<String>,
+}
+
+/// A network the instance should be attached to when it is created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkAttachment {
+    /// Network name or ID
+    pub name: String,
+    #[serde(flatten)]
+    pub endpoint: NetworkEndpointConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppInstanceRequest {
+    pub name: String,
+    pub image: String,
+    pub ports: Option<Vec<PortMapping>>,
+    pub environment: Option<HashMap<String, String>>,
+    pub volumes: Option<Vec<VolumeMapping>>,
+    /// Whether to start the container right after creating it (defaults to true)
+    pub start: Option<bool>,
+    /// Creates the container without starting it and reports it as `standby` until
+    /// `POST /instances/<id>/activate`, for failover workloads that need a fast start
+    pub standby: Option<bool>,
+    /// Namespace for role bindings (default `default`)
+    pub namespace: Option<String>,
+    /// Overrides the image's default command
+    pub command: Option<Vec<String>>,
+    /// Overrides the image's entrypoint
+    pub entrypoint: Option<Vec<String>>,
+    pub working_dir: Option<String>,
+    pub user: Option<String>,
+    pub hostname: Option<String>,
+    /// Additional /etc/hosts entries in `host:ip` form
+    pub extra_hosts: Option<Vec<String>>,
+    /// Networks to join before the container is started
+    pub networks: Option<Vec<NetworkAttachment>>,
+    /// Isolation technology for Windows containers (`process` or `hyperv`)
+    pub isolation: Option<Isolation>,
+    /// Node taints this instance may be placed despite
+    pub tolerations: Option<Vec<Toleration>>,
+    /// Requirements checked on create; unmet ones reject with `unschedulable`
+    pub constraints: Option<Constraints>,
+    /// Lower-priority instances may be stopped to make room for this one (default 0)
+    pub priority: Option<i32>,
+    /// Never stopped by preemption, whatever its priority
+    pub protected: Option<bool>,
+    /// Names of instances this one needs; on host shutdown they are stopped after it
+    pub depends_on: Option<Vec<String>>,
+    /// Seconds to wait for a graceful stop before the container is killed
+    pub stop_grace_period: Option<i64>,
+    /// CPUs the instance may run on, e.g. `0-3` or `1,3`
+    pub cpuset_cpus: Option<String>,
+    /// NUMA nodes the instance may allocate memory from, e.g. `0`
+    pub cpuset_mems: Option<String>,
+    /// Relative CPU weight against other containers (default 1024)
+    pub cpu_shares: Option<i64>,
+    /// CPU cores the instance may use, e.g. `1.5`
+    pub cpu_limit: Option<f64>,
+    /// Memory limit in bytes
+    pub memory_limit: Option<i64>,
+    /// Memory plus swap in bytes (at least `memory_limit`), or -1 for unlimited swap
+    pub memory_swap: Option<i64>,
+    /// Real-time scheduler period and runtime in microseconds
+    pub cpu_rt_period: Option<i64>,
+    pub cpu_rt_runtime: Option<i64>,
+    /// Resource limits such as `nofile`, `nproc` or `memlock`
+    pub ulimits: Option<Vec<Ulimit>>,
+    /// Namespaced kernel parameters, e.g. `net.core.somaxconn`
+    pub sysctls: Option<HashMap<String, String>>,
+    /// DNS servers; each DNS field falls back to the agent default when omitted
+    pub dns: Option<Vec<String>>,
+    pub dns_search: Option<Vec<String>>,
+    /// resolv.conf options, e.g. `ndots:2`
+    pub dns_options: Option<Vec<String>>,
+    /// Host devices to expose, subject to the agent's device allowlist
+    pub devices: Option<Vec<DeviceMapping>>,
+    /// Egress/ingress rate limits; adjustable live through `PUT /instances/<id>/bandwidth`
+    pub bandwidth: Option<BandwidthLimit>,
+    /// Rules for splitting log lines into fields; adjustable through `PUT /instances/<id>/log-parsing`
+    pub log_parsing: Option<LogParsing>,
+    /// Health derived from the rate of error lines in the logs
+    pub log_health: Option<LogHealth>,
+    /// Prometheus endpoint inside the instance, re-exported through the agent's `/metrics`
+    pub metrics: Option<MetricsScrape>,
+    /// `host` opts out of the daemon's user namespace remapping, if the agent allows it
+    pub userns_mode: Option<String>,
+    /// Name of a profile uploaded to `/profiles/seccomp`
+    pub seccomp_profile: Option<String>,
+    /// Mounts the container's root filesystem read-only; volumes stay writable
+    pub read_only: Option<bool>,
+    /// Keep stdin open so `POST /instances/<id>/stdin` can write to it
+    pub stdin_open: Option<bool>,
+    /// Close stdin after the first writer detaches, delivering EOF
+    pub stdin_once: Option<bool>,
+    /// Set by the agent: the digest `image` resolved to when the instance was deployed
+    pub image_digest: Option<String>,
+}
+
+impl AppInstanceRequest {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn namespace(&self) -> &str {
+        self.namespace.as_deref().unwrap_or("default")
+    }
+
+    pub fn depends_on(&self) -> &[String] {
+        self.depends_on.as_deref().unwrap_or_default()
+    }
+
+    pub fn stop_grace_period(&self) -> Option<i64> {
+        self.stop_grace_period
+    }
+
+    pub fn seccomp_profile(&self) -> Option<&str> {
+        self.seccomp_profile.as_deref()
+    }
+
+    pub fn bandwidth(&self) -> Option<&BandwidthLimit> {
+        self.bandwidth.as_ref()
+    }
+
+    pub fn set_bandwidth(&mut self, bandwidth: Option<BandwidthLimit>) {
+        self.bandwidth = bandwidth;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Isolation {
+    Default,
+    Process,
+    Hyperv,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceMapping {
+    /// e.g. `/dev/ttyUSB0`
+    pub path_on_host: String,
+    /// Defaults to the host path
+    pub path_in_container: Option<String>,
+    /// Any combination of `r`, `w` and `m` (mknod); defaults to `rwm`
+    pub cgroup_permissions: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ulimit {
+    pub name: String,
+    pub soft: i64,
+    pub hard: i64,
+}
+
+/// Connection state of the Docker daemon as tracked by the supervisor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum DaemonState {
+    Connecting,
+    Ready { version: String, since: String },
+    Unavailable { attempt: u32, error: String, retry_in_secs: u64 },
+}
+
+/// Allocatable vs reserved resources, as used for placement decisions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceCapacity {
+    pub oversubscription_ratio: f64,
+    pub cpu_allocatable: f64,
+    pub cpu_reserved: f64,
+    pub memory_allocatable: u64,
+    pub memory_reserved: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StdinWrite {
+    pub bytes_written: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Activation {
+    pub id: String,
+    /// How long Docker took to start the container
+    pub start_ms: f64,
+}
+
+/// A command to run inside a running instance
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecRequest {
+    /// Defaults to `/bin/sh`
+    #[serde(default)]
+    pub cmd: Vec<String>,
+    /// Allocates a pseudo-TTY; output then arrives as one stream
+    #[serde(default)]
+    pub tty: bool,
+    pub working_dir: Option<String>,
+    /// `KEY=value` pairs added to the instance's environment
+    #[serde(default)]
+    pub env: Vec<String>,
+    pub user: Option<String>,
+}
+
+/// An exec created by `POST /instances/<id>/exec`, attached through the WebSocket route
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecSession {
+    pub id: String,
+    pub instance_id: String,
+    pub tty: bool,
+}
+
+/// Terminal size of a TTY exec, in characters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecResize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthStatus {
+    pub status: String,
+    pub docker: DaemonState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    pub stream: String,
+    pub timestamp: String,
+    pub message: String,
+    /// Set when the instance has log parsing rules and the line matched them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceLogs {
+    /// `stdout`, `stderr`, or `all`
+    pub stream: String,
+    pub lines: Vec<LogLine>,
+    /// Older lines left out to stay within `tail`
+    pub dropped_lines: u64,
+    /// Lines Docker split at its 16 KiB buffer and that were joined back together
+    pub rejoined_lines: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeInfo {
+    pub name: String,
+    pub mountpoint: String,
+    pub labels: HashMap<String, String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeCreateRequest {
+    pub name: String,
+    pub labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkInfo {
+    pub id: String,
+    pub name: String,
+    pub driver: String,
+    pub scope: String,
+    pub containers: HashMap<String, NetworkContainerInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkContainerInfo {
+    pub name: String,
+    pub endpoint_id: String,
+    pub ipv4_address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkCreateRequest {
+    pub name: String,
+    pub driver: Option<String>,
+    pub labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentInfo {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub platform: String,
+    /// Whether the daemon runs Linux or Windows containers
+    pub container_mode: String,
+    /// How the daemon is hosted: native, wsl2, or docker-desktop
+    pub docker_backend: String,
+    pub instance_count: usize,
+    pub status: String,
+    pub resources: SystemResources,
+    pub capacity: Option<ResourceCapacity>,
+    pub capabilities: AgentCapabilities,
+    pub labels: HashMap<String, String>,
+    pub taints: Vec<Taint>,
+    pub topology: HostTopology,
+    pub userns: UsernsInfo,
+    pub cgroup: CgroupInfo,
+    pub crypto: CryptoInfo,
+    /// Every known or configured feature flag and whether it is on for this agent
+    #[serde(default)]
+    pub feature_flags: Vec<FeatureFlag>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CgroupInfo {
+    /// `v1`, `v2`, `hybrid` (v1 controllers with a v2 mount), or `none` off Linux
+    pub mode: String,
+    /// `cgroupfs` or `systemd`, as reported by the daemon
+    pub driver: Option<String>,
+    /// Controllers enabled at the v2 root, e.g. `cpu`, `memory`, `io`
+    pub controllers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CryptoInfo {
+    /// `standard` or `fips`, from `OMNI_CRYPTO_POLICY`
+    pub policy: String,
+    /// Whether the agent was built with the `fips` feature
+    pub fips_build: bool,
+    /// What outbound TLS runs on: `native-tls` or `rustls/aws-lc-rs`
+    pub tls: String,
+    /// What SHA-256 and HMAC run on: `rustcrypto` or `aws-lc-rs`
+    pub hashing: String,
+    /// Suites outbound TLS may negotiate; empty when the platform TLS library decides
+    pub cipher_suites: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostTopology {
+    /// Online CPUs in cpuset list form, e.g. `0-15`
+    pub cpus_online: Option<String>,
+    pub numa_nodes: Vec<NumaNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NumaNode {
+    pub id: u32,
+    /// CPUs on this node in cpuset list form, usable as `cpuset_cpus`
+    pub cpus: String,
+    pub memory_total: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemResources {
+    pub cpu_count: usize,
+    pub memory_total: u64,
+    pub memory_available: u64,
+    /// Sums over `disks`
+    pub disk_total: u64,
+    pub disk_available: u64,
+    pub disks: Vec<DiskUsage>,
+}
+
+/// One local volume, e.g. `/` on Linux or `C:\` on Windows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub mount: String,
+    pub device: Option<String>,
+    pub filesystem: Option<String>,
+    pub total: u64,
+    pub available: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentCapabilities {
+    pub schema_version: u32,
+    pub architecture: String,
+    pub runtimes: Vec<String>,
+    pub default_runtime: Option<String>,
+    pub gpu: Option<GpuInfo>,
+    pub instance_kinds: Vec<String>,
+    /// Names of the CPI providers loaded from the CPI directory
+    pub cpi_providers: Vec<String>,
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuInfo {
+    pub vendor: String,
+    pub count: usize,
+    pub driver_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DockerDaemonInfo {
+    pub connection: DaemonState,
+    pub version: String,
+    pub api_version: String,
+    pub operating_system: String,
+    pub architecture: String,
+    pub kernel_version: String,
+    pub storage_driver: String,
+    pub docker_root_dir: String,
+    pub cgroup_driver: String,
+    pub cgroup_version: String,
+    pub logging_driver: String,
+    pub default_runtime: String,
+    pub security_options: Vec<String>,
+}
@@ -0,0 +1,15 @@
+//! Concurrency limits on Docker-heavy endpoints
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitSaturation {
+    /// `stats`, `logs` or `create`
+    pub class: String,
+    pub max: usize,
+    pub in_use: usize,
+    /// Requests queued for a slot
+    pub waiting: usize,
+    /// Requests turned away with 429 since startup
+    pub rejected: u64,
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LintFinding {
+    /// Stable rule ID, e.g. `image_latest_tag` or `memory_limit`
+    pub rule: String,
+    pub severity: LintSeverity,
+    /// Spec field the finding is about, if any
+    pub field: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LintReport {
+    /// False when any finding is at or above the requested `fail_on` severity
+    pub passed: bool,
+    /// Whether the image was found locally, so rules about its healthcheck and user ran
+    pub image_inspected: bool,
+    pub findings: Vec<LintFinding>,
+}
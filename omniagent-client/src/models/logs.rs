@@ -0,0 +1,36 @@
+//! Structured log parsing
+
+use serde::{Deserialize, Serialize};
+
+/// How an instance's log lines are split into fields. A `level` field (or `severity` or
+/// `lvl`) also sets the line's level.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum LogParsing {
+    /// Each line is a JSON object; its top-level fields become the line's fields
+    Json,
+    /// Named captures become fields, e.g. `^(?P<level>\w+) (?P<msg>.*)$`
+    Regex { pattern: String },
+}
+
+/// Marks an instance unhealthy while error lines arrive faster than the threshold, for apps
+/// without HTTP probes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogHealth {
+    /// Regexes for error lines; empty uses a default matching error, exception, fatal and
+    /// panic. With log parsing rules, lines at error level or above count as well.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    pub threshold_per_minute: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogHealthStatus {
+    pub instance_id: String,
+    pub healthy: bool,
+    pub errors_last_minute: u32,
+    pub threshold_per_minute: u32,
+    pub last_error: Option<String>,
+    /// When `healthy` last changed, or when watching started
+    pub since: String,
+}
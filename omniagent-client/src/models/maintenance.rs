@@ -0,0 +1,35 @@
+//! Maintenance windows
+
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowState {
+    Scheduled,
+    Active,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    pub id: String,
+    pub name: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    /// Shell command run when the window opens (e.g. drain instances)
+    pub pre_hook: Option<String>,
+    /// Shell command run when the window closes (e.g. reconcile)
+    pub post_hook: Option<String>,
+    pub state: WindowState,
+    pub last_hook_output: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceWindowRequest {
+    pub name: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub pre_hook: Option<String>,
+    pub post_hook: Option<String>,
+}
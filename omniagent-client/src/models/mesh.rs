@@ -0,0 +1,34 @@
+//! WireGuard mesh between agents
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeshPeer {
+    pub agent_id: String,
+    pub public_key: String,
+    /// `host:port` the peer's WireGuard listens on; omitted for peers behind NAT
+    pub endpoint: Option<String>,
+    /// The peer's overlay address and container subnet, in CIDR form
+    pub allowed_ips: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerStatus {
+    #[serde(flatten)]
+    pub peer: MeshPeer,
+    /// Unix timestamp of the last completed handshake
+    pub latest_handshake: Option<i64>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshStatus {
+    pub enabled: bool,
+    pub interface: Option<String>,
+    pub public_key: Option<String>,
+    pub address: Option<String>,
+    pub subnet: Option<String>,
+    pub endpoint: Option<String>,
+    pub peers: Vec<PeerStatus>,
+}
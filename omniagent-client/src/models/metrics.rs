@@ -0,0 +1,11 @@
+//! Prometheus metrics re-exported by the agent
+
+use serde::{Deserialize, Serialize};
+
+/// Where the agent scrapes an instance's Prometheus metrics, on the container's own address
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsScrape {
+    pub port: u16,
+    /// Defaults to `/metrics`
+    pub path: Option<String>,
+}
@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+pub mod access;
+pub mod apply;
+pub mod bandwidth;
+pub mod capture;
+pub mod checks;
+pub mod cpi;
+pub mod diagnostics;
+pub mod disk;
+pub mod events;
+pub mod flags;
+pub mod ha;
+pub mod host;
+pub mod housekeeping;
+pub mod images;
+pub mod instances;
+pub mod limits;
+pub mod lint;
+pub mod logs;
+pub mod maintenance;
+pub mod mesh;
+pub mod nettest;
+pub mod metrics;
+pub mod node;
+pub mod plugins;
+pub mod rbac;
+pub mod registry_cache;
+pub mod seccomp;
+pub mod share;
+pub mod state;
+pub mod usage;
+pub mod userns;
+pub mod views;
+
+/// A result with non-fatal advisories about the request that produced it. The result's
+/// fields stay at the top level, so clients that ignore `warnings` see the plain type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithWarnings<T> {
+    #[serde(flatten)]
+    pub result: T,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
@@ -0,0 +1,28 @@
+//! Connectivity tests run from inside an instance's network namespace
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetTestRequest {
+    /// `host:port` for DNS and TCP checks, or an `http(s)://` URL to also make a GET request
+    pub target: String,
+    /// Per-check timeout in seconds, default 5
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetCheck {
+    /// `dns`, `tcp` or `http`
+    pub check: String,
+    pub success: bool,
+    pub duration_ms: Option<f64>,
+    /// Resolved address for `dns`, status line for `http`
+    pub detail: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetTestReport {
+    pub target: String,
+    pub checks: Vec<NetCheck>,
+}
@@ -0,0 +1,83 @@
+//! Node labels, taints and tolerations
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TaintEffect {
+    /// Instances without a matching toleration are rejected
+    NoSchedule,
+    /// Advisory only; reported but not enforced
+    PreferNoSchedule,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Taint {
+    pub key: String,
+    pub value: Option<String>,
+    pub effect: TaintEffect,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TolerationOperator {
+    Equal,
+    Exists,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Toleration {
+    /// Taint key to tolerate; omitted with `Exists` tolerates every taint
+    pub key: Option<String>,
+    pub operator: Option<TolerationOperator>,
+    pub value: Option<String>,
+    /// Effect to tolerate; omitted tolerates all effects
+    pub effect: Option<TaintEffect>,
+}
+
+impl Toleration {
+    /// Whether this toleration lets an instance ignore the taint
+    pub fn tolerates(&self, taint: &Taint) -> bool {
+        if self.effect.as_ref().is_some_and(|effect| *effect != taint.effect) {
+            return false;
+        }
+
+        match (self.operator.as_ref().unwrap_or(&TolerationOperator::Equal), &self.key) {
+            (TolerationOperator::Exists, None) => true,
+            (TolerationOperator::Exists, Some(key)) => *key == taint.key,
+            (TolerationOperator::Equal, Some(key)) => *key == taint.key && self.value == taint.value,
+            (TolerationOperator::Equal, None) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeLabels {
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeTaints {
+    pub taints: Vec<Taint>,
+}
+
+/// Placement requirements an agent checks before creating an instance
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Constraints {
+    /// Node labels that must be present with exactly these values
+    pub node_labels: Option<HashMap<String, String>>,
+    /// Bytes of memory that must be available on the host
+    pub min_free_memory: Option<u64>,
+    /// `gpu`, or any feature the agent advertises in its capabilities
+    pub capabilities: Option<Vec<String>>,
+}
+
+/// Body of a create rejected because the instance can't be placed on this agent. Parses as
+/// an `AccessError` too, so clients that only know that shape still get the code and message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unschedulable {
+    /// Always `unschedulable`
+    pub code: String,
+    pub error: String,
+    /// Every unmet taint or constraint, not just the first
+    pub reasons: Vec<String>,
+}
@@ -0,0 +1,10 @@
+//! WASM plugins loaded by the agent
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub name: String,
+    /// Exported hooks: `pre_create`, `post_start` and `handle` (serves `/plugins/<name>/...`)
+    pub hooks: Vec<String>,
+}
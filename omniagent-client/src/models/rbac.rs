@@ -0,0 +1,41 @@
+//! Role-based access control: roles granting verbs on resource types, bound to API keys per
+//! namespace
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// `create`, `update`, `delete` or `*`
+    pub verbs: Vec<String>,
+    /// `instances`, `volumes`, `networks` or `*`
+    pub resources: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Role {
+    pub name: String,
+    pub rules: Vec<PolicyRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleRequest {
+    pub rules: Vec<PolicyRule>,
+}
+
+/// Grants a role to API keys within namespaces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleBinding {
+    pub name: String,
+    pub role: String,
+    /// API key IDs, or `*` for every minted key
+    pub subjects: Vec<String>,
+    /// Namespaces the role applies in, or `*` for all
+    pub namespaces: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleBindingRequest {
+    pub role: String,
+    pub subjects: Vec<String>,
+    pub namespaces: Vec<String>,
+}
@@ -0,0 +1,11 @@
+//! Pull-through registry cache
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryCacheStatus {
+    pub enabled: bool,
+    pub upstream: Option<String>,
+    pub endpoint: Option<String>,
+    pub container_status: Option<String>,
+}
@@ -0,0 +1,10 @@
+//! Seccomp profile library
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeccompProfileSummary {
+    pub name: String,
+    pub default_action: String,
+    pub syscall_rules: usize,
+}
@@ -0,0 +1,24 @@
+//! Capability URLs: signed, expiring links to one instance's logs or exec session
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShareRequest {
+    /// `logs` or `exec`
+    pub action: String,
+    /// The exec session to share, for `exec`
+    pub exec_id: Option<String>,
+    /// Link lifetime in seconds (default 900)
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedLink {
+    /// Path under the agent, or an absolute URL when the agent knows its public address
+    pub url: String,
+    pub token: String,
+    pub action: String,
+    pub instance_id: String,
+    pub exec_id: Option<String>,
+    pub expires_at: String,
+}
@@ -0,0 +1,43 @@
+//! State synchronisation for the orchestrator
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceSummary {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub status: String,
+    pub restart_count: i64,
+    pub started_at: String,
+    #[serde(default)]
+    pub created_at: String,
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedInstance {
+    /// State version at which this instance last changed
+    pub version: u64,
+    pub hash: String,
+    pub instance: InstanceSummary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateDelta {
+    pub version: u64,
+    /// True when `since` predates retained history and `changed` holds every instance
+    pub full_resync: bool,
+    pub changed: Vec<TrackedInstance>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateDigest {
+    pub version: u64,
+    /// Instance ID to content hash
+    pub instances: HashMap<String, String>,
+    pub memory_available: u64,
+    pub load_average: f64,
+}
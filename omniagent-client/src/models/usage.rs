@@ -0,0 +1,19 @@
+//! Per-instance resource usage for chargeback
+
+use serde::{Deserialize, Serialize};
+
+/// Usage of one instance over one metering period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub instance_id: String,
+    pub name: String,
+    pub period_start: String,
+    pub period_end: String,
+    /// Seconds the instance was running during the period
+    pub runtime_seconds: f64,
+    pub cpu_seconds: f64,
+    /// Working-set memory integrated over the period, in GiB-hours
+    pub memory_gb_hours: f64,
+    pub network_rx_bytes: u64,
+    pub network_tx_bytes: u64,
+}
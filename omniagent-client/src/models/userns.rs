@@ -0,0 +1,27 @@
+//! User namespace remapping
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdRange {
+    pub start: u32,
+    pub count: u32,
+}
+
+impl IdRange {
+    pub fn contains(&self, id: u32) -> bool {
+        id >= self.start && id - self.start < self.count
+    }
+}
+
+/// The daemon's user namespace remapping, as reported in `/agent/info`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsernsInfo {
+    /// Whether the daemon runs containers in a remapped user namespace
+    pub enabled: bool,
+    /// The `userns-remap` user, e.g. `dockremap`
+    pub remap_user: Option<String>,
+    /// Host IDs container IDs 0.. map onto, from /etc/subuid and /etc/subgid
+    pub uid_range: Option<IdRange>,
+    pub gid_range: Option<IdRange>,
+}
@@ -0,0 +1,18 @@
+//! Saved views: named instance queries shared by dashboards and CLIs
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedView {
+    pub name: String,
+    /// Instance query in the `/instances/search` language, e.g. `status=running label.tier=web`
+    pub query: String,
+    pub description: Option<String>,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedViewRequest {
+    pub query: String,
+    pub description: Option<String>,
+}
@@ -0,0 +1,76 @@
+//! Runs against a live agent at `OMNI_AGENT_URL`; every test is a no-op when it isn't set.
+//! Instance lifecycle tests also need `OMNI_AGENT_TEST_IMAGE`, since they create containers.
+
+use omniagent_client::models::instances::AppInstanceRequest;
+use omniagent_client::Client;
+
+fn client() -> Option<Client> {
+    let url = std::env::var("OMNI_AGENT_URL").ok()?;
+    Some(Client::new(&url).expect("OMNI_AGENT_URL must be a valid URL"))
+}
+
+#[tokio::test]
+async fn reads_agent_status() {
+    let Some(client) = client() else {
+        return;
+    };
+
+    let health = client.health_check().await.unwrap();
+    assert!(!health.status.is_empty());
+
+    let info = client.get_agent_info().await.unwrap();
+    assert!(!info.capabilities.features.is_empty());
+
+    client.get_read_only().await.unwrap();
+    client.get_leader_status().await.unwrap();
+    client.get_disk_status().await.unwrap();
+    client.get_mesh_status().await.unwrap();
+}
+
+#[tokio::test]
+async fn state_delta_matches_digest() {
+    let Some(client) = client() else {
+        return;
+    };
+
+    let digest = client.get_state_digest().await.unwrap();
+    let delta = client.get_state_delta(Some(0)).await.unwrap();
+    assert!(delta.version >= digest.version);
+    for tracked in &delta.changed {
+        assert!(!tracked.instance.id.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn missing_resources_are_none() {
+    let Some(client) = client() else {
+        return;
+    };
+
+    assert!(client.get_instance("omniagent-client-missing").await.unwrap().is_none());
+    assert!(client.get_preload_job("omniagent-client-missing").await.unwrap().is_none());
+    assert!(client.get_seccomp_profile("omniagent-client-missing").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn instance_lifecycle() {
+    let (Some(client), Ok(image)) = (client(), std::env::var("OMNI_AGENT_TEST_IMAGE")) else {
+        return;
+    };
+
+    let request = AppInstanceRequest {
+        name: format!("omniagent-client-test-{}", std::process::id()),
+        image,
+        ..Default::default()
+    };
+    let instance = client.create_instance(&request).await.unwrap().result;
+    assert_eq!(instance.name, request.name);
+    assert!(instance.image_digest.is_some());
+
+    let fetched = client.get_instance(&instance.id).await.unwrap().expect("created instance is listed");
+    assert_eq!(fetched.id, instance.id);
+
+    client.stop_instance(&instance.id).await.unwrap();
+    client.delete_instance(&instance.id).await.unwrap();
+    assert!(client.get_instance(&instance.id).await.unwrap().is_none());
+}
@@ -0,0 +1,153 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::event_bus::{AgentEvent, EventBus};
+pub use omniagent_client::models::diagnostics::ClockSkew;
+
+/// Seconds between the NTP epoch (1900) and the Unix epoch
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+
+fn now_secs() -> f64 {
+    chrono::Utc::now().timestamp_micros() as f64 / 1e6
+}
+
+/// Unix time of a 64-bit NTP timestamp
+fn ntp_time(bytes: &[u8]) -> f64 {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as f64 / 4_294_967_296.0;
+    seconds + fraction - NTP_UNIX_OFFSET
+}
+
+/// NTP server from `OMNI_NTP_SERVER` (default `pool.ntp.org:123`); `off` disables NTP checks
+pub fn ntp_server() -> Option<String> {
+    let server = std::env::var("OMNI_NTP_SERVER").unwrap_or_else(|_| "pool.ntp.org:123".to_string());
+    match server.as_str() {
+        "" | "off" => None,
+        _ if server.contains(':') => Some(server),
+        _ => Some(format!("{}:123", server)),
+    }
+}
+
+/// Agent time minus the server's, in seconds, from one SNTP exchange corrected for the
+/// round trip
+pub async fn ntp_skew(server: &str) -> Result<f64, String> {
+    let address = tokio::net::lookup_host(server).await
+        .map_err(|e| format!("Failed to resolve {}: {}", server, e))?
+        .next()
+        .ok_or_else(|| format!("{} did not resolve", server))?;
+    let bind = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = tokio::net::UdpSocket::bind(bind).await
+        .map_err(|e| format!("Failed to open NTP socket: {}", e))?;
+
+    // LI 0, version 4, mode 3 (client)
+    let mut packet = [0u8; 48];
+    packet[0] = 0x23;
+    let sent = now_secs();
+    socket.send_to(&packet, address).await
+        .map_err(|e| format!("Failed to query {}: {}", server, e))?;
+    let received = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut packet)).await
+        .map_err(|_| format!("{} did not answer", server))?
+        .map_err(|e| format!("Failed to query {}: {}", server, e))?;
+    let arrived = now_secs();
+
+    if received < 48 || packet[0] & 0x07 != 4 || packet[1] == 0 {
+        return Err(format!("{} sent an unusable answer", server));
+    }
+    let offset = ((ntp_time(&packet[32..40]) - sent) + (ntp_time(&packet[40..48]) - arrived)) / 2.0;
+    Ok(-offset)
+}
+
+/// Agent time minus the orchestrator's, from its `Date` header (one-second resolution)
+async fn orchestrator_skew(url: &str) -> Result<f64, String> {
+    let response = crate::crypto::http_client().get(url).timeout(Duration::from_secs(5)).send().await
+        .map_err(|e| format!("{} unreachable: {}", url, e))?;
+    let time = response.headers().get("Date")
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| chrono::DateTime::parse_from_rfc2822(date).ok())
+        .ok_or_else(|| format!("{} sent no Date header", url))?;
+    Ok((chrono::Utc::now() - time.with_timezone(&chrono::Utc)).num_milliseconds() as f64 / 1000.0)
+}
+
+/// Measures clock skew every `OMNI_CLOCK_CHECK_INTERVAL` seconds (default 300) against NTP,
+/// falling back to the orchestrator where UDP to NTP is blocked. Alerts when the skew
+/// exceeds `OMNI_CLOCK_DRIFT_THRESHOLD` seconds (default 2), since certificate and token
+/// validation start failing on drifted edge nodes.
+#[derive(Clone)]
+pub struct ClockMonitor {
+    last: Arc<Mutex<Option<ClockSkew>>>,
+}
+
+impl ClockMonitor {
+    pub fn start(bus: &EventBus) -> Self {
+        let monitor = ClockMonitor {
+            last: Arc::new(Mutex::new(None)),
+        };
+        let interval = std::env::var("OMNI_CLOCK_CHECK_INTERVAL").ok()
+            .and_then(|secs| secs.parse().ok())
+            .filter(|secs| *secs >= 30)
+            .unwrap_or(300);
+        let threshold = std::env::var("OMNI_CLOCK_DRIFT_THRESHOLD").ok()
+            .and_then(|secs| secs.parse::<f64>().ok())
+            .filter(|secs| *secs > 0.0)
+            .unwrap_or(2.0);
+        let orchestrator = std::env::var("OMNI_ORCHESTRATOR_URL").ok();
+        if ntp_server().is_none() && orchestrator.is_none() {
+            return monitor;
+        }
+
+        let last = monitor.last.clone();
+        let bus = bus.clone();
+        tokio::spawn(async move {
+            let mut drifted = false;
+            loop {
+                let mut measured = Err("NTP checks are disabled".to_string());
+                if let Some(server) = ntp_server() {
+                    measured = ntp_skew(&server).await.map(|skew| ("ntp", server, skew));
+                }
+                if let (Err(_), Some(url)) = (&measured, &orchestrator) {
+                    measured = orchestrator_skew(url).await.map(|skew| ("orchestrator", url.clone(), skew));
+                }
+
+                match measured {
+                    Ok((source, reference, skew_secs)) => {
+                        if (skew_secs.abs() > threshold) != drifted {
+                            drifted = !drifted;
+                            let message = if drifted {
+                                format!("Agent clock is {:+.1}s from {}, beyond the {:.1}s threshold", skew_secs, reference, threshold)
+                            } else {
+                                format!("Agent clock back within {:.1}s of {} ({:+.1}s)", threshold, reference, skew_secs)
+                            };
+                            if drifted {
+                                log::warn!("{}", message);
+                            } else {
+                                log::info!("{}", message);
+                            }
+                            bus.publish(AgentEvent::Alert {
+                                severity: if drifted { "warning" } else { "info" }.to_string(),
+                                source: "clock_drift".to_string(),
+                                message,
+                                timestamp: chrono::Utc::now().to_rfc3339(),
+                            });
+                        }
+                        *last.lock().unwrap() = Some(ClockSkew {
+                            source: source.to_string(),
+                            reference,
+                            skew_secs,
+                            measured_at: chrono::Utc::now().to_rfc3339(),
+                        });
+                    },
+                    Err(e) => log::error!("Failed to measure clock skew: {}", e),
+                }
+
+                tokio::time::sleep(Duration::from_secs(interval)).await;
+            }
+        });
+
+        monitor
+    }
+
+    /// The latest measurement, if any succeeded
+    pub fn last(&self) -> Option<ClockSkew> {
+        self.last.lock().unwrap().clone()
+    }
+}
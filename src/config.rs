@@ -0,0 +1,115 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use omniagent_client::models::flags::FeatureFlagRequest;
+
+/// Read in order when `OMNI_CONFIG` is unset; the first that exists is used
+const SEARCH_PATHS: &[&str] = &[
+    "omni-agent.toml",
+    "omni-agent.yaml",
+    "omni-agent.yml",
+    "/etc/omni-agent/config.toml",
+    "/etc/omni-agent/config.yaml",
+];
+
+/// Agent settings from a TOML or YAML file, each overridden by its environment variable.
+/// Settings not listed here are only read from the environment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `OMNI_LISTEN`: binds as `ip:port`, a bare port, or `unix:/path`
+    pub listen: Vec<String>,
+    /// `OMNI_PORT`: port for binds that don't name one
+    pub port: u16,
+    /// `DOCKER_HOST`: `unix://`, `tcp://`, `http://` or `npipe://` daemon endpoint. Unset
+    /// picks the local socket.
+    pub docker_host: Option<String>,
+    /// `OMNI_CPI_DIR`
+    pub cpi_dir: PathBuf,
+    /// `OMNI_CPI_TIMEOUT`: for actions and requests that don't set their own
+    pub cpi_timeout_secs: u64,
+    /// `OMNI_METRICS_SCRAPE_INTERVAL`, at least 5
+    pub metrics_scrape_interval_secs: u64,
+    /// Feature flags by name, from the file only; flags set through the API override them
+    pub flags: BTreeMap<String, FeatureFlagRequest>,
+    /// The file these settings came from, if any
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: Vec::new(),
+            port: 8000,
+            docker_host: None,
+            cpi_dir: PathBuf::from("./CPIs"),
+            cpi_timeout_secs: 300,
+            metrics_scrape_interval_secs: 30,
+            flags: BTreeMap::new(),
+            path: None,
+        }
+    }
+}
+
+fn parse_file(path: &Path) -> Result<Config, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+    let config = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+        _ => toml::from_str(&text).map_err(|e| e.to_string()),
+    };
+    config.map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+}
+
+fn env_override<T: std::str::FromStr>(name: &str, setting: &mut T) -> Result<(), String> {
+    match std::env::var(name) {
+        Ok(value) if !value.is_empty() => {
+            *setting = value.parse().map_err(|_| format!("Invalid {} {}", name, value))?;
+            Ok(())
+        },
+        _ => Ok(()),
+    }
+}
+
+impl Config {
+    /// Reads the file named by `OMNI_CONFIG`, else the first of the search paths that exists,
+    /// else starts from the defaults; then applies environment overrides. A named file that is
+    /// missing is an error.
+    pub fn load() -> Result<Self, String> {
+        let path = match std::env::var("OMNI_CONFIG") {
+            Ok(path) if !path.is_empty() => Some(PathBuf::from(path)),
+            _ => SEARCH_PATHS.iter().map(PathBuf::from).find(|path| path.is_file()),
+        };
+        let mut config = match &path {
+            Some(path) => parse_file(path)?,
+            None => Config::default(),
+        };
+        config.path = path;
+
+        if let Ok(listen) = std::env::var("OMNI_LISTEN") {
+            config.listen = listen.split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        env_override("OMNI_PORT", &mut config.port)?;
+        if let Ok(docker_host) = std::env::var("DOCKER_HOST") {
+            config.docker_host = Some(docker_host).filter(|host| !host.is_empty());
+        }
+        env_override("OMNI_CPI_DIR", &mut config.cpi_dir)?;
+        env_override("OMNI_CPI_TIMEOUT", &mut config.cpi_timeout_secs)?;
+        env_override("OMNI_METRICS_SCRAPE_INTERVAL", &mut config.metrics_scrape_interval_secs)?;
+
+        if config.cpi_timeout_secs == 0 {
+            return Err("cpi_timeout_secs must be positive".to_string());
+        }
+        config.metrics_scrape_interval_secs = config.metrics_scrape_interval_secs.max(5);
+        if let Some(name) = config.flags.iter().find(|(_, flag)| flag.percentage.is_some_and(|percentage| percentage > 100)).map(|(name, _)| name) {
+            return Err(format!("flags.{}.percentage must be at most 100", name));
+        }
+        Ok(config)
+    }
+}
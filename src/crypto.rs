@@ -0,0 +1,210 @@
+use std::sync::OnceLock;
+
+pub use omniagent_client::models::instances::CryptoInfo;
+
+/// `OMNI_CRYPTO_POLICY`. Under `fips`, outbound TLS (HTTP clients and the telemetry uplink)
+/// runs on rustls with the FIPS-validated aws-lc-rs provider and only its approved suites.
+/// A `fips` build also takes SHA-256 and HMAC from aws-lc-rs whatever the policy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CryptoPolicy {
+    Standard,
+    Fips,
+}
+
+static POLICY: OnceLock<CryptoPolicy> = OnceLock::new();
+
+pub fn fips_build() -> bool {
+    cfg!(feature = "fips")
+}
+
+/// Reads the policy, `fips` by default in a `fips` build, and installs its TLS provider.
+/// Asking for `fips` from a build without it is an error rather than a silent downgrade.
+/// Call before building any HTTP client.
+pub fn init() -> Result<CryptoPolicy, String> {
+    let default = if fips_build() { "fips" } else { "standard" };
+    let policy = match std::env::var("OMNI_CRYPTO_POLICY").unwrap_or_else(|_| default.to_string()).as_str() {
+        "standard" => CryptoPolicy::Standard,
+        "fips" if fips_build() => CryptoPolicy::Fips,
+        "fips" => return Err("OMNI_CRYPTO_POLICY=fips needs an agent built with the fips feature".to_string()),
+        other => return Err(format!("Unknown OMNI_CRYPTO_POLICY {}; expected standard or fips", other)),
+    };
+    #[cfg(feature = "fips")]
+    if policy == CryptoPolicy::Fips {
+        fips::install()?;
+    }
+    let _ = POLICY.set(policy);
+    Ok(policy)
+}
+
+pub fn policy() -> CryptoPolicy {
+    POLICY.get().copied().unwrap_or(CryptoPolicy::Standard)
+}
+
+pub fn info() -> CryptoInfo {
+    let fips = policy() == CryptoPolicy::Fips;
+    #[cfg(feature = "fips")]
+    let cipher_suites = if fips { fips::cipher_suites() } else { Vec::new() };
+    #[cfg(not(feature = "fips"))]
+    let cipher_suites = Vec::new();
+
+    CryptoInfo {
+        policy: if fips { "fips" } else { "standard" }.to_string(),
+        fips_build: fips_build(),
+        tls: if fips { "rustls/aws-lc-rs" } else { "native-tls" }.to_string(),
+        hashing: if fips_build() { "aws-lc-rs" } else { "rustcrypto" }.to_string(),
+        cipher_suites,
+    }
+}
+
+/// Builder for outbound HTTP clients; every client the agent makes should start here so it
+/// follows the policy
+pub fn http_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    #[cfg(feature = "fips")]
+    if policy() == CryptoPolicy::Fips {
+        return builder.use_preconfigured_tls(fips::client_config());
+    }
+    builder
+}
+
+pub fn http_client() -> reqwest::Client {
+    http_builder().build().expect("Failed to build HTTP client")
+}
+
+/// TLS connector for outbound WebSockets; None leaves it to tungstenite
+pub fn ws_connector() -> Option<tokio_tungstenite::Connector> {
+    #[cfg(feature = "fips")]
+    if policy() == CryptoPolicy::Fips {
+        return Some(tokio_tungstenite::Connector::Rustls(std::sync::Arc::new(fips::client_config())));
+    }
+    None
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub use hashing::{hmac_sha256, verify_hmac_sha256, Sha256};
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::default();
+    hasher.update(data);
+    hasher.finish()
+}
+
+#[cfg(not(feature = "fips"))]
+mod hashing {
+    use hmac::{Hmac, Mac};
+    use sha2::Digest;
+
+    /// Incremental SHA-256
+    #[derive(Default)]
+    pub struct Sha256(sha2::Sha256);
+
+    impl Sha256 {
+        pub fn update(&mut self, data: &[u8]) {
+            self.0.update(data);
+        }
+
+        /// The digest as lowercase hex
+        pub fn finish(self) -> String {
+            super::hex(&self.0.finalize())
+        }
+    }
+
+    fn mac(key: &[u8], data: &[u8]) -> Hmac<sha2::Sha256> {
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+        mac.update(data);
+        mac
+    }
+
+    pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+        mac(key, data).finalize().into_bytes().to_vec()
+    }
+
+    /// Checks a tag in constant time
+    pub fn verify_hmac_sha256(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+        mac(key, data).verify_slice(tag).is_ok()
+    }
+}
+
+#[cfg(feature = "fips")]
+mod hashing {
+    use aws_lc_rs::{digest, hmac};
+
+    /// Incremental SHA-256
+    pub struct Sha256(digest::Context);
+
+    impl Default for Sha256 {
+        fn default() -> Self {
+            Sha256(digest::Context::new(&digest::SHA256))
+        }
+    }
+
+    impl Sha256 {
+        pub fn update(&mut self, data: &[u8]) {
+            self.0.update(data);
+        }
+
+        /// The digest as lowercase hex
+        pub fn finish(self) -> String {
+            super::hex(self.0.finish().as_ref())
+        }
+    }
+
+    pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+    }
+
+    /// Checks a tag in constant time
+    pub fn verify_hmac_sha256(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+        hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, key), data, tag).is_ok()
+    }
+}
+
+#[cfg(feature = "fips")]
+mod fips {
+    use rustls::crypto::CryptoProvider;
+    use std::sync::{Arc, OnceLock};
+
+    static CLIENT_CONFIG: OnceLock<rustls::ClientConfig> = OnceLock::new();
+
+    pub fn install() -> Result<(), String> {
+        let provider = rustls::crypto::default_fips_provider();
+        if !provider.fips() {
+            return Err("The aws-lc-rs TLS provider is not in FIPS mode".to_string());
+        }
+        provider.install_default()
+            .map_err(|_| "A TLS crypto provider was installed before the FIPS one".to_string())
+    }
+
+    fn provider() -> Arc<CryptoProvider> {
+        CryptoProvider::get_default().cloned().expect("crypto::init installs the FIPS provider")
+    }
+
+    pub fn cipher_suites() -> Vec<String> {
+        provider().cipher_suites.iter()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .collect()
+    }
+
+    /// TLS 1.2 and 1.3 with the FIPS provider, trusting the platform's root certificates
+    pub fn client_config() -> rustls::ClientConfig {
+        CLIENT_CONFIG.get_or_init(|| {
+            let mut roots = rustls::RootCertStore::empty();
+            let native = rustls_native_certs::load_native_certs();
+            for error in native.errors {
+                eprintln!("Failed to load a platform root certificate: {}", error);
+            }
+            roots.add_parsable_certificates(native.certs);
+
+            let config = rustls::ClientConfig::builder_with_provider(provider())
+                .with_safe_default_protocol_versions()
+                .expect("The FIPS provider supports TLS 1.2 and 1.3")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            assert!(config.fips(), "TLS client config is not FIPS-approved");
+            config
+        }).clone()
+    }
+}
@@ -0,0 +1,24 @@
+use tokio::sync::broadcast;
+pub use omniagent_client::models::events::AgentEvent;
+
+/// In-process fan-out of agent events; slow subscribers drop the oldest events
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<AgentEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(1024);
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: AgentEvent) {
+        // An error only means nobody is subscribed right now
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AgentEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,57 @@
+use rocket::serde::Serialize;
+use std::time::Duration;
+
+use crate::agent::Agent;
+use crate::clock::{ClockMonitor, ClockSkew};
+use crate::routes::state::{StateDigest, StateTracker};
+
+#[derive(Debug, Serialize)]
+struct Heartbeat {
+    agent_id: String,
+    name: String,
+    version: String,
+    timestamp: String,
+    state: StateDigest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clock_skew: Option<ClockSkew>,
+}
+
+/// Posts a heartbeat with the current state digest and latest clock skew to `OMNI_ORCHESTRATOR_URL` every
+/// `OMNI_HEARTBEAT_INTERVAL` seconds (default 30). Does nothing if no orchestrator is set.
+pub fn start(agent: &Agent, tracker: StateTracker, clock: ClockMonitor) {
+    let Ok(orchestrator) = std::env::var("OMNI_ORCHESTRATOR_URL") else {
+        return;
+    };
+    let interval = std::env::var("OMNI_HEARTBEAT_INTERVAL").ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(30);
+
+    let url = format!("{}/agents/{}/heartbeat", orchestrator.trim_end_matches('/'), agent.id());
+    let agent_id = agent.id().to_string();
+    let name = agent.name().to_string();
+    let version = agent.version().to_string();
+
+    tokio::spawn(async move {
+        let client = crate::crypto::http_client();
+        loop {
+            let heartbeat = Heartbeat {
+                agent_id: agent_id.clone(),
+                name: name.clone(),
+                version: version.clone(),
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                state: tracker.digest(),
+                clock_skew: clock.last(),
+            };
+
+            match client.post(&url).json(&heartbeat).timeout(Duration::from_secs(10)).send().await {
+                Ok(response) if !response.status().is_success() => {
+                    eprintln!("Orchestrator rejected heartbeat: {}", response.status());
+                },
+                Err(e) => eprintln!("Failed to send heartbeat: {}", e),
+                Ok(_) => {}
+            }
+
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    });
+}
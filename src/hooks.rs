@@ -0,0 +1,107 @@
+use rocket::serde::Serialize;
+use rocket::serde::json;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::event_bus::{AgentEvent, EventBus};
+
+/// Most output, in characters, kept from a hook for the log
+const MAX_OUTPUT: usize = 4096;
+
+/// Executable scripts in `OMNI_HOOKS_DIR`, named after the event they handle:
+///
+/// - `on-create` after an instance is created, with the instance
+/// - `on-crash` when an instance exits non-zero or is OOM-killed, with the lifecycle event
+/// - `on-deploy-failure` when a create or update fails, with the instance name, image and error
+///
+/// Each gets its JSON payload on stdin and is killed after `OMNI_HOOK_TIMEOUT` seconds
+/// (default 30). Hooks run in the background; their exit status and output are logged.
+#[derive(Clone, Default)]
+pub struct ScriptHooks {
+    dir: Option<PathBuf>,
+    timeout: Duration,
+}
+
+impl ScriptHooks {
+    pub fn from_env() -> Self {
+        ScriptHooks {
+            dir: std::env::var("OMNI_HOOKS_DIR").ok().map(PathBuf::from),
+            timeout: Duration::from_secs(std::env::var("OMNI_HOOK_TIMEOUT").ok()
+                .and_then(|secs| secs.parse().ok())
+                .filter(|secs| *secs > 0)
+                .unwrap_or(30)),
+        }
+    }
+
+    /// Starts the `hook` script, if there is one, with `payload` on its stdin
+    pub fn run(&self, hook: &'static str, payload: &impl Serialize) {
+        let Some(path) = self.dir.as_ref().map(|dir| dir.join(hook)).filter(|path| path.is_file()) else {
+            return;
+        };
+        let input = json::to_string(payload).unwrap_or_default();
+        let timeout = self.timeout;
+
+        tokio::spawn(async move {
+            match tokio::time::timeout(timeout, execute(&path, input)).await {
+                Ok(Ok(output)) => {
+                    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                    text.push_str(&String::from_utf8_lossy(&output.stderr));
+                    let text: String = text.trim().chars().take(MAX_OUTPUT).collect();
+                    if output.status.success() {
+                        log::info!("Hook {} succeeded: {}", hook, text);
+                    } else {
+                        log::warn!("Hook {} exited with {}: {}", hook, output.status, text);
+                    }
+                },
+                Ok(Err(e)) => log::error!("Failed to run hook {}: {}", hook, e),
+                Err(_) => log::warn!("Hook {} timed out after {}s and was killed", hook, timeout.as_secs()),
+            }
+        });
+    }
+
+    /// Runs `on-crash` for non-zero exits and OOM kills
+    pub fn start(&self, bus: &EventBus) {
+        if self.dir.is_none() {
+            return;
+        }
+
+        let hooks = self.clone();
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let crashed = match &event {
+                            AgentEvent::Lifecycle { action, exit_code, .. } => action == "oom" || (action == "die" && exit_code.is_some_and(|code| code != 0)),
+                            _ => false,
+                        };
+                        if crashed {
+                            hooks.run("on-crash", &event);
+                        }
+                    },
+                    Err(RecvError::Lagged(skipped)) => log::warn!("Crash hook dropped {} events", skipped),
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+}
+
+/// Runs a hook to completion; it is killed if the future is dropped
+async fn execute(path: &Path, input: String) -> std::io::Result<std::process::Output> {
+    let mut child = tokio::process::Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its payload may exit before reading it
+        let _ = stdin.write_all(input.as_bytes()).await;
+    }
+    child.wait_with_output().await
+}
@@ -0,0 +1,236 @@
+pub use omniagent_client::models::instances::DiskUsage;
+
+/// Host memory and disk figures from the platform's own interfaces. `sys_info` reports zeros
+/// or a single volume on some hosts, notably Windows, so it is only the fallback.
+pub trait HostStatsProvider: Send + Sync {
+    /// Total and available physical memory in bytes
+    fn memory(&self) -> Option<(u64, u64)>;
+    /// Local fixed disks, one entry per volume
+    fn disks(&self) -> Vec<DiskUsage>;
+}
+
+pub fn provider() -> &'static dyn HostStatsProvider {
+    #[cfg(target_os = "linux")]
+    let provider: &'static dyn HostStatsProvider = &ProcStats;
+    #[cfg(windows)]
+    let provider: &'static dyn HostStatsProvider = &WindowsStats;
+    #[cfg(target_os = "macos")]
+    let provider: &'static dyn HostStatsProvider = &SysctlStats;
+    #[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+    let provider: &'static dyn HostStatsProvider = &SysInfoStats;
+    provider
+}
+
+/// Total and available memory in bytes, zero when unknown
+pub fn memory() -> (u64, u64) {
+    provider().memory().unwrap_or((0, 0))
+}
+
+/// Summed total and available bytes across all disks
+pub fn disk_totals() -> (u64, u64) {
+    provider().disks().iter().fold((0, 0), |(total, available), disk| (total + disk.total, available + disk.available))
+}
+
+/// The disk holding `path`: the one with the longest mount point that prefixes it
+pub fn disk_for(path: &str) -> Option<DiskUsage> {
+    let path = path.replace('\\', "/").to_lowercase();
+    provider().disks().into_iter()
+        .filter(|disk| path.starts_with(&disk.mount.replace('\\', "/").to_lowercase()))
+        .max_by_key(|disk| disk.mount.len())
+}
+
+/// Reads `/proc/meminfo` and statvfs of each block device in `/proc/self/mounts`
+#[cfg(target_os = "linux")]
+pub struct ProcStats;
+
+/// Undoes the octal escapes `/proc/self/mounts` uses for spaces and tabs in paths
+#[cfg(target_os = "linux")]
+fn unescape_mount(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(i) = rest.find('\\') {
+        result.push_str(&rest[..i]);
+        match rest.get(i + 1..i + 4).and_then(|octal| u8::from_str_radix(octal, 8).ok()) {
+            Some(byte) => {
+                result.push(byte as char);
+                rest = &rest[i + 4..];
+            },
+            None => {
+                result.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(target_os = "linux")]
+impl HostStatsProvider for ProcStats {
+    fn memory(&self) -> Option<(u64, u64)> {
+        let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+        // e.g. "MemAvailable:   12345678 kB"
+        let field = |name: &str| meminfo.lines()
+            .find(|line| line.starts_with(name))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|kb| kb.parse::<u64>().ok())
+            .map(|kb| kb * 1024);
+        Some((field("MemTotal:")?, field("MemAvailable:").or_else(|| field("MemFree:"))?))
+    }
+
+    fn disks(&self) -> Vec<DiskUsage> {
+        let mounts = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+        let mut devices = std::collections::HashSet::new();
+        mounts.lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let (device, mount, filesystem) = (fields.next()?, fields.next()?, fields.next()?);
+                // Bind mounts repeat a device; the first mount is the real one
+                if !device.starts_with("/dev/") || !devices.insert(device.to_string()) {
+                    return None;
+                }
+
+                let mount = unescape_mount(mount);
+                let stats = nix::sys::statvfs::statvfs(mount.as_str()).ok()?;
+                let block = stats.fragment_size() as u64;
+                Some(DiskUsage {
+                    mount,
+                    device: Some(device.to_string()),
+                    filesystem: Some(filesystem.to_string()),
+                    total: stats.blocks() as u64 * block,
+                    available: stats.blocks_available() as u64 * block,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Uses `GlobalMemoryStatusEx` and `GetDiskFreeSpaceExW` on each fixed drive
+#[cfg(windows)]
+pub struct WindowsStats;
+
+#[cfg(windows)]
+impl HostStatsProvider for WindowsStats {
+    fn memory(&self) -> Option<(u64, u64)> {
+        use winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+
+        let mut status: MEMORYSTATUSEX = unsafe { std::mem::zeroed() };
+        status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+        if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+            return None;
+        }
+        Some((status.ullTotalPhys, status.ullAvailPhys))
+    }
+
+    fn disks(&self) -> Vec<DiskUsage> {
+        use winapi::um::fileapi::{GetDiskFreeSpaceExW, GetDriveTypeW, GetLogicalDriveStringsW};
+        use winapi::um::winbase::DRIVE_FIXED;
+        use winapi::um::winnt::ULARGE_INTEGER;
+
+        // A NUL-separated list of roots such as "C:\", ending with an extra NUL
+        let mut buffer = [0u16; 512];
+        let len = unsafe { GetLogicalDriveStringsW(buffer.len() as u32, buffer.as_mut_ptr()) } as usize;
+        if len == 0 || len > buffer.len() {
+            return Vec::new();
+        }
+
+        buffer[..len].split(|c| *c == 0)
+            .filter(|root| !root.is_empty())
+            .filter_map(|root| {
+                let root: Vec<u16> = root.iter().copied().chain(std::iter::once(0)).collect();
+                if unsafe { GetDriveTypeW(root.as_ptr()) } != DRIVE_FIXED {
+                    return None;
+                }
+
+                let mut available: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+                let mut total: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+                let mut free: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+                if unsafe { GetDiskFreeSpaceExW(root.as_ptr(), &mut available, &mut total, &mut free) } == 0 {
+                    return None;
+                }
+
+                Some(DiskUsage {
+                    mount: String::from_utf16_lossy(&root[..root.len() - 1]),
+                    device: None,
+                    filesystem: None,
+                    total: unsafe { *total.QuadPart() },
+                    available: unsafe { *available.QuadPart() },
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads `sysctl` and `vm_stat` for memory and `df` for local volumes
+#[cfg(target_os = "macos")]
+pub struct SysctlStats;
+
+#[cfg(target_os = "macos")]
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program).args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(target_os = "macos")]
+impl HostStatsProvider for SysctlStats {
+    fn memory(&self) -> Option<(u64, u64)> {
+        let total = command_output("sysctl", &["-n", "hw.memsize"])?.trim().parse().ok()?;
+        let page_size: u64 = command_output("sysctl", &["-n", "hw.pagesize"])?.trim().parse().ok()?;
+
+        // e.g. "Pages free:                               12345."
+        let vm_stat = command_output("vm_stat", &[])?;
+        let pages = |name: &str| vm_stat.lines()
+            .find(|line| line.starts_with(name))
+            .and_then(|line| line.rsplit(':').next())
+            .and_then(|count| count.trim().trim_end_matches('.').parse::<u64>().ok())
+            .unwrap_or(0);
+        let available = (pages("Pages free") + pages("Pages inactive") + pages("Pages speculative")) * page_size;
+        Some((total, available))
+    }
+
+    fn disks(&self) -> Vec<DiskUsage> {
+        // POSIX format: device, 1024-blocks, used, available, capacity, mount point
+        command_output("df", &["-kPl"]).unwrap_or_default()
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let device = fields.next()?;
+                let total: u64 = fields.next()?.parse().ok()?;
+                let _used = fields.next()?;
+                let available: u64 = fields.next()?.parse().ok()?;
+                let _capacity = fields.next()?;
+                let mount = fields.collect::<Vec<_>>().join(" ");
+                device.starts_with("/dev/").then(|| DiskUsage {
+                    mount,
+                    device: Some(device.to_string()),
+                    filesystem: None,
+                    total: total * 1024,
+                    available: available * 1024,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Fallback for other platforms; reports the disks as a single volume
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+pub struct SysInfoStats;
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+impl HostStatsProvider for SysInfoStats {
+    fn memory(&self) -> Option<(u64, u64)> {
+        let memory = sys_info::mem_info().ok()?;
+        Some((memory.total * 1024, memory.avail * 1024))
+    }
+
+    fn disks(&self) -> Vec<DiskUsage> {
+        sys_info::disk_info().map(|disk| vec![DiskUsage {
+            mount: "/".to_string(),
+            device: None,
+            filesystem: None,
+            total: disk.total * 1024,
+            available: disk.free * 1024,
+        }]).unwrap_or_default()
+    }
+}
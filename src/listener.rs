@@ -0,0 +1,144 @@
+use rocket::fairing::AdHoc;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bind {
+    Tcp(SocketAddr),
+    /// Local-only socket for an orchestrator running on the same host
+    Unix(std::path::PathBuf),
+}
+
+impl std::fmt::Display for Bind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bind::Tcp(addr) => write!(f, "{}", addr),
+            Bind::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Where the HTTP API listens. Rocket binds a single TCP address, so the first TCP bind is
+/// handed to it and every other bind forwards connections to that one.
+#[derive(Debug, Clone)]
+pub struct ListenConfig {
+    primary: SocketAddr,
+    extra: Vec<Bind>,
+}
+
+fn parse_bind(entry: &str, port: u16) -> Result<Bind, String> {
+    if let Some(path) = entry.strip_prefix("unix:") {
+        return Ok(Bind::Unix(path.into()));
+    }
+    if entry.starts_with('/') {
+        return Ok(Bind::Unix(entry.into()));
+    }
+    if let Ok(port) = entry.parse::<u16>() {
+        return Ok(Bind::Tcp(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port)));
+    }
+    if let Ok(ip) = entry.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return Ok(Bind::Tcp(SocketAddr::new(ip, port)));
+    }
+    entry.parse::<SocketAddr>()
+        .map(Bind::Tcp)
+        .map_err(|_| format!("Invalid listen address {}; expected ip:port, a port, or unix:/path", entry))
+}
+
+impl ListenConfig {
+    /// Parses the configured binds, e.g. `0.0.0.0:8000` and `unix:/run/omni/agent.sock`.
+    /// Entries without a port use `port`; with no binds the agent listens on `0.0.0.0:<port>`.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let port = config.port;
+        let mut binds = config.listen.iter()
+            .map(|entry| parse_bind(entry, port))
+            .collect::<Result<Vec<_>, _>>()?;
+        if binds.is_empty() {
+            binds.push(Bind::Tcp(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port)));
+        }
+
+        // With only Unix sockets, Rocket gets an ephemeral loopback port nobody else is told about
+        let primary = match binds.iter().position(|bind| matches!(bind, Bind::Tcp(_))) {
+            Some(i) => match binds.remove(i) {
+                Bind::Tcp(addr) => addr,
+                Bind::Unix(_) => unreachable!(),
+            },
+            None => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+        };
+        Ok(ListenConfig { primary, extra: binds })
+    }
+
+    pub fn primary(&self) -> SocketAddr {
+        self.primary
+    }
+
+    /// Opens the extra binds once Rocket is listening and knows its port
+    pub fn fairing(&self) -> AdHoc {
+        let (primary, extra) = (self.primary, self.extra.clone());
+        AdHoc::on_liftoff("Extra listeners", move |rocket| Box::pin(async move {
+            let ip = match primary.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+                IpAddr::V6(ip) if ip.is_unspecified() => Ipv6Addr::LOCALHOST.into(),
+                ip => ip,
+            };
+            let target = SocketAddr::new(ip, rocket.config().port);
+            log::info!("HTTP API listening on {}", SocketAddr::new(primary.ip(), target.port()));
+
+            for bind in extra {
+                if let Err(e) = listen(&bind, target).await {
+                    log::error!("Failed to listen on {}: {}", bind, e);
+                    continue;
+                }
+                log::info!("HTTP API listening on {}", bind);
+            }
+        }))
+    }
+}
+
+async fn forward<S: AsyncRead + AsyncWrite + Unpin>(mut inbound: S, target: SocketAddr) {
+    match TcpStream::connect(target).await {
+        Ok(mut outbound) => {
+            let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+        },
+        Err(e) => log::error!("Failed to forward connection to {}: {}", target, e),
+    }
+}
+
+async fn listen(bind: &Bind, target: SocketAddr) -> Result<(), String> {
+    match bind {
+        Bind::Tcp(addr) => {
+            let listener = TcpListener::bind(addr).await.map_err(|e| e.to_string())?;
+            tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => { tokio::spawn(forward(stream, target)); },
+                        Err(e) => log::error!("Failed to accept connection: {}", e),
+                    }
+                }
+            });
+        },
+        #[cfg(unix)]
+        Bind::Unix(path) => {
+            use std::os::unix::fs::FileTypeExt;
+
+            // A socket left behind by a previous run would make bind fail
+            if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                std::fs::remove_file(path).map_err(|e| format!("Failed to remove stale socket: {}", e))?;
+            }
+            let listener = tokio::net::UnixListener::bind(path).map_err(|e| e.to_string())?;
+            tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => { tokio::spawn(forward(stream, target)); },
+                        Err(e) => log::error!("Failed to accept connection: {}", e),
+                    }
+                }
+            });
+        },
+        #[cfg(not(unix))]
+        Bind::Unix(_) => return Err("Unix sockets are not supported on this platform".to_string()),
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use env_logger::{Builder, Target, WriteStyle};
+use log::LevelFilter;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable lines, with the startup banner
+    Text,
+    /// One JSON object per line for log collectors
+    Json,
+}
+
+/// Console output settings, from the command line or `OMNI_QUIET`, `OMNI_LOG_FORMAT`
+/// and `OMNI_DEBUG_ROUTES`
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    /// Skip the banner and log only warnings and errors
+    pub quiet: bool,
+    pub format: LogFormat,
+    /// Log Rocket's launch details, including the route table
+    pub debug_routes: bool,
+}
+
+fn parse_format(value: &str) -> LogFormat {
+    match value {
+        "json" => LogFormat::Json,
+        "text" => LogFormat::Text,
+        other => {
+            eprintln!("Unknown log format {}, using text", other);
+            LogFormat::Text
+        }
+    }
+}
+
+impl LogOptions {
+    pub fn from_args() -> Self {
+        let flag = |name: &str| std::env::var(name).is_ok_and(|value| value == "1" || value == "true");
+        let mut options = LogOptions {
+            quiet: flag("OMNI_QUIET"),
+            format: std::env::var("OMNI_LOG_FORMAT").map(|value| parse_format(&value)).unwrap_or(LogFormat::Text),
+            debug_routes: flag("OMNI_DEBUG_ROUTES"),
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--quiet" | "-q" => options.quiet = true,
+                "--debug-routes" => options.debug_routes = true,
+                "--log-format" => match args.next() {
+                    Some(value) => options.format = parse_format(&value),
+                    None => eprintln!("--log-format needs a value (text or json)"),
+                },
+                _ => match arg.strip_prefix("--log-format=") {
+                    Some(value) => options.format = parse_format(value),
+                    None => eprintln!("Ignoring unknown argument {}", arg),
+                },
+            }
+        }
+        options
+    }
+
+    /// Whether to print the ASCII banner before logging starts
+    pub fn show_banner(&self) -> bool {
+        !self.quiet && self.format == LogFormat::Text
+    }
+
+    /// Installs the global logger. Rocket logs through it too, so its launch output and
+    /// request lines follow the same format; they are limited to warnings unless
+    /// `debug_routes` is set. `RUST_LOG` can still override any of this.
+    pub fn init(&self) {
+        let mut builder = Builder::new();
+        builder
+            .target(Target::Stdout)
+            .filter_level(if self.quiet { LevelFilter::Warn } else { LevelFilter::Info })
+            .filter_module("rocket", LevelFilter::Warn);
+        if self.debug_routes {
+            builder.filter_module("rocket::launch", LevelFilter::Info);
+        }
+
+        if self.format == LogFormat::Json {
+            colored::control::set_override(false);
+            builder.write_style(WriteStyle::Never).format(|buf, record| {
+                let line = rocket::serde::json::json!({
+                    "timestamp": chrono::Utc::now().to_rfc3339(),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                writeln!(buf, "{}", line)
+            });
+        }
+
+        builder.parse_env("RUST_LOG").init();
+    }
+}
@@ -1,13 +1,47 @@
-use colored::Colorize;
-use rocket::routes;
-
 pub mod routes;
-use routes::{index, instances};
+use routes::{index, diagnostics, bandwidth, preemption, usage};
 use routes::instances::AppManager;
+use routes::images::ImageManager;
+use routes::registry_cache::RegistryCache;
+use routes::state::StateTracker;
+use routes::ha::LeaderElection;
+use routes::access::{AdminToken, ApiKeys, AuthFailures, ReadOnlyMode};
+use routes::rbac::Authorizer;
+use routes::share::ShareSigner;
+use routes::disk::DiskMonitor;
+use routes::housekeeping::Housekeeping;
+use routes::log_health::LogHealthMonitor;
+use routes::metrics::MetricsScraper;
+use routes::diagnostics::RecentEvents;
+use routes::mesh::Mesh;
+use routes::limits::ConcurrencyLimits;
+use routes::checks::SyntheticChecks;
+use std::sync::Arc;
 
 mod agent;
 use agent::Agent;
 
+mod websocket;
+mod heartbeat;
+mod event_bus;
+mod publishers;
+mod mqtt;
+mod telemetry;
+mod remote_write;
+mod state_store;
+mod host_stats;
+mod clock;
+mod hooks;
+mod logging;
+mod listener;
+mod crypto;
+mod config;
+use config::Config;
+use event_bus::EventBus;
+use clock::ClockMonitor;
+use logging::LogOptions;
+use listener::ListenConfig;
+
 
 
 const BANNER: &str = r#"
@@ -21,60 +55,196 @@ const BANNER: &str = r#"
 "#;
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
-    println!("{}", BANNER.replace("{}", &env!("CARGO_PKG_VERSION")));
+    let log_options = LogOptions::from_args();
+    if log_options.show_banner() {
+        println!("{}", BANNER.replace("{}", env!("CARGO_PKG_VERSION")));
+    }
+    log_options.init();
+
+    match crypto::init() {
+        Ok(policy) => log::info!("Crypto policy: {:?}", policy),
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match &config.path {
+        Some(path) => log::info!("Config file: {}", path.display()),
+        None => log::info!("No config file found; using defaults and environment variables"),
+    }
+
     let agent = Agent::new("OmniAgent 1".to_string(), env!("CARGO_PKG_VERSION").to_string());
-    println!("+-----------------------------------------------------------------");
-    println!("| Selected UUID for agent: {}", agent.id().to_string().bright_green());
-    println!("| Agent name: {}", agent.name().bright_blue());
-    println!("+-----------------------------------------------------------------");
-
-    let routes = routes![
-        index::     index,
-        instances:: list_instances,
-        instances:: get_instance,
-        instances:: create_instance,
-        instances:: start_instance,
-        instances:: stop_instance,
-        instances:: restart_instance,
-        instances:: update_instance,
-        instances:: delete_instance,
-        instances:: list_images,
-        instances:: stream_events,
-        instances:: health_check,
-        instances:: get_instance_logs,
-        instances:: get_instance_stats,
-        instances:: pause_instance,
-        instances:: unpause_instance,
-        instances:: inspect_instance,
-        instances:: list_volumes,
-        instances:: create_volume,
-        instances:: delete_volume,
-        instances:: list_networks,
-        instances:: create_network,
-        instances:: delete_network,
-        instances:: connect_instance_to_network,
-        instances:: disconnect_instance_from_network,
-        instances:: get_agent_info
-
-    ];
+    log::info!("Selected UUID for agent: {}", agent.id());
+    log::info!("Agent name: {}", agent.name());
+
+    let routes = routes::all();
 
     let routes_clone = routes.clone();
-    let app_manager = match AppManager::new() {
+    let store = match state_store::connect_from_env().await {
+        Ok(store) => store,
+        Err(e) => {
+            log::error!("Failed to open state store: {}", e);
+            std::process::exit(1);
+        }
+    };
+    log::info!("State store backend: {}", store.backend());
+
+    let app_manager = match AppManager::new(store.clone(), &config) {
         Ok(manager) => manager,
         Err(e) => {
-            eprintln!("Failed to initialize AppManager: {}", e);
+            log::error!("Failed to initialize AppManager: {}", e);
             std::process::exit(1);
         }
     };
+    match app_manager.restore().await {
+        Ok((count, 0)) => log::info!("Restored {} instance records", count),
+        Ok((count, gone)) => log::info!("Restored {} instance records; {} containers no longer exist", count, gone),
+        Err(e) => log::error!("Failed to restore instance records: {}", e),
+    }
+    match app_manager.flags().load().await {
+        Ok(0) => {},
+        Ok(count) => log::info!("Loaded {} feature flags set through the API", count),
+        Err(e) => log::error!("Failed to load feature flags: {}", e),
+    }
+    let event_bus = EventBus::new();
+    let recent_events = RecentEvents::start(&event_bus);
+    publishers::start(&event_bus, &agent.id().to_string());
+    telemetry::start(&event_bus, &agent.id().to_string());
+
+    app_manager.supervise(event_bus.clone());
+    app_manager.plugins().start(&event_bus);
+    app_manager.hooks().start(&event_bus);
+    diagnostics::self_test(&app_manager, &agent.id().to_string()).await;
+    app_manager.maintenance().start_scheduler();
+
+    let election = LeaderElection::from_env(agent.id().to_string());
+    election.start(store.clone(), event_bus.clone());
+    let read_only = ReadOnlyMode::from_env();
+    if read_only.is_enabled() {
+        log::info!("Read-only mode: mutating endpoints are disabled");
+    }
+    let api_keys = match ApiKeys::load(store.clone()).await {
+        Ok(api_keys) => api_keys,
+        Err(e) => {
+            log::error!("Failed to load API keys: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if api_keys.count() == 0 {
+        log::warn!("No API keys configured: instance mutations are unauthenticated");
+    }
+    let authorizer = match Authorizer::load(store.clone()).await {
+        Ok(authorizer) => authorizer,
+        Err(e) => {
+            log::error!("Failed to load RBAC roles: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let auth_failures = AuthFailures::from_env(store.clone(), event_bus.clone());
+
+    let image_manager = Arc::new(ImageManager::new());
+    if let Err(e) = image_manager.load_pinned(store.as_ref()).await {
+        log::error!("Failed to load pinned images: {}", e);
+    }
+    let disk_monitor = DiskMonitor::from_env();
+    disk_monitor.start(app_manager.docker().clone(), image_manager.clone(), store.clone(), app_manager.maintenance().clone(), event_bus.clone());
+    preemption::start(app_manager.docker().clone(), store.clone(), event_bus.clone());
+    usage::start(app_manager.docker().clone(), store.clone());
+    bandwidth::start(app_manager.docker().clone(), store);
+    let log_health = LogHealthMonitor::new();
+    log_health.start(app_manager.clone(), event_bus.clone());
+    let limits = ConcurrencyLimits::from_env();
+    let metrics_scraper = MetricsScraper::new(limits.clone(), &config);
+    metrics_scraper.start(app_manager.clone());
+    remote_write::start(metrics_scraper.clone(), &agent.id().to_string());
+    let synthetic_checks = SyntheticChecks::new();
+    synthetic_checks.start(app_manager.clone(), election.clone(), event_bus.clone());
+    let housekeeping = Housekeeping::from_env();
+    housekeeping.start(app_manager.clone(), election.clone());
+
+    let mesh = Mesh::from_env();
+    if mesh.is_enabled() {
+        log::info!("WireGuard mesh: enabled");
+        mesh.start(&agent.id().to_string(), app_manager.docker().clone());
+    }
+
+    let state_tracker = StateTracker::new(event_bus.clone());
+    state_tracker.start(app_manager.docker().clone());
+    let clock = ClockMonitor::start(&event_bus);
+    heartbeat::start(&agent, state_tracker.clone(), clock);
+    let mqtt_context = mqtt::CommandContext {
+        docker: app_manager.docker().clone(),
+        tracker: state_tracker.clone(),
+        election: election.clone(),
+        read_only: read_only.clone(),
+    };
+    mqtt::start(&event_bus, mqtt_context, &agent.id().to_string());
+
+    if mqtt::only_mode() {
+        log::info!("Running in MQTT-only mode; HTTP API disabled");
+        let _ = tokio::signal::ctrl_c().await;
+        return Ok(());
+    }
+
+    let listen = match ListenConfig::from_config(&config) {
+        Ok(listen) => listen,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let registry_cache = Arc::new(RegistryCache::from_env());
+    if registry_cache.is_enabled() {
+        let docker = app_manager.docker().clone();
+        let cache = registry_cache.clone();
+        tokio::spawn(async move {
+            if let Err(e) = cache.ensure_running(&docker).await {
+                log::error!("{}", e);
+            }
+        });
+    }
 
     let rocket_instance = rocket::build()
         .mount("/", routes)
+        .register("/", routes::catchers())
         .configure(rocket::Config {
-            address: "0.0.0.0".parse().unwrap(),
+            address: listen.primary().ip(),
+            port: listen.primary().port(),
+            cli_colors: log_options.format == logging::LogFormat::Text,
             ..rocket::Config::default()
         })
+        .attach(listen.fairing())
         .manage(routes_clone)
-        .manage(app_manager);
+        .manage(app_manager)
+        .manage(image_manager)
+        .manage(registry_cache)
+        .manage(state_tracker)
+        .manage(event_bus)
+        .manage(election)
+        .manage(read_only)
+        .manage(api_keys)
+        .manage(AdminToken::from_env())
+        .manage(authorizer)
+        .manage(ShareSigner::from_env())
+        .manage(auth_failures)
+        .manage(disk_monitor)
+        .manage(housekeeping)
+        .manage(log_health)
+        .manage(metrics_scraper)
+        .manage(recent_events)
+        .manage(mesh)
+        .manage(limits)
+        .manage(synthetic_checks)
+        .manage(agent);
 
     // Collect routes information before launch
     index::collect_routes(&rocket_instance);
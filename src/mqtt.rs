@@ -0,0 +1,204 @@
+use bollard::Docker;
+
+use crate::event_bus::EventBus;
+use crate::routes::access::ReadOnlyMode;
+use crate::routes::ha::LeaderElection;
+use crate::routes::state::StateTracker;
+
+/// What MQTT commands act on, and the state that decides whether they may mutate
+#[derive(Clone)]
+#[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+pub struct CommandContext {
+    pub docker: Docker,
+    pub tracker: StateTracker,
+    pub election: LeaderElection,
+    pub read_only: ReadOnlyMode,
+}
+
+/// True when `OMNI_MQTT_ONLY` asks the agent to run without its HTTP server
+pub fn only_mode() -> bool {
+    std::env::var("OMNI_MQTT_ONLY").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
+/// Connects to `OMNI_MQTT_URL` (e.g. `mqtt://broker:1883`) when set. The agent subscribes to
+/// `<prefix>/commands`, answers on `<prefix>/responses`, mirrors bus events to
+/// `<prefix>/events/<topic>`, and keeps a retained `<prefix>/status` of `online`, with an
+/// `offline` last will. The prefix defaults to `omniagent/<agent id>` and can be set with
+/// `OMNI_MQTT_TOPIC_PREFIX`; `OMNI_MQTT_QOS` selects QoS 0-2 (default 1).
+pub fn start(bus: &EventBus, context: CommandContext, agent_id: &str) {
+    let Ok(url) = std::env::var("OMNI_MQTT_URL") else {
+        return;
+    };
+    let prefix = std::env::var("OMNI_MQTT_TOPIC_PREFIX")
+        .unwrap_or_else(|_| format!("omniagent/{}", agent_id));
+
+    imp::start(bus, context, url, prefix, agent_id);
+}
+
+#[cfg(feature = "mqtt")]
+mod imp {
+    use rocket::serde::{Deserialize, Serialize};
+    use rocket::serde::json::{self, Value};
+    use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+    use std::time::Duration;
+    use tokio::sync::broadcast::error::RecvError;
+
+    use crate::event_bus::EventBus;
+    use crate::routes::access;
+    use super::CommandContext;
+
+    #[derive(Debug, Deserialize)]
+    struct MqttCommand {
+        /// Echoed back so callers can correlate responses
+        request_id: Option<String>,
+        action: String,
+        instance_id: Option<String>,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct MqttResponse {
+        request_id: Option<String>,
+        ok: bool,
+        result: Option<Value>,
+        error: Option<String>,
+    }
+
+    fn qos() -> QoS {
+        match std::env::var("OMNI_MQTT_QOS").as_deref() {
+            Ok("0") => QoS::AtMostOnce,
+            Ok("2") => QoS::ExactlyOnce,
+            _ => QoS::AtLeastOnce,
+        }
+    }
+
+    async fn execute(command: &MqttCommand, context: &CommandContext) -> Result<Value, String> {
+        let CommandContext { docker, tracker, election, read_only } = context;
+        let instance = || command.instance_id.as_deref()
+            .ok_or_else(|| format!("Action {} requires an instance_id", command.action));
+
+        let read_only_action = matches!(command.action.as_str(), "list" | "digest");
+        if !read_only_action {
+            access::check_mutation(read_only, election)
+                .map_err(|(_, error)| error.to_string())?;
+        }
+
+        match command.action.as_str() {
+            "list" => json::to_value(tracker.delta(0)).map_err(|e| e.to_string()),
+            "digest" => json::to_value(tracker.digest()).map_err(|e| e.to_string()),
+            "start" => docker.start_container(instance()?, None::<bollard::container::StartContainerOptions<String>>).await
+                .map(|_| Value::Null).map_err(|e| e.to_string()),
+            "stop" => docker.stop_container(instance()?, Some(bollard::container::StopContainerOptions { t: 30 })).await
+                .map(|_| Value::Null).map_err(|e| e.to_string()),
+            "restart" => docker.restart_container(instance()?, Some(bollard::container::RestartContainerOptions { t: 30 })).await
+                .map(|_| Value::Null).map_err(|e| e.to_string()),
+            "pause" => docker.pause_container(instance()?).await
+                .map(|_| Value::Null).map_err(|e| e.to_string()),
+            "unpause" => docker.unpause_container(instance()?).await
+                .map(|_| Value::Null).map_err(|e| e.to_string()),
+            "delete" => docker.remove_container(instance()?, Some(bollard::container::RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            })).await.map(|_| Value::Null).map_err(|e| e.to_string()),
+            other => Err(format!("Unknown action {}", other)),
+        }
+    }
+
+    pub fn start(bus: &EventBus, context: CommandContext, url: String, prefix: String, agent_id: &str) {
+        // Accept `mqtt://host:port`, `tcp://host:port` or a bare `host[:port]`
+        let address = url.split("://").last().unwrap_or(&url).trim_end_matches('/');
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => match port.parse() {
+                Ok(port) => (host.to_string(), port),
+                Err(_) => {
+                    eprintln!("Invalid port in OMNI_MQTT_URL {}", url);
+                    return;
+                }
+            },
+            None => (address.to_string(), 1883),
+        };
+        let mut options = MqttOptions::new(format!("omniagent-{}", agent_id), host, port);
+
+        let qos = qos();
+        let status_topic = format!("{}/status", prefix);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(status_topic.clone(), "offline", qos, true));
+
+        let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+        // Mirror bus events onto MQTT
+        let mut events = bus.subscribe();
+        let events_client = client.clone();
+        let events_prefix = prefix.clone();
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("MQTT publisher dropped {} events", skipped);
+                        continue;
+                    },
+                    Err(RecvError::Closed) => break,
+                };
+                let topic = format!("{}/events/{}", events_prefix, event.topic());
+                let payload = json::to_string(&event).unwrap_or_default();
+                if let Err(e) = events_client.publish(topic, qos, false, payload).await {
+                    eprintln!("Failed to publish event to MQTT: {}", e);
+                }
+            }
+        });
+
+        let command_topic = format!("{}/commands", prefix);
+        let response_topic = format!("{}/responses", prefix);
+
+        tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        // Sessions are clean, so subscriptions are renewed on every connect. The
+                        // non-blocking variants avoid stalling the event loop that drains requests.
+                        if let Err(e) = client.try_subscribe(command_topic.clone(), qos) {
+                            eprintln!("Failed to subscribe to {}: {}", command_topic, e);
+                        }
+                        if let Err(e) = client.try_publish(status_topic.clone(), qos, true, "online") {
+                            eprintln!("Failed to publish MQTT status: {}", e);
+                        }
+                    },
+                    Ok(Event::Incoming(Packet::Publish(publish))) if publish.topic == command_topic => {
+                        let client = client.clone();
+                        let context = context.clone();
+                        let response_topic = response_topic.clone();
+
+                        tokio::spawn(async move {
+                            let response = match json::from_slice::<MqttCommand>(&publish.payload) {
+                                Ok(command) => match execute(&command, &context).await {
+                                    Ok(result) => MqttResponse { request_id: command.request_id, ok: true, result: Some(result), error: None },
+                                    Err(e) => MqttResponse { request_id: command.request_id, ok: false, result: None, error: Some(e) },
+                                },
+                                Err(e) => MqttResponse { request_id: None, ok: false, result: None, error: Some(format!("Invalid command: {}", e)) },
+                            };
+                            let payload = json::to_string(&response).unwrap_or_default();
+                            if let Err(e) = client.publish(response_topic, qos, false, payload).await {
+                                eprintln!("Failed to publish MQTT response: {}", e);
+                            }
+                        });
+                    },
+                    Ok(_) => {},
+                    Err(e) => {
+                        eprintln!("MQTT connection error: {}", e);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(not(feature = "mqtt"))]
+mod imp {
+    use crate::event_bus::EventBus;
+    use super::CommandContext;
+
+    pub fn start(_bus: &EventBus, _context: CommandContext, _url: String, _prefix: String, _agent_id: &str) {
+        eprintln!("OMNI_MQTT_URL is set but this build lacks the `mqtt` feature; MQTT control is disabled");
+    }
+}
@@ -0,0 +1,96 @@
+use crate::event_bus::EventBus;
+#[cfg(any(feature = "nats", feature = "kafka"))]
+use crate::event_bus::AgentEvent;
+#[cfg(any(feature = "nats", feature = "kafka"))]
+use tokio::sync::broadcast::error::RecvError;
+
+/// Starts the external event publishers enabled by environment variables:
+///
+/// - `OMNI_NATS_URL` publishes to `<OMNI_NATS_SUBJECT_PREFIX>.<topic>` (requires the `nats` feature)
+/// - `OMNI_KAFKA_BROKERS` publishes to `OMNI_KAFKA_TOPIC`, keyed by event topic (requires the `kafka` feature)
+pub fn start(bus: &EventBus, agent_id: &str) {
+    if let Ok(url) = std::env::var("OMNI_NATS_URL") {
+        let prefix = std::env::var("OMNI_NATS_SUBJECT_PREFIX")
+            .unwrap_or_else(|_| format!("omniagent.{}", agent_id));
+        start_nats(bus, url, prefix);
+    }
+
+    if let Ok(brokers) = std::env::var("OMNI_KAFKA_BROKERS") {
+        let topic = std::env::var("OMNI_KAFKA_TOPIC").unwrap_or_else(|_| "omniagent-events".to_string());
+        start_kafka(bus, brokers, topic, agent_id.to_string());
+    }
+}
+
+#[cfg(any(feature = "nats", feature = "kafka"))]
+/// Receives the next event, skipping over any that were dropped because we fell behind
+async fn next_event(events: &mut tokio::sync::broadcast::Receiver<AgentEvent>, publisher: &str) -> Option<AgentEvent> {
+    loop {
+        match events.recv().await {
+            Ok(event) => return Some(event),
+            Err(RecvError::Lagged(skipped)) => eprintln!("{} publisher dropped {} events", publisher, skipped),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+fn start_nats(bus: &EventBus, url: String, prefix: String) {
+    let mut events = bus.subscribe();
+
+    tokio::spawn(async move {
+        let client = match async_nats::connect(&url).await {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("Failed to connect to NATS at {}: {}", url, e);
+                return;
+            }
+        };
+
+        while let Some(event) = next_event(&mut events, "NATS").await {
+            let subject = format!("{}.{}", prefix, event.topic());
+            let payload = rocket::serde::json::to_string(&event).unwrap_or_default();
+            if let Err(e) = client.publish(subject, payload.into()).await {
+                eprintln!("Failed to publish event to NATS: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "nats"))]
+fn start_nats(_bus: &EventBus, _url: String, _prefix: String) {
+    eprintln!("OMNI_NATS_URL is set but this build lacks the `nats` feature; NATS publishing is disabled");
+}
+
+#[cfg(feature = "kafka")]
+fn start_kafka(bus: &EventBus, brokers: String, topic: String, agent_id: String) {
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+
+    let producer: FutureProducer = match rdkafka::ClientConfig::new()
+        .set("bootstrap.servers", &brokers)
+        .set("message.timeout.ms", "10000")
+        .create()
+    {
+        Ok(producer) => producer,
+        Err(e) => {
+            eprintln!("Failed to create Kafka producer for {}: {}", brokers, e);
+            return;
+        }
+    };
+    let mut events = bus.subscribe();
+
+    tokio::spawn(async move {
+        while let Some(event) = next_event(&mut events, "Kafka").await {
+            let payload = rocket::serde::json::to_string(&event).unwrap_or_default();
+            let key = format!("{}.{}", agent_id, event.topic());
+            let record = FutureRecord::to(&topic).key(&key).payload(&payload);
+            if let Err((e, _)) = producer.send(record, std::time::Duration::from_secs(5)).await {
+                eprintln!("Failed to publish event to Kafka: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "kafka"))]
+fn start_kafka(_bus: &EventBus, _brokers: String, _topic: String, _agent_id: String) {
+    eprintln!("OMNI_KAFKA_BROKERS is set but this build lacks the `kafka` feature; Kafka publishing is disabled");
+}
@@ -0,0 +1,230 @@
+use crate::routes::metrics::MetricsScraper;
+
+/// Starts the Prometheus remote_write exporter when `OMNI_REMOTE_WRITE_URL` is set (requires
+/// the `remote_write` feature). Every `OMNI_REMOTE_WRITE_INTERVAL` seconds (default 30) the
+/// samples of `/metrics` are written to a WAL under `OMNI_STATE_DIR` in batches of at most
+/// `OMNI_REMOTE_WRITE_BATCH` series (default 2000), then sent oldest first. While the
+/// endpoint is unreachable the WAL grows up to `OMNI_REMOTE_WRITE_WAL_MAX_MB` (default 256),
+/// dropping its oldest batches beyond that.
+///
+/// Every series carries an `agent_id` label plus the `key=value` pairs of
+/// `OMNI_REMOTE_WRITE_LABELS`, e.g. `fleet=eu-west,env=prod`. `OMNI_REMOTE_WRITE_TOKEN` is
+/// sent as a bearer token.
+pub fn start(scraper: MetricsScraper, agent_id: &str) {
+    if let Ok(url) = std::env::var("OMNI_REMOTE_WRITE_URL") {
+        start_exporter(scraper, url, agent_id.to_string());
+    }
+}
+
+#[cfg(feature = "remote_write")]
+mod wire {
+    /// The remote_write 1.0 protobuf messages
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WriteRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub timeseries: Vec<TimeSeries>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TimeSeries {
+        #[prost(message, repeated, tag = "1")]
+        pub labels: Vec<Label>,
+        #[prost(message, repeated, tag = "2")]
+        pub samples: Vec<Sample>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Label {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub value: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Sample {
+        #[prost(double, tag = "1")]
+        pub value: f64,
+        #[prost(int64, tag = "2")]
+        pub timestamp: i64,
+    }
+}
+
+#[cfg(feature = "remote_write")]
+struct Exporter {
+    url: String,
+    token: Option<String>,
+    wal: std::path::PathBuf,
+    batch: usize,
+    wal_max_bytes: u64,
+    labels: Vec<(String, String)>,
+    http: reqwest::Client,
+}
+
+#[cfg(feature = "remote_write")]
+impl Exporter {
+    /// Encodes the current samples into snappy-compressed batches in the WAL
+    fn collect(&self, scraper: &MetricsScraper, sequence: &mut u64) -> Result<(), String> {
+        use prost::Message;
+
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let timeseries: Vec<wire::TimeSeries> = scraper.samples().into_iter()
+            .map(|sample| {
+                let mut labels: Vec<wire::Label> = std::iter::once(("__name__".to_string(), sample.name))
+                    .chain(sample.labels)
+                    .chain(self.labels.iter().cloned())
+                    .map(|(name, value)| wire::Label { name, value })
+                    .collect();
+                labels.sort_by(|a, b| a.name.cmp(&b.name));
+                labels.dedup_by(|a, b| a.name == b.name);
+                wire::TimeSeries {
+                    labels,
+                    samples: vec![wire::Sample { value: sample.value, timestamp }],
+                }
+            })
+            .collect();
+
+        for chunk in timeseries.chunks(self.batch) {
+            let request = wire::WriteRequest { timeseries: chunk.to_vec() };
+            let body = snap::raw::Encoder::new().compress_vec(&request.encode_to_vec())
+                .map_err(|e| format!("Failed to compress remote_write batch: {}", e))?;
+
+            *sequence += 1;
+            let path = self.wal.join(format!("{:020}-{:06}.snappy", timestamp, sequence));
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, body)
+                .and_then(|_| std::fs::rename(&tmp, &path))
+                .map_err(|e| format!("Failed to write remote_write WAL: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// WAL batches, oldest first, with their sizes
+    fn batches(&self) -> Vec<(std::path::PathBuf, u64)> {
+        let mut batches: Vec<(std::path::PathBuf, u64)> = std::fs::read_dir(&self.wal).into_iter().flatten()
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "snappy"))
+            .map(|entry| (entry.path(), entry.metadata().map(|metadata| metadata.len()).unwrap_or(0)))
+            .collect();
+        batches.sort();
+        batches
+    }
+
+    /// Drops the oldest batches while the WAL is over its size limit
+    fn truncate(&self) {
+        let batches = self.batches();
+        let mut size: u64 = batches.iter().map(|(_, len)| len).sum();
+        let mut dropped = 0;
+        for (path, len) in batches {
+            if size <= self.wal_max_bytes {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                size -= len;
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            eprintln!("remote_write WAL is full; dropped {} oldest batches", dropped);
+        }
+    }
+
+    /// Sends WAL batches oldest first, stopping at the first one that should be retried
+    async fn flush(&self) -> Result<usize, String> {
+        let mut sent = 0;
+        for (path, _) in self.batches() {
+            let body = std::fs::read(&path)
+                .map_err(|e| format!("Failed to read remote_write WAL: {}", e))?;
+
+            let mut request = self.http.post(&self.url)
+                .header("Content-Encoding", "snappy")
+                .header("Content-Type", "application/x-protobuf")
+                .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                .body(body);
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+
+            let response = request.send().await
+                .map_err(|e| format!("Failed to send metrics to {}: {}", self.url, e))?;
+            let status = response.status();
+            if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Err(format!("{} responded with {}", self.url, status));
+            }
+            // Other rejections won't succeed on retry, so the batch is dropped
+            if !status.is_success() {
+                let detail = response.text().await.unwrap_or_default();
+                eprintln!("{} rejected a remote_write batch with {}: {}", self.url, status, detail.trim());
+            }
+            let _ = std::fs::remove_file(&path);
+            sent += 1;
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(feature = "remote_write")]
+fn start_exporter(scraper: MetricsScraper, url: String, agent_id: String) {
+    use std::time::{Duration, Instant};
+
+    let env_number = |name: &str, default: u64| std::env::var(name).ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default);
+    let interval = Duration::from_secs(env_number("OMNI_REMOTE_WRITE_INTERVAL", 30));
+
+    let mut labels = vec![("agent_id".to_string(), agent_id)];
+    labels.extend(std::env::var("OMNI_REMOTE_WRITE_LABELS").unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string())));
+
+    let state_dir = std::env::var("OMNI_STATE_DIR").unwrap_or_else(|_| "./state".to_string());
+    let exporter = Exporter {
+        url,
+        token: std::env::var("OMNI_REMOTE_WRITE_TOKEN").ok(),
+        wal: std::path::Path::new(&state_dir).join("remote-write-wal"),
+        batch: env_number("OMNI_REMOTE_WRITE_BATCH", 2000) as usize,
+        wal_max_bytes: env_number("OMNI_REMOTE_WRITE_WAL_MAX_MB", 256) * 1024 * 1024,
+        labels,
+        http: crate::crypto::http_builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default(),
+    };
+    if let Err(e) = std::fs::create_dir_all(&exporter.wal) {
+        eprintln!("Failed to create remote_write WAL at {}: {}", exporter.wal.display(), e);
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut sequence = 0;
+        let mut backoff = interval;
+        let mut retry_at = Instant::now();
+        loop {
+            tokio::time::sleep(interval).await;
+
+            if let Err(e) = exporter.collect(&scraper, &mut sequence) {
+                eprintln!("{}", e);
+            }
+            exporter.truncate();
+            if Instant::now() < retry_at {
+                continue;
+            }
+
+            match exporter.flush().await {
+                Ok(_) => backoff = interval,
+                Err(e) => {
+                    backoff = (backoff * 2).min(Duration::from_secs(300));
+                    retry_at = Instant::now() + backoff;
+                    eprintln!("{}; retrying in {}s", e, backoff.as_secs());
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "remote_write"))]
+fn start_exporter(_scraper: MetricsScraper, _url: String, _agent_id: String) {
+    eprintln!("OMNI_REMOTE_WRITE_URL is set but this build lacks the `remote_write` feature; remote_write is disabled");
+}
@@ -0,0 +1,658 @@
+use rocket::{catch, delete, get, post, put};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::status;
+use rocket::serde::json::{self, Json};
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::crypto;
+use crate::event_bus::{AgentEvent, EventBus};
+use crate::state_store::{self, StateStore};
+use super::ha::LeaderElection;
+use super::instances::AppManager;
+use super::rbac::{self, Authorizer};
+pub use omniagent_client::models::access::{AccessError, ReadOnlyStatus, ReadOnlyRequest, ApiKeyInfo, ApiKeyRequest, MintedApiKey};
+
+/// Header carrying the API key
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Disables every mutating endpoint while reads, logs and metrics keep working
+#[derive(Clone)]
+pub struct ReadOnlyMode {
+    enabled: Arc<AtomicBool>,
+    locked: bool,
+}
+
+impl ReadOnlyMode {
+    pub fn from_env() -> Self {
+        let locked = std::env::var("OMNI_READ_ONLY").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+        ReadOnlyMode {
+            enabled: Arc::new(AtomicBool::new(locked)),
+            locked,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    fn status(&self) -> ReadOnlyStatus {
+        ReadOnlyStatus { enabled: self.is_enabled(), locked: self.locked }
+    }
+}
+
+/// Whether this agent may currently change anything, shared by the HTTP guard and MQTT
+pub fn check_mutation(read_only: &ReadOnlyMode, election: &LeaderElection) -> Result<(), (Status, AccessError)> {
+    if read_only.is_enabled() {
+        return Err((Status::Forbidden, AccessError::new("agent_read_only", "This agent is in read-only mode")));
+    }
+    if !election.is_leader() {
+        return Err((Status::ServiceUnavailable, AccessError::new("not_leader", "This agent is a follower; send mutations to the leader")));
+    }
+    Ok(())
+}
+
+/// Request guard for mutating routes. Rejects with 403 in read-only mode and with 503 on an
+/// HA follower; the catchers below turn the cached reason into a JSON body.
+#[derive(Clone, Copy)]
+pub struct Mutation(());
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Mutation {
+    type Error = AccessError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let (Some(read_only), Some(election)) = (req.rocket().state::<ReadOnlyMode>(), req.rocket().state::<LeaderElection>()) else {
+            return Outcome::Success(Mutation(()));
+        };
+
+        match check_mutation(read_only, election) {
+            Ok(()) => Outcome::Success(Mutation(())),
+            Err((status, error)) => {
+                req.local_cache(|| Some(error.clone()));
+                Outcome::Error((status, error))
+            }
+        }
+    }
+}
+
+/// Rejections that suggest guessing at credentials, as opposed to missing permissions
+const CREDENTIAL_FAILURES: &[&str] = &["api_key_required", "invalid_api_key", "admin_required", "invalid_share_token"];
+
+#[derive(Default)]
+struct FailureRecord {
+    failures: u32,
+    window_start: Option<Instant>,
+    lockouts: u32,
+    locked_until: Option<Instant>,
+}
+
+impl FailureRecord {
+    /// Counts a failure at `now` and returns the lockout it starts, if it reaches
+    /// `max_failures` within `window`. Each lockout is twice the previous one, up to an hour.
+    fn fail(&mut self, now: Instant, max_failures: u32, window: Duration, lockout: Duration) -> Option<Duration> {
+        if self.window_start.is_none_or(|start| now.duration_since(start) >= window) {
+            self.failures = 0;
+            self.window_start = Some(now);
+        }
+        self.failures += 1;
+        if self.failures < max_failures {
+            return None;
+        }
+        self.failures = 0;
+        self.window_start = None;
+        self.lockouts += 1;
+        let lockout = (lockout * 2u32.saturating_pow(self.lockouts - 1)).min(Duration::from_secs(3600));
+        self.locked_until = Some(now + lockout);
+        Some(lockout)
+    }
+
+    /// Time left at `now` until a locked-out address may try again
+    fn locked_for(&self, now: Instant) -> Option<Duration> {
+        self.locked_until?.checked_duration_since(now).filter(|remaining| !remaining.is_zero())
+    }
+}
+
+/// Failed authentication attempts per client address. `OMNI_AUTH_MAX_FAILURES` failures
+/// (default 5) within `OMNI_AUTH_FAILURE_WINDOW` seconds (default 300) lock the address out
+/// for `OMNI_AUTH_LOCKOUT_SECS` seconds (default 60), doubling with each further lockout up
+/// to an hour. Every failure is audited and every lockout raises an alert.
+#[derive(Clone)]
+pub struct AuthFailures {
+    sources: Arc<Mutex<HashMap<IpAddr, FailureRecord>>>,
+    store: Arc<dyn StateStore>,
+    bus: EventBus,
+    max_failures: u32,
+    window: Duration,
+    lockout: Duration,
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+impl AuthFailures {
+    pub fn from_env(store: Arc<dyn StateStore>, bus: EventBus) -> Self {
+        AuthFailures {
+            sources: Arc::new(Mutex::new(HashMap::new())),
+            store,
+            bus,
+            max_failures: env_u64("OMNI_AUTH_MAX_FAILURES", 5) as u32,
+            window: Duration::from_secs(env_u64("OMNI_AUTH_FAILURE_WINDOW", 300)),
+            lockout: Duration::from_secs(env_u64("OMNI_AUTH_LOCKOUT_SECS", 60)),
+        }
+    }
+
+    /// Seconds until a locked-out address may try again
+    fn locked_out(&self, source: IpAddr) -> Option<u64> {
+        let sources = self.sources.lock().unwrap();
+        let remaining = sources.get(&source)?.locked_for(Instant::now())?;
+        Some(remaining.as_secs() + 1)
+    }
+
+    async fn failed(&self, source: IpAddr, code: &str, path: &str) {
+        let now = Instant::now();
+        let lockout = {
+            let mut sources = self.sources.lock().unwrap();
+            if sources.len() > 4096 {
+                let window = self.window;
+                sources.retain(|_, record| {
+                    record.locked_until.is_some_and(|until| until > now)
+                        || record.window_start.is_some_and(|start| now.duration_since(start) < window)
+                });
+            }
+            sources.entry(source).or_default().fail(now, self.max_failures, self.window, self.lockout)
+        };
+
+        let record = json::json!({
+            "action": "auth_failure",
+            "source": source.to_string(),
+            "code": code,
+            "path": path,
+            "locked_out_secs": lockout.map(|lockout| lockout.as_secs()),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        });
+        if let Err(e) = state_store::append(self.store.as_ref(), state_store::AUDIT, &record).await {
+            log::error!("Failed to record audit entry: {}", e);
+        }
+        if let Some(lockout) = lockout {
+            let message = format!("Locked out {} for {}s after repeated authentication failures", source, lockout.as_secs());
+            log::warn!("{}", message);
+            self.bus.publish(AgentEvent::Alert {
+                severity: "warning".to_string(),
+                source: "auth".to_string(),
+                message,
+                timestamp: chrono::Utc::now().to_rfc3339(),
+            });
+        }
+    }
+}
+
+/// Finishes an authenticating guard: turns away locked-out addresses with 429, counts
+/// credential failures against the client address and caches rejections for the catchers
+pub async fn authenticate<T>(req: &Request<'_>, result: Result<T, (Status, AccessError)>) -> Outcome<T, AccessError> {
+    let result = match req.rocket().state::<AuthFailures>().zip(req.client_ip()) {
+        Some((failures, source)) => match failures.locked_out(source) {
+            Some(retry) => Err((Status::TooManyRequests, AccessError::new(
+                "auth_locked_out",
+                &format!("Too many failed authentication attempts; retry in {}s", retry),
+            ))),
+            None => {
+                // Successes don't clear earlier failures, which would let a scanner reset
+                // its count with any request that needs no credentials
+                if let Err((_, error)) = &result {
+                    if CREDENTIAL_FAILURES.contains(&error.code.as_str()) {
+                        failures.failed(source, &error.code, req.uri().path().as_str()).await;
+                    }
+                }
+                result
+            }
+        },
+        None => result,
+    };
+    match result {
+        Ok(value) => Outcome::Success(value),
+        Err((status, error)) => {
+            req.local_cache(|| Some(error.clone()));
+            Outcome::Error((status, error))
+        }
+    }
+}
+
+/// The token admin-scoped routes accept, from `OMNI_ADMIN_TOKEN`; admin routes are disabled
+/// without one
+#[derive(Clone)]
+pub struct AdminToken(Option<String>);
+
+impl AdminToken {
+    pub fn new(token: &str) -> Self {
+        AdminToken(Some(token.to_string()).filter(|token| !token.is_empty()))
+    }
+
+    pub fn from_env() -> Self {
+        AdminToken::new(&std::env::var("OMNI_ADMIN_TOKEN").unwrap_or_default())
+    }
+}
+
+/// Request guard for admin-scoped routes, which need `Authorization: Bearer <token>` with
+/// the managed [`AdminToken`]. A missing or wrong token is rejected with 401; admin routes
+/// are disabled, with 403, while no token is set.
+#[derive(Clone, Copy)]
+pub struct Admin(());
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = AccessError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let result = match req.rocket().state::<AdminToken>().and_then(|token| token.0.as_ref()) {
+            None => Err((Status::Forbidden, AccessError::new("admin_disabled", "Admin endpoints are disabled; set OMNI_ADMIN_TOKEN to enable them"))),
+            Some(token) => {
+                let presented = req.headers().get_one("Authorization").and_then(|value| value.strip_prefix("Bearer "));
+                if presented.is_some_and(|presented| same_secret(presented, token)) {
+                    Ok(Admin(()))
+                } else {
+                    Err((Status::Unauthorized, AccessError::new("admin_required", "This endpoint requires the admin token")))
+                }
+            }
+        };
+        authenticate(req, result).await
+    }
+}
+
+/// Compares the SHA-256 digests of two secrets in constant time, so neither the timing of
+/// the comparison nor the secrets' lengths reveal how much of `presented` is right
+fn same_secret(presented: &str, expected: &str) -> bool {
+    hash_key(presented).bytes().zip(hash_key(expected).bytes())
+        .fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+/// A minted key as persisted: its metadata and the SHA-256 of the key itself
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredApiKey {
+    #[serde(flatten)]
+    info: ApiKeyInfo,
+    hash: String,
+}
+
+fn hash_key(key: &str) -> String {
+    crypto::sha256_hex(key.as_bytes())
+}
+
+/// API keys accepted by the [`ApiKey`] guard: the comma-separated `OMNI_API_KEYS` plus keys
+/// minted through the API. Keys are only enforced once at least one exists, so agents
+/// without any keep working unauthenticated.
+#[derive(Clone)]
+pub struct ApiKeys {
+    store: Arc<dyn StateStore>,
+    /// Hashes of the keys from `OMNI_API_KEYS`
+    configured: Arc<Vec<String>>,
+    minted: Arc<Mutex<BTreeMap<String, StoredApiKey>>>,
+}
+
+impl ApiKeys {
+    pub async fn load(store: Arc<dyn StateStore>) -> Result<Self, String> {
+        let configured = std::env::var("OMNI_API_KEYS").unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(hash_key)
+            .collect();
+        let mut minted = BTreeMap::new();
+        for (id, value) in store.list(state_store::API_KEYS).await? {
+            match json::from_value::<StoredApiKey>(value) {
+                Ok(key) => {
+                    minted.insert(id, key);
+                },
+                Err(e) => log::warn!("Skipping unreadable API key {}: {}", id, e),
+            }
+        }
+        Ok(ApiKeys {
+            store,
+            configured: Arc::new(configured),
+            minted: Arc::new(Mutex::new(minted)),
+        })
+    }
+
+    /// Number of accepted keys; zero means keys are not enforced
+    pub fn count(&self) -> usize {
+        self.configured.len() + self.minted.lock().unwrap().len()
+    }
+
+    /// The ID of the minted key presented, or `None` for an `OMNI_API_KEYS` key or while no
+    /// keys exist
+    fn check(&self, presented: Option<&str>) -> Result<Option<String>, AccessError> {
+        if self.count() == 0 {
+            return Ok(None);
+        }
+        let Some(presented) = presented else {
+            return Err(AccessError::new("api_key_required", "This endpoint requires an API key in the X-API-Key header"));
+        };
+        let hash = hash_key(presented);
+        if self.configured.contains(&hash) {
+            return Ok(None);
+        }
+        self.minted.lock().unwrap().values()
+            .find(|key| key.hash == hash)
+            .map(|key| Some(key.info.id.clone()))
+            .ok_or_else(|| AccessError::new("invalid_api_key", "The API key is not valid"))
+    }
+
+    fn list(&self) -> Vec<ApiKeyInfo> {
+        self.minted.lock().unwrap().values().map(|key| key.info.clone()).collect()
+    }
+
+    /// Generates a key under `info` and persists its hash, replacing any previous key with
+    /// the same ID
+    async fn issue(&self, info: ApiKeyInfo, action: &str) -> Result<MintedApiKey, String> {
+        let key = format!("omni_{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+        let info = ApiKeyInfo { prefix: key[..12].to_string(), ..info };
+        let stored = StoredApiKey { info: info.clone(), hash: hash_key(&key) };
+        let value = json::to_value(&stored).map_err(|e| format!("Failed to serialize API key: {}", e))?;
+        self.store.put(state_store::API_KEYS, &info.id, &value).await
+            .map_err(|e| format!("Failed to save API key: {}", e))?;
+        self.minted.lock().unwrap().insert(info.id.clone(), stored);
+        audit(self.store.as_ref(), action, &info.id).await;
+        Ok(MintedApiKey { info, key })
+    }
+}
+
+async fn audit(store: &dyn StateStore, action: &str, key_id: &str) {
+    let record = json::json!({
+        "action": action,
+        "key_id": key_id,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    if let Err(e) = state_store::append(store, state_store::AUDIT, &record).await {
+        log::error!("Failed to record audit entry: {}", e);
+    }
+}
+
+/// Request guard for mutations and for reading logs of or attaching to instances. Once any
+/// API key exists it rejects requests without a valid `X-API-Key` header with 401; once any
+/// role binding exists, requests on an existing resource the key's roles don't cover are
+/// rejected with 403.
+#[derive(Clone)]
+pub struct ApiKey {
+    identity: Option<String>,
+    authorizer: Option<Authorizer>,
+}
+
+impl ApiKey {
+    /// Checks the key's role bindings, for creations the guard can't see the namespace of
+    pub fn authorize(&self, verb: &str, resource: &str, namespace: &str) -> Result<(), String> {
+        match &self.authorizer {
+            Some(authorizer) => authorizer.authorize(self.identity.as_deref(), verb, resource, namespace)
+                .map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = AccessError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let checked = req.rocket().state::<ApiKeys>()
+            .map_or(Ok(None), |keys| keys.check(req.headers().get_one(API_KEY_HEADER)))
+            .map(|identity| ApiKey { identity, authorizer: req.rocket().state::<Authorizer>().cloned() })
+            .map_err(|error| (Status::Unauthorized, error));
+        let key = match authenticate(req, checked).await {
+            Outcome::Success(key) => key,
+            outcome => return outcome,
+        };
+
+        let (Some(authorizer), Some(app_manager)) = (&key.authorizer, req.rocket().state::<AppManager>()) else {
+            return Outcome::Success(key);
+        };
+        if key.identity.is_none() || !authorizer.enforced() {
+            return Outcome::Success(key);
+        }
+        let Some((verb, resource, namespace)) = rbac::target(req, app_manager.docker()).await else {
+            return Outcome::Success(key);
+        };
+        match authorizer.authorize(key.identity.as_deref(), verb, resource, &namespace) {
+            Ok(()) => Outcome::Success(key),
+            Err(error) => {
+                req.local_cache(|| Some(error.clone()));
+                Outcome::Error((Status::Forbidden, error))
+            }
+        }
+    }
+}
+
+fn access_error(status: Status, req: &Request<'_>) -> Json<AccessError> {
+    let cached: &Option<AccessError> = req.local_cache(|| None);
+    Json(cached.clone().unwrap_or_else(|| AccessError::new(
+        &status.reason_lossy().to_lowercase().replace(' ', "_"),
+        status.reason_lossy(),
+    )))
+}
+
+// Catchers
+#[catch(401)]
+pub fn unauthorized(req: &Request<'_>) -> Json<AccessError> {
+    access_error(Status::Unauthorized, req)
+}
+
+#[catch(403)]
+pub fn forbidden(req: &Request<'_>) -> Json<AccessError> {
+    access_error(Status::Forbidden, req)
+}
+
+#[catch(429)]
+pub fn too_many_requests(req: &Request<'_>) -> Json<AccessError> {
+    access_error(Status::TooManyRequests, req)
+}
+
+#[catch(503)]
+pub fn service_unavailable(req: &Request<'_>) -> Json<AccessError> {
+    access_error(Status::ServiceUnavailable, req)
+}
+
+#[catch(507)]
+pub fn insufficient_storage(req: &Request<'_>) -> Json<AccessError> {
+    access_error(Status::InsufficientStorage, req)
+}
+
+// API Endpoints
+#[get("/agent/read-only")]
+pub fn get_read_only(read_only: &State<ReadOnlyMode>) -> Json<ReadOnlyStatus> {
+    Json(read_only.status())
+}
+
+#[put("/agent/read-only", format = "json", data = "<read_only_req>")]
+pub fn set_read_only(read_only_req: Json<ReadOnlyRequest>, read_only: &State<ReadOnlyMode>, _admin: Admin) -> Result<Json<ReadOnlyStatus>, status::Custom<Json<AccessError>>> {
+    if read_only.locked && !read_only_req.enabled {
+        return Err(status::Custom(Status::Forbidden, Json(AccessError::new(
+            "read_only_locked",
+            "Read-only mode is set by OMNI_READ_ONLY and cannot be disabled through the API",
+        ))));
+    }
+    read_only.enabled.store(read_only_req.enabled, Ordering::SeqCst);
+    log::info!("Read-only mode {}", if read_only_req.enabled { "enabled" } else { "disabled" });
+    Ok(Json(read_only.status()))
+}
+
+
+#[get("/agent/api-keys")]
+pub fn list_api_keys(api_keys: &State<ApiKeys>, _admin: Admin) -> Json<Vec<ApiKeyInfo>> {
+    Json(api_keys.list())
+}
+
+#[post("/agent/api-keys", format = "json", data = "<key_req>")]
+pub async fn mint_api_key(key_req: Json<ApiKeyRequest>, api_keys: &State<ApiKeys>, _admin: Admin) -> Result<Json<MintedApiKey>, String> {
+    let info = ApiKeyInfo {
+        id: uuid::Uuid::new_v4().simple().to_string()[..12].to_string(),
+        name: key_req.into_inner().name,
+        prefix: String::new(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        rotated_at: None,
+    };
+    let minted = api_keys.issue(info, "mint_api_key").await?;
+    log::info!("Minted API key {}", minted.info.id);
+    Ok(Json(minted))
+}
+
+/// Replaces a key's secret; the old one stops working immediately
+#[post("/agent/api-keys/<id>/rotate")]
+pub async fn rotate_api_key(id: String, api_keys: &State<ApiKeys>, _admin: Admin) -> Result<Json<MintedApiKey>, String> {
+    let info = api_keys.minted.lock().unwrap().get(&id).map(|key| key.info.clone())
+        .ok_or_else(|| format!("API key {} does not exist", id))?;
+    let info = ApiKeyInfo { rotated_at: Some(chrono::Utc::now().to_rfc3339()), ..info };
+    let rotated = api_keys.issue(info, "rotate_api_key").await?;
+    log::info!("Rotated API key {}", id);
+    Ok(Json(rotated))
+}
+
+#[delete("/agent/api-keys/<id>")]
+pub async fn revoke_api_key(id: String, api_keys: &State<ApiKeys>, _admin: Admin) -> Result<Json<ApiKeyInfo>, String> {
+    let info = api_keys.minted.lock().unwrap().get(&id).map(|key| key.info.clone())
+        .ok_or_else(|| format!("API key {} does not exist", id))?;
+    api_keys.store.delete(state_store::API_KEYS, &id).await
+        .map_err(|e| format!("Failed to delete API key: {}", e))?;
+    api_keys.minted.lock().unwrap().remove(&id);
+    audit(api_keys.store.as_ref(), "revoke_api_key", &id).await;
+    log::info!("Revoked API key {}", id);
+    Ok(Json(info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::{ContentType, Header};
+    use rocket::local::blocking::Client;
+
+    const ADMIN_TOKEN: &str = "test-admin-token";
+
+    fn client() -> Client {
+        client_with(AdminToken::new(ADMIN_TOKEN))
+    }
+
+    fn client_with(admin_token: AdminToken) -> Client {
+        let rocket = rocket::build()
+            .manage(ReadOnlyMode { enabled: Arc::new(AtomicBool::new(true)), locked: false })
+            .manage(admin_token)
+            .mount("/", rocket::routes![get_read_only, set_read_only])
+            .register("/", rocket::catchers![unauthorized, forbidden]);
+        Client::tracked(rocket).unwrap()
+    }
+
+    fn set_read_only_as(client: &Client, authorization: Option<&str>) -> Status {
+        let mut request = client.put("/agent/read-only")
+            .header(ContentType::JSON)
+            .body(r#"{"enabled": false}"#);
+        if let Some(authorization) = authorization {
+            request = request.header(Header::new("Authorization", authorization.to_string()));
+        }
+        request.dispatch().status()
+    }
+
+    fn read_only_enabled(client: &Client) -> bool {
+        client.get("/agent/read-only").dispatch().into_json::<ReadOnlyStatus>().unwrap().enabled
+    }
+
+    const MAX_FAILURES: u32 = 3;
+    const WINDOW: Duration = Duration::from_secs(300);
+    const LOCKOUT: Duration = Duration::from_secs(60);
+
+    fn fail(record: &mut FailureRecord, now: Instant) -> Option<Duration> {
+        record.fail(now, MAX_FAILURES, WINDOW, LOCKOUT)
+    }
+
+    #[test]
+    fn locks_out_at_the_failure_threshold() {
+        let now = Instant::now();
+        let mut record = FailureRecord::default();
+        assert_eq!(fail(&mut record, now), None);
+        assert_eq!(fail(&mut record, now + Duration::from_secs(1)), None);
+        assert_eq!(record.locked_for(now), None);
+        assert_eq!(fail(&mut record, now + Duration::from_secs(2)), Some(LOCKOUT));
+        assert_eq!(record.locked_for(now + Duration::from_secs(2)), Some(LOCKOUT));
+    }
+
+    #[test]
+    fn lockout_ends_after_its_window() {
+        let now = Instant::now();
+        let mut record = FailureRecord::default();
+        for _ in 0..MAX_FAILURES {
+            fail(&mut record, now);
+        }
+        assert_eq!(record.locked_for(now + LOCKOUT - Duration::from_secs(1)), Some(Duration::from_secs(1)));
+        assert_eq!(record.locked_for(now + LOCKOUT), None);
+    }
+
+    #[test]
+    fn failures_outside_the_window_start_a_new_count() {
+        let now = Instant::now();
+        let mut record = FailureRecord::default();
+        fail(&mut record, now);
+        fail(&mut record, now + Duration::from_secs(1));
+        // The window has passed, so this is the first failure of a new one
+        let later = now + WINDOW;
+        assert_eq!(fail(&mut record, later), None);
+        assert_eq!(fail(&mut record, later + Duration::from_secs(1)), None);
+        assert!(fail(&mut record, later + Duration::from_secs(2)).is_some());
+    }
+
+    #[test]
+    fn count_resets_after_a_lockout_and_repeat_lockouts_double() {
+        let now = Instant::now();
+        let mut record = FailureRecord::default();
+        for _ in 0..MAX_FAILURES {
+            fail(&mut record, now);
+        }
+        let after = now + LOCKOUT;
+        assert_eq!(fail(&mut record, after), None);
+        assert_eq!(fail(&mut record, after), None);
+        assert_eq!(fail(&mut record, after), Some(LOCKOUT * 2));
+        assert_eq!(fail(&mut record, after), None);
+    }
+
+    #[test]
+    fn lockouts_stop_growing_at_an_hour() {
+        let now = Instant::now();
+        let mut record = FailureRecord { lockouts: 20, ..Default::default() };
+        for _ in 1..MAX_FAILURES {
+            fail(&mut record, now);
+        }
+        assert_eq!(fail(&mut record, now), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn compares_secrets_exactly() {
+        assert!(same_secret(ADMIN_TOKEN, ADMIN_TOKEN));
+        assert!(!same_secret("test-admin-tokem", ADMIN_TOKEN));
+        assert!(!same_secret("test-admin", ADMIN_TOKEN));
+        assert!(!same_secret("", ADMIN_TOKEN));
+    }
+
+    #[test]
+    fn set_read_only_needs_the_admin_token() {
+        let client = client();
+        assert_eq!(set_read_only_as(&client, None), Status::Unauthorized);
+        assert_eq!(set_read_only_as(&client, Some("Bearer wrong-token")), Status::Unauthorized);
+        assert!(read_only_enabled(&client));
+
+        assert_eq!(set_read_only_as(&client, Some(&format!("Bearer {}", ADMIN_TOKEN))), Status::Ok);
+        assert!(!read_only_enabled(&client));
+    }
+
+    #[test]
+    fn admin_routes_are_disabled_without_a_token() {
+        let client = client_with(AdminToken::new(""));
+        assert_eq!(set_read_only_as(&client, None), Status::Forbidden);
+        assert_eq!(set_read_only_as(&client, Some("Bearer ")), Status::Forbidden);
+        assert!(read_only_enabled(&client));
+    }
+}
@@ -0,0 +1,184 @@
+use rocket::post;
+use rocket::serde::json::{self, Json, Value};
+use rocket::State;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+use super::access::{ApiKey, Mutation};
+use super::instances::{self, AppInstanceRequest, AppManager, CreateError, Deploy, NetworkCreateRequest, VolumeCreateRequest};
+use super::limits::{Create, Slot};
+pub use omniagent_client::models::apply::{ManifestResource, ApplyAction, ApplyResult, ApplyReport};
+
+/// Splits a bundle into documents. YAML is a superset of JSON, so both parse here; a document
+/// holding a list contributes each item.
+fn parse_bundle(bundle: &str) -> Result<Vec<Value>, String> {
+    let mut documents = Vec::new();
+    for (i, document) in serde_yaml::Deserializer::from_str(bundle).enumerate() {
+        let value = Value::deserialize(document).map_err(|e| format!("Document {}: {}", i + 1, e))?;
+        match value {
+            Value::Null => {},
+            Value::Array(items) => documents.extend(items),
+            value => documents.push(value),
+        }
+    }
+    Ok(documents)
+}
+
+fn result(kind: &str, name: &str, action: ApplyAction, id: Option<String>, error: Option<String>) -> ApplyResult {
+    ApplyResult { kind: kind.to_string(), name: name.to_string(), action, id, error }
+}
+
+fn failed(kind: &str, name: &str, error: String) -> ApplyResult {
+    result(kind, name, ApplyAction::Failed, None, Some(error))
+}
+
+fn create_error(error: CreateError) -> String {
+    match error {
+        CreateError::Failed(error) => error,
+        CreateError::Unschedulable(Json(unschedulable)) => format!("{}: {}", unschedulable.error, unschedulable.reasons.join("; ")),
+    }
+}
+
+/// Instances ordered so each comes after the bundle's instances it depends on. Instances in
+/// a dependency cycle are returned separately.
+fn apply_order(specs: Vec<AppInstanceRequest>) -> (Vec<AppInstanceRequest>, Vec<AppInstanceRequest>) {
+    let names: HashSet<String> = specs.iter().map(|spec| spec.name().to_string()).collect();
+    let mut placed: HashSet<String> = HashSet::new();
+    let mut remaining = specs;
+    let mut ordered = Vec::with_capacity(remaining.len());
+
+    while let Some(index) = remaining.iter().position(|spec| {
+        spec.depends_on().iter().all(|dependency| !names.contains(dependency) || placed.contains(dependency))
+    }) {
+        let spec = remaining.remove(index);
+        placed.insert(spec.name().to_string());
+        ordered.push(spec);
+    }
+    (ordered, remaining)
+}
+
+async fn apply_volume(request: VolumeCreateRequest, app_manager: &State<AppManager>, mutation: Mutation, key: ApiKey) -> ApplyResult {
+    let name = request.name.clone();
+    match app_manager.docker().inspect_volume(&name).await {
+        // Docker volumes can't be changed in place
+        Ok(existing) => {
+            let labels_match = request.labels.iter().flatten().all(|(key, value)| existing.labels.get(key) == Some(value));
+            if labels_match {
+                result("volume", &name, ApplyAction::Unchanged, Some(name.clone()), None)
+            } else {
+                failed("volume", &name, "Volume exists with different labels and can't be updated".to_string())
+            }
+        },
+        Err(_) => match instances::create_volume(Json(request), app_manager, mutation, key).await {
+            Ok(Json(volume)) => result("volume", &name, ApplyAction::Created, Some(volume.name), None),
+            Err(e) => failed("volume", &name, e),
+        },
+    }
+}
+
+async fn apply_network(request: NetworkCreateRequest, app_manager: &State<AppManager>, mutation: Mutation, key: ApiKey) -> ApplyResult {
+    let name = request.name.clone();
+    match app_manager.docker().inspect_network::<String>(&name, None).await {
+        // Docker networks can't be changed in place
+        Ok(existing) => {
+            let driver_matches = request.driver.as_ref().is_none_or(|driver| existing.driver.as_ref() == Some(driver));
+            let labels_match = request.labels.iter().flatten()
+                .all(|(key, value)| existing.labels.as_ref().and_then(|labels| labels.get(key)) == Some(value));
+            if driver_matches && labels_match {
+                result("network", &name, ApplyAction::Unchanged, existing.id, None)
+            } else {
+                failed("network", &name, "Network exists with a different driver or labels and can't be updated".to_string())
+            }
+        },
+        Err(_) => match instances::create_network(Json(request), app_manager, mutation, key).await {
+            Ok(Json(network)) => result("network", &name, ApplyAction::Created, Some(network.id), None),
+            Err(e) => failed("network", &name, e),
+        },
+    }
+}
+
+/// Creates the instance, or replaces the managed instance of the same name when its spec
+/// differs. The image digest is resolved at create time, so it only counts when given.
+async fn apply_instance(spec: AppInstanceRequest, existing: Option<(String, AppInstanceRequest)>, deploy: Deploy<'_>) -> ApplyResult {
+    let name = spec.name().to_string();
+    let Some((id, mut current)) = existing else {
+        return match instances::create_instance(Json(spec), deploy, Slot::nested()).await {
+            Ok(Json(created)) => result("instance", &name, ApplyAction::Created, Some(created.result.id), None),
+            Err(e) => failed("instance", &name, create_error(e)),
+        };
+    };
+
+    if spec.image_digest.is_none() {
+        current.image_digest = None;
+    }
+    if json::to_value(&current).ok() == json::to_value(&spec).ok() {
+        return result("instance", &name, ApplyAction::Unchanged, Some(id), None);
+    }
+    // The API key guard only checks the current namespace on PATCH /instances/<id>
+    if let Err(e) = deploy.key.authorize("update", "instances", current.namespace()) {
+        return failed("instance", &name, e);
+    }
+    match instances::update_instance(id, Json(spec), deploy, Slot::nested()).await {
+        Ok(Json(updated)) => result("instance", &name, ApplyAction::Updated, Some(updated.result.id), None),
+        Err(e) => failed("instance", &name, create_error(e)),
+    }
+}
+
+// API Endpoints
+
+/// Applies a multi-document YAML or JSON bundle of `{kind, spec}` resources: networks first,
+/// then volumes, then instances in dependency order. A failed resource doesn't stop the
+/// others, but instances depending on a failed instance are skipped.
+#[post("/apply", data = "<bundle>")]
+pub async fn apply_bundle(bundle: String, deploy: Deploy<'_>, _slot: Slot<Create>) -> Result<Json<ApplyReport>, String> {
+    let (app_manager, mutation, key) = (deploy.app_manager, deploy.mutation, &deploy.key);
+    let documents = parse_bundle(&bundle).map_err(|e| format!("Invalid bundle: {}", e))?;
+
+    let mut report = ApplyReport::default();
+    let (mut networks, mut volumes, mut specs) = (Vec::new(), Vec::new(), Vec::new());
+    for document in documents {
+        let kind = document["kind"].as_str().unwrap_or_default().to_string();
+        let name = document["spec"]["name"].as_str().unwrap_or_default().to_string();
+        if kind == "template" {
+            report.results.push(failed(&kind, &name, "Templates are not supported by this agent".to_string()));
+            continue;
+        }
+        match json::from_value::<ManifestResource>(document) {
+            Ok(ManifestResource::Network(network)) => networks.push(network),
+            Ok(ManifestResource::Volume(volume)) => volumes.push(volume),
+            Ok(ManifestResource::Instance(spec)) => specs.push(*spec),
+            Err(e) => report.results.push(failed(&kind, &name, format!("Invalid resource: {}", e))),
+        }
+    }
+
+    for network in networks {
+        report.results.push(apply_network(network, app_manager, mutation, key.clone()).await);
+    }
+    for volume in volumes {
+        report.results.push(apply_volume(volume, app_manager, mutation, key.clone()).await);
+    }
+
+    let mut existing: HashMap<String, (String, AppInstanceRequest)> = app_manager.managed_specs().await?
+        .into_iter()
+        .map(|(id, spec)| (spec.name().to_string(), (id, spec)))
+        .collect();
+    let (ordered, cyclic) = apply_order(specs);
+    let mut failed_instances: HashSet<String> = HashSet::new();
+    for spec in ordered {
+        let name = spec.name().to_string();
+        let result = match spec.depends_on().iter().find(|dependency| failed_instances.contains(*dependency)) {
+            Some(dependency) => failed("instance", &name, format!("Skipped because dependency {} failed", dependency)),
+            None => apply_instance(spec, existing.remove(&name), deploy.clone()).await,
+        };
+        if result.action == ApplyAction::Failed {
+            failed_instances.insert(name);
+        }
+        report.results.push(result);
+    }
+    for spec in cyclic {
+        report.results.push(failed("instance", spec.name(), "Dependency cycle".to_string()));
+    }
+
+    report.failed = report.results.iter().filter(|result| result.action == ApplyAction::Failed).count();
+    Ok(Json(report))
+}
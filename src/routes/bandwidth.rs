@@ -0,0 +1,126 @@
+use rocket::put;
+use rocket::serde::{json::{self, Json}};
+use rocket::State;
+use std::collections::HashMap;
+use std::sync::Arc;
+use bollard::Docker;
+use bollard::system::EventsOptions;
+use futures::stream::StreamExt;
+
+use crate::state_store::{self, StateStore};
+use super::access::{ApiKey, Mutation};
+use super::instances::{AppInstanceRequest, AppManager};
+pub use omniagent_client::models::bandwidth::BandwidthLimit;
+
+/// Burst allowance: 100ms worth of traffic, but never below 16 KiB
+fn burst_bytes(rate_kbit: u64) -> u64 {
+    (rate_kbit * 1000 / 8 / 10).max(16 * 1024)
+}
+
+async fn tc(pid: i64, args: &[&str]) -> Result<(), String> {
+    let output = tokio::process::Command::new("nsenter")
+        .args(["-t", &pid.to_string(), "-n", "tc"])
+        .args(args)
+        .output().await
+        .map_err(|e| format!("Failed to run tc (is iproute2 installed?): {}", e))?;
+    if !output.status.success() {
+        return Err(format!("tc {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// Shapes every interface in the instance's network namespace: a token bucket on egress and
+/// a policer on ingress. Limits live in the namespace, so they're reapplied on every start.
+pub async fn apply(docker: &Docker, id: &str, limit: &BandwidthLimit) -> Result<(), String> {
+    if !cfg!(target_os = "linux") {
+        return Err("Bandwidth limits require Linux traffic control".to_string());
+    }
+
+    let container = docker.inspect_container(id, None).await
+        .map_err(|e| format!("Failed to inspect instance: {}", e))?;
+    let pid = container.state.and_then(|state| state.pid).unwrap_or(0);
+    if pid == 0 {
+        // Not running; the start watcher applies the limit later
+        return Ok(());
+    }
+
+    // /proc/<pid>/net/dev lists the interfaces of the container's namespace
+    let interfaces: Vec<String> = tokio::fs::read_to_string(format!("/proc/{}/net/dev", pid)).await
+        .map_err(|e| format!("Failed to read instance interfaces: {}", e))?
+        .lines()
+        .skip(2)
+        .filter_map(|line| line.split(':').next().map(|name| name.trim().to_string()))
+        .filter(|name| name != "lo")
+        .collect();
+
+    for interface in &interfaces {
+        let dev = interface.as_str();
+        // Clear previous limits; these fail harmlessly when nothing was set
+        let _ = tc(pid, &["qdisc", "del", "dev", dev, "root"]).await;
+        let _ = tc(pid, &["qdisc", "del", "dev", dev, "ingress"]).await;
+
+        if let Some(rate) = limit.egress_kbit {
+            tc(pid, &["qdisc", "add", "dev", dev, "root", "tbf",
+                "rate", &format!("{}kbit", rate),
+                "burst", &burst_bytes(rate).to_string(),
+                "latency", "50ms"]).await?;
+        }
+        if let Some(rate) = limit.ingress_kbit {
+            tc(pid, &["qdisc", "add", "dev", dev, "handle", "ffff:", "ingress"]).await?;
+            tc(pid, &["filter", "add", "dev", dev, "parent", "ffff:", "protocol", "all",
+                "u32", "match", "u32", "0", "0",
+                "police", "rate", &format!("{}kbit", rate),
+                "burst", &burst_bytes(rate).to_string(),
+                "drop", "flowid", ":1"]).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reapplies stored limits whenever an instance starts, since a restart gets a fresh namespace
+pub fn start(docker: Docker, store: Arc<dyn StateStore>) {
+    tokio::spawn(async move {
+        loop {
+            let options = Some(EventsOptions::<String> {
+                filters: HashMap::from([
+                    ("type".to_string(), vec!["container".to_string()]),
+                    ("event".to_string(), vec!["start".to_string()]),
+                ]),
+                ..Default::default()
+            });
+            let mut events = docker.events(options);
+
+            while let Some(event) = events.next().await {
+                let Some(id) = event.ok().and_then(|event| event.actor).and_then(|actor| actor.id) else {
+                    continue;
+                };
+                let spec = match store.get(state_store::SPECS, &id).await {
+                    Ok(Some(spec)) => json::from_value::<AppInstanceRequest>(spec).ok(),
+                    _ => None,
+                };
+                let Some(limit) = spec.and_then(|spec| spec.bandwidth().cloned()) else {
+                    continue;
+                };
+                if let Err(e) = apply(&docker, &id, &limit).await {
+                    eprintln!("Failed to apply bandwidth limit to {}: {}", id, e);
+                }
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        }
+    });
+}
+
+// API Endpoints
+#[put("/instances/<id>/bandwidth", format = "json", data = "<limit_req>")]
+pub async fn set_instance_bandwidth(id: String, limit_req: Json<BandwidthLimit>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<BandwidthLimit>, String> {
+    let mut spec = app_manager.spec(&id).await?
+        .ok_or_else(|| format!("Instance {} is not managed by this agent", id))?;
+
+    let limit = limit_req.into_inner();
+    apply(app_manager.docker(), &id, &limit).await?;
+
+    spec.set_bandwidth(Some(limit.clone()).filter(|limit| *limit != BandwidthLimit::default()));
+    app_manager.save_spec(&id, &spec).await?;
+    Ok(Json(limit))
+}
@@ -0,0 +1,66 @@
+use rocket::post;
+use rocket::http::Header;
+use rocket::serde::json::Json;
+use rocket::{Responder, State};
+use std::time::Duration;
+
+use crate::state_store;
+use super::access::Admin;
+use super::instances::AppManager;
+use super::netns;
+pub use omniagent_client::models::capture::CaptureRequest;
+
+#[derive(Responder)]
+#[response(content_type = "application/vnd.tcpdump.pcap")]
+pub struct Pcap(Vec<u8>, Header<'static>);
+
+fn env_limit(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+/// Runs tcpdump in the instance's network namespace. Captures are capped at
+/// `OMNI_CAPTURE_MAX_SECS` (default 300) and `OMNI_CAPTURE_MAX_BYTES` (default 100 MiB); one
+/// cut off by the size limit may end mid-packet.
+#[post("/instances/<id>/capture", format = "json", data = "<capture_req>")]
+pub async fn capture_instance(id: String, capture_req: Json<CaptureRequest>, app_manager: &State<AppManager>, _admin: Admin) -> Result<Pcap, String> {
+    let docker = app_manager.docker();
+    let id = netns::running_container(docker, &id).await?;
+
+    let duration = capture_req.duration_secs.unwrap_or(10).min(env_limit("OMNI_CAPTURE_MAX_SECS", 300));
+    let max_bytes = capture_req.max_bytes.unwrap_or(10 * 1024 * 1024).min(env_limit("OMNI_CAPTURE_MAX_BYTES", 100 * 1024 * 1024));
+
+    let mut command = vec![
+        "tcpdump".to_string(),
+        "-i".to_string(),
+        capture_req.interface.clone().unwrap_or_else(|| "any".to_string()),
+        "-U".to_string(),
+        "-w".to_string(),
+        "-".to_string(),
+    ];
+    command.extend(capture_req.filter.clone().filter(|filter| !filter.trim().is_empty()));
+
+    let output = netns::run(docker, &id, command, &["NET_ADMIN", "NET_RAW"], Duration::from_secs(duration), max_bytes as usize).await?;
+    if output.exit_code.is_some_and(|code| code != 0) {
+        return Err(format!("tcpdump failed: {}", output.stderr.trim()));
+    }
+    let pcap = output.stdout;
+
+    log::info!("Captured {} bytes of traffic from instance {}", pcap.len(), id);
+    let record = rocket::serde::json::json!({
+        "action": "capture",
+        "instance_id": id,
+        "duration_secs": duration,
+        "filter": capture_req.filter,
+        "bytes": pcap.len(),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    if let Err(e) = state_store::append(app_manager.store(), state_store::AUDIT, &record).await {
+        log::error!("Failed to record audit entry: {}", e);
+    }
+
+    let filename = format!("{}-{}.pcap", &id[..12.min(id.len())], chrono::Utc::now().format("%Y%m%dT%H%M%SZ"));
+    Ok(Pcap(pcap, Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", filename))))
+}
@@ -0,0 +1,53 @@
+use bollard::container::{MemoryStats, MemoryStatsStats};
+pub use omniagent_client::models::instances::CgroupInfo;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+pub fn is_v2() -> bool {
+    std::path::Path::new(CGROUP_ROOT).join("cgroup.controllers").exists()
+}
+
+/// Reads the host's cgroup layout; the daemon's driver comes from `docker info`
+pub fn detect(info: Option<&bollard::models::SystemInfo>) -> CgroupInfo {
+    let root = std::path::Path::new(CGROUP_ROOT);
+    let controllers = std::fs::read_to_string(root.join("cgroup.controllers"))
+        .map(|controllers| controllers.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let mode = if is_v2() {
+        "v2"
+    } else if root.join("unified").exists() {
+        "hybrid"
+    } else if root.join("memory").exists() {
+        "v1"
+    } else {
+        "none"
+    };
+
+    CgroupInfo {
+        mode: mode.to_string(),
+        driver: info.and_then(|info| info.cgroup_driver).map(|driver| driver.to_string()),
+        controllers,
+    }
+}
+
+/// Rejects limits the host's cgroup version can't enforce. Docker accepts real-time CPU
+/// settings on v2 hosts but the container then fails to start.
+pub fn check_limits(cpu_rt_period: Option<i64>, cpu_rt_runtime: Option<i64>) -> Result<(), String> {
+    if is_v2() && (cpu_rt_period.is_some() || cpu_rt_runtime.is_some()) {
+        return Err("Real-time CPU scheduling (cpu_rt_period, cpu_rt_runtime) needs cgroup v1; this host uses cgroup v2".to_string());
+    }
+    Ok(())
+}
+
+/// Memory in use minus reclaimable page cache, as `docker stats` reports it. Raw usage
+/// counts the cache too, which on v2 hosts makes idle containers look close to their limit.
+pub fn working_set(memory: &MemoryStats) -> Option<u64> {
+    let usage = memory.usage?;
+    let inactive_file = match &memory.stats {
+        Some(MemoryStatsStats::V1(stats)) => stats.total_inactive_file,
+        Some(MemoryStatsStats::V2(stats)) => stats.inactive_file,
+        None => 0,
+    };
+    Some(usage.saturating_sub(inactive_file))
+}
@@ -0,0 +1,477 @@
+use rocket::{delete, get, put};
+use rocket::serde::json::{self, Json};
+use rocket::State;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+
+use crate::event_bus::{AgentEvent, EventBus};
+use crate::state_store::{self, StateStore};
+use super::access::{ApiKey, Mutation};
+use super::ha::LeaderElection;
+use super::instances::AppManager;
+pub use omniagent_client::models::checks::{CheckHistory, CheckKind, CheckRequest, CheckResult, CheckStatus, CheckTransition, SyntheticCheck};
+
+/// How often the scheduler looks for checks that are due
+const TICK: Duration = Duration::from_secs(5);
+/// Probes of each check kept in memory for its history
+const RECENT_RESULTS: usize = 100;
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name != "status"
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Checks a target against its kind, with a stand-in address for `{instance}`
+fn validate_target(kind: CheckKind, target: &str) -> Result<(), String> {
+    let target = target.replace("{instance}", "127.0.0.1");
+    match kind {
+        CheckKind::Http => match reqwest::Url::parse(&target) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+            _ => Err(format!("Invalid http target {}; expected an http:// or https:// URL", target)),
+        },
+        CheckKind::Tcp => match target.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+            _ => Err(format!("Invalid tcp target {}; expected host:port", target)),
+        },
+        // The host is handed to ping, which would take a leading - for an option
+        CheckKind::Icmp if target.is_empty() || target.starts_with('-') || target.contains(char::is_whitespace) => {
+            Err(format!("Invalid icmp target {}; expected a host", target))
+        },
+        CheckKind::Icmp => Ok(()),
+    }
+}
+
+fn check_from_request(name: String, check_req: CheckRequest) -> Result<SyntheticCheck, String> {
+    validate_target(check_req.kind, &check_req.target)?;
+    match (&check_req.instance, check_req.target.contains("{instance}")) {
+        (Some(_), false) => return Err("instance is set but target doesn't use {instance}".to_string()),
+        (None, true) => return Err("target uses {instance} but instance is not set".to_string()),
+        _ => {},
+    }
+    if check_req.expected_status.is_some() && check_req.kind != CheckKind::Http {
+        return Err("expected_status only applies to http checks".to_string());
+    }
+
+    let interval_secs = check_req.interval_secs.unwrap_or(60);
+    if interval_secs < 10 {
+        return Err("interval_secs must be at least 10".to_string());
+    }
+    let timeout_secs = check_req.timeout_secs.unwrap_or(5);
+    if timeout_secs == 0 || timeout_secs > interval_secs {
+        return Err("timeout_secs must be between 1 and interval_secs".to_string());
+    }
+    let failure_threshold = check_req.failure_threshold.unwrap_or(1);
+    if failure_threshold == 0 {
+        return Err("failure_threshold must be at least 1".to_string());
+    }
+
+    Ok(SyntheticCheck {
+        name,
+        kind: check_req.kind,
+        target: check_req.target,
+        instance: check_req.instance,
+        interval_secs,
+        timeout_secs,
+        expected_status: check_req.expected_status,
+        failure_threshold,
+        description: check_req.description,
+        updated_at: Utc::now().to_rfc3339(),
+    })
+}
+
+async fn load(store: &dyn StateStore, name: &str) -> Result<Option<SyntheticCheck>, String> {
+    let Some(value) = store.get(state_store::CHECKS, name).await? else {
+        return Ok(None);
+    };
+    json::from_value(value).map(Some).map_err(|e| format!("Failed to read check {}: {}", name, e))
+}
+
+async fn load_all(store: &dyn StateStore) -> Result<Vec<SyntheticCheck>, String> {
+    Ok(store.list(state_store::CHECKS).await?
+        .into_iter()
+        .filter_map(|(_, value)| json::from_value(value).ok())
+        .collect())
+}
+
+/// Fills `{instance}` with the address of the instance's first network that has one
+async fn resolve(check: &SyntheticCheck, app_manager: &AppManager) -> Result<String, String> {
+    let Some(instance) = &check.instance else {
+        return Ok(check.target.clone());
+    };
+    let container = app_manager.docker().inspect_container(instance, None).await
+        .map_err(|e| format!("Failed to inspect instance {}: {}", instance, e))?;
+    let ip = container.network_settings
+        .and_then(|settings| settings.networks)
+        .and_then(|networks| networks.into_values()
+            .filter_map(|endpoint| endpoint.ip_address)
+            .find(|ip| !ip.is_empty()))
+        .ok_or_else(|| format!("Instance {} has no reachable network address", instance))?;
+    Ok(check.target.replace("{instance}", &ip))
+}
+
+/// The HTTP status of an http probe, and whether the probe passed
+async fn run(check: &SyntheticCheck, target: &str, http: &reqwest::Client) -> (Option<u16>, Result<(), String>) {
+    match check.kind {
+        CheckKind::Http => match http.get(target).send().await {
+            Ok(response) => {
+                let status = response.status();
+                let passed = match check.expected_status {
+                    Some(expected) => status.as_u16() == expected,
+                    None => status.is_success(),
+                };
+                let result = if passed { Ok(()) } else { Err(format!("Unexpected status {}", status)) };
+                (Some(status.as_u16()), result)
+            },
+            Err(e) => (None, Err(format!("Request to {} failed: {}", target, e))),
+        },
+        CheckKind::Tcp => match tokio::net::TcpStream::connect(target).await {
+            Ok(_) => (None, Ok(())),
+            Err(e) => (None, Err(format!("Failed to connect to {}: {}", target, e))),
+        },
+        // Unprivileged ICMP sockets aren't available everywhere, so use the system's ping
+        CheckKind::Icmp => {
+            let mut command = tokio::process::Command::new("ping");
+            command.args(if cfg!(windows) { ["-n", "1"] } else { ["-c", "1"] })
+                .arg(target)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .kill_on_drop(true);
+            match command.status().await {
+                Ok(status) if status.success() => (None, Ok(())),
+                Ok(_) => (None, Err(format!("No reply from {}", target))),
+                Err(e) => (None, Err(format!("Failed to run ping: {}", e))),
+            }
+        },
+    }
+}
+
+async fn probe(check: &SyntheticCheck, app_manager: &AppManager, http: &reqwest::Client) -> CheckResult {
+    let started = Instant::now();
+    let (status_code, outcome) = match resolve(check, app_manager).await {
+        Ok(target) => tokio::time::timeout(Duration::from_secs(check.timeout_secs), run(check, &target, http)).await
+            .unwrap_or_else(|_| (None, Err(format!("Timed out after {}s", check.timeout_secs)))),
+        Err(e) => (None, Err(e)),
+    };
+    CheckResult {
+        up: outcome.is_ok(),
+        latency_ms: outcome.is_ok().then(|| started.elapsed().as_secs_f64() * 1000.0),
+        status_code,
+        error: outcome.err(),
+        timestamp: Utc::now().to_rfc3339(),
+    }
+}
+
+/// Share of `from`..`to` spent up, from time-ordered transitions. Time before the first
+/// transition has no known state and is left out.
+fn uptime_percent(transitions: &[(DateTime<Utc>, bool)], from: DateTime<Utc>, to: DateTime<Utc>) -> Option<f64> {
+    let (mut up_secs, mut known_secs) = (0.0, 0.0);
+    for (i, (at, up)) in transitions.iter().enumerate() {
+        let start = (*at).max(from);
+        let end = transitions.get(i + 1).map_or(to, |(next, _)| *next).min(to);
+        if end > start {
+            let secs = (end - start).num_milliseconds() as f64 / 1000.0;
+            known_secs += secs;
+            if *up {
+                up_secs += secs;
+            }
+        }
+    }
+    (known_secs > 0.0).then(|| up_secs / known_secs * 100.0)
+}
+
+async fn transitions(store: &dyn StateStore, name: &str) -> Result<Vec<(String, CheckTransition)>, String> {
+    Ok(store.list(state_store::CHECK_HISTORY).await?
+        .into_iter()
+        .filter_map(|(key, value)| json::from_value::<CheckTransition>(value).ok().map(|transition| (key, transition)))
+        .filter(|(_, transition)| transition.check == name)
+        .collect())
+}
+
+fn unknown_status(name: &str) -> CheckStatus {
+    CheckStatus {
+        name: name.to_string(),
+        up: None,
+        since: None,
+        consecutive_failures: 0,
+        last_result: None,
+    }
+}
+
+struct Probe {
+    status: CheckStatus,
+    recent: VecDeque<CheckResult>,
+    last_run: Instant,
+}
+
+/// Runs the checks saved under `/checks` on their intervals. Only the HA leader probes, so
+/// agents sharing a store don't probe twice. Each time a check goes up or down the change is
+/// appended to its history, and going down, or coming back up, raises an alert.
+#[derive(Clone, Default)]
+pub struct SyntheticChecks {
+    probes: Arc<Mutex<HashMap<String, Probe>>>,
+}
+
+impl SyntheticChecks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&self, app_manager: AppManager, election: LeaderElection, bus: EventBus) {
+        let checks = self.clone();
+        let http = crate::crypto::http_client();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(TICK).await;
+                if !election.is_leader() {
+                    continue;
+                }
+
+                let defined = match load_all(app_manager.store()).await {
+                    Ok(defined) => defined,
+                    Err(e) => {
+                        log::error!("Failed to load synthetic checks: {}", e);
+                        continue;
+                    }
+                };
+                let due: Vec<SyntheticCheck> = {
+                    let mut probes = checks.probes.lock().unwrap();
+                    probes.retain(|name, _| defined.iter().any(|check| &check.name == name));
+                    defined.into_iter()
+                        .filter(|check| {
+                            let interval = Duration::from_secs(check.interval_secs);
+                            if probes.get(&check.name).is_some_and(|probe| probe.last_run.elapsed() < interval) {
+                                return false;
+                            }
+                            probes.entry(check.name.clone())
+                                .or_insert_with(|| Probe {
+                                    status: unknown_status(&check.name),
+                                    recent: VecDeque::new(),
+                                    last_run: Instant::now(),
+                                })
+                                .last_run = Instant::now();
+                            true
+                        })
+                        .collect()
+                };
+
+                for check in due {
+                    let (checks, app_manager, http, bus) = (checks.clone(), app_manager.clone(), http.clone(), bus.clone());
+                    tokio::spawn(async move {
+                        let result = probe(&check, &app_manager, &http).await;
+                        checks.record(&check, result, app_manager.store(), &bus).await;
+                    });
+                }
+            }
+        });
+    }
+
+    async fn record(&self, check: &SyntheticCheck, result: CheckResult, store: &dyn StateStore, bus: &EventBus) {
+        let transition = {
+            let mut probes = self.probes.lock().unwrap();
+            let Some(probe) = probes.get_mut(&check.name) else {
+                return;
+            };
+            probe.recent.push_back(result.clone());
+            if probe.recent.len() > RECENT_RESULTS {
+                probe.recent.pop_front();
+            }
+
+            let status = &mut probe.status;
+            status.consecutive_failures = if result.up { 0 } else { status.consecutive_failures + 1 };
+            let up = match result.up {
+                true => Some(true),
+                false if status.consecutive_failures >= check.failure_threshold => Some(false),
+                false => status.up,
+            };
+            let previous = status.up;
+            status.last_result = Some(result.clone());
+            if up == previous {
+                return;
+            }
+            status.up = up;
+            status.since = Some(result.timestamp.clone());
+            (previous, up.unwrap_or_default())
+        };
+
+        let (previous, up) = transition;
+        let record = CheckTransition {
+            check: check.name.clone(),
+            up,
+            error: result.error.clone(),
+            timestamp: result.timestamp,
+        };
+        let appended = match json::to_value(&record) {
+            Ok(record) => state_store::append(store, state_store::CHECK_HISTORY, &record).await,
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = appended {
+            log::error!("Failed to record history of check {}: {}", check.name, e);
+        }
+
+        let alert = match (previous, up) {
+            (_, false) => Some(("warning", format!("Check {} is down: {}", check.name, result.error.unwrap_or_default()))),
+            (Some(false), true) => Some(("info", format!("Check {} is back up", check.name))),
+            _ => None,
+        };
+        if let Some((severity, message)) = alert {
+            match severity {
+                "warning" => log::warn!("{}", message),
+                _ => log::info!("{}", message),
+            }
+            bus.publish(AgentEvent::Alert {
+                severity: severity.to_string(),
+                source: "checks".to_string(),
+                message,
+                timestamp: Utc::now().to_rfc3339(),
+            });
+        }
+    }
+
+    fn status(&self, check: &SyntheticCheck) -> CheckStatus {
+        self.probes.lock().unwrap().get(&check.name)
+            .map(|probe| probe.status.clone())
+            .unwrap_or_else(|| unknown_status(&check.name))
+    }
+
+    fn recent(&self, name: &str) -> Vec<CheckResult> {
+        self.probes.lock().unwrap().get(name)
+            .map(|probe| probe.recent.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets a check's state so a replaced definition is probed afresh
+    fn reset(&self, name: &str) {
+        self.probes.lock().unwrap().remove(name);
+    }
+}
+
+/// Removes check history older than `OMNI_CHECK_RETENTION_DAYS` (default 90), keeping each
+/// check's latest transition so its current state stays known
+pub async fn prune(store: &dyn StateStore) -> Result<usize, String> {
+    let days = std::env::var("OMNI_CHECK_RETENTION_DAYS").ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(90);
+    let cutoff = Utc::now() - chrono::Duration::days(days);
+
+    let mut records = store.list(state_store::CHECK_HISTORY).await?;
+    let mut latest = HashSet::new();
+    let mut expired = Vec::new();
+    // Keys are time-ordered, so the first record seen of each check is its latest
+    records.reverse();
+    for (key, record) in records {
+        if latest.insert(record["check"].as_str().unwrap_or_default().to_string()) {
+            continue;
+        }
+        let old = record["timestamp"].as_str()
+            .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+            .is_some_and(|at| at < cutoff);
+        if old {
+            expired.push(key);
+        }
+    }
+    store.delete_many(state_store::CHECK_HISTORY, &expired).await?;
+    Ok(expired.len())
+}
+
+fn parse_time(value: Option<&str>, name: &str) -> Result<Option<DateTime<Utc>>, String> {
+    value.map(|value| DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("Invalid {} timestamp {}: {}", name, value, e)))
+        .transpose()
+}
+
+// API Endpoints
+#[get("/checks")]
+pub async fn list_checks(app_manager: &State<AppManager>) -> Result<Json<Vec<SyntheticCheck>>, String> {
+    load_all(app_manager.store()).await
+        .map(Json)
+        .map_err(|e| format!("Failed to list checks: {}", e))
+}
+
+/// Current state of every check
+#[get("/checks/status")]
+pub async fn list_check_statuses(app_manager: &State<AppManager>, checks: &State<SyntheticChecks>) -> Result<Json<Vec<CheckStatus>>, String> {
+    let defined = load_all(app_manager.store()).await
+        .map_err(|e| format!("Failed to list checks: {}", e))?;
+    Ok(Json(defined.iter().map(|check| checks.status(check)).collect()))
+}
+
+#[get("/checks/<name>")]
+pub async fn get_check(name: String, app_manager: &State<AppManager>) -> Result<Option<Json<SyntheticCheck>>, String> {
+    load(app_manager.store(), &name).await.map(|check| check.map(Json))
+}
+
+/// Creates or replaces a check; a replaced check starts over with an unknown state
+#[put("/checks/<name>", format = "json", data = "<check_req>")]
+pub async fn put_check(name: String, check_req: Json<CheckRequest>, app_manager: &State<AppManager>, checks: &State<SyntheticChecks>, _mutation: Mutation, _key: ApiKey) -> Result<Json<SyntheticCheck>, String> {
+    if !valid_name(&name) {
+        return Err(format!("Invalid check name {}", name));
+    }
+    let check = check_from_request(name, check_req.into_inner())?;
+    let value = json::to_value(&check).map_err(|e| format!("Failed to serialize check: {}", e))?;
+    app_manager.store().put(state_store::CHECKS, &check.name, &value).await
+        .map_err(|e| format!("Failed to save check: {}", e))?;
+    checks.reset(&check.name);
+    Ok(Json(check))
+}
+
+/// Deletes a check along with its history
+#[delete("/checks/<name>")]
+pub async fn delete_check(name: String, app_manager: &State<AppManager>, checks: &State<SyntheticChecks>, _mutation: Mutation, _key: ApiKey) -> Result<Json<SyntheticCheck>, String> {
+    let store = app_manager.store();
+    let check = load(store, &name).await?
+        .ok_or_else(|| format!("Check {} does not exist", name))?;
+    store.delete(state_store::CHECKS, &name).await
+        .map_err(|e| format!("Failed to delete check: {}", e))?;
+    checks.reset(&name);
+
+    let history = transitions(store, &name).await
+        .map_err(|e| format!("Failed to read check history: {}", e))?;
+    for (key, _) in history {
+        store.delete(state_store::CHECK_HISTORY, &key).await
+            .map_err(|e| format!("Failed to delete check history: {}", e))?;
+    }
+    Ok(Json(check))
+}
+
+/// Ups and downs of a check over `from`..`to` (RFC 3339, default the last 24 hours), its
+/// uptime over that window and its latest probes
+#[get("/checks/<name>/history?<from>&<to>")]
+pub async fn get_check_history(name: String, from: Option<&str>, to: Option<&str>, app_manager: &State<AppManager>, checks: &State<SyntheticChecks>) -> Result<Option<Json<CheckHistory>>, String> {
+    let now = Utc::now();
+    let to = parse_time(to, "to")?.unwrap_or(now).min(now);
+    let from = parse_time(from, "from")?.unwrap_or(to - chrono::Duration::hours(24));
+    if from >= to {
+        return Err("from must be before to".to_string());
+    }
+
+    let store = app_manager.store();
+    if load(store, &name).await?.is_none() {
+        return Ok(None);
+    }
+    let history: Vec<CheckTransition> = transitions(store, &name).await
+        .map_err(|e| format!("Failed to read check history: {}", e))?
+        .into_iter()
+        .map(|(_, transition)| transition)
+        .collect();
+    let timed: Vec<(DateTime<Utc>, bool)> = history.iter()
+        .filter_map(|transition| DateTime::parse_from_rfc3339(&transition.timestamp).ok()
+            .map(|at| (at.with_timezone(&Utc), transition.up)))
+        .collect();
+
+    Ok(Some(Json(CheckHistory {
+        uptime_percent: uptime_percent(&timed, from, to),
+        transitions: history.into_iter()
+            .filter(|transition| DateTime::parse_from_rfc3339(&transition.timestamp).is_ok_and(|at| at >= from && at <= to))
+            .collect(),
+        recent: checks.recent(&name),
+        name,
+        from: from.to_rfc3339(),
+        to: to.to_rfc3339(),
+    })))
+}
@@ -0,0 +1,824 @@
+use rocket::{delete, get, post};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::Deserialize;
+use rocket::serde::json::{self, Json, Value};
+use rocket::State;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::Notify;
+
+use crate::config::Config;
+use crate::state_store;
+use super::access::{Admin, ApiKey};
+use super::instances::AppManager;
+pub use omniagent_client::models::cpi::{CpiAction, CpiProvider, CpiLoadError, CpiRegistryStatus, CpiExecRequest, CpiExecResult, CpiExecution};
+
+/// Output kept from each stream of a CPI command
+const MAX_OUTPUT: usize = 1024 * 1024;
+
+/// A CPI file. Fields this agent doesn't use, such as `parse_rules`, are ignored.
+#[derive(Debug, Deserialize)]
+struct CpiFile {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    version: Option<String>,
+    #[serde(default)]
+    default_settings: HashMap<String, Value>,
+    actions: BTreeMap<String, ActionFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActionFile {
+    command: String,
+    #[serde(default)]
+    params: Vec<String>,
+    timeout_secs: Option<u64>,
+}
+
+/// Splits a command template into words the way a shell would for quoting, but without
+/// expansion; parameters are substituted into the words afterwards so values can't inject
+/// arguments
+fn split_words(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated single quote".to_string()),
+                    }
+                }
+            },
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated double quote".to_string()),
+                    }
+                }
+            },
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    if words.is_empty() {
+        return Err("Empty command".to_string());
+    }
+    Ok(words)
+}
+
+fn is_placeholder(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `{name}` placeholders in a word
+fn placeholders(word: &str) -> Vec<&str> {
+    word.split('{').skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .filter(|name| is_placeholder(name))
+        .collect()
+}
+
+/// How values substituted into a word are escaped
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quoting {
+    /// A word handed straight to the program, which no shell parses again
+    Argument,
+    /// Part of an `sh -c` script
+    Posix,
+    /// Part of a `powershell -Command` script
+    PowerShell,
+    /// Part of a command line parsed again in a way that can't be quoted safely, such as a
+    /// `cmd /c` one or a shell script run over `ssh`
+    Unsafe,
+}
+
+struct Word {
+    text: String,
+    quoting: Quoting,
+}
+
+fn program_name(program: &str) -> String {
+    let name = program.rsplit(['/', '\\']).next().unwrap_or(program).to_lowercase();
+    name.strip_suffix(".exe").map(str::to_string).unwrap_or(name)
+}
+
+/// Index of the word after the first argument matching `is_flag`
+fn after_flag(words: &[String], is_flag: impl Fn(&str) -> bool) -> Option<usize> {
+    words.iter().skip(1).position(|word| is_flag(word)).map(|i| i + 2)
+}
+
+/// A program that runs the rest of its arguments as a command
+struct Wrapper {
+    name: &'static str,
+    /// Options whose value is the next word
+    value_options: &'static [&'static str],
+    /// Options after which the command is parsed again as a line, such as `env -S`
+    line_options: &'static [&'static str],
+    /// Whether `NAME=value` words may come before the command
+    assignments: bool,
+    /// Words such as `timeout`'s duration between the options and the command
+    operands: usize,
+}
+
+const WRAPPERS: &[Wrapper] = &[
+    Wrapper {
+        name: "sudo",
+        value_options: &["-u", "-g", "-h", "-p", "-C", "-D", "-r", "-t", "-U", "-T", "--user", "--group", "--host", "--prompt", "--close-from", "--chdir", "--role", "--type", "--other-user", "--command-timeout"],
+        line_options: &["-s", "-i", "--shell", "--login"],
+        assignments: true,
+        operands: 0,
+    },
+    Wrapper { name: "doas", value_options: &["-u", "-C"], line_options: &["-s"], assignments: false, operands: 0 },
+    Wrapper { name: "env", value_options: &["-u", "-C", "--unset", "--chdir"], line_options: &["-S", "--split-string"], assignments: true, operands: 0 },
+    Wrapper { name: "nice", value_options: &["-n", "--adjustment"], line_options: &[], assignments: false, operands: 0 },
+    Wrapper { name: "nohup", value_options: &[], line_options: &[], assignments: false, operands: 0 },
+    Wrapper { name: "timeout", value_options: &["-s", "-k", "--signal", "--kill-after"], line_options: &[], assignments: false, operands: 1 },
+    Wrapper { name: "stdbuf", value_options: &["-i", "-o", "-e"], line_options: &[], assignments: false, operands: 0 },
+];
+
+const SSH_VALUE_OPTIONS: &[&str] = &["-B", "-b", "-c", "-D", "-E", "-e", "-F", "-I", "-i", "-J", "-L", "-l", "-m", "-O", "-o", "-P", "-p", "-Q", "-R", "-S", "-W", "-w"];
+
+const EXEC_VALUE_OPTIONS: &[&str] = &["-e", "-u", "-w", "--env", "--env-file", "--user", "--workdir", "--detach-keys"];
+
+/// Index of the first word from `i` that is neither an option, an option's value nor, where
+/// allowed, a `NAME=value` assignment
+fn skip_options(words: &[String], mut i: usize, value_options: &[&str], assignments: bool) -> usize {
+    while let Some(word) = words.get(i) {
+        if word == "--" {
+            return i + 1;
+        }
+        if word.starts_with('-') && word.len() > 1 {
+            i += if value_options.contains(&word.as_str()) { 2 } else { 1 };
+        } else if assignments && word.split_once('=').is_some_and(|(name, _)| is_placeholder(name)) {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// The words a shell will parse again, and how values put there are quoted
+type Script = Option<(Range<usize>, Quoting)>;
+
+/// Finds the program a command starting at `start` finally runs, looking through wrappers
+/// such as `sudo`, `env` and `docker exec`, and the script it hands a shell, if any. `ssh`
+/// joins everything after the host into one line for the remote shell.
+fn find_script(words: &[String], start: usize) -> Result<(usize, Script), String> {
+    let mut program = start;
+    loop {
+        let name = program_name(&words[program]);
+        let command = if let Some(wrapper) = WRAPPERS.iter().find(|wrapper| wrapper.name == name) {
+            let command = skip_options(words, program + 1, wrapper.value_options, wrapper.assignments) + wrapper.operands;
+            let options = &words[program + 1..command.min(words.len())];
+            if options.iter().any(|option| wrapper.line_options.iter().any(|line_option| option.starts_with(line_option))) {
+                return Ok((command, Some((program + 1..words.len(), Quoting::Unsafe))));
+            }
+            command
+        } else if name == "ssh" {
+            let remote = skip_options(words, program + 1, SSH_VALUE_OPTIONS, false) + 1;
+            if remote >= words.len() {
+                return Err(format!("{} runs no command", words[program]));
+            }
+            // A shell the remote command starts would parse values a second time
+            let nested = split_words(&words[remote..].join(" "))
+                .and_then(|line| find_script(&line, 0))
+                .map_or(true, |(_, script)| script.is_some());
+            let quoting = if nested { Quoting::Unsafe } else { Quoting::Posix };
+            return Ok((remote, Some((remote..words.len(), quoting))));
+        } else if matches!(name.as_str(), "docker" | "podman") && words.get(program + 1).is_some_and(|word| word == "exec") {
+            // Past the container
+            skip_options(words, program + 2, EXEC_VALUE_OPTIONS, false) + 1
+        } else if name == "kubectl" && words.get(program + 1).is_some_and(|word| word == "exec") {
+            words.iter().skip(program + 2).position(|word| word == "--")
+                .map(|i| program + 3 + i)
+                .ok_or_else(|| format!("{} exec needs -- before its command", words[program]))?
+        } else {
+            break;
+        };
+        if command >= words.len() {
+            return Err(format!("{} runs no command", words[program]));
+        }
+        program = command;
+    }
+
+    let command = &words[program..];
+    let script = match program_name(&command[0]).as_str() {
+        // Only the script itself; later words become its positional parameters
+        "sh" | "bash" | "dash" | "ash" | "zsh" | "ksh" => after_flag(command, |word| word.starts_with('-') && !word.starts_with("--") && word.contains('c'))
+            .map(|i| (program + i..program + i + 1, Quoting::Posix)),
+        // PowerShell joins every word after -Command into the script
+        "powershell" | "pwsh" => after_flag(command, |word| word.eq_ignore_ascii_case("-command") || word.eq_ignore_ascii_case("-c"))
+            .map(|i| (program + i..words.len(), Quoting::PowerShell)),
+        "cmd" => after_flag(command, |word| word.eq_ignore_ascii_case("/c") || word.eq_ignore_ascii_case("/k"))
+            .map(|i| (program + i..words.len(), Quoting::Unsafe)),
+        _ => None,
+    };
+    Ok((program, script))
+}
+
+/// Marks the words that a shell the command starts will parse again, so values put there
+/// get quoted for that shell. Also returns the index of the program that finally runs.
+fn classify(words: Vec<String>) -> Result<(Vec<Word>, usize), String> {
+    let (program, script) = find_script(&words, 0)?;
+    let words = words.into_iter().enumerate()
+        .map(|(i, text)| {
+            let quoting = match &script {
+                Some((range, quoting)) if range.contains(&i) => *quoting,
+                _ => Quoting::Argument,
+            };
+            Word { text, quoting }
+        })
+        .collect();
+    Ok((words, program))
+}
+
+/// First placeholder a shell script puts inside quotes, where the agent's own quoting would be
+/// undone. `\\` escapes in POSIX shells and `` ` `` in PowerShell.
+fn quoted_placeholder(script: &str, quoting: Quoting) -> Option<&str> {
+    let escape = if quoting == Quoting::PowerShell { '`' } else { '\\' };
+    let mut quote = None;
+    let mut chars = script.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {},
+            (_, c) if c == escape => {
+                chars.next();
+            },
+            (None, '\'' | '"') => quote = Some(c),
+            (Some('"'), '"') => quote = None,
+            (Some(_), '{') => {
+                if let Some((name, _)) = script[i + 1..].split_once('}').filter(|(name, _)| is_placeholder(name)) {
+                    return Some(name);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+fn quote(value: &str, quoting: Quoting) -> String {
+    match quoting {
+        Quoting::Argument | Quoting::Unsafe => value.to_string(),
+        Quoting::Posix => format!("'{}'", value.replace('\'', "'\\''")),
+        Quoting::PowerShell => {
+            // PowerShell also ends single-quoted strings at typographic single quotes
+            let mut quoted = String::from("'");
+            for c in value.chars() {
+                if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+                    quoted.push(c);
+                }
+                quoted.push(c);
+            }
+            quoted.push('\'');
+            quoted
+        },
+    }
+}
+
+fn param_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Fills a word's placeholders in one pass, so braces inside values are never expanded.
+/// A value that makes up a whole argument can't start with `-`, where the program would
+/// take it for an option, unless it is a number.
+fn substitute(word: &Word, values: &HashMap<String, String>) -> Result<String, String> {
+    let mut substituted = String::new();
+    let mut rest = word.text.as_str();
+    while let Some(start) = rest.find('{') {
+        substituted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(name) = rest[1..].split_once('}').map(|(name, _)| name).filter(|name| is_placeholder(name)) else {
+            substituted.push('{');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let value = values.get(name).ok_or_else(|| format!("Missing parameter {}", name))?;
+        if value.contains('\0') {
+            return Err(format!("Parameter {} contains a NUL byte", name));
+        }
+        let whole_argument = word.quoting == Quoting::Argument && word.text.len() == name.len() + 2;
+        if whole_argument && value.starts_with('-') && value.parse::<f64>().is_err() {
+            return Err(format!("Parameter {} starts with -, so it would be read as an option", name));
+        }
+        substituted.push_str(&quote(value, word.quoting));
+        rest = &rest[name.len() + 2..];
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+struct Action {
+    words: Vec<Word>,
+    params: Vec<String>,
+    timeout_secs: Option<u64>,
+}
+
+struct Cpi {
+    summary: CpiProvider,
+    default_settings: HashMap<String, Value>,
+    actions: BTreeMap<String, Action>,
+}
+
+/// Validates one action: every placeholder in its command must be a declared parameter or a
+/// default setting, and neither the program nor a line that can't be quoted safely, such as
+/// a `cmd /c` one, may contain one. Shell scripts must leave them unquoted, since the agent
+/// quotes each value for that shell.
+fn parse_action(name: &str, action: ActionFile, default_settings: &HashMap<String, Value>) -> Result<Action, String> {
+    let (words, program) = split_words(&action.command).and_then(classify)
+        .map_err(|e| format!("Action {}: {}", name, e))?;
+    if !placeholders(&words[program].text).is_empty() {
+        return Err(format!("Action {} takes its program from a parameter", name));
+    }
+    if words.iter().any(|word| word.quoting == Quoting::Unsafe && !placeholders(&word.text).is_empty()) {
+        return Err(format!("Action {} puts parameters in a command line that is parsed again and can't be quoted safely, such as cmd /c, env -S or a shell over ssh; call the program directly or use PowerShell", name));
+    }
+    for word in words.iter().filter(|word| matches!(word.quoting, Quoting::Posix | Quoting::PowerShell)) {
+        if let Some(placeholder) = quoted_placeholder(&word.text, word.quoting) {
+            return Err(format!("Action {} quotes {{{}}} in its script; leave it unquoted and the agent quotes the value", name, placeholder));
+        }
+    }
+    for placeholder in words.iter().flat_map(|word| placeholders(&word.text)) {
+        if !action.params.iter().any(|param| param == placeholder) && !default_settings.contains_key(placeholder) {
+            return Err(format!("Action {} uses {{{}}}, which is neither a parameter nor a default setting", name, placeholder));
+        }
+    }
+    if action.timeout_secs == Some(0) {
+        return Err(format!("Action {} has a zero timeout", name));
+    }
+    Ok(Action { words, params: action.params, timeout_secs: action.timeout_secs })
+}
+
+/// Parses and validates one CPI file; only `command` providers are supported
+fn load(path: &std::path::Path) -> Result<Cpi, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read: {}", e))?;
+    let file: CpiFile = json::from_str(&text).map_err(|e| format!("Invalid CPI JSON: {}", e))?;
+    // `executions` would be shadowed by GET /cpis/executions
+    if file.name.is_empty() || file.name == "executions" || !file.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid provider name {:?}", file.name));
+    }
+    if file.kind != "command" {
+        return Err(format!("Unsupported CPI type {}; only command is supported", file.kind));
+    }
+    if file.actions.is_empty() {
+        return Err("Defines no actions".to_string());
+    }
+
+    let mut actions = BTreeMap::new();
+    for (name, action) in file.actions {
+        let action = parse_action(&name, action, &file.default_settings)?;
+        actions.insert(name, action);
+    }
+
+    Ok(Cpi {
+        summary: CpiProvider {
+            name: file.name,
+            kind: file.kind,
+            version: file.version,
+            file: path.display().to_string(),
+            default_settings: file.default_settings.clone(),
+            actions: actions.iter()
+                .map(|(name, action)| CpiAction { name: name.clone(), params: action.params.clone(), timeout_secs: action.timeout_secs })
+                .collect(),
+        },
+        default_settings: file.default_settings,
+        actions,
+    })
+}
+
+#[derive(Default)]
+struct Loaded {
+    providers: BTreeMap<String, Arc<Cpi>>,
+    errors: Vec<CpiLoadError>,
+}
+
+/// Running actions by execution ID, with what cancels each
+type RunningMap = Mutex<HashMap<String, (CpiExecution, Arc<Notify>)>>;
+
+/// Removes a run from the registry however it ends, including when the request is dropped
+struct Running<'a> {
+    running: &'a RunningMap,
+    id: String,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Cloud provider interfaces loaded from the `*.json` files in the CPI directory, with
+/// actions run by provider and action name
+#[derive(Clone)]
+pub struct CpiRegistry {
+    dir: PathBuf,
+    loaded: Arc<RwLock<Loaded>>,
+    default_timeout_secs: u64,
+    running: Arc<RunningMap>,
+}
+
+impl CpiRegistry {
+    pub fn new(config: &Config) -> Self {
+        let registry = CpiRegistry {
+            dir: config.cpi_dir.clone(),
+            default_timeout_secs: config.cpi_timeout_secs,
+            loaded: Arc::new(RwLock::new(Loaded::default())),
+            running: Arc::new(Mutex::new(HashMap::new())),
+        };
+        registry.reload();
+        registry
+    }
+
+    /// Rescans the directory, replacing every provider
+    pub fn reload(&self) -> CpiRegistryStatus {
+        let mut loaded = Loaded::default();
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&self.dir).into_iter().flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let result = load(&path).and_then(|cpi| match loaded.providers.get(&cpi.summary.name) {
+                Some(existing) => Err(format!("Provider {} is already defined in {}", cpi.summary.name, existing.summary.file)),
+                None => Ok(cpi),
+            });
+            match result {
+                Ok(cpi) => {
+                    log::info!("Loaded CPI provider {} from {}", cpi.summary.name, path.display());
+                    loaded.providers.insert(cpi.summary.name.clone(), Arc::new(cpi));
+                },
+                Err(error) => {
+                    log::warn!("Skipping CPI file {}: {}", path.display(), error);
+                    loaded.errors.push(CpiLoadError { file: path.display().to_string(), error });
+                }
+            }
+        }
+
+        *self.loaded.write().unwrap() = loaded;
+        self.status()
+    }
+
+    pub fn status(&self) -> CpiRegistryStatus {
+        let loaded = self.loaded.read().unwrap();
+        CpiRegistryStatus {
+            dir: self.dir.display().to_string(),
+            providers: loaded.providers.values().map(|cpi| cpi.summary.clone()).collect(),
+            errors: loaded.errors.clone(),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.loaded.read().unwrap().providers.keys().cloned().collect()
+    }
+
+    pub fn executions(&self) -> Vec<CpiExecution> {
+        let mut executions: Vec<CpiExecution> = self.running.lock().unwrap().values()
+            .map(|(execution, _)| execution.clone())
+            .collect();
+        executions.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        executions
+    }
+
+    /// Kills a running action; its caller gets a `cancelled` result
+    pub fn cancel(&self, id: &str) -> Result<CpiExecution, String> {
+        let running = self.running.lock().unwrap();
+        let (execution, cancel) = running.get(id)
+            .ok_or_else(|| format!("CPI execution {} is not running", id))?;
+        cancel.notify_one();
+        Ok(execution.clone())
+    }
+
+    /// Runs `action` of provider `provider`, filling placeholders from the request's params and
+    /// then the provider's default settings. Only the action's declared params may be passed,
+    /// so a request can't override a default setting the action doesn't expose. The timeout is
+    /// the request's, else the action's, else the configured default. Missing or invalid
+    /// params are rejected with 422 before anything runs.
+    pub async fn execute(&self, provider: &str, action: &str, exec_req: &CpiExecRequest) -> Result<CpiExecResult, (Status, String)> {
+        let cpi = self.loaded.read().unwrap().providers.get(provider).cloned()
+            .ok_or_else(|| (Status::NotFound, format!("CPI provider {} is not loaded", provider)))?;
+        let template = cpi.actions.get(action)
+            .ok_or_else(|| (Status::NotFound, format!("CPI provider {} has no action {}", provider, action)))?;
+
+        if let Some(name) = exec_req.params.keys().find(|name| !template.params.contains(name)) {
+            return Err((Status::UnprocessableEntity, format!("Action {} takes no parameter {}", action, name)));
+        }
+        let values: HashMap<String, String> = cpi.default_settings.iter()
+            .chain(exec_req.params.iter())
+            .map(|(name, value)| (name.clone(), param_value(value)))
+            .collect();
+        let mut missing: Vec<&str> = template.words.iter()
+            .flat_map(|word| placeholders(&word.text))
+            .filter(|name| !values.contains_key(*name))
+            .collect();
+        missing.sort();
+        missing.dedup();
+        if !missing.is_empty() {
+            return Err((Status::UnprocessableEntity, format!("Missing parameters {}", missing.join(", "))));
+        }
+        let words = template.words.iter()
+            .map(|word| substitute(word, &values))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| (Status::UnprocessableEntity, e))?;
+
+        let timeout = exec_req.timeout_secs.or(template.timeout_secs)
+            .filter(|secs| *secs > 0)
+            .unwrap_or(self.default_timeout_secs);
+
+        let id = exec_req.execution_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let cancel = Arc::new(Notify::new());
+        let _running = {
+            let mut running = self.running.lock().unwrap();
+            if running.contains_key(&id) {
+                return Err((Status::Conflict, format!("CPI execution {} is already running", id)));
+            }
+            let execution = CpiExecution {
+                id: id.clone(),
+                provider: provider.to_string(),
+                action: action.to_string(),
+                started_at: chrono::Utc::now().to_rfc3339(),
+                timeout_secs: timeout,
+            };
+            running.insert(id.clone(), (execution, cancel.clone()));
+            Running { running: &self.running, id: id.clone() }
+        };
+
+        let started = Instant::now();
+        let mut child = tokio::process::Command::new(&words[0])
+            .args(&words[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| (Status::InternalServerError, format!("Failed to run {}: {}", words[0], e)))?;
+
+        let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+            return Err((Status::InternalServerError, format!("Failed to capture the output of {}", words[0])));
+        };
+        let output = async {
+            tokio::try_join!(child.wait(), read_capped(stdout), read_capped(stderr))
+        };
+
+        // Dropping the child on timeout or cancellation kills it
+        let (status, exit_code, stdout, stderr) = tokio::select! {
+            output = output => {
+                let (exit, stdout, stderr) = output.map_err(|e| (Status::InternalServerError, format!("Failed to run {}: {}", words[0], e)))?;
+                ("exited", exit.code(), stdout, stderr)
+            },
+            _ = tokio::time::sleep(Duration::from_secs(timeout)) => ("timed_out", None, Vec::new(), format!("Timed out after {}s", timeout).into_bytes()),
+            _ = cancel.notified() => ("cancelled", None, Vec::new(), b"Cancelled".to_vec()),
+        };
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+
+        Ok(CpiExecResult {
+            id,
+            provider: provider.to_string(),
+            action: action.to_string(),
+            status: status.to_string(),
+            exit_code,
+            stdout: text(&stdout),
+            stderr: text(&stderr),
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+/// Reads a stream to its end, keeping only its first `MAX_OUTPUT` bytes; the rest is read
+/// and dropped so the command never blocks on a full pipe
+async fn read_capped(mut stream: impl AsyncRead + Unpin) -> std::io::Result<Vec<u8>> {
+    let mut kept = Vec::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(kept);
+        }
+        let room = MAX_OUTPUT - kept.len();
+        kept.extend_from_slice(&buffer[..read.min(room)]);
+    }
+}
+
+// API Endpoints
+#[get("/cpis")]
+pub fn list_cpis(app_manager: &State<AppManager>, _key: ApiKey) -> Json<CpiRegistryStatus> {
+    Json(app_manager.cpis().status())
+}
+
+#[get("/cpis/<name>")]
+pub fn get_cpi(name: String, app_manager: &State<AppManager>, _key: ApiKey) -> Option<Json<CpiProvider>> {
+    app_manager.cpis().status().providers.into_iter()
+        .find(|provider| provider.name == name)
+        .map(Json)
+}
+
+#[post("/cpis/reload")]
+pub fn reload_cpis(app_manager: &State<AppManager>, _admin: Admin) -> Json<CpiRegistryStatus> {
+    Json(app_manager.cpis().reload())
+}
+
+#[post("/cpis/<name>/actions/<action>", format = "json", data = "<exec_req>")]
+pub async fn execute_cpi_action(name: String, action: String, exec_req: Json<CpiExecRequest>, app_manager: &State<AppManager>, _admin: Admin) -> Result<Json<CpiExecResult>, status::Custom<String>> {
+    let result = app_manager.cpis().execute(&name, &action, &exec_req).await
+        .map_err(|(code, message)| status::Custom(code, message))?;
+
+    let record = json::json!({
+        "action": "cpi_exec",
+        "execution_id": result.id,
+        "provider": name,
+        "cpi_action": action,
+        "status": result.status,
+        "exit_code": result.exit_code,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    if let Err(e) = state_store::append(app_manager.store(), state_store::AUDIT, &record).await {
+        eprintln!("Failed to record audit entry: {}", e);
+    }
+    Ok(Json(result))
+}
+
+#[get("/cpis/executions")]
+pub fn list_cpi_executions(app_manager: &State<AppManager>, _admin: Admin) -> Json<Vec<CpiExecution>> {
+    Json(app_manager.cpis().executions())
+}
+
+#[delete("/cpis/executions/<id>")]
+pub fn cancel_cpi_execution(id: String, app_manager: &State<AppManager>, _admin: Admin) -> Result<Json<CpiExecution>, String> {
+    app_manager.cpis().cancel(&id).map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Quoting::{Argument, Posix, Unsafe};
+
+    fn quoting(command: &str) -> Vec<Quoting> {
+        let (words, _) = classify(split_words(command).unwrap()).unwrap();
+        words.iter().map(|word| word.quoting).collect()
+    }
+
+    fn action(command: &str, params: &[&str]) -> Result<Action, String> {
+        let action = ActionFile {
+            command: command.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            timeout_secs: None,
+        };
+        parse_action("test", action, &HashMap::new())
+    }
+
+    fn run(command: &str, value: &str) -> Vec<String> {
+        let values = HashMap::from([("x".to_string(), value.to_string())]);
+        action(command, &["x"]).unwrap().words.iter()
+            .map(|word| substitute(word, &values).unwrap())
+            .collect()
+    }
+
+
+    #[test]
+    fn plain_programs_get_arguments() {
+        assert_eq!(quoting("ls -l {x}"), [Argument; 3]);
+        assert_eq!(run("ls -l {x}", "a; rm -rf /"), ["ls", "-l", "a; rm -rf /"]);
+    }
+
+    #[test]
+    fn looks_through_sudo() {
+        assert_eq!(quoting("sudo -u root sh -c \"echo {x}\""), [Argument, Argument, Argument, Argument, Argument, Posix]);
+        assert_eq!(run("sudo sh -c \"echo {x}\"", "a; rm -rf /"), ["sudo", "sh", "-c", "echo 'a; rm -rf /'"]);
+        assert_eq!(quoting("sudo -s echo {x}"), [Argument, Unsafe, Unsafe, Unsafe]);
+    }
+
+    #[test]
+    fn looks_through_env() {
+        assert_eq!(quoting("env -i FOO=1 -u BAR bash -c \"echo {x}\""), [Argument, Argument, Argument, Argument, Argument, Argument, Argument, Posix]);
+        assert_eq!(run("env FOO=1 bash -c \"echo {x}\"", "$(id)"), ["env", "FOO=1", "bash", "-c", "echo '$(id)'"]);
+        assert!(action("env -S \"sh -c\" {x}", &["x"]).is_err());
+    }
+
+    #[test]
+    fn looks_through_nice_and_timeout() {
+        assert_eq!(quoting("nice -n 5 sh -c {x}"), [Argument, Argument, Argument, Argument, Argument, Posix]);
+        assert_eq!(quoting("timeout -s KILL 30 sh -c {x}"), [Argument, Argument, Argument, Argument, Argument, Argument, Posix]);
+        assert_eq!(quoting("nohup nice sudo sh -c {x}"), [Argument, Argument, Argument, Argument, Argument, Posix]);
+    }
+
+    #[test]
+    fn quotes_the_remote_command_over_ssh() {
+        assert_eq!(quoting("ssh -p 22 host ls {x}"), [Argument, Argument, Argument, Argument, Posix, Posix]);
+        assert_eq!(run("ssh host ls {x}", "a; reboot"), ["ssh", "host", "ls", "'a; reboot'"]);
+        assert!(action("ssh host {x}", &["x"]).is_err());
+        // The remote shell and the one it starts would each parse the value
+        assert!(action("ssh host sh -c \"echo {x}\"", &["x"]).is_err());
+        assert!(action("ssh host \"sh -c echo\\ {x}\"", &["x"]).is_err());
+        assert!(action("ssh host sudo bash -c {x}", &["x"]).is_err());
+    }
+
+    #[test]
+    fn looks_through_container_exec() {
+        assert_eq!(quoting("docker exec -u root -it web sh -c {x}"), [Argument, Argument, Argument, Argument, Argument, Argument, Argument, Argument, Posix]);
+        assert_eq!(quoting("podman exec web ls {x}"), [Argument; 5]);
+        assert_eq!(quoting("kubectl exec -n prod pod -- bash -c {x}"), [Argument, Argument, Argument, Argument, Argument, Argument, Argument, Argument, Posix]);
+        assert!(action("kubectl exec pod bash -c {x}", &["x"]).is_err());
+    }
+
+    fn registry(command: &str, params: &[&str]) -> CpiRegistry {
+        let default_settings = HashMap::from([("region".to_string(), Value::from("eu"))]);
+        let action = ActionFile {
+            command: command.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            timeout_secs: None,
+        };
+        let actions = BTreeMap::from([("run".to_string(), parse_action("run", action, &default_settings).unwrap())]);
+        let summary = CpiProvider {
+            name: "test".to_string(),
+            kind: "command".to_string(),
+            version: None,
+            file: "test.json".to_string(),
+            default_settings: default_settings.clone(),
+            actions: Vec::new(),
+        };
+        let cpi = Cpi { summary, default_settings, actions };
+        CpiRegistry {
+            dir: PathBuf::new(),
+            loaded: Arc::new(RwLock::new(Loaded { providers: BTreeMap::from([("test".to_string(), Arc::new(cpi))]), errors: Vec::new() })),
+            default_timeout_secs: 10,
+            running: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_params_the_action_does_not_declare() {
+        let registry = registry("echo {x} {region}", &["x"]);
+        let exec_req = |params: &[(&str, &str)]| CpiExecRequest {
+            params: params.iter().map(|(name, value)| (name.to_string(), Value::from(*value))).collect(),
+            ..Default::default()
+        };
+
+        let result = registry.execute("test", "run", &exec_req(&[("x", "1")])).await.unwrap();
+        assert_eq!(result.stdout, "1 eu\n");
+        let (code, error) = registry.execute("test", "run", &exec_req(&[("x", "1"), ("region", "us")])).await.unwrap_err();
+        assert_eq!(code, Status::UnprocessableEntity);
+        assert!(error.contains("takes no parameter region"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_and_invalid_params_before_running() {
+        let registry = registry("echo {x} {y} {x}", &["x", "y"]);
+        let exec_req = |params: &[(&str, &str)]| CpiExecRequest {
+            params: params.iter().map(|(name, value)| (name.to_string(), Value::from(*value))).collect(),
+            ..Default::default()
+        };
+
+        let (code, error) = registry.execute("test", "run", &exec_req(&[])).await.unwrap_err();
+        assert_eq!((code, error.as_str()), (Status::UnprocessableEntity, "Missing parameters x, y"));
+        let (code, error) = registry.execute("test", "run", &exec_req(&[("x", "1")])).await.unwrap_err();
+        assert_eq!((code, error.as_str()), (Status::UnprocessableEntity, "Missing parameters y"));
+        let (code, _) = registry.execute("test", "run", &exec_req(&[("x", "-rf"), ("y", "1")])).await.unwrap_err();
+        assert_eq!(code, Status::UnprocessableEntity);
+        let (code, _) = registry.execute("other", "run", &exec_req(&[])).await.unwrap_err();
+        assert_eq!(code, Status::NotFound);
+    }
+
+    #[tokio::test]
+    async fn keeps_only_the_start_of_long_output() {
+        let output = vec![b'a'; MAX_OUTPUT + 100_000];
+        assert_eq!(read_capped(output.as_slice()).await.unwrap().len(), MAX_OUTPUT);
+        assert_eq!(read_capped(&b"short"[..]).await.unwrap(), b"short");
+
+        let result = registry("head -c 3000000 /dev/zero", &[]).execute("test", "run", &CpiExecRequest::default()).await.unwrap();
+        assert_eq!(result.status, "exited");
+        assert_eq!(result.exit_code, Some(0));
+        assert_eq!(result.stdout.len(), MAX_OUTPUT);
+    }
+
+    #[test]
+    fn rejects_a_wrapped_program_from_a_parameter() {
+        assert!(action("sudo {x}", &["x"]).is_err());
+        assert!(action("docker exec web {x}", &["x"]).is_err());
+        assert!(action("sudo -u root", &[]).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use rocket::get;
+use rocket::http::Header;
+use rocket::serde::{json::{self, Json}};
+use rocket::{Responder, State};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::agent::Agent;
+use crate::clock;
+use crate::event_bus::{AgentEvent, EventBus};
+use crate::host_stats;
+use super::access::Admin;
+use super::instances::AppManager;
+use super::state::StateTracker;
+pub use omniagent_client::models::diagnostics::{CheckStatus, DiagnosticCheck, DiagnosticsReport};
+
+/// Events retained for diagnostics bundles
+const RECENT_EVENTS: usize = 500;
+
+/// Ring buffer of the latest agent events, standing in for logs in support bundles
+#[derive(Clone)]
+pub struct RecentEvents {
+    events: Arc<Mutex<VecDeque<AgentEvent>>>,
+}
+
+impl RecentEvents {
+    pub fn start(bus: &EventBus) -> Self {
+        let recent = RecentEvents {
+            events: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_EVENTS))),
+        };
+        let events = recent.events.clone();
+        let mut receiver = bus.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        let mut events = events.lock().unwrap();
+                        if events.len() == RECENT_EVENTS {
+                            events.pop_front();
+                        }
+                        events.push_back(event);
+                    },
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        recent
+    }
+}
+
+fn check(name: &str, started: Instant, status: CheckStatus, detail: impl Into<String>) -> DiagnosticCheck {
+    DiagnosticCheck {
+        name: name.to_string(),
+        status,
+        detail: detail.into(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+fn skew_status(skew_secs: f64) -> CheckStatus {
+    match skew_secs.abs() {
+        s if s > 30.0 => CheckStatus::Fail,
+        s if s > 2.0 => CheckStatus::Warn,
+        _ => CheckStatus::Pass,
+    }
+}
+
+fn usage_status(used_percent: f64) -> CheckStatus {
+    match used_percent {
+        p if p > 95.0 => CheckStatus::Fail,
+        p if p > 85.0 => CheckStatus::Warn,
+        _ => CheckStatus::Pass,
+    }
+}
+
+#[cfg(unix)]
+fn inode_usage(path: &str) -> Result<f64, String> {
+    let stats = nix::sys::statvfs::statvfs(path).map_err(|e| e.to_string())?;
+    let total = stats.files() as f64;
+    if total == 0.0 {
+        return Err("filesystem does not report inodes".to_string());
+    }
+    Ok((total - stats.files_free() as f64) / total * 100.0)
+}
+
+/// Runs every check; individual failures are reported, never returned as errors
+pub async fn run_checks(app_manager: &AppManager, agent_id: &str) -> DiagnosticsReport {
+    let mut checks = Vec::new();
+    let docker = app_manager.docker();
+
+    let started = Instant::now();
+    checks.push(match docker.ping().await {
+        Ok(_) => {
+            let latency = started.elapsed().as_millis();
+            let status = if latency > 500 { CheckStatus::Warn } else { CheckStatus::Pass };
+            check("docker_api", started, status, format!("Ping answered in {}ms", latency))
+        },
+        Err(e) => check("docker_api", started, CheckStatus::Fail, format!("Docker API unreachable: {}", e)),
+    });
+
+    let started = Instant::now();
+    let info = docker.info().await.ok();
+    let docker_root = info.as_ref().and_then(|info| info.docker_root_dir.clone()).unwrap_or_else(|| "/".to_string());
+
+    checks.push(match host_stats::disk_for(&docker_root) {
+        Some(disk) if disk.total > 0 => {
+            let used = disk.total.saturating_sub(disk.available) as f64 / disk.total as f64 * 100.0;
+            check("disk_space", started, usage_status(used), format!("{:.1}% used on {}, {} MiB free", used, disk.mount, disk.available / 1024 / 1024))
+        },
+        Some(_) => check("disk_space", started, CheckStatus::Skipped, "Disk size not reported"),
+        None => check("disk_space", started, CheckStatus::Skipped, format!("No disk found for {}", docker_root)),
+    });
+
+    let started = Instant::now();
+    #[cfg(unix)]
+    checks.push(match inode_usage(&docker_root) {
+        Ok(used) => check("inodes", started, usage_status(used), format!("{:.1}% of inodes used on {}", used, docker_root)),
+        Err(e) => check("inodes", started, CheckStatus::Skipped, format!("Inode usage unavailable for {}: {}", docker_root, e)),
+    });
+    #[cfg(not(unix))]
+    checks.push(check("inodes", started, CheckStatus::Skipped, "Inode usage is not tracked on this platform"));
+
+    let started = Instant::now();
+    let daemon_time = info.as_ref()
+        .and_then(|info| info.system_time.as_deref())
+        .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok());
+    checks.push(match daemon_time {
+        Some(time) => {
+            let skew = (chrono::Utc::now() - time.with_timezone(&chrono::Utc)).num_milliseconds() as f64 / 1000.0;
+            check("clock_skew_docker", started, skew_status(skew), format!("Agent clock is {:+.1}s from the Docker daemon", skew))
+        },
+        None => check("clock_skew_docker", started, CheckStatus::Skipped, "Docker daemon did not report its time"),
+    });
+
+    let started = Instant::now();
+    checks.push(match clock::ntp_server() {
+        Some(server) => match clock::ntp_skew(&server).await {
+            Ok(skew) => check("clock_skew_ntp", started, skew_status(skew), format!("Agent clock is {:+.3}s from {}", skew, server)),
+            Err(e) => check("clock_skew_ntp", started, CheckStatus::Warn, e),
+        },
+        None => check("clock_skew_ntp", started, CheckStatus::Skipped, "OMNI_NTP_SERVER is off"),
+    });
+
+    let started = Instant::now();
+    let cpis = app_manager.cpis().status();
+    checks.push(match (cpis.providers.len(), cpis.errors.len()) {
+        (0, 0) => check("cpi", started, CheckStatus::Skipped, format!("No CPI files in {}", cpis.dir)),
+        (loaded, 0) => check("cpi", started, CheckStatus::Pass, format!("{} CPI providers loaded from {}", loaded, cpis.dir)),
+        (loaded, failed) => check("cpi", started, CheckStatus::Warn, format!("{} CPI providers loaded and {} files rejected in {}", loaded, failed, cpis.dir)),
+    });
+
+    let started = Instant::now();
+    match std::env::var("OMNI_ORCHESTRATOR_URL") {
+        Ok(url) => {
+            let response = crate::crypto::http_client().get(&url).timeout(Duration::from_secs(5)).send().await;
+            match response {
+                Ok(response) => {
+                    checks.push(check("orchestrator_reachable", started, CheckStatus::Pass,
+                        format!("{} answered {} in {}ms", url, response.status(), started.elapsed().as_millis())));
+
+                    let started = Instant::now();
+                    let orchestrator_time = response.headers().get("Date")
+                        .and_then(|date| date.to_str().ok())
+                        .and_then(|date| chrono::DateTime::parse_from_rfc2822(date).ok());
+                    if let Some(time) = orchestrator_time {
+                        let skew = (chrono::Utc::now() - time.with_timezone(&chrono::Utc)).num_milliseconds() as f64 / 1000.0;
+                        checks.push(check("clock_skew_orchestrator", started, skew_status(skew),
+                            format!("Agent clock is {:+.1}s from the orchestrator", skew)));
+                    }
+                },
+                Err(e) => checks.push(check("orchestrator_reachable", started, CheckStatus::Fail, format!("{} unreachable: {}", url, e))),
+            }
+        },
+        Err(_) => checks.push(check("orchestrator_reachable", started, CheckStatus::Skipped, "OMNI_ORCHESTRATOR_URL is not set")),
+    }
+
+    let overall = if checks.iter().any(|c| c.status == CheckStatus::Fail) {
+        CheckStatus::Fail
+    } else if checks.iter().any(|c| c.status == CheckStatus::Warn) {
+        CheckStatus::Warn
+    } else {
+        CheckStatus::Pass
+    };
+
+    DiagnosticsReport {
+        agent_id: agent_id.to_string(),
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        overall,
+        checks,
+    }
+}
+
+/// Logs failing and warning checks at startup
+pub async fn self_test(app_manager: &AppManager, agent_id: &str) {
+    let report = run_checks(app_manager, agent_id).await;
+    for check in report.checks.iter().filter(|c| matches!(c.status, CheckStatus::Fail | CheckStatus::Warn)) {
+        log::warn!("Self-test {:?}: {} - {}", check.status, check.name, check.detail);
+    }
+    log::info!("Self-test: {:?}", report.overall);
+}
+
+/// Agent configuration from the environment with secrets and URL credentials masked
+fn redacted_config() -> Vec<(String, String)> {
+    let mut config: Vec<(String, String)> = std::env::vars()
+        .filter(|(name, _)| name.starts_with("OMNI_") || name.starts_with("DOCKER_"))
+        .map(|(name, value)| {
+            let secret = ["TOKEN", "SECRET", "PASSWORD", "PASS", "KEY", "CREDENTIAL", "AUTH", "CERT"].iter().any(|word| name.contains(word));
+            let value = if secret {
+                "<redacted>".to_string()
+            } else if let Some((scheme, rest)) = value.split_once("://").filter(|(_, rest)| rest.contains('@')) {
+                let host = rest.rsplit_once('@').map(|(_, host)| host).unwrap_or(rest);
+                format!("{}://<redacted>@{}", scheme, host)
+            } else {
+                value
+            };
+            (name, value)
+        })
+        .collect();
+    config.sort();
+    config
+}
+
+fn append_file(archive: &mut tar::Builder<flate2::write::GzEncoder<Vec<u8>>>, name: &str, contents: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, name, contents)
+}
+
+#[derive(Responder)]
+#[response(content_type = "application/gzip")]
+pub struct DiagnosticsBundle(Vec<u8>, Header<'static>);
+
+// API Endpoints
+#[get("/agent/diagnostics")]
+pub async fn get_diagnostics(app_manager: &State<AppManager>, agent: &State<Agent>) -> Json<DiagnosticsReport> {
+    Json(run_checks(app_manager, &agent.id().to_string()).await)
+}
+
+/// Config, instance state and recent events in one tarball, so it needs the admin token
+#[get("/agent/diagnostics/bundle")]
+pub async fn get_diagnostics_bundle(app_manager: &State<AppManager>, agent: &State<Agent>, tracker: &State<StateTracker>, recent: &State<RecentEvents>, _admin: Admin) -> Result<DiagnosticsBundle, String> {
+    let report = run_checks(app_manager, &agent.id().to_string()).await;
+    let events: Vec<AgentEvent> = recent.events.lock().unwrap().iter().cloned().collect();
+    let events = events.iter()
+        .filter_map(|event| json::to_string(event).ok())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let config = redacted_config().into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let files = [
+        ("diagnostics.json", json::to_pretty_string(&report).map_err(|e| e.to_string())?),
+        ("config.env", config),
+        ("state.json", json::to_pretty_string(&tracker.delta(0)).map_err(|e| e.to_string())?),
+        ("events.jsonl", events),
+    ];
+
+    let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+    for (name, contents) in &files {
+        append_file(&mut archive, name, contents.as_bytes())
+            .map_err(|e| format!("Failed to build diagnostics bundle: {}", e))?;
+    }
+    let bytes = archive.into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| format!("Failed to build diagnostics bundle: {}", e))?;
+
+    let filename = format!("omni-agent-diagnostics-{}.tar.gz", chrono::Utc::now().format("%Y%m%dT%H%M%SZ"));
+    Ok(DiagnosticsBundle(bytes, Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", filename))))
+}
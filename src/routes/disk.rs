@@ -0,0 +1,293 @@
+use rocket::get;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use bollard::Docker;
+use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+use bollard::image::{ListImagesOptions, RemoveImageOptions};
+
+use crate::event_bus::{AgentEvent, EventBus};
+use crate::host_stats;
+use crate::state_store::{self, StateStore};
+use super::access::AccessError;
+use super::images::ImageManager;
+use super::maintenance::MaintenanceWindows;
+pub use omniagent_client::models::disk::{DiskStatus, CleanupReport, VolumeStatus};
+
+/// Pressure clears once usage falls this many points below the threshold
+const HYSTERESIS_PERCENT: f64 = 5.0;
+
+/// Watches usage of the volumes holding Docker's data root and the agent's state directory,
+/// which are often separate mounts, against `OMNI_DISK_PRESSURE_THRESHOLD` (percent, default
+/// 90). When the data root's volume crosses it, cleanup removes unpinned unused images least
+/// recently used first, then exited containers the agent doesn't manage, then dangling volumes.
+#[derive(Clone)]
+pub struct DiskMonitor {
+    threshold_percent: f64,
+    state_dir: String,
+    docker_root: Arc<Mutex<Option<String>>>,
+    status: Arc<Mutex<DiskStatus>>,
+}
+
+fn percent(total: u64, available: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    total.saturating_sub(available) as f64 / total as f64 * 100.0
+}
+
+/// The volume holding each path, merging paths that share one. Falls back to the sum of all
+/// disks when none of the paths can be placed.
+fn volumes(paths: &[String]) -> Vec<VolumeStatus> {
+    let mut volumes: Vec<VolumeStatus> = Vec::new();
+    for path in paths {
+        let Some(disk) = host_stats::disk_for(path) else {
+            continue;
+        };
+        match volumes.iter_mut().find(|volume| volume.mount == disk.mount) {
+            Some(volume) => volume.paths.push(path.clone()),
+            None => volumes.push(VolumeStatus {
+                used_percent: percent(disk.total, disk.available),
+                mount: disk.mount,
+                paths: vec![path.clone()],
+                total: disk.total,
+                available: disk.available,
+                under_pressure: false,
+            }),
+        }
+    }
+
+    if volumes.is_empty() {
+        let (total, available) = host_stats::disk_totals();
+        volumes.push(VolumeStatus {
+            mount: "*".to_string(),
+            paths: paths.to_vec(),
+            used_percent: percent(total, available),
+            total,
+            available,
+            under_pressure: false,
+        });
+    }
+    volumes
+}
+
+impl DiskMonitor {
+    pub fn from_env() -> Self {
+        let threshold_percent = std::env::var("OMNI_DISK_PRESSURE_THRESHOLD").ok()
+            .and_then(|percent| percent.parse().ok())
+            .filter(|percent: &f64| *percent > HYSTERESIS_PERCENT && *percent <= 100.0)
+            .unwrap_or(90.0);
+        let state_dir = std::env::var("OMNI_STATE_DIR").unwrap_or_else(|_| "./state".to_string());
+        let state_dir = std::path::absolute(&state_dir)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or(state_dir);
+
+        let volumes = volumes(std::slice::from_ref(&state_dir));
+        DiskMonitor {
+            threshold_percent,
+            state_dir,
+            docker_root: Arc::new(Mutex::new(None)),
+            status: Arc::new(Mutex::new(DiskStatus {
+                threshold_percent,
+                used_percent: volumes.iter().map(|volume| volume.used_percent).fold(0.0, f64::max),
+                under_pressure: false,
+                last_cleanup: None,
+                volumes,
+            })),
+        }
+    }
+
+    pub fn under_pressure(&self) -> bool {
+        self.status.lock().unwrap().under_pressure
+    }
+
+    fn watched_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self.docker_root.lock().unwrap().iter().cloned().collect();
+        paths.push(self.state_dir.clone());
+        paths
+    }
+
+    /// Whether the data root's volume, the one cleanup frees space on, is back under the threshold
+    fn relieved(&self) -> bool {
+        let docker_root = self.docker_root.lock().unwrap().clone();
+        let used = match docker_root.as_deref().and_then(host_stats::disk_for) {
+            Some(disk) => percent(disk.total, disk.available),
+            None => {
+                let (total, available) = host_stats::disk_totals();
+                percent(total, available)
+            }
+        };
+        used < self.threshold_percent - HYSTERESIS_PERCENT
+    }
+
+    /// Checks usage every 30 seconds, alerting as each volume enters or leaves pressure. Cleanup
+    /// is skipped during maintenance windows but creates stay refused until usage drops.
+    pub fn start(&self, docker: Docker, images: Arc<ImageManager>, store: Arc<dyn StateStore>, maintenance: MaintenanceWindows, bus: EventBus) {
+        let monitor = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                if monitor.docker_root.lock().unwrap().is_none() {
+                    if let Some(root) = docker.info().await.ok().and_then(|info| info.docker_root_dir) {
+                        *monitor.docker_root.lock().unwrap() = Some(root);
+                    }
+                }
+                let docker_root = monitor.docker_root.lock().unwrap().clone();
+
+                let previous = monitor.status.lock().unwrap().volumes.clone();
+                let mut volumes = volumes(&monitor.watched_paths());
+                for volume in &mut volumes {
+                    let was_under_pressure = previous.iter().any(|old| old.mount == volume.mount && old.under_pressure);
+                    volume.under_pressure = if was_under_pressure {
+                        volume.used_percent >= monitor.threshold_percent - HYSTERESIS_PERCENT
+                    } else {
+                        volume.used_percent >= monitor.threshold_percent
+                    };
+                    if volume.under_pressure == was_under_pressure {
+                        continue;
+                    }
+
+                    let message = if volume.under_pressure {
+                        format!("Disk usage on {} ({}) at {:.1}% exceeds {:.1}%; refusing new instances", volume.mount, volume.paths.join(", "), volume.used_percent, monitor.threshold_percent)
+                    } else {
+                        format!("Disk usage on {} ({}) back to {:.1}%", volume.mount, volume.paths.join(", "), volume.used_percent)
+                    };
+                    if volume.under_pressure {
+                        log::warn!("{}", message);
+                    } else {
+                        log::info!("{}", message);
+                    }
+                    bus.publish(AgentEvent::Alert {
+                        severity: if volume.under_pressure { "critical" } else { "info" }.to_string(),
+                        source: "disk_pressure".to_string(),
+                        message,
+                        timestamp: chrono::Utc::now().to_rfc3339(),
+                    });
+                }
+
+                let under_pressure = volumes.iter().any(|volume| volume.under_pressure);
+                let cleanup_needed = volumes.iter()
+                    .any(|volume| volume.under_pressure && docker_root.as_ref().is_none_or(|root| volume.paths.contains(root)));
+                {
+                    let mut status = monitor.status.lock().unwrap();
+                    status.used_percent = volumes.iter().map(|volume| volume.used_percent).fold(0.0, f64::max);
+                    status.under_pressure = under_pressure;
+                    status.volumes = volumes;
+                }
+
+                if cleanup_needed && !maintenance.in_maintenance() {
+                    let report = monitor.cleanup(&docker, &images, store.as_ref()).await;
+                    log::info!("Disk pressure cleanup reclaimed {} bytes", report.space_reclaimed);
+                    monitor.status.lock().unwrap().last_cleanup = Some(report);
+                }
+
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+        });
+    }
+
+    async fn cleanup(&self, docker: &Docker, images: &ImageManager, store: &dyn StateStore) -> CleanupReport {
+        let mut report = CleanupReport::default();
+
+        let containers = docker.list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            ..Default::default()
+        })).await.unwrap_or_default();
+
+        // Images are "used" when a container was last created from them
+        let mut last_used: HashMap<String, i64> = HashMap::new();
+        let mut in_use: HashSet<String> = HashSet::new();
+        for container in &containers {
+            if let Some(image_id) = &container.image_id {
+                in_use.insert(image_id.clone());
+                let created = container.created.unwrap_or(0);
+                let entry = last_used.entry(image_id.clone()).or_insert(created);
+                *entry = (*entry).max(created);
+            }
+        }
+
+        let mut candidates: Vec<_> = docker.list_images(Some(ListImagesOptions::<String>::default())).await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|image| !in_use.contains(&image.id))
+            .filter(|image| !image.repo_tags.iter().any(|tag| images.is_pinned(tag)))
+            .collect();
+        candidates.sort_by_key(|image| last_used.get(&image.id).copied().unwrap_or(image.created));
+
+        for image in candidates {
+            if self.relieved() {
+                break;
+            }
+            match docker.remove_image(&image.id, Some(RemoveImageOptions { force: false, noprune: false }), None).await {
+                Ok(_) => {
+                    report.space_reclaimed += image.size.max(0) as u64;
+                    report.images_removed.push(image.repo_tags.first().cloned().unwrap_or(image.id));
+                },
+                Err(e) => log::error!("Failed to remove image {}: {}", image.id, e),
+            }
+        }
+
+        // Never remove instances the agent created, even when stopped
+        let managed: HashSet<String> = store.list(state_store::SPECS).await
+            .map(|specs| specs.into_iter().map(|(id, _)| id).collect())
+            .unwrap_or_default();
+        for container in containers {
+            if self.relieved() {
+                break;
+            }
+            let Some(id) = container.id else {
+                continue;
+            };
+            if container.state.as_deref() != Some("exited") || managed.contains(&id) {
+                continue;
+            }
+            match docker.remove_container(&id, Some(RemoveContainerOptions::default())).await {
+                Ok(_) => report.containers_removed.push(id),
+                Err(e) => log::error!("Failed to remove container {}: {}", id, e),
+            }
+        }
+
+        if !self.relieved() {
+            match docker.prune_volumes(None::<bollard::volume::PruneVolumesOptions<String>>).await {
+                Ok(pruned) => {
+                    report.volumes_removed = pruned.volumes_deleted.unwrap_or_default();
+                    report.space_reclaimed += pruned.space_reclaimed.unwrap_or(0).max(0) as u64;
+                },
+                Err(e) => log::error!("Failed to prune dangling volumes: {}", e),
+            }
+        }
+
+        report.finished_at = chrono::Utc::now().to_rfc3339();
+        report
+    }
+}
+
+/// Request guard for routes that consume disk; rejects with 507 while under pressure
+#[derive(Clone, Copy)]
+pub struct DiskSpace(());
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DiskSpace {
+    type Error = AccessError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.rocket().state::<DiskMonitor>() {
+            Some(monitor) if monitor.under_pressure() => {
+                let error = AccessError::new("disk_pressure", "Disk usage is above the pressure threshold; free space before creating instances");
+                req.local_cache(|| Some(error.clone()));
+                Outcome::Error((Status::InsufficientStorage, error))
+            },
+            _ => Outcome::Success(DiskSpace(())),
+        }
+    }
+}
+
+// API Endpoints
+#[get("/agent/disk")]
+pub fn get_disk_status(monitor: &State<DiskMonitor>) -> Json<DiskStatus> {
+    Json(monitor.status.lock().unwrap().clone())
+}
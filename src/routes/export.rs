@@ -0,0 +1,114 @@
+use rocket::http::MediaType;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+
+use super::instances::AppInstance;
+use super::usage::UsageRecord;
+
+/// Response format for list routes: CSV when asked for with `?format=csv` or
+/// `Accept: text/csv`, JSON otherwise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ExportFormat {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let csv = match req.query_value::<&str>("format") {
+            Some(Ok(format)) => format == "csv",
+            _ => req.accept().is_some_and(|accept| accept.preferred().media_type() == &MediaType::CSV),
+        };
+        Outcome::Success(if csv { ExportFormat::Csv } else { ExportFormat::Json })
+    }
+}
+
+#[derive(rocket::Responder)]
+pub enum Export<T: Serialize> {
+    Json(Json<T>),
+    #[response(content_type = "text/csv")]
+    Csv(String),
+}
+
+/// A type that flattens into one spreadsheet row
+pub trait CsvRow {
+    const HEADERS: &'static [&'static str];
+    fn fields(&self) -> Vec<String>;
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn to_csv<R: CsvRow>(rows: &[R]) -> String {
+    let mut csv = R::HEADERS.join(",");
+    csv.push('\n');
+    for row in rows {
+        let fields: Vec<String> = row.fields().iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+impl ExportFormat {
+    pub fn respond<R: CsvRow + Serialize>(self, rows: Vec<R>) -> Export<Vec<R>> {
+        match self {
+            ExportFormat::Json => Export::Json(Json(rows)),
+            ExportFormat::Csv => Export::Csv(to_csv(&rows)),
+        }
+    }
+}
+
+impl CsvRow for AppInstance {
+    const HEADERS: &'static [&'static str] = &["id", "name", "image", "image_digest", "status", "created_at", "ports", "agent_id"];
+
+    fn fields(&self) -> Vec<String> {
+        let ports: Vec<String> = self.ports.iter()
+            .map(|port| format!("{}:{}/{}", port.host_port, port.container_port, port.protocol))
+            .collect();
+        vec![
+            self.id.clone(), self.name.clone(), self.image.clone(), self.image_digest.clone().unwrap_or_default(),
+            self.status.clone(), self.created_at.clone(), ports.join(" "), self.agent_id.clone(),
+        ]
+    }
+}
+
+impl CsvRow for UsageRecord {
+    const HEADERS: &'static [&'static str] = &[
+        "instance_id", "name", "period_start", "period_end", "runtime_seconds", "cpu_seconds",
+        "memory_gb_hours", "network_rx_bytes", "network_tx_bytes",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.instance_id.clone(), self.name.clone(), self.period_start.clone(), self.period_end.clone(),
+            format!("{:.3}", self.runtime_seconds), format!("{:.3}", self.cpu_seconds), format!("{:.6}", self.memory_gb_hours),
+            self.network_rx_bytes.to_string(), self.network_tx_bytes.to_string(),
+        ]
+    }
+}
+
+/// One row per image tag
+pub struct ImageRow {
+    pub tag: String,
+    pub id: String,
+    pub size: i64,
+    pub created: i64,
+}
+
+impl CsvRow for ImageRow {
+    const HEADERS: &'static [&'static str] = &["tag", "id", "size", "created"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.tag.clone(), self.id.clone(), self.size.to_string(), self.created.to_string()]
+    }
+}
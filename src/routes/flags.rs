@@ -0,0 +1,214 @@
+use rocket::{delete, get, put};
+use rocket::serde::json::{self, Json};
+use rocket::State;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use chrono::Utc;
+
+use crate::config::Config;
+use crate::crypto;
+use crate::state_store::{self, StateStore};
+use super::access::Admin;
+use super::instances::AppManager;
+use super::rbac::DEFAULT_NAMESPACE;
+pub use omniagent_client::models::flags::{FeatureFlag, FeatureFlagRequest, FlagEvaluation};
+
+/// Updates keep the old container, renamed, until the new one is created, and restore it
+/// if creation fails
+pub const UPDATE_ROLLBACK: &str = "update-rollback";
+
+/// Flags this agent checks, listed even when nobody has set them
+const KNOWN_FLAGS: &[(&str, &str)] = &[
+    (UPDATE_ROLLBACK, "Keep an updated instance's old container until its replacement is created"),
+];
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Feature flags from the config file, overridden by those set through the API, which are
+/// kept in the state store. Flags nobody has set are off.
+#[derive(Clone)]
+pub struct FeatureFlags {
+    store: Arc<dyn StateStore>,
+    /// Hashed with each flag's name to place this agent within percentage rollouts
+    hostname: String,
+    configured: Arc<BTreeMap<String, FeatureFlag>>,
+    overrides: Arc<Mutex<BTreeMap<String, FeatureFlag>>>,
+}
+
+impl FeatureFlags {
+    pub fn new(store: Arc<dyn StateStore>, config: &Config) -> Self {
+        let hostname = hostname::get().unwrap_or_default().to_string_lossy().to_string();
+        let mut configured = BTreeMap::new();
+        for (name, description) in KNOWN_FLAGS {
+            let flag_req = FeatureFlagRequest { description: Some(description.to_string()), ..Default::default() };
+            configured.insert(name.to_string(), to_flag(&hostname, name, flag_req, "default", None));
+        }
+        for (name, flag_req) in &config.flags {
+            configured.insert(name.clone(), to_flag(&hostname, name, flag_req.clone(), "config", None));
+        }
+        FeatureFlags {
+            store,
+            hostname,
+            configured: Arc::new(configured),
+            overrides: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Reads the flags set through the API; returns how many there are
+    pub async fn load(&self) -> Result<usize, String> {
+        let mut overrides = BTreeMap::new();
+        for (name, value) in self.store.list(state_store::FLAGS).await? {
+            match json::from_value::<FeatureFlag>(value) {
+                // Re-placed in case the hostname changed since the flag was set
+                Ok(flag) => {
+                    let flag_req = FeatureFlagRequest {
+                        enabled: flag.enabled,
+                        namespaces: flag.namespaces,
+                        percentage: flag.percentage,
+                        description: flag.description,
+                    };
+                    overrides.insert(name.clone(), to_flag(&self.hostname, &name, flag_req, "api", flag.updated_at));
+                },
+                Err(e) => eprintln!("Skipping unreadable {} entry {}: {}", state_store::FLAGS, name, e),
+            }
+        }
+        let count = overrides.len();
+        *self.overrides.lock().unwrap() = overrides;
+        Ok(count)
+    }
+
+    pub fn list(&self) -> Vec<FeatureFlag> {
+        let mut flags = (*self.configured).clone();
+        flags.extend(self.overrides.lock().unwrap().clone());
+        flags.into_values().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<FeatureFlag> {
+        self.overrides.lock().unwrap().get(name).cloned()
+            .or_else(|| self.configured.get(name).cloned())
+    }
+
+    /// Whether flag `name` is on for this agent in `namespace`
+    pub fn is_enabled(&self, name: &str, namespace: &str) -> bool {
+        self.get(name).is_some_and(|flag| {
+            flag.active && (flag.namespaces.is_empty() || flag.namespaces.iter().any(|allowed| allowed == namespace))
+        })
+    }
+}
+
+fn to_flag(hostname: &str, name: &str, flag_req: FeatureFlagRequest, source: &str, updated_at: Option<String>) -> FeatureFlag {
+    let active = flag_req.enabled && flag_req.percentage.is_none_or(|percentage| bucket(hostname, name) < percentage);
+    FeatureFlag {
+        name: name.to_string(),
+        enabled: flag_req.enabled,
+        namespaces: flag_req.namespaces,
+        percentage: flag_req.percentage,
+        description: flag_req.description,
+        source: source.to_string(),
+        active,
+        updated_at,
+    }
+}
+
+/// This agent's place, 0-99, in a flag's rollout. Hashing in the flag name spreads each
+/// flag's first agents differently.
+fn bucket(hostname: &str, name: &str) -> u8 {
+    let hash = crypto::sha256_hex(format!("{}/{}", name, hostname).as_bytes());
+    (u32::from_str_radix(&hash[..8], 16).unwrap_or(0) % 100) as u8
+}
+
+#[get("/flags")]
+pub fn list_flags(app_manager: &State<AppManager>) -> Json<Vec<FeatureFlag>> {
+    Json(app_manager.flags().list())
+}
+
+#[get("/flags/<name>")]
+pub fn get_flag(name: String, app_manager: &State<AppManager>) -> Option<Json<FeatureFlag>> {
+    app_manager.flags().get(&name).map(Json)
+}
+
+/// Whether a flag is on for this agent in `namespace` (default `default`)
+#[get("/flags/<name>/evaluate?<namespace>")]
+pub fn evaluate_flag(name: String, namespace: Option<String>, app_manager: &State<AppManager>) -> Json<FlagEvaluation> {
+    let namespace = namespace.unwrap_or_else(|| DEFAULT_NAMESPACE.to_string());
+    let enabled = app_manager.flags().is_enabled(&name, &namespace);
+    Json(FlagEvaluation { name, namespace, enabled })
+}
+
+/// Sets a flag, overriding the config file until the flag is deleted
+#[put("/flags/<name>", format = "json", data = "<flag_req>")]
+pub async fn put_flag(name: String, flag_req: Json<FeatureFlagRequest>, app_manager: &State<AppManager>, _admin: Admin) -> Result<Json<FeatureFlag>, String> {
+    if !valid_name(&name) {
+        return Err(format!("Invalid flag name {}", name));
+    }
+    if flag_req.percentage.is_some_and(|percentage| percentage > 100) {
+        return Err("percentage must be at most 100".to_string());
+    }
+    let flags = app_manager.flags();
+    let flag = to_flag(&flags.hostname, &name, flag_req.into_inner(), "api", Some(Utc::now().to_rfc3339()));
+    let value = json::to_value(&flag).map_err(|e| format!("Failed to serialize flag: {}", e))?;
+    flags.store.put(state_store::FLAGS, &name, &value).await
+        .map_err(|e| format!("Failed to save flag: {}", e))?;
+    flags.overrides.lock().unwrap().insert(name, flag.clone());
+    Ok(Json(flag))
+}
+
+/// Removes a flag set through the API and returns the flag now in effect, from the config
+/// file or the default, if any
+#[delete("/flags/<name>")]
+pub async fn delete_flag(name: String, app_manager: &State<AppManager>, _admin: Admin) -> Result<Json<Option<FeatureFlag>>, String> {
+    let flags = app_manager.flags();
+    if !flags.overrides.lock().unwrap().contains_key(&name) {
+        return Err(format!("Flag {} was not set through the API", name));
+    }
+    flags.store.delete(state_store::FLAGS, &name).await
+        .map_err(|e| format!("Failed to delete flag: {}", e))?;
+    flags.overrides.lock().unwrap().remove(&name);
+    Ok(Json(flags.get(&name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active(hostname: &str, percentage: u8) -> bool {
+        let flag_req = FeatureFlagRequest { enabled: true, percentage: Some(percentage), ..Default::default() };
+        to_flag(hostname, "test-flag", flag_req, "api", None).active
+    }
+
+    #[test]
+    fn buckets_are_stable_and_in_range() {
+        for i in 0..500 {
+            let hostname = format!("agent-{}", i);
+            let place = bucket(&hostname, "test-flag");
+            assert!(place < 100);
+            assert_eq!(place, bucket(&hostname, "test-flag"));
+        }
+        // The flag name spreads agents differently for each flag
+        let moved = (0..100).filter(|i| {
+            let hostname = format!("agent-{}", i);
+            bucket(&hostname, "test-flag") != bucket(&hostname, "other-flag")
+        }).count();
+        assert!(moved > 50);
+    }
+
+    #[test]
+    fn percentage_bounds_cover_no_agent_or_every_agent() {
+        for i in 0..500 {
+            let hostname = format!("agent-{}", i);
+            assert!(!active(&hostname, 0));
+            assert!(active(&hostname, 100));
+            assert_eq!(active(&hostname, 50), bucket(&hostname, "test-flag") < 50);
+        }
+    }
+
+    #[test]
+    fn a_disabled_flag_is_inactive_at_any_percentage() {
+        let flag_req = FeatureFlagRequest { enabled: false, percentage: Some(100), ..Default::default() };
+        assert!(!to_flag("agent-0", "test-flag", flag_req, "api", None).active);
+        let flag_req = FeatureFlagRequest { enabled: true, ..Default::default() };
+        assert!(to_flag("agent-0", "test-flag", flag_req, "api", None).active);
+    }
+}
@@ -0,0 +1,97 @@
+use rocket::get;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::event_bus::{AgentEvent, EventBus};
+use crate::state_store::StateStore;
+pub use omniagent_client::models::ha::LeaderStatus;
+
+/// Active/standby election between agents sharing a state store. Only the leader accepts
+/// mutations (see `access::Mutation`); followers keep serving reads and take over once the
+/// leader's lease lapses.
+#[derive(Clone)]
+pub struct LeaderElection {
+    /// Lease name from `OMNI_HA_GROUP`; unset disables election
+    group: Option<String>,
+    holder: String,
+    ttl: Duration,
+    is_leader: Arc<AtomicBool>,
+}
+
+impl LeaderElection {
+    /// Reads `OMNI_HA_GROUP` and `OMNI_HA_LEASE_TTL` (seconds, default 15)
+    pub fn from_env(holder: String) -> Self {
+        let group = std::env::var("OMNI_HA_GROUP").ok().filter(|group| !group.is_empty());
+        let ttl = std::env::var("OMNI_HA_LEASE_TTL").ok()
+            .and_then(|secs| secs.parse().ok())
+            .filter(|secs| *secs >= 3)
+            .unwrap_or(15);
+
+        LeaderElection {
+            is_leader: Arc::new(AtomicBool::new(group.is_none())),
+            group,
+            holder,
+            ttl: Duration::from_secs(ttl),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::SeqCst)
+    }
+
+    /// Renews the lease every third of its TTL. A leader that cannot reach the store steps
+    /// down, since it can no longer prove its lease is current.
+    pub fn start(&self, store: Arc<dyn StateStore>, bus: EventBus) {
+        let Some(group) = self.group.clone() else {
+            return;
+        };
+        let election = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let leader = match store.acquire_lease(&group, &election.holder, election.ttl).await {
+                    Ok(acquired) => acquired,
+                    Err(e) => {
+                        log::warn!("Leader election in group {} failed: {}", group, e);
+                        false
+                    }
+                };
+
+                if election.is_leader.swap(leader, Ordering::SeqCst) != leader {
+                    let message = if leader {
+                        format!("Agent {} became leader of group {}", election.holder, group)
+                    } else {
+                        format!("Agent {} stepped down as leader of group {}", election.holder, group)
+                    };
+                    log::info!("{}", message);
+                    bus.publish(AgentEvent::Alert {
+                        severity: "warning".to_string(),
+                        source: "leader_election".to_string(),
+                        message,
+                        timestamp: chrono::Utc::now().to_rfc3339(),
+                    });
+                }
+
+                tokio::time::sleep(election.ttl / 3).await;
+            }
+        });
+    }
+
+    pub fn status(&self) -> LeaderStatus {
+        LeaderStatus {
+            ha_enabled: self.group.is_some(),
+            group: self.group.clone(),
+            holder: self.holder.clone(),
+            is_leader: self.is_leader(),
+        }
+    }
+}
+
+// API Endpoints
+#[get("/agent/leader")]
+pub fn get_leader_status(election: &State<LeaderElection>) -> Json<LeaderStatus> {
+    Json(election.status())
+}
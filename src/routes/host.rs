@@ -0,0 +1,169 @@
+use rocket::post;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use bollard::container::StopContainerOptions;
+
+use crate::agent::Agent;
+use super::instances::{AppInstanceRequest, AppManager};
+use super::access::{ApiKey, Mutation};
+pub use omniagent_client::models::host::{ShutdownHostRequest, StoppedInstance, ShutdownReport};
+
+/// Grace period for instances that don't set `stop_grace_period`
+const DEFAULT_GRACE_PERIOD: i64 = 30;
+
+/// Orders instances so each is stopped before anything it depends on. Instances caught in a
+/// dependency cycle are stopped last, in name order.
+fn stop_order(specs: Vec<(String, AppInstanceRequest)>) -> Vec<(String, AppInstanceRequest)> {
+    let names: HashSet<String> = specs.iter().map(|(_, spec)| spec.name().to_string()).collect();
+    // Number of managed instances that still need each instance running
+    let mut dependents: HashMap<String, usize> = HashMap::new();
+    for (_, spec) in &specs {
+        for dependency in spec.depends_on().iter().filter(|d| names.contains(*d)) {
+            *dependents.entry(dependency.clone()).or_default() += 1;
+        }
+    }
+
+    let mut remaining = specs;
+    remaining.sort_by(|a, b| a.1.name().cmp(b.1.name()));
+    let mut ordered = Vec::with_capacity(remaining.len());
+
+    while let Some(index) = remaining.iter().position(|(_, spec)| dependents.get(spec.name()).copied().unwrap_or(0) == 0) {
+        let (id, spec) = remaining.remove(index);
+        for dependency in spec.depends_on() {
+            if let Some(count) = dependents.get_mut(dependency) {
+                *count = count.saturating_sub(1);
+            }
+        }
+        ordered.push((id, spec));
+    }
+
+    if !remaining.is_empty() {
+        log::warn!("Dependency cycle among {} instances; stopping them in name order", remaining.len());
+        ordered.extend(remaining);
+    }
+    ordered
+}
+
+async fn notify_orchestrator(agent_id: &str, report: &ShutdownReport) -> bool {
+    let Ok(orchestrator) = std::env::var("OMNI_ORCHESTRATOR_URL") else {
+        return false;
+    };
+    let url = format!("{}/agents/{}/shutdown", orchestrator.trim_end_matches('/'), agent_id);
+
+    match crate::crypto::http_client().post(&url).json(report).timeout(Duration::from_secs(10)).send().await {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) => {
+            log::warn!("Orchestrator rejected shutdown notice: {}", response.status());
+            false
+        },
+        Err(e) => {
+            log::error!("Failed to notify orchestrator of shutdown: {}", e);
+            false
+        }
+    }
+}
+
+/// Powers off after a short delay so the HTTP response can still be delivered
+fn schedule_power_off() {
+    tokio::spawn(async {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        log::warn!("Powering off host");
+
+        #[cfg(windows)]
+        let result = tokio::process::Command::new("shutdown").args(["/s", "/t", "0"]).status().await;
+        #[cfg(not(windows))]
+        let result = tokio::process::Command::new("shutdown").args(["-h", "now"]).status().await;
+
+        match result {
+            Ok(status) if !status.success() => log::warn!("Host power-off exited with {}", status),
+            Err(e) => log::error!("Failed to power off host: {}", e),
+            Ok(_) => {}
+        }
+    });
+}
+
+// API Endpoints
+#[post("/agent/shutdown-host", data = "<shutdown_req>")]
+pub async fn shutdown_host(shutdown_req: Option<Json<ShutdownHostRequest>>, app_manager: &State<AppManager>, agent: &State<Agent>, _mutation: Mutation, _key: ApiKey) -> Result<Json<ShutdownReport>, String> {
+    let shutdown_req = shutdown_req.map(|req| req.into_inner()).unwrap_or_default();
+    let default_grace_period = shutdown_req.default_grace_period.unwrap_or(DEFAULT_GRACE_PERIOD);
+
+    let specs = app_manager.managed_specs().await?;
+    let mut stopped = Vec::new();
+
+    for (id, spec) in stop_order(specs) {
+        let grace_period = spec.stop_grace_period().unwrap_or(default_grace_period);
+        log::info!("Stopping {} ({}s grace period)", spec.name(), grace_period);
+
+        let error = match app_manager.docker().stop_container(&id, Some(StopContainerOptions { t: grace_period })).await {
+            Ok(_) => None,
+            // 304: already stopped
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 304, .. }) => None,
+            Err(e) => {
+                log::error!("Failed to stop {}: {}", spec.name(), e);
+                Some(e.to_string())
+            }
+        };
+
+        stopped.push(StoppedInstance {
+            id,
+            name: spec.name().to_string(),
+            grace_period,
+            error,
+        });
+    }
+
+    // State store writes are synchronous, so the audit entry is the last thing to flush
+    let agent_id = agent.id().to_string();
+    app_manager.audit("shutdown_host", &agent_id).await;
+
+    let mut report = ShutdownReport {
+        agent_id: agent_id.clone(),
+        stopped,
+        orchestrator_notified: false,
+        powering_off: shutdown_req.power_off.unwrap_or(false),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    };
+    report.orchestrator_notified = notify_orchestrator(&agent_id, &report).await;
+
+    if report.powering_off {
+        schedule_power_off();
+    }
+
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json;
+
+    fn spec(name: &str, depends_on: &[&str]) -> (String, AppInstanceRequest) {
+        let spec = json::from_value(json::json!({ "name": name, "image": "busybox", "depends_on": depends_on })).unwrap();
+        (format!("id-{}", name), spec)
+    }
+
+    fn names(ordered: Vec<(String, AppInstanceRequest)>) -> Vec<String> {
+        ordered.into_iter().map(|(_, spec)| spec.name().to_string()).collect()
+    }
+
+    #[test]
+    fn stops_dependents_before_their_dependencies() {
+        let specs = vec![spec("db", &[]), spec("api", &["db", "cache"]), spec("cache", &[]), spec("web", &["api"])];
+        assert_eq!(names(stop_order(specs)), ["web", "api", "cache", "db"]);
+    }
+
+    #[test]
+    fn ignores_dependencies_on_unmanaged_instances() {
+        let specs = vec![spec("b", &["external"]), spec("a", &[])];
+        assert_eq!(names(stop_order(specs)), ["a", "b"]);
+    }
+
+    #[test]
+    fn stops_a_cycle_last_in_name_order() {
+        let specs = vec![spec("y", &["x"]), spec("x", &["y"]), spec("solo", &[]), spec("front", &["x"])];
+        assert_eq!(names(stop_order(specs)), ["front", "solo", "x", "y"]);
+    }
+}
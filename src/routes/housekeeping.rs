@@ -0,0 +1,151 @@
+use rocket::get;
+use rocket::serde::json::{self, Json};
+use rocket::State;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::state_store::{self, StateStore};
+use super::ha::LeaderElection;
+use super::checks;
+use super::usage;
+use super::instances::{AppInstance, AppInstanceRequest, AppManager};
+pub use omniagent_client::models::housekeeping::{HousekeepingStatus, HousekeepingReport};
+
+/// Periodic upkeep of the state store: compaction, removal of records for containers that
+/// no longer exist, of audit records older than `OMNI_AUDIT_RETENTION_DAYS` (default 365),
+/// of expired check history and of expired usage records, whose earlier days are also
+/// rolled up into daily totals, and integrity checks. Runs every
+/// `OMNI_HOUSEKEEPING_INTERVAL` seconds (default 3600, minimum 60).
+#[derive(Clone)]
+pub struct Housekeeping {
+    interval: Duration,
+    last_run: Arc<Mutex<Option<HousekeepingReport>>>,
+}
+
+/// Checks that every collection can be read, that instance and spec records parse and that
+/// they come in pairs
+async fn check_integrity(store: &dyn StateStore) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut keys: Vec<BTreeSet<String>> = Vec::new();
+
+    for collection in [state_store::INSTANCES, state_store::SPECS] {
+        let records = match store.list(collection).await {
+            Ok(records) => records,
+            Err(e) => {
+                errors.push(format!("Failed to list {}: {}", collection, e));
+                keys.push(BTreeSet::new());
+                continue;
+            }
+        };
+        for (id, record) in &records {
+            let parsed = if collection == state_store::INSTANCES {
+                json::from_value::<AppInstance>(record.clone()).err()
+            } else {
+                json::from_value::<AppInstanceRequest>(record.clone()).err()
+            };
+            if let Some(e) = parsed {
+                errors.push(format!("Unreadable {} record {}: {}", collection, id, e));
+            }
+        }
+        keys.push(records.into_iter().map(|(id, _)| id).collect());
+    }
+
+    for id in keys[0].difference(&keys[1]) {
+        errors.push(format!("Instance {} has no spec record", id));
+    }
+    for id in keys[1].difference(&keys[0]) {
+        errors.push(format!("Spec {} has no instance record", id));
+    }
+
+    for collection in state_store::all_collections().iter().filter(|collection| ![state_store::INSTANCES, state_store::SPECS].contains(collection)) {
+        if let Err(e) = store.list(collection).await {
+            errors.push(format!("Failed to list {}: {}", collection, e));
+        }
+    }
+    errors
+}
+
+impl Housekeeping {
+    pub fn from_env() -> Self {
+        let interval = std::env::var("OMNI_HOUSEKEEPING_INTERVAL").ok()
+            .and_then(|secs| secs.parse().ok())
+            .filter(|secs| *secs >= 60)
+            .unwrap_or(3600);
+
+        Housekeeping {
+            interval: Duration::from_secs(interval),
+            last_run: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Orphans are only pruned by the leader: followers share its store and would race it
+    async fn run(&self, app_manager: &AppManager, election: &LeaderElection) -> HousekeepingReport {
+        let mut report = HousekeepingReport {
+            started_at: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        };
+
+        report.compaction = match app_manager.store().compact().await {
+            Ok(summary) => summary,
+            Err(e) => format!("Compaction failed: {}", e),
+        };
+
+        if election.is_leader() {
+            match app_manager.prune_orphans().await {
+                Ok(orphans) => report.orphans_removed = orphans,
+                Err(e) => log::error!("Failed to prune orphaned records: {}", e),
+            }
+            match usage::prune(app_manager.store()).await {
+                Ok(pruned) => report.usage_records_pruned = pruned,
+                Err(e) => log::error!("Failed to prune usage records: {}", e),
+            }
+            let audit_days = std::env::var("OMNI_AUDIT_RETENTION_DAYS").ok()
+                .and_then(|days| days.parse().ok())
+                .unwrap_or(365);
+            let cutoff = chrono::Utc::now() - chrono::Duration::days(audit_days);
+            match state_store::prune_appended(app_manager.store(), state_store::AUDIT, cutoff).await {
+                Ok(pruned) => report.audit_records_pruned = pruned,
+                Err(e) => log::error!("Failed to prune audit records: {}", e),
+            }
+            match checks::prune(app_manager.store()).await {
+                Ok(pruned) => report.check_history_pruned = pruned,
+                Err(e) => log::error!("Failed to prune check history: {}", e),
+            }
+        }
+
+        report.integrity_errors = check_integrity(app_manager.store()).await;
+        report.finished_at = chrono::Utc::now().to_rfc3339();
+        report
+    }
+
+    pub fn start(&self, app_manager: AppManager, election: LeaderElection) {
+        let housekeeping = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(housekeeping.interval).await;
+
+                let report = housekeeping.run(&app_manager, &election).await;
+                if !report.orphans_removed.is_empty() {
+                    log::info!("Housekeeping removed records of {} missing containers", report.orphans_removed.len());
+                }
+                for error in &report.integrity_errors {
+                    log::warn!("State store integrity: {}", error);
+                }
+                *housekeeping.last_run.lock().unwrap() = Some(report);
+            }
+        });
+    }
+}
+
+// API Endpoints
+#[get("/agent/maintenance/status")]
+pub fn get_housekeeping_status(housekeeping: &State<Housekeeping>, app_manager: &State<AppManager>, election: &State<LeaderElection>) -> Json<HousekeepingStatus> {
+    Json(HousekeepingStatus {
+        backend: app_manager.store().backend().to_string(),
+        interval_secs: housekeeping.interval.as_secs(),
+        is_leader: election.is_leader(),
+        last_run: housekeeping.last_run.lock().unwrap().clone(),
+    })
+}
@@ -0,0 +1,595 @@
+use rocket::{get, post, put, FromForm};
+use rocket::data::{Data, ToByteUnit};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::{self, Json};
+use rocket::State;
+use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use bollard::Docker;
+use bollard::image::{BuildImageOptions, CreateImageOptions, TagImageOptions};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use futures::stream::{BoxStream, StreamExt};
+use tokio::sync::watch;
+
+use crate::state_store::{self, StateStore};
+use super::instances::AppManager;
+use super::registry_cache::RegistryCache;
+use super::layer_sharing::LayerSharing;
+use super::access::{ApiKey, Mutation};
+use super::disk::DiskSpace;
+use super::limits::{Create, Slot};
+pub use omniagent_client::models::images::{PreloadRequest, PreloadJob, ImagePullProgress, PinnedImages, ImageMetadata};
+
+fn image_metadata(image: &str, source: &str, digest: Option<String>, config: bollard::models::ImageConfig) -> ImageMetadata {
+    let mut exposed_ports: Vec<String> = config.exposed_ports.unwrap_or_default().into_keys().collect();
+    exposed_ports.sort();
+    let mut volumes: Vec<String> = config.volumes.unwrap_or_default().into_keys().collect();
+    volumes.sort();
+
+    ImageMetadata {
+        image: image.to_string(),
+        source: source.to_string(),
+        digest,
+        exposed_ports,
+        env: config.env.unwrap_or_default().iter()
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+        entrypoint: config.entrypoint,
+        cmd: config.cmd,
+        volumes,
+        labels: config.labels.unwrap_or_default(),
+        working_dir: config.working_dir.filter(|dir| !dir.is_empty()),
+        user: config.user.filter(|user| !user.is_empty()),
+    }
+}
+
+/// A pull in progress that later requesters of the same image wait on
+struct InFlightPull {
+    done: Shared<BoxFuture<'static, Result<(), String>>>,
+    progress: watch::Receiver<f64>,
+}
+
+/// Tracks background image pulls and the images pinned against cleanup. Pins are saved in
+/// the state store so cleanup keeps honoring them after a restart.
+pub struct ImageManager {
+    pinned: Arc<Mutex<HashSet<String>>>,
+    jobs: Arc<Mutex<HashMap<String, PreloadJob>>>,
+    pulls: Arc<Mutex<HashMap<String, InFlightPull>>>,
+    layers: LayerSharing,
+}
+
+impl ImageManager {
+    pub fn new() -> Self {
+        ImageManager {
+            pinned: Arc::new(Mutex::new(HashSet::new())),
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            pulls: Arc::new(Mutex::new(HashMap::new())),
+            layers: LayerSharing::from_env(),
+        }
+    }
+
+    /// Reads the pinned images saved in the state store; returns how many there are
+    pub async fn load_pinned(&self, store: &dyn StateStore) -> Result<usize, String> {
+        let pinned: Vec<String> = store.list(state_store::PINNED_IMAGES).await?.into_iter()
+            .map(|(image, _)| image)
+            .collect();
+        let count = pinned.len();
+        self.pinned.lock().unwrap().extend(pinned);
+        Ok(count)
+    }
+
+    /// Pins `images` alongside those already pinned
+    async fn pin(&self, store: &dyn StateStore, images: &[String]) -> Result<(), String> {
+        let record = json::json!({ "pinned_at": chrono::Utc::now().to_rfc3339() });
+        for image in images {
+            store.put(state_store::PINNED_IMAGES, image, &record).await?;
+        }
+        self.pinned.lock().unwrap().extend(images.iter().cloned());
+        Ok(())
+    }
+
+    /// Replaces the pinned images
+    async fn set_pinned(&self, store: &dyn StateStore, pinned: HashSet<String>) -> Result<(), String> {
+        let current = self.pinned.lock().unwrap().clone();
+        let unpinned: Vec<String> = current.difference(&pinned).cloned().collect();
+        store.delete_many(state_store::PINNED_IMAGES, &unpinned).await?;
+        let added: Vec<String> = pinned.difference(&current).cloned().collect();
+        self.pin(store, &added).await?;
+        *self.pinned.lock().unwrap() = pinned;
+        Ok(())
+    }
+
+    /// The blob cache shared with peer agents
+    pub fn layers(&self) -> &LayerSharing {
+        &self.layers
+    }
+
+    /// Pulls `image`, or joins the pull of it already running, so concurrent creates and
+    /// preloads of one image share a single download. The pull runs to completion even if
+    /// every requester goes away.
+    pub async fn pull(&self, docker: &Docker, cache: &Arc<RegistryCache>, image: &str, on_progress: impl Fn(f64)) -> Result<(), String> {
+        let key = normalize_image_ref(image);
+        let (mut done, mut progress) = {
+            let mut pulls = self.pulls.lock().unwrap();
+            match pulls.get(&key) {
+                Some(pull) => (pull.done.clone(), pull.progress.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(0.0);
+                    let (docker, cache, layers, pulls_ref, image) = (docker.clone(), cache.clone(), self.layers.clone(), self.pulls.clone(), key.clone());
+                    // The entry is inserted below before this lock is released, so the task
+                    // can't remove it first
+                    let task = tokio::spawn(async move {
+                        let result = pull_image(&docker, &cache, &layers, &image, |percent| {
+                            let _ = sender.send(percent);
+                        }).await;
+                        pulls_ref.lock().unwrap().remove(&image);
+                        result
+                    });
+                    let done = async move {
+                        task.await.map_err(|e| format!("Pull task failed: {}", e))?
+                    }.boxed().shared();
+                    pulls.insert(key, InFlightPull { done: done.clone(), progress: receiver.clone() });
+                    (done, receiver)
+                }
+            }
+        };
+
+        loop {
+            tokio::select! {
+                result = &mut done => return result,
+                changed = progress.changed() => match changed {
+                    Ok(()) => on_progress(*progress.borrow()),
+                    Err(_) => return done.await,
+                },
+            }
+        }
+    }
+
+    /// Pulls `image` unless Docker already has it
+    pub async fn ensure_image(&self, docker: &Docker, cache: &Arc<RegistryCache>, image: &str) -> Result<(), String> {
+        if docker.inspect_image(image).await.is_ok() {
+            return Ok(());
+        }
+        self.pull(docker, cache, image, |_| {}).await
+            .map_err(|e| format!("Failed to pull image {}: {}", image, e))
+    }
+
+    /// Whether cleanup must keep this image
+    pub fn is_pinned(&self, image: &str) -> bool {
+        self.pinned.lock().unwrap().contains(&normalize_image_ref(image))
+    }
+
+    fn update_image(&self, job_id: &str, index: usize, update: impl FnOnce(&mut ImagePullProgress)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(job_id) {
+            if let Some(progress) = job.images.get_mut(index) {
+                update(progress);
+            }
+        }
+    }
+}
+
+impl Default for ImageManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends `:latest` to references without a tag or digest, as the Docker CLI does
+pub fn normalize_image_ref(image: &str) -> String {
+    let last_segment = image.rsplit('/').next().unwrap_or(image);
+    if image.contains('@') || last_segment.contains(':') {
+        image.to_string()
+    } else {
+        format!("{}:latest", image)
+    }
+}
+
+/// Pulls an image, reporting overall download progress (0-100) as layers arrive
+async fn pull_with_progress(docker: &Docker, image: &str, on_progress: impl Fn(f64)) -> Result<(), String> {
+    let options = Some(CreateImageOptions {
+        from_image: image.to_string(),
+        ..Default::default()
+    });
+
+    // Per-layer (current, total) byte counts
+    let mut layers: HashMap<String, (i64, i64)> = HashMap::new();
+    let mut stream = docker.create_image(options, None, None);
+
+    while let Some(item) = stream.next().await {
+        let info = item.map_err(|e| e.to_string())?;
+        if let Some(message) = info.error {
+            return Err(message);
+        }
+        if let (Some(layer), Some(detail)) = (info.id, info.progress_detail) {
+            if let (Some(current), Some(total)) = (detail.current, detail.total) {
+                layers.insert(layer, (current, total));
+            }
+        }
+        let (current, total) = layers.values()
+            .fold((0i64, 0i64), |(c, t), (lc, lt)| (c + lc, t + lt));
+        if total > 0 {
+            on_progress((current as f64 / total as f64 * 100.0).min(100.0));
+        }
+    }
+
+    Ok(())
+}
+
+/// Pulls from peer agents when any are configured, then through the registry cache when it
+/// serves the image, tagging the result with the original reference. Falls back to pulling
+/// directly if neither is available.
+async fn pull_image(docker: &Docker, cache: &RegistryCache, layers: &LayerSharing, image: &str, on_progress: impl Fn(f64)) -> Result<(), String> {
+    if layers.is_enabled() && !image.contains('@') {
+        match layers.pull(docker, image, &on_progress).await {
+            Ok(()) => return Ok(()),
+            Err(e) => eprintln!("Peer pull of {} failed, pulling from the registry: {}", image, e),
+        }
+    }
+
+    if let Some(cached) = cache.rewrite(image) {
+        match pull_with_progress(docker, &cached, &on_progress).await {
+            Ok(()) => {
+                let (repo, tag) = image.rsplit_once(':').unwrap_or((image, "latest"));
+                return docker.tag_image(&cached, Some(TagImageOptions { repo, tag })).await
+                    .map_err(|e| format!("Failed to tag {} as {}: {}", cached, image, e));
+            },
+            Err(e) => eprintln!("Registry cache pull of {} failed, pulling directly: {}", cached, e),
+        }
+    }
+
+    pull_with_progress(docker, image, on_progress).await
+}
+
+/// Registry host, repository and tag or digest of a normalized reference, using Docker Hub
+/// conventions for references without a registry
+pub fn parse_image_ref(image: &str) -> (String, String, String) {
+    let (name, reference) = match image.split_once('@') {
+        Some((name, digest)) => (name, digest.to_string()),
+        None => {
+            let last_segment = image.rsplit('/').next().unwrap_or(image);
+            let (_, tag) = last_segment.rsplit_once(':').unwrap_or((last_segment, "latest"));
+            (image.strip_suffix(&format!(":{}", tag)).unwrap_or(image), tag.to_string())
+        }
+    };
+
+    match name.split_once('/') {
+        Some((registry, repo)) if registry.contains('.') || registry.contains(':') || registry == "localhost" => {
+            (registry.to_string(), repo.to_string(), reference)
+        },
+        Some(_) => ("registry-1.docker.io".to_string(), name.to_string(), reference),
+        None => ("registry-1.docker.io".to_string(), format!("library/{}", name), reference),
+    }
+}
+
+/// Sends a registry request, retrying once with an anonymous bearer token if challenged
+async fn registry_get(client: &reqwest::Client, url: &str, accept: &str, token: &mut Option<String>) -> Result<reqwest::Response, String> {
+    let send = |token: &Option<String>| {
+        let mut request = client.get(url).header("Accept", accept).timeout(std::time::Duration::from_secs(30));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send()
+    };
+
+    let response = send(token).await.map_err(|e| e.to_string())?;
+    if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+        return Ok(response);
+    }
+
+    // WWW-Authenticate: Bearer realm="...",service="...",scope="..."
+    let challenge = response.headers().get("WWW-Authenticate")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| "Registry requires credentials".to_string())?;
+    let params: HashMap<&str, &str> = challenge.split(',')
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim_matches('"')))
+        .collect();
+    let realm = params.get("realm").ok_or_else(|| "Registry auth challenge has no realm".to_string())?;
+    let query: Vec<(&str, &str)> = ["service", "scope"].iter()
+        .filter_map(|key| params.get(key).map(|value| (*key, *value)))
+        .collect();
+
+    let auth: rocket::serde::json::Value = client.get(*realm).query(&query).send().await
+        .map_err(|e| format!("Failed to get registry token: {}", e))?
+        .json().await
+        .map_err(|e| format!("Failed to read registry token: {}", e))?;
+    *token = auth.get("token").or_else(|| auth.get("access_token"))
+        .and_then(|token| token.as_str())
+        .map(|token| token.to_string());
+
+    send(token).await.map_err(|e| e.to_string())
+}
+
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, application/vnd.docker.distribution.manifest.list.v2+json, application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+
+/// Fetches a manifest or index and the digest the registry reports for it
+async fn fetch_manifest(client: &reqwest::Client, base: &str, reference: &str, token: &mut Option<String>) -> Result<(rocket::serde::json::Value, Option<String>), String> {
+    let url = format!("{}/manifests/{}", base, reference);
+    let response = registry_get(client, &url, MANIFEST_TYPES, token).await?;
+    if !response.status().is_success() {
+        return Err(format!("Registry returned {} for {}", response.status(), url));
+    }
+    let digest = response.headers().get("Docker-Content-Digest")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let manifest = response.json().await.map_err(|e| e.to_string())?;
+    Ok((manifest, digest))
+}
+
+/// The image's manifest for the agent's own platform, resolving multi-platform indexes, with
+/// the registry's repository URL, the token that authorized the request and the manifest
+/// digest. Only anonymous access is supported.
+pub async fn platform_manifest(client: &reqwest::Client, image: &str) -> Result<(String, rocket::serde::json::Value, Option<String>, Option<String>), String> {
+    let (registry, repo, reference) = parse_image_ref(image);
+    let base = format!("https://{}/v2/{}", registry, repo);
+    let mut token = None;
+
+    let (mut manifest, mut digest) = fetch_manifest(client, &base, &reference, &mut token).await?;
+
+    if let Some(manifests) = manifest.get("manifests").and_then(|m| m.as_array()) {
+        let architecture = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            other => other,
+        };
+        let platform_digest = manifests.iter()
+            .find(|m| m["platform"]["os"] == "linux" && m["platform"]["architecture"] == architecture)
+            .and_then(|m| m["digest"].as_str())
+            .ok_or_else(|| format!("{} has no linux/{} image", image, architecture))?
+            .to_string();
+        manifest = fetch_manifest(client, &base, &platform_digest, &mut token).await?.0;
+        digest = digest.or(Some(platform_digest));
+    }
+
+    Ok((base, manifest, token, digest))
+}
+
+/// Reads an image's config blob from its registry without pulling layers
+async fn fetch_remote_metadata(image: &str) -> Result<ImageMetadata, String> {
+    let client = crate::crypto::http_client();
+    let (base, manifest, mut token, digest) = platform_manifest(&client, image).await?;
+
+    let config_digest = manifest["config"]["digest"].as_str()
+        .ok_or_else(|| format!("Manifest for {} has no config", image))?;
+    let response = registry_get(&client, &format!("{}/blobs/{}", base, config_digest), "*/*", &mut token).await?;
+    if !response.status().is_success() {
+        return Err(format!("Registry returned {} for the config of {}", response.status(), image));
+    }
+    let blob: rocket::serde::json::Value = response.json().await.map_err(|e| e.to_string())?;
+    let config: bollard::models::ImageConfig = rocket::serde::json::from_value(blob.get("config").cloned().unwrap_or_default())
+        .map_err(|e| format!("Failed to parse config of {}: {}", image, e))?;
+
+    Ok(image_metadata(image, "registry", digest, config))
+}
+
+async fn run_preload(docker: Docker, images: Arc<ImageManager>, cache: Arc<RegistryCache>, job_id: String, refs: Vec<String>) {
+    let mut failed = false;
+
+    for (index, image) in refs.iter().enumerate() {
+        images.update_image(&job_id, index, |p| p.status = "pulling".to_string());
+
+        let result = images.pull(&docker, &cache, image, |percent| {
+            images.update_image(&job_id, index, |p| p.percent = percent);
+        }).await;
+
+        match result {
+            Err(e) => {
+                eprintln!("Failed to preload image {}: {}", image, e);
+                failed = true;
+                images.update_image(&job_id, index, |p| {
+                    p.status = "failed".to_string();
+                    p.error = Some(e);
+                });
+            },
+            Ok(()) => images.update_image(&job_id, index, |p| {
+                p.status = "complete".to_string();
+                p.percent = 100.0;
+            }),
+        }
+    }
+
+    if let Some(job) = images.jobs.lock().unwrap().get_mut(&job_id) {
+        job.status = if failed { "failed" } else { "complete" }.to_string();
+        job.finished_at = Some(chrono::Utc::now().to_string());
+    }
+}
+
+// API Endpoints
+#[post("/images/preload", format = "json", data = "<preload_req>")]
+pub async fn preload_images(preload_req: Json<PreloadRequest>, app_manager: &State<AppManager>, image_manager: &State<Arc<ImageManager>>, registry_cache: &State<Arc<RegistryCache>>, _mutation: Mutation, _key: ApiKey) -> Result<Json<PreloadJob>, String> {
+    if preload_req.images.is_empty() {
+        return Err("No images to preload".to_string());
+    }
+
+    let refs: Vec<String> = preload_req.images.iter().map(|image| normalize_image_ref(image)).collect();
+
+    if preload_req.pin.unwrap_or(false) {
+        image_manager.pin(app_manager.store(), &refs).await
+            .map_err(|e| format!("Failed to pin images: {}", e))?;
+    }
+
+    let job = PreloadJob {
+        id: uuid::Uuid::new_v4().to_string(),
+        status: "running".to_string(),
+        created_at: chrono::Utc::now().to_string(),
+        finished_at: None,
+        images: refs.iter().map(|image| ImagePullProgress {
+            image: image.clone(),
+            status: "pending".to_string(),
+            percent: 0.0,
+            error: None,
+        }).collect(),
+    };
+    image_manager.jobs.lock().unwrap().insert(job.id.clone(), job.clone());
+
+    tokio::spawn(run_preload(
+        app_manager.docker().clone(),
+        image_manager.inner().clone(),
+        registry_cache.inner().clone(),
+        job.id.clone(),
+        refs,
+    ));
+
+    Ok(Json(job))
+}
+
+#[get("/images/preload")]
+pub fn list_preload_jobs(image_manager: &State<Arc<ImageManager>>) -> Json<Vec<PreloadJob>> {
+    let mut jobs: Vec<PreloadJob> = image_manager.jobs.lock().unwrap().values().cloned().collect();
+    jobs.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Json(jobs)
+}
+
+#[get("/images/preload/<job_id>")]
+pub fn get_preload_job(job_id: String, image_manager: &State<Arc<ImageManager>>) -> Option<Json<PreloadJob>> {
+    image_manager.jobs.lock().unwrap().get(&job_id).cloned().map(Json)
+}
+
+/// The name must be URL-encoded, e.g. `/images/library%2Fnginx%3A1.27/metadata`. Ranked
+/// after `/images/preload/<job_id>`, which would otherwise collide with it.
+#[get("/images/<name>/metadata", rank = 2)]
+pub async fn get_image_metadata(name: String, app_manager: &State<AppManager>) -> Result<Json<ImageMetadata>, String> {
+    let image = normalize_image_ref(&name);
+    if let Ok(inspect) = app_manager.docker().inspect_image(&image).await {
+        let digest = inspect.repo_digests.unwrap_or_default().into_iter().next()
+            .and_then(|digest| digest.split_once('@').map(|(_, digest)| digest.to_string()));
+        return Ok(Json(image_metadata(&image, "local", digest, inspect.config.unwrap_or_default())));
+    }
+
+    fetch_remote_metadata(&image).await
+        .map(Json)
+        .map_err(|e| format!("Failed to get metadata for {}: {}", image, e))
+}
+
+#[get("/images/pinned")]
+pub fn list_pinned_images(image_manager: &State<Arc<ImageManager>>) -> Json<PinnedImages> {
+    let mut images: Vec<String> = image_manager.pinned.lock().unwrap().iter().cloned().collect();
+    images.sort();
+    Json(PinnedImages { images })
+}
+
+#[put("/images/pinned", format = "json", data = "<pinned_req>")]
+pub async fn set_pinned_images(pinned_req: Json<PinnedImages>, app_manager: &State<AppManager>, image_manager: &State<Arc<ImageManager>>, _mutation: Mutation, _key: ApiKey) -> Result<Json<PinnedImages>, String> {
+    let pinned: HashSet<String> = pinned_req.images.iter().map(|image| normalize_image_ref(image)).collect();
+    let mut images: Vec<String> = pinned.iter().cloned().collect();
+    images.sort();
+    image_manager.set_pinned(app_manager.store(), pinned).await
+        .map_err(|e| format!("Failed to save pinned images: {}", e))?;
+    Ok(Json(PinnedImages { images }))
+}
+
+/// Query parameters of an image build
+#[derive(FromForm)]
+pub struct BuildQuery {
+    tag: String,
+    dockerfile: Option<String>,
+    /// `KEY=VALUE` build arguments
+    buildarg: Vec<String>,
+    pull: Option<bool>,
+    nocache: Option<bool>,
+}
+
+/// Builds an image from an uploaded tar build context (a Dockerfile plus the files it
+/// copies) of up to `OMNI_BUILD_MAX_MB` (default 512), streaming Docker's build messages as
+/// `build` server-sent events. Repeat `buildarg=KEY=VALUE` for build arguments. A failed
+/// build ends with an `error` event.
+#[post("/images/build?<query..>", data = "<context>")]
+pub async fn build_image(query: BuildQuery, context: Data<'_>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey, _disk: DiskSpace, slot: Slot<Create>) -> Result<EventStream<BoxStream<'static, Event>>, String> {
+    let BuildQuery { tag, dockerfile, buildarg, pull, nocache } = query;
+    let max_mb = std::env::var("OMNI_BUILD_MAX_MB").ok()
+        .and_then(|mb| mb.parse::<u64>().ok())
+        .filter(|mb| *mb > 0)
+        .unwrap_or(512);
+    let context = context.open(max_mb.mebibytes()).into_bytes().await
+        .map_err(|e| format!("Failed to read build context: {}", e))?;
+    if !context.is_complete() {
+        return Err(format!("Build context exceeds {} MiB", max_mb));
+    }
+
+    let options = BuildImageOptions {
+        dockerfile: dockerfile.unwrap_or_else(|| "Dockerfile".to_string()),
+        t: tag.clone(),
+        buildargs: buildarg.iter()
+            .filter_map(|arg| arg.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+        pull: pull.unwrap_or(false),
+        nocache: nocache.unwrap_or(false),
+        rm: true,
+        forcerm: true,
+        ..Default::default()
+    };
+
+    // The build runs to completion even if the client goes away, holding its create slot
+    let docker = app_manager.docker().clone();
+    let (sender, receiver) = tokio::sync::mpsc::channel(64);
+    tokio::spawn(async move {
+        let _slot = slot;
+        let mut stream = docker.build_image(options, None, Some(context.into_inner().into()));
+        while let Some(info) = stream.next().await {
+            let failure = match info {
+                Ok(info) => match info.error.clone() {
+                    Some(message) => Some(message),
+                    None => {
+                        // Docker's build message, minus the fields only errors use
+                        let message = rocket::serde::json::json!({
+                            "stream": info.stream,
+                            "status": info.status,
+                            "id": info.id,
+                            "progress": info.progress,
+                            "aux": info.aux,
+                        });
+                        let _ = sender.send(Event::json(&message).event("build")).await;
+                        None
+                    }
+                },
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(message) = failure {
+                log::warn!("Build of {} failed: {}", tag, message);
+                let _ = sender.send(Event::data(message).event("error")).await;
+                return;
+            }
+        }
+        log::info!("Built image {}", tag);
+    });
+
+    let events = futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|event| (event, receiver))
+    });
+    Ok(EventStream::from(events.boxed()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_store::FileStore;
+
+    #[test]
+    fn parses_a_build_query() {
+        let query: BuildQuery = rocket::form::Form::parse("tag=app:1&buildarg=A=1&buildarg=B=x=y&pull=true&unknown=1").unwrap();
+        assert_eq!(query.tag, "app:1");
+        assert_eq!(query.buildarg, ["A=1", "B=x=y"]);
+        assert_eq!((query.dockerfile, query.pull, query.nocache), (None, Some(true), None));
+        assert!(rocket::form::Form::<BuildQuery>::parse("pull=true").is_err());
+    }
+
+    #[tokio::test]
+    async fn pins_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("omni-pins-{}", uuid::Uuid::new_v4()));
+        let store = FileStore::open(dir.clone()).await.unwrap();
+
+        let images = ImageManager::new();
+        images.pin(&store, &["nginx:1.27".to_string()]).await.unwrap();
+        images.set_pinned(&store, HashSet::from(["redis:7".to_string(), "postgres:16".to_string()])).await.unwrap();
+
+        let restarted = ImageManager::default();
+        assert_eq!(restarted.load_pinned(&store).await.unwrap(), 2);
+        assert!(restarted.is_pinned("redis:7"));
+        assert!(restarted.is_pinned("postgres:16"));
+        assert!(!restarted.is_pinned("nginx:1.27"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -104,6 +104,28 @@ pub fn collect_routes(rocket: &Rocket<Build>) {
     }
 }
 
+/// Management UI assets, compiled into the binary so the UI works without network access
+const UI_HTML: &str = include_str!("../ui/index.html");
+const UI_SCRIPT: &str = include_str!("../ui/app.js");
+const UI_STYLES: &str = include_str!("../ui/app.css");
+
+/// Minimal management UI for operating this agent when the central dashboard is unreachable:
+/// instances with start and stop, live logs, and CPU and memory sparklines
+#[get("/ui")]
+pub fn ui() -> content::RawHtml<&'static str> {
+    content::RawHtml(UI_HTML)
+}
+
+#[get("/ui/app.js")]
+pub fn ui_script() -> content::RawJavaScript<&'static str> {
+    content::RawJavaScript(UI_SCRIPT)
+}
+
+#[get("/ui/app.css")]
+pub fn ui_styles() -> content::RawCss<&'static str> {
+    content::RawCss(UI_STYLES)
+}
+
 /// Routes listing endpoint providing HTML representation of routes
 #[get("/")]
 pub fn index() -> content::RawHtml<String> {
@@ -325,6 +347,7 @@ pub fn index() -> content::RawHtml<String> {
     <div class="container">
         <h1>Welcome to OmniAgent</h1>
         <p>OmniAgent is a distributed system for managing application instances within a given worker on the OmniCloud platform. Please refer to the API documentation below to get started!</p>
+        <p><a href="/ui">Open the management UI</a></p>
     </div>
 
     <div class="routes-section">
@@ -1,62 +1,181 @@
-use rocket::{delete, get, post, patch, put};
-use rocket::serde::{Serialize, Deserialize, json::Json};
+use rocket::{delete, get, post, patch, put, Responder};
+use rocket::serde::json::Json;
 use rocket::State;
-use rocket::FromForm;
+use rocket::http::Status;
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::status;
+use rocket::response::stream::{Event, EventStream};
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use bollard::Docker;
-use bollard::container::{CreateContainerOptions, Config, StartContainerOptions, StopContainerOptions, RemoveContainerOptions, ListContainersOptions};
+use bollard::container::{CreateContainerOptions, Config, StartContainerOptions, StopContainerOptions, RemoveContainerOptions, ListContainersOptions, RenameContainerOptions};
 use bollard::image::ListImagesOptions;
 use bollard::system::EventsOptions;
-use futures::stream::{StreamExt, TryStreamExt};
-
-// Data structures
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppInstance {
-    id: String,
-    name: String,
-    image: String,
-    status: String,
-    created_at: String,
-    ports: Vec<PortMapping>,
-    environment: HashMap<String, String>,
-    volumes: Vec<VolumeMapping>,
-    agent_id: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PortMapping {
-    host_port: u16,
-    container_port: u16,
-    protocol: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VolumeMapping {
-    host_path: String,
-    container_path: String,
-}
-#[derive(Debug, Clone, rocket::serde::Serialize, rocket::serde::Deserialize)]
-#[serde(crate = "rocket::serde")]
-pub struct AppInstanceRequest {
-    name: String,
-    image: String,
-    ports: Option<Vec<PortMapping>>,
-    environment: Option<HashMap<String, String>>,
-    volumes: Option<Vec<VolumeMapping>>,
-}
-
-// Docker client wrapper
+use futures::sink::SinkExt;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::websocket::{to_io_error, Channel, Message, WebSocket};
+use super::node::{NodeConfig, Unschedulable};
+use super::maintenance::MaintenanceWindows;
+use super::access::{AccessError, ApiKey, Mutation};
+use super::bandwidth;
+use super::userns;
+use super::seccomp;
+use super::disk::DiskSpace;
+use super::limits::{Create, Logs, Slot, Stats};
+use super::images::{self, ImageManager};
+use super::registry_cache::RegistryCache;
+use super::preemption;
+use super::cgroup;
+use super::logs;
+use super::log_health;
+use super::export::{self, Export, ExportFormat, ImageRow};
+use super::plugins::Plugins;
+use super::cpi::CpiRegistry;
+use super::flags::{self, FeatureFlags};
+use super::rbac;
+use super::search::Query;
+use super::state::StateTracker;
+use super::views;
+use crate::crypto;
+use crate::host_stats;
+use crate::state_store::{self, StateStore};
+use crate::event_bus::{AgentEvent, EventBus};
+use crate::hooks::ScriptHooks;
+pub use omniagent_client::models::WithWarnings;
+pub use omniagent_client::models::instances::{AppInstance, PortMapping, VolumeMapping, NetworkEndpointConfig, NetworkAttachment, AppInstanceRequest, Isolation, DeviceMapping, Ulimit, DaemonState, ResourceCapacity, StdinWrite, Activation, ResourceLimits, ExecRequest, ExecSession, ExecResize, HealthStatus, LogLine, InstanceLogs, VolumeInfo, VolumeCreateRequest, NetworkInfo, NetworkContainerInfo, NetworkCreateRequest, AgentInfo, HostTopology, NumaNode, SystemResources, AgentCapabilities, GpuInfo, DockerDaemonInfo};
+
+/// Container labels recording the requested image and the digest it resolved to
+pub(crate) const IMAGE_LABEL: &str = "omni.image";
+const IMAGE_DIGEST_LABEL: &str = "omni.image.digest";
+/// Marks instances created in standby
+const STANDBY_LABEL: &str = "omni.standby";
+
+/// Docker's state, except that a standby instance not yet started is `standby`
+pub(crate) fn instance_status(status: String, labels: &HashMap<String, String>) -> String {
+    if labels.contains_key(STANDBY_LABEL) && (status == "created" || status == "Created") {
+        "standby".to_string()
+    } else {
+        status
+    }
+}
+
+fn endpoint_settings(endpoint: &NetworkEndpointConfig) -> bollard::models::EndpointSettings {
+    let ipam_config = if endpoint.ipv4_address.is_some() || endpoint.ipv6_address.is_some() {
+        Some(bollard::models::EndpointIpamConfig {
+            ipv4_address: endpoint.ipv4_address.clone(),
+            ipv6_address: endpoint.ipv6_address.clone(),
+            ..Default::default()
+        })
+    } else {
+        None
+    };
+
+    bollard::models::EndpointSettings {
+        aliases: endpoint.aliases.clone(),
+        ipam_config,
+        ..Default::default()
+    }
+}
+
+fn to_device(device: &DeviceMapping) -> Result<bollard::models::DeviceMapping, String> {
+    let permissions = device.cgroup_permissions.clone().unwrap_or_else(|| "rwm".to_string());
+    if permissions.is_empty() || !permissions.chars().all(|c| "rwm".contains(c)) {
+        return Err(format!("Invalid cgroup permissions {} for device {}", permissions, device.path_on_host));
+    }
+
+    Ok(bollard::models::DeviceMapping {
+        path_on_host: Some(device.path_on_host.clone()),
+        path_in_container: Some(device.path_in_container.clone().unwrap_or_else(|| device.path_on_host.clone())),
+        cgroup_permissions: Some(permissions),
+    })
+}
+
+/// Agent-wide resolver settings applied to instances that don't set their own
+#[derive(Debug, Clone)]
+struct DnsDefaults {
+    dns: Option<Vec<String>>,
+    dns_search: Option<Vec<String>>,
+    dns_options: Option<Vec<String>>,
+}
+
+impl DnsDefaults {
+    /// Reads comma-separated `OMNI_DEFAULT_DNS`, `OMNI_DEFAULT_DNS_SEARCH` and `OMNI_DEFAULT_DNS_OPTIONS`
+    fn from_env() -> Self {
+        let list = |name: &str| std::env::var(name).ok().map(|value| value.split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect::<Vec<_>>());
+
+        DnsDefaults {
+            dns: list("OMNI_DEFAULT_DNS"),
+            dns_search: list("OMNI_DEFAULT_DNS_SEARCH"),
+            dns_options: list("OMNI_DEFAULT_DNS_OPTIONS"),
+        }
+    }
+}
+
+// Docker client wrapper; clones share the same state
+#[derive(Clone)]
 pub struct AppManager {
     docker: Docker,
     instances: Arc<Mutex<HashMap<String, AppInstance>>>,
+    daemon: Arc<Mutex<DaemonState>>,
+    /// How far reservations may exceed physical CPU/memory (`OMNI_OVERSUBSCRIPTION_RATIO`)
+    oversubscription_ratio: f64,
+    node: NodeConfig,
+    maintenance: MaintenanceWindows,
+    dns_defaults: DnsDefaults,
+    /// Durable record of instances, their specs and an audit trail
+    store: Arc<dyn StateStore>,
+    plugins: Plugins,
+    hooks: ScriptHooks,
+    cpis: CpiRegistry,
+    flags: FeatureFlags,
+}
+
+/// The limits in a container's host config; Docker reports unset limits as 0
+fn resource_limits(host_config: &bollard::models::HostConfig) -> ResourceLimits {
+    ResourceLimits {
+        cpu_limit: host_config.nano_cpus.filter(|nano_cpus| *nano_cpus > 0).map(|nano_cpus| nano_cpus as f64 / 1e9),
+        memory_limit: host_config.memory.filter(|memory| *memory > 0),
+        memory_swap: host_config.memory_swap.filter(|swap| *swap != 0),
+        cpu_shares: host_config.cpu_shares.filter(|shares| *shares > 0),
+    }
+}
+
+/// Rejects limits Docker would refuse with a less helpful message
+pub(crate) fn check_resource_limits(app_req: &AppInstanceRequest) -> Result<(), String> {
+    if app_req.cpu_limit.is_some_and(|cpus| cpus <= 0.0) {
+        return Err("cpu_limit must be positive".to_string());
+    }
+    if app_req.memory_limit.is_some_and(|memory| memory <= 0) {
+        return Err("memory_limit must be positive".to_string());
+    }
+    match (app_req.memory_swap, app_req.memory_limit) {
+        (Some(-1), Some(_)) | (None, _) => Ok(()),
+        (Some(_), None) => Err("memory_swap requires memory_limit".to_string()),
+        (Some(swap), Some(memory)) if swap < memory => Err("memory_swap must be at least memory_limit, or -1".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// CPU (in cores) and memory (in bytes) requested by a container's host config
+pub fn requested_resources(host_config: &bollard::models::HostConfig) -> (f64, u64) {
+    let cpus = match (host_config.nano_cpus, host_config.cpu_quota, host_config.cpu_period) {
+        (Some(nano_cpus), _, _) if nano_cpus > 0 => nano_cpus as f64 / 1e9,
+        (_, Some(quota), Some(period)) if quota > 0 && period > 0 => quota as f64 / period as f64,
+        _ => 0.0,
+    };
+    let memory = host_config.memory.filter(|m| *m > 0).unwrap_or(0) as u64;
+    (cpus, memory)
 }
 
 impl AppManager {
-    pub fn new() -> Result<Self, String> {
-        // Connect to Docker with default configuration
-        // Works across platforms without additional config
-        let docker = match Docker::connect_with_local_defaults() {
+    pub fn new(store: Arc<dyn StateStore>, config: &crate::config::Config) -> Result<Self, String> {
+        let docker = match Self::connect(config.docker_host.as_deref()) {
             Ok(docker) => docker,
             Err(e) => return Err(format!("Failed to connect to Docker: {}", e)),
         };
@@ -64,13 +183,356 @@ impl AppManager {
         Ok(AppManager {
             docker,
             instances: Arc::new(Mutex::new(HashMap::new())),
+            daemon: Arc::new(Mutex::new(DaemonState::Connecting)),
+            oversubscription_ratio: std::env::var("OMNI_OVERSUBSCRIPTION_RATIO").ok()
+                .and_then(|ratio| ratio.parse().ok())
+                .filter(|ratio: &f64| *ratio > 0.0)
+                .unwrap_or(1.0),
+            node: NodeConfig::from_env(),
+            maintenance: MaintenanceWindows::new(),
+            dns_defaults: DnsDefaults::from_env(),
+            flags: FeatureFlags::new(store.clone(), config),
+            store,
+            plugins: Plugins::load(),
+            hooks: ScriptHooks::from_env(),
+            cpis: CpiRegistry::new(config),
         })
     }
-}
+
+    /// Reloads instance records persisted by a previous run and reconciles them with Docker:
+    /// statuses are refreshed, and records of containers that no longer exist are left out
+    /// (housekeeping removes them from the store). Returns how many were restored and left
+    /// out. If Docker can't be listed the records are restored as stored.
+    pub async fn restore(&self) -> Result<(usize, usize), String> {
+        let records = self.store.list(state_store::INSTANCES).await?;
+        let live: Option<HashMap<String, bollard::models::ContainerSummary>> = match self.docker.list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            ..Default::default()
+        })).await {
+            Ok(containers) => Some(containers.into_iter()
+                .filter_map(|container| Some((container.id.clone()?, container)))
+                .collect()),
+            Err(e) => {
+                eprintln!("Failed to list containers, restoring instance records unreconciled: {}", e);
+                None
+            }
+        };
+
+        let mut restored = Vec::new();
+        let mut gone = 0;
+        for (id, record) in records {
+            let mut instance = match rocket::serde::json::from_value::<AppInstance>(record) {
+                Ok(instance) => instance,
+                Err(e) => {
+                    eprintln!("Skipping unreadable instance record {}: {}", id, e);
+                    continue;
+                }
+            };
+            match live.as_ref().map(|live| live.get(&id)) {
+                Some(None) => {
+                    gone += 1;
+                    continue;
+                },
+                Some(Some(container)) => {
+                    let status = instance_status(container.state.clone().unwrap_or_default(), &container.labels.clone().unwrap_or_default());
+                    if status != instance.status {
+                        instance.status = status;
+                        let result = match rocket::serde::json::to_value(&instance) {
+                            Ok(record) => self.store.put(state_store::INSTANCES, &id, &record).await,
+                            Err(e) => Err(e.to_string()),
+                        };
+                        if let Err(e) = result {
+                            eprintln!("Failed to update instance record for {}: {}", id, e);
+                        }
+                    }
+                },
+                None => {},
+            }
+            restored.push((id, instance));
+        }
+
+        let count = restored.len();
+        self.instances.lock().unwrap().extend(restored);
+        Ok((count, gone))
+    }
+
+    /// Saves an instance and the request it was created from. Docker remains the source of
+    /// truth, so store failures are logged rather than failing the API call.
+    async fn persist(&self, instance: &AppInstance, spec: &AppInstanceRequest) {
+        let records = [
+            (state_store::INSTANCES, rocket::serde::json::to_value(instance)),
+            (state_store::SPECS, rocket::serde::json::to_value(spec)),
+        ];
+        for (collection, record) in records {
+            let result = match record {
+                Ok(record) => self.store.put(collection, &instance.id, &record).await,
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to persist {} record for {}: {}", collection, instance.id, e);
+            }
+        }
+    }
+
+    /// Specs of every instance created through this agent, keyed by container ID
+    pub async fn managed_specs(&self) -> Result<Vec<(String, AppInstanceRequest)>, String> {
+        let records = self.store.list(state_store::SPECS).await?;
+        Ok(records.into_iter()
+            .filter_map(|(id, record)| match rocket::serde::json::from_value(record) {
+                Ok(spec) => Some((id, spec)),
+                Err(e) => {
+                    eprintln!("Skipping unreadable spec record {}: {}", id, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    pub fn store(&self) -> &dyn StateStore {
+        self.store.as_ref()
+    }
+
+    pub fn plugins(&self) -> &Plugins {
+        &self.plugins
+    }
+
+    pub fn hooks(&self) -> &ScriptHooks {
+        &self.hooks
+    }
+
+    pub fn cpis(&self) -> &CpiRegistry {
+        &self.cpis
+    }
+
+    pub fn flags(&self) -> &FeatureFlags {
+        &self.flags
+    }
+
+    pub async fn spec(&self, id: &str) -> Result<Option<AppInstanceRequest>, String> {
+        match self.store.get(state_store::SPECS, id).await? {
+            Some(record) => rocket::serde::json::from_value(record)
+                .map(Some)
+                .map_err(|e| format!("Failed to read spec for {}: {}", id, e)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save_spec(&self, id: &str, spec: &AppInstanceRequest) -> Result<(), String> {
+        let record = rocket::serde::json::to_value(spec).map_err(|e| e.to_string())?;
+        self.store.put(state_store::SPECS, id, &record).await
+    }
+
+    /// Removes the records of instances whose containers Docker no longer has, e.g. ones
+    /// removed outside the agent. Returns their IDs.
+    pub async fn prune_orphans(&self) -> Result<Vec<String>, String> {
+        let live: HashSet<String> = self.docker.list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            ..Default::default()
+        })).await
+            .map_err(|e| format!("Failed to list containers: {}", e))?
+            .into_iter()
+            .filter_map(|container| container.id)
+            .collect();
+
+        let mut recorded: BTreeSet<String> = BTreeSet::new();
+        for collection in [state_store::INSTANCES, state_store::SPECS] {
+            recorded.extend(self.store.list(collection).await?.into_iter().map(|(id, _)| id));
+        }
+
+        let orphans: Vec<String> = recorded.into_iter().filter(|id| !live.contains(id)).collect();
+        for id in &orphans {
+            self.instances.lock().unwrap().remove(id);
+            self.forget(id).await;
+            self.audit("prune", id).await;
+        }
+        Ok(orphans)
+    }
+
+    async fn forget(&self, id: &str) {
+        for collection in [state_store::INSTANCES, state_store::SPECS] {
+            if let Err(e) = self.store.delete(collection, id).await {
+                eprintln!("Failed to remove {} record for {}: {}", collection, id, e);
+            }
+        }
+    }
+
+    pub async fn audit(&self, action: &str, instance_id: &str) {
+        let record = rocket::serde::json::json!({
+            "action": action,
+            "instance_id": instance_id,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        });
+        if let Err(e) = state_store::append(self.store.as_ref(), state_store::AUDIT, &record).await {
+            eprintln!("Failed to record audit entry: {}", e);
+        }
+    }
+
+    /// Picks the daemon endpoint: `OMNI_DOCKER_PIPE` on Windows, the configured Docker host,
+    /// then the system socket, then the rootless socket under `$XDG_RUNTIME_DIR`
+    fn connect(docker_host: Option<&str>) -> Result<Docker, bollard::errors::Error> {
+        #[cfg(windows)]
+        if let Ok(pipe) = std::env::var("OMNI_DOCKER_PIPE") {
+            return Docker::connect_with_named_pipe(&pipe, 120, bollard::API_DEFAULT_VERSION);
+        }
+
+        if let Some(host) = docker_host {
+            #[cfg(unix)]
+            if host.starts_with("unix://") {
+                return Docker::connect_with_unix(host, 120, bollard::API_DEFAULT_VERSION);
+            }
+            #[cfg(windows)]
+            if host.starts_with("npipe://") {
+                return Docker::connect_with_named_pipe(host, 120, bollard::API_DEFAULT_VERSION);
+            }
+            return Docker::connect_with_http(host, 120, bollard::API_DEFAULT_VERSION);
+        }
+
+        #[cfg(unix)]
+        if !std::path::Path::new("/var/run/docker.sock").exists() {
+            if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+                let rootless = format!("{}/docker.sock", runtime_dir);
+                if std::path::Path::new(&rootless).exists() {
+                    return Docker::connect_with_unix(&rootless, 120, bollard::API_DEFAULT_VERSION);
+                }
+            }
+        }
+
+        // Works across platforms without additional config
+        Docker::connect_with_local_defaults()
+    }
+
+    /// Watches the daemon in the background, retrying with exponential backoff while it is
+    /// unreachable instead of failing startup
+    pub fn supervise(&self, bus: EventBus) {
+        let docker = self.docker.clone();
+        let daemon = self.daemon.clone();
+
+        tokio::spawn(async move {
+            let mut attempt: u32 = 0;
+            loop {
+                match docker.version().await {
+                    Ok(version) => {
+                        attempt = 0;
+                        {
+                            let mut state = daemon.lock().unwrap();
+                            if !matches!(*state, DaemonState::Ready { .. }) {
+                                let version = version.version.unwrap_or_default();
+                                log::info!("Connected to Docker daemon {}", version);
+                                bus.publish(AgentEvent::Health {
+                                    status: "ready".to_string(),
+                                    detail: Some(version.clone()),
+                                    timestamp: chrono::Utc::now().to_rfc3339(),
+                                });
+                                *state = DaemonState::Ready {
+                                    version,
+                                    since: chrono::Utc::now().to_string(),
+                                };
+                            }
+                        }
+                        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+                    },
+                    Err(e) => {
+                        attempt += 1;
+                        let retry_in_secs = 2u64.saturating_pow(attempt.min(6)).min(60);
+                        log::warn!("Docker daemon unavailable (attempt {}), retrying in {}s: {}", attempt, retry_in_secs, e);
+                        {
+                            let mut state = daemon.lock().unwrap();
+                            if !matches!(*state, DaemonState::Unavailable { .. }) {
+                                bus.publish(AgentEvent::Health {
+                                    status: "unavailable".to_string(),
+                                    detail: Some(e.to_string()),
+                                    timestamp: chrono::Utc::now().to_rfc3339(),
+                                });
+                            }
+                            *state = DaemonState::Unavailable {
+                                attempt,
+                                error: e.to_string(),
+                                retry_in_secs,
+                            };
+                        }
+                        tokio::time::sleep(std::time::Duration::from_secs(retry_in_secs)).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Sums the CPU and memory limits of all running containers
+    pub async fn reserved_resources(&self) -> Result<(f64, u64), String> {
+        let containers = self.docker.list_containers(Some(ListContainersOptions::<String> {
+            all: false,
+            ..Default::default()
+        })).await.map_err(|e| format!("Failed to list containers: {}", e))?;
+
+        let mut reserved = (0.0, 0);
+        for id in containers.into_iter().filter_map(|c| c.id) {
+            if let Ok(container) = self.docker.inspect_container(&id, None).await {
+                if let Some(host_config) = container.host_config {
+                    let (cpus, memory) = requested_resources(&host_config);
+                    reserved.0 += cpus;
+                    reserved.1 += memory;
+                }
+            }
+        }
+        Ok(reserved)
+    }
+
+    pub async fn capacity(&self) -> Result<ResourceCapacity, String> {
+        let (cpu_reserved, memory_reserved) = self.reserved_resources().await?;
+        let (memory_total, _) = host_stats::memory();
+
+        Ok(ResourceCapacity {
+            oversubscription_ratio: self.oversubscription_ratio,
+            cpu_allocatable: num_cpus::get() as f64 * self.oversubscription_ratio,
+            cpu_reserved,
+            memory_allocatable: (memory_total as f64 * self.oversubscription_ratio) as u64,
+            memory_reserved,
+        })
+    }
+
+    /// Rejects limits that would push reservations past the allocatable capacity
+    pub async fn check_capacity(&self, host_config: &bollard::models::HostConfig) -> Result<(), String> {
+        let (cpus, memory) = requested_resources(host_config);
+        if cpus == 0.0 && memory == 0 {
+            return Ok(());
+        }
+
+        let capacity = self.capacity().await?;
+        if cpus > 0.0 && capacity.cpu_reserved + cpus > capacity.cpu_allocatable {
+            return Err(format!("Insufficient CPU: requested {:.2}, reserved {:.2} of {:.2} allocatable",
+                cpus, capacity.cpu_reserved, capacity.cpu_allocatable));
+        }
+        if memory > 0 && capacity.memory_allocatable > 0 && capacity.memory_reserved + memory > capacity.memory_allocatable {
+            return Err(format!("Insufficient memory: requested {} bytes, reserved {} of {} allocatable",
+                memory, capacity.memory_reserved, capacity.memory_allocatable));
+        }
+        Ok(())
+    }
+
+    pub fn daemon_state(&self) -> DaemonState {
+        self.daemon.lock().unwrap().clone()
+    }
+
+    pub fn docker(&self) -> &Docker {
+        &self.docker
+    }
+
+    pub fn node(&self) -> &NodeConfig {
+        &self.node
+    }
+
+    pub fn maintenance(&self) -> &MaintenanceWindows {
+        &self.maintenance
+    }}
 
 // API Endpoints
-#[get("/instances")]
-pub async fn list_instances(app_manager: &State<AppManager>) -> Json<Vec<AppInstance>> {
+/// Lists all containers, or with `view` only the instances matching that saved view
+#[get("/instances?<view>")]
+pub async fn list_instances(view: Option<String>, app_manager: &State<AppManager>, tracker: &State<StateTracker>, format: ExportFormat) -> Result<Export<Vec<AppInstance>>, String> {
+    let visible = match view {
+        Some(view) => Some(Query::matching_ids(&[views::query(app_manager.store(), &view).await?], tracker)),
+        None => None,
+    };
     let mut instances = Vec::new();
     
     // List containers via Docker API
@@ -84,18 +546,24 @@ pub async fn list_instances(app_manager: &State<AppManager>) -> Json<Vec<AppInst
             for container in containers {
                 if let (Some(id), Some(image), Some(names), Some(created), Some(status)) = 
                    (container.id, container.image, container.names, container.created, container.status) {
+                    if visible.as_ref().is_some_and(|visible| !visible.contains(&id)) {
+                        continue;
+                    }
                     if let Some(name) = names.first() {
                         let name = name.trim_start_matches('/').to_string();
+                        let labels = container.labels.unwrap_or_default();
                         let app_instance = AppInstance {
                             id: id.clone(),
                             name,
-                            image,
-                            status,
+                            image: labels.get(IMAGE_LABEL).cloned().unwrap_or(image),
+                            status: instance_status(status, &labels),
                             created_at: created.to_string(),
                             ports: Vec::new(), // Would need to parse from container.ports
                             environment: HashMap::new(), // Would need additional API call
                             volumes: Vec::new(), // Would need additional API call
                             agent_id: "current".to_string(), // In a distributed setup, this would be the agent ID
+                            image_digest: labels.get(IMAGE_DIGEST_LABEL).cloned(),
+                            limits: None,
                         };
                         instances.push(app_instance);
                     }
@@ -107,7 +575,7 @@ pub async fn list_instances(app_manager: &State<AppManager>) -> Json<Vec<AppInst
         }
     }
     
-    Json(instances)
+    Ok(format.respond(instances))
 }
 
 #[get("/instances/<id>")]
@@ -121,16 +589,19 @@ pub async fn get_instance(id: String, app_manager: &State<AppManager>) -> Option
             let name = container.name?;
             let name = name.trim_start_matches('/').to_string();
             
+            let labels = config.labels.unwrap_or_default();
             let app_instance = AppInstance {
                 id: container.id.unwrap_or(id),
                 name,
-                image: config.image.unwrap_or_default(),
-                status: state.status.map(|s| s.to_string()).unwrap_or_else(|| "unknown".to_string()),
+                image: labels.get(IMAGE_LABEL).cloned().or(config.image).unwrap_or_default(),
+                status: instance_status(state.status.map(|s| s.to_string()).unwrap_or_else(|| "unknown".to_string()), &labels),
                 created_at: container.created.unwrap_or_default(),
                 ports: Vec::new(), // Would need to parse from container.network_settings
                 environment: HashMap::new(), // Would need to parse from config.env
                 volumes: Vec::new(), // Would need to parse from container.mounts
                 agent_id: "current".to_string(),
+                image_digest: labels.get(IMAGE_DIGEST_LABEL).cloned(),
+                limits: container.host_config.as_ref().map(resource_limits),
             };
             
             Some(Json(app_instance))
@@ -138,8 +609,199 @@ pub async fn get_instance(id: String, app_manager: &State<AppManager>) -> Option
         Err(_) => None
     }
 }
+/// Resolves an image reference to `repo@sha256:...`. Images that were never pushed or pulled
+/// have no repo digest and resolve to their local image ID instead.
+async fn resolve_image_digest(docker: &Docker, image: &str) -> Result<String, String> {
+    if image.contains('@') {
+        return Ok(image.to_string());
+    }
+
+    let inspect = docker.inspect_image(image).await
+        .map_err(|e| format!("Failed to resolve image {}: {}", image, e))?;
+
+    // Strip the tag, but not a registry port such as `registry:5000/app`
+    let last_segment = image.rsplit('/').next().unwrap_or(image);
+    let repo = match last_segment.rsplit_once(':') {
+        Some((_, tag)) => &image[..image.len() - tag.len() - 1],
+        None => image,
+    };
+
+    let repo_digests = inspect.repo_digests.unwrap_or_default();
+    repo_digests.iter()
+        .find(|digest| digest.split('@').next() == Some(repo))
+        .or_else(|| repo_digests.first())
+        .cloned()
+        .or(inspect.id)
+        .ok_or_else(|| format!("Image {} has no digest or ID", image))
+}
+
+/// Advisories about a spec that is valid but probably not what the caller wants
+fn spec_warnings(app_req: &AppInstanceRequest) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let (_, _, reference) = images::parse_image_ref(&app_req.image);
+    if reference == "latest" {
+        warnings.push(format!("Image {} uses the latest tag; pin a tag or digest for repeatable deploys", app_req.image));
+    }
+    for port in app_req.ports.iter().flatten().filter(|port| port.host_port != 0 && port.host_port < 1024) {
+        warnings.push(format!("Host port {} is below 1024 and needs elevated privileges on most hosts", port.host_port));
+    }
+    if app_req.userns_mode.as_deref() == Some("host") {
+        warnings.push("userns_mode host runs the instance without user namespace remapping".to_string());
+    }
+    if app_req.stdin_once == Some(true) && app_req.stdin_open != Some(true) {
+        warnings.push("stdin_once has no effect without stdin_open".to_string());
+    }
+
+    warnings
+}
+
+/// Every taint and constraint that keeps the instance off this agent
+fn unschedulable_reasons(node: &NodeConfig, app_req: &AppInstanceRequest) -> Vec<String> {
+    let mut reasons = Vec::new();
+    if let Err(e) = node.check_tolerations(app_req.tolerations.as_deref().unwrap_or_default()) {
+        reasons.push(e);
+    }
+    let Some(constraints) = &app_req.constraints else {
+        return reasons;
+    };
+
+    if let Some(labels) = &constraints.node_labels {
+        reasons.extend(node.unmatched_labels(labels));
+    }
+    if let Some(required) = constraints.min_free_memory {
+        let (_, available) = host_stats::memory();
+        if available < required {
+            reasons.push(format!("Host has {} bytes of memory available, {} required", available, required));
+        }
+    }
+    for capability in constraints.capabilities.iter().flatten() {
+        let present = match capability.as_str() {
+            "gpu" => detect_gpu().is_some(),
+            feature => AGENT_FEATURES.contains(&feature),
+        };
+        if !present {
+            reasons.push(format!("Agent lacks capability {}", capability));
+        }
+    }
+    reasons
+}
+
+/// Create failures: plain messages like every other handler, or a structured rejection
+/// when the instance can't be placed here
+#[derive(Responder)]
+pub enum CreateError {
+    Failed(String),
+    #[response(status = 409)]
+    Unschedulable(Json<Unschedulable>),
+}
+
+impl From<String> for CreateError {
+    fn from(error: String) -> Self {
+        CreateError::Failed(error)
+    }
+}
+
+impl CreateError {
+    fn message(&self) -> &str {
+        match self {
+            CreateError::Failed(error) => error,
+            CreateError::Unschedulable(unschedulable) => &unschedulable.error,
+        }
+    }
+}
+
+/// What creating or updating an instance takes from the request: the shared state it works
+/// with, and the guards that must pass first. `apply` builds one for each instance in a
+/// bundle.
+#[derive(Clone)]
+pub struct Deploy<'r> {
+    pub app_manager: &'r State<AppManager>,
+    pub bus: &'r State<EventBus>,
+    pub images: &'r State<Arc<ImageManager>>,
+    pub registry_cache: &'r State<Arc<RegistryCache>>,
+    pub mutation: Mutation,
+    pub key: ApiKey,
+    pub disk: DiskSpace,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Deploy<'r> {
+    type Error = AccessError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let mutation = try_outcome!(req.guard::<Mutation>().await);
+        let key = try_outcome!(req.guard::<ApiKey>().await);
+        let disk = try_outcome!(req.guard::<DiskSpace>().await);
+        let rocket = req.rocket();
+        let (Some(app_manager), Some(bus), Some(images), Some(registry_cache)) = (State::get(rocket), State::get(rocket), State::get(rocket), State::get(rocket)) else {
+            return Outcome::Error((Status::InternalServerError, AccessError::new("unmanaged_state", "Deploy state is not managed by this server")));
+        };
+        Outcome::Success(Deploy { app_manager, bus, images, registry_cache, mutation, key, disk })
+    }
+}
+
+/// Runs the `on-create` or `on-deploy-failure` hook for the outcome of a create or update
+fn run_deploy_hooks(app_manager: &AppManager, name: &str, image: &str, result: &Result<Json<WithWarnings<AppInstance>>, CreateError>) {
+    match result {
+        Ok(created) => app_manager.hooks.run("on-create", &created.result),
+        Err(e) => app_manager.hooks.run("on-deploy-failure", &rocket::serde::json::json!({
+            "name": name,
+            "image": image,
+            "error": e.message(),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        })),
+    }
+}
+
 #[post("/instances", format = "json", data = "<app_req>")]
-pub async fn create_instance(app_req: Json<AppInstanceRequest>, app_manager: &State<AppManager>) -> Result<Json<AppInstance>, String> {
+pub async fn create_instance(app_req: Json<AppInstanceRequest>, deploy: Deploy<'_>, slot: Slot<Create>) -> Result<Json<WithWarnings<AppInstance>>, CreateError> {
+    deploy.key.authorize("create", "instances", app_req.namespace())?;
+    let (name, image) = (app_req.name.clone(), app_req.image.clone());
+    let result = create(app_req, &deploy, &slot).await;
+    run_deploy_hooks(deploy.app_manager, &name, &image, &result);
+    result
+}
+
+/// The guards in `deploy` and `_slot` are held until the container is created
+async fn create(mut app_req: Json<AppInstanceRequest>, deploy: &Deploy<'_>, _slot: &Slot<Create>) -> Result<Json<WithWarnings<AppInstance>>, CreateError> {
+    let (app_manager, bus, images, registry_cache) = (deploy.app_manager, deploy.bus, deploy.images, deploy.registry_cache);
+    app_manager.plugins.pre_create(&mut app_req).await?;
+    let reasons = unschedulable_reasons(&app_manager.node, &app_req);
+    if !reasons.is_empty() {
+        return Err(CreateError::Unschedulable(Json(Unschedulable {
+            code: "unschedulable".to_string(),
+            error: format!("Instance {} can't be placed on this agent", app_req.name),
+            reasons,
+        })));
+    }
+    app_manager.node.check_host_options(
+        app_req.sysctls.iter().flat_map(|sysctls| sysctls.keys()),
+        app_req.ulimits.iter().flatten().map(|ulimit| &ulimit.name),
+    )?;
+    app_manager.node.check_devices(app_req.devices.iter().flatten().map(|device| &device.path_on_host))?;
+    let devices = match &app_req.devices {
+        Some(devices) => Some(devices.iter().map(to_device).collect::<Result<Vec<_>, _>>()?),
+        None => None,
+    };
+    app_manager.node.check_userns_mode(app_req.userns_mode.as_deref())?;
+    cgroup::check_limits(app_req.cpu_rt_period, app_req.cpu_rt_runtime)?;
+    check_resource_limits(&app_req)?;
+    if let Some(parsing) = &app_req.log_parsing {
+        logs::compile(parsing)?;
+    }
+    if let Some(health) = &app_req.log_health {
+        log_health::compile(health)?;
+    }
+    if app_req.userns_mode.is_none() {
+        let info = app_manager.docker.info().await.ok();
+        userns::check_bind_ownership(&userns::detect(info.as_ref()), app_req.volumes.iter().flatten().map(|volume| &volume.host_path))?;
+    }
+    let security_opt = match &app_req.seccomp_profile {
+        Some(profile) => Some(vec![seccomp::security_opt(app_manager.store(), profile).await?]),
+        None => None,
+    };
+
     // Prepare container configuration
     let name = app_req.name.clone();
     
@@ -171,57 +833,167 @@ pub async fn create_instance(app_req: Json<AppInstanceRequest>, app_manager: &St
         }
     }
     
+    // The first network is attached at creation, the rest are connected before start
+    let networks = app_req.networks.clone().unwrap_or_default();
+    let networking_config = networks.first().map(|network| bollard::container::NetworkingConfig {
+        endpoints_config: HashMap::from([(network.name.clone(), endpoint_settings(&network.endpoint))]),
+    });
+
+    // Pin the tag to what it points at now so restarts and reschedules run the same image
+    images.ensure_image(&app_manager.docker, registry_cache, &app_req.image).await?;
+    let image_digest = resolve_image_digest(&app_manager.docker, &app_req.image).await?;
+    app_req.image_digest = Some(image_digest.clone());
+
     // Create container
     let options = Some(CreateContainerOptions {
         name: &name,
         platform: None,
     });
     
+    let standby = app_req.standby.unwrap_or(false);
+    let mut labels = HashMap::from([
+        (IMAGE_LABEL.to_string(), app_req.image.clone()),
+        (IMAGE_DIGEST_LABEL.to_string(), image_digest.clone()),
+    ]);
+    if standby {
+        labels.insert(STANDBY_LABEL.to_string(), "true".to_string());
+    }
+    if let Some(namespace) = &app_req.namespace {
+        labels.insert(rbac::NAMESPACE_LABEL.to_string(), namespace.clone());
+    }
     let config = Config {
-        image: Some(app_req.image.clone()),
+        image: Some(image_digest.clone()),
+        labels: Some(labels.clone()),
         env: Some(env_vars),
+        cmd: app_req.command.clone(),
+        entrypoint: app_req.entrypoint.clone(),
+        working_dir: app_req.working_dir.clone(),
+        user: app_req.user.clone(),
+        hostname: app_req.hostname.clone(),
+        stop_timeout: app_req.stop_grace_period,
+        open_stdin: app_req.stdin_open,
+        stdin_once: app_req.stdin_once,
         exposed_ports: Some(HashMap::new()), // Would need to populate from app_req.ports
         host_config: Some(bollard::models::HostConfig {
             port_bindings: Some(port_bindings),
             binds: Some(volume_bindings),
+            extra_hosts: app_req.extra_hosts.clone(),
+            network_mode: networks.first().map(|network| network.name.clone()),
+            isolation: app_req.isolation.map(|isolation| match isolation {
+                Isolation::Default => bollard::models::HostConfigIsolationEnum::DEFAULT,
+                Isolation::Process => bollard::models::HostConfigIsolationEnum::PROCESS,
+                Isolation::Hyperv => bollard::models::HostConfigIsolationEnum::HYPERV,
+            }),
+            cpuset_cpus: app_req.cpuset_cpus.clone(),
+            cpuset_mems: app_req.cpuset_mems.clone(),
+            cpu_shares: app_req.cpu_shares,
+            nano_cpus: app_req.cpu_limit.map(|cpus| (cpus * 1e9) as i64),
+            memory: app_req.memory_limit,
+            memory_swap: app_req.memory_swap,
+            cpu_realtime_period: app_req.cpu_rt_period,
+            cpu_realtime_runtime: app_req.cpu_rt_runtime,
+            ulimits: app_req.ulimits.as_ref().map(|ulimits| ulimits.iter()
+                .map(|ulimit| bollard::models::ResourcesUlimits {
+                    name: Some(ulimit.name.clone()),
+                    soft: Some(ulimit.soft),
+                    hard: Some(ulimit.hard),
+                })
+                .collect()),
+            sysctls: app_req.sysctls.clone(),
+            readonly_rootfs: app_req.read_only,
+            userns_mode: app_req.userns_mode.clone(),
+            security_opt,
+            dns: app_req.dns.clone().or_else(|| app_manager.dns_defaults.dns.clone()),
+            dns_search: app_req.dns_search.clone().or_else(|| app_manager.dns_defaults.dns_search.clone()),
+            dns_options: app_req.dns_options.clone().or_else(|| app_manager.dns_defaults.dns_options.clone()),
+            devices,
             ..Default::default()
         }),
+        networking_config,
         ..Default::default()
     };
     
-    match app_manager.docker.create_container(options, config).await {
-        Ok(response) => {
-            // Start the container
-            let id = response.id;
-            match app_manager.docker.start_container(&id, None::<StartContainerOptions<String>>).await {
-                Ok(_) => {
-                    // Create app instance object
-                    let app_instance = AppInstance {
-                        id: id.clone(),
-                        name: app_req.name.clone(),
-                        image: app_req.image.clone(),
-                        status: "running".to_string(),
-                        created_at: chrono::Utc::now().to_string(),
-                        ports: app_req.ports.clone().unwrap_or_default(),
-                        environment: app_req.environment.clone().unwrap_or_default(),
-                        volumes: app_req.volumes.clone().unwrap_or_default(),
-                        agent_id: "current".to_string(),
-                    };
-                    
-                    // Store the instance in our local state
-                    app_manager.instances.lock().unwrap().insert(id, app_instance.clone());
-                    
-                    Ok(Json(app_instance))
-                },
-                Err(e) => Err(format!("Failed to start instance: {}", e))
+    if let Some(host_config) = &config.host_config {
+        if let Err(e) = app_manager.check_capacity(host_config).await {
+            if !preemption::make_room(app_manager, bus, host_config, app_req.priority.unwrap_or(0), &app_req.name).await {
+                return Err(e.into());
             }
-        },
-        Err(e) => Err(format!("Failed to create instance: {}", e))
+        }
+    }
+
+    let id = match app_manager.docker.create_container(options, config).await {
+        Ok(response) => response.id,
+        Err(e) => return Err(format!("Failed to create instance: {}", e).into())
+    };
+
+    for network in networks.iter().skip(1) {
+        let options = bollard::network::ConnectNetworkOptions {
+            container: id.clone(),
+            endpoint_config: endpoint_settings(&network.endpoint),
+        };
+
+        if let Err(e) = app_manager.docker.connect_network(&network.name, options).await {
+            // Don't leave a half-configured container behind
+            let _ = app_manager.docker.remove_container(&id, Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            })).await;
+            return Err(format!("Failed to connect instance to network {}: {}", network.name, e).into());
+        }
+    }
+
+    // Start the container unless the caller asked to defer it or keep it in standby
+    if app_req.start.unwrap_or(true) && !standby {
+        if let Err(e) = app_manager.docker.start_container(&id, None::<StartContainerOptions<String>>).await {
+            return Err(format!("Failed to start instance: {}", e).into());
+        }
     }
+
+    // Report the state Docker actually has for the container
+    let (status, created_at, limits) = match app_manager.docker.inspect_container(&id, None).await {
+        Ok(container) => (
+            instance_status(container.state
+                .and_then(|state| state.status)
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "unknown".to_string()), &labels),
+            container.created.unwrap_or_else(|| chrono::Utc::now().to_string()),
+            container.host_config.as_ref().map(resource_limits),
+        ),
+        Err(e) => return Err(format!("Failed to inspect created instance: {}", e).into())
+    };
+
+    // Create app instance object
+    let app_instance = AppInstance {
+        id: id.clone(),
+        name: app_req.name.clone(),
+        image: app_req.image.clone(),
+        status,
+        created_at,
+        ports: app_req.ports.clone().unwrap_or_default(),
+        environment: app_req.environment.clone().unwrap_or_default(),
+        volumes: app_req.volumes.clone().unwrap_or_default(),
+        agent_id: "current".to_string(),
+        image_digest: Some(image_digest),
+        limits,
+    };
+
+    // Store the instance in our local state
+    app_manager.instances.lock().unwrap().insert(id.clone(), app_instance.clone());
+    app_manager.persist(&app_instance, &app_req).await;
+    app_manager.audit("create", &id).await;
+
+    // The start event fired before the spec was stored, so apply limits here once
+    if let Some(limit) = &app_req.bandwidth {
+        if let Err(e) = bandwidth::apply(&app_manager.docker, &id, limit).await {
+            eprintln!("Failed to apply bandwidth limit to {}: {}", id, e);
+        }
+    }
+
+    Ok(Json(WithWarnings { result: app_instance, warnings: spec_warnings(&app_req) }))
 }
 
 #[put("/instances/<id>/start")]
-pub async fn start_instance(id: String, app_manager: &State<AppManager>) -> Result<Json<AppInstance>, String> {
+pub async fn start_instance(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<AppInstance>, String> {
     // Start container
     match app_manager.docker.start_container(&id, None::<StartContainerOptions<String>>).await {
         Ok(_) => {
@@ -235,8 +1007,32 @@ pub async fn start_instance(id: String, app_manager: &State<AppManager>) -> Resu
     }
 }
 
+/// Starts an instance created in standby. Everything else was done at creation, so this
+/// is only Docker's start.
+#[post("/instances/<id>/activate")]
+pub async fn activate_instance(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<Activation>, String> {
+    let container = app_manager.docker.inspect_container(&id, None).await
+        .map_err(|e| format!("Failed to inspect instance: {}", e))?;
+    let standby = container.config.and_then(|config| config.labels)
+        .is_some_and(|labels| labels.contains_key(STANDBY_LABEL));
+    let created = container.state.and_then(|state| state.status) == Some(bollard::models::ContainerStateStatusEnum::CREATED);
+    if !standby || !created {
+        return Err(format!("Instance {} is not in standby", id));
+    }
+
+    let started = std::time::Instant::now();
+    app_manager.docker.start_container(&id, None::<StartContainerOptions<String>>).await
+        .map_err(|e| format!("Failed to activate instance: {}", e))?;
+    let start_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let id = container.id.unwrap_or(id);
+    log::info!("Activated standby instance {} in {:.1} ms", id, start_ms);
+    app_manager.audit("activate", &id).await;
+    Ok(Json(Activation { id, start_ms }))
+}
+
 #[put("/instances/<id>/stop")]
-pub async fn stop_instance(id: String, app_manager: &State<AppManager>) -> Result<Json<AppInstance>, String> {
+pub async fn stop_instance(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<AppInstance>, String> {
     // Stop container
     let options = Some(StopContainerOptions {
         t: 30, // Give it 30 seconds to shut down gracefully
@@ -255,7 +1051,7 @@ pub async fn stop_instance(id: String, app_manager: &State<AppManager>) -> Resul
 }
 
 #[put("/instances/<id>/restart")]
-pub async fn restart_instance(id: String, app_manager: &State<AppManager>) -> Result<Json<AppInstance>, String> {
+pub async fn restart_instance(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<AppInstance>, String> {
     // Restart container
     let options = Some(bollard::container::RestartContainerOptions {
         t: 30, // Give it 30 seconds to shut down gracefully
@@ -272,8 +1068,55 @@ pub async fn restart_instance(id: String, app_manager: &State<AppManager>) -> Re
         Err(e) => Err(format!("Failed to restart instance: {}", e))
     }
 }
+/// Writes the request body (up to 1 MiB) to a running instance's stdin. Instances created
+/// with `stdin_once` see EOF once the write completes.
+#[post("/instances/<id>/stdin", data = "<input>")]
+pub async fn write_instance_stdin(id: String, input: rocket::data::Data<'_>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<StdinWrite>, String> {
+    use rocket::data::ToByteUnit;
+
+    let container = app_manager.docker.inspect_container(&id, None).await
+        .map_err(|e| format!("Failed to inspect instance: {}", e))?;
+    if !container.config.and_then(|config| config.open_stdin).unwrap_or(false) {
+        return Err(format!("Instance {} was not created with stdin_open", id));
+    }
+    if !container.state.and_then(|state| state.running).unwrap_or(false) {
+        return Err(format!("Instance {} is not running", id));
+    }
+
+    let bytes = input.open(1.mebibytes()).into_bytes().await
+        .map_err(|e| format!("Failed to read input: {}", e))?;
+    if !bytes.is_complete() {
+        return Err("Input exceeds 1 MiB".to_string());
+    }
+
+    let options = Some(bollard::container::AttachContainerOptions::<String> {
+        stdin: Some(true),
+        stream: Some(true),
+        ..Default::default()
+    });
+    let mut attached = app_manager.docker.attach_container(&id, options).await
+        .map_err(|e| format!("Failed to attach to instance: {}", e))?;
+    let written = async {
+        attached.input.write_all(&bytes).await?;
+        attached.input.flush().await?;
+        attached.input.shutdown().await
+    };
+    written.await.map_err(|e| format!("Failed to write to stdin: {}", e))?;
+
+    Ok(Json(StdinWrite { bytes_written: bytes.len() }))
+}
+
 #[patch("/instances/<id>", format = "json", data = "<update_req>")]
-pub async fn update_instance(id: String, update_req: Json<AppInstanceRequest>, app_manager: &State<AppManager>) -> Result<Json<AppInstance>, String> {
+pub async fn update_instance(id: String, update_req: Json<AppInstanceRequest>, deploy: Deploy<'_>, slot: Slot<Create>) -> Result<Json<WithWarnings<AppInstance>>, CreateError> {
+    // The guard covered the instance's current namespace; the update may move it
+    deploy.key.authorize("update", "instances", update_req.namespace())?;
+    let (name, image) = (update_req.name.clone(), update_req.image.clone());
+    let result = update(id, update_req, &deploy, &slot).await;
+    run_deploy_hooks(deploy.app_manager, &name, &image, &result);
+    result
+}
+
+async fn update(id: String, update_req: Json<AppInstanceRequest>, deploy: &Deploy<'_>, slot: &Slot<Create>) -> Result<Json<WithWarnings<AppInstance>>, CreateError> {
     // For updating, we generally need to:
     // 1. Stop the existing container
     // 2. Remove it (but keep volumes if they're managed externally)
@@ -283,10 +1126,58 @@ pub async fn update_instance(id: String, update_req: Json<AppInstanceRequest>, a
     // This is a simplified implementation
     // In practice, you'd want to check what actually changed and handle it accordingly
     
+    let (app_manager, images, registry_cache) = (deploy.app_manager, deploy.images, deploy.registry_cache);
+
+    // Pull first so a bad image leaves the running instance alone
+    images.ensure_image(&app_manager.docker, registry_cache, &update_req.image).await?;
+
+    // Under the `update-rollback` flag the old container is renamed aside rather than
+    // removed, and only removed once its replacement is created. If creation fails it gets
+    // its name back and is restarted if it was running.
+    if app_manager.flags.is_enabled(flags::UPDATE_ROLLBACK, update_req.namespace()) {
+        let container = app_manager.docker.inspect_container(&id, None).await
+            .map_err(|e| format!("Failed to inspect instance for update: {}", e))?;
+        let id = container.id.unwrap_or(id);
+        let name = container.name.unwrap_or_default().trim_start_matches('/').to_string();
+        let running = container.state.and_then(|state| state.running).unwrap_or(false);
+
+        stop_instance(id.clone(), app_manager, deploy.mutation, deploy.key.clone()).await
+            .map_err(|e| format!("Failed to stop instance for update: {}", e))?;
+        let aside = format!("{}-replaced-{}", name, &id[..12.min(id.len())]);
+        app_manager.docker.rename_container(&id, RenameContainerOptions { name: aside.as_str() }).await
+            .map_err(|e| format!("Failed to rename instance for update: {}", e))?;
+
+        return match create(update_req, deploy, slot).await {
+            Ok(created) => {
+                let options = Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                });
+                if let Err(e) = app_manager.docker.remove_container(&id, options).await {
+                    eprintln!("Failed to remove replaced container {}: {}", aside, e);
+                }
+                app_manager.instances.lock().unwrap().remove(&id);
+                app_manager.forget(&id).await;
+                app_manager.audit("update", &id).await;
+                Ok(created)
+            },
+            Err(e) => {
+                if let Err(e) = app_manager.docker.rename_container(&id, RenameContainerOptions { name: name.as_str() }).await {
+                    eprintln!("Failed to restore the name of container {} after a failed update: {}", aside, e);
+                } else if running {
+                    if let Err(e) = app_manager.docker.start_container(&id, None::<StartContainerOptions<String>>).await {
+                        eprintln!("Failed to restart instance {} after a failed update: {}", name, e);
+                    }
+                }
+                Err(e)
+            }
+        };
+    }
+
     // First, stop the container
-    let stop_result = stop_instance(id.clone(), app_manager).await;
+    let stop_result = stop_instance(id.clone(), app_manager, deploy.mutation, deploy.key.clone()).await;
     if stop_result.is_err() {
-        return Err(format!("Failed to stop instance for update: {}", stop_result.err().unwrap()));
+        return Err(format!("Failed to stop instance for update: {}", stop_result.err().unwrap()).into());
     }
     
     // Then remove it
@@ -297,15 +1188,18 @@ pub async fn update_instance(id: String, update_req: Json<AppInstanceRequest>, a
     
     match app_manager.docker.remove_container(&id, options).await {
         Ok(_) => {
+            app_manager.instances.lock().unwrap().remove(&id);
+            app_manager.forget(&id).await;
+            app_manager.audit("update", &id).await;
             // Now create a new one with the updated config
-            create_instance(update_req, app_manager).await
+            create(update_req, deploy, slot).await
         },
-        Err(e) => Err(format!("Failed to remove instance for update: {}", e))
+        Err(e) => Err(format!("Failed to remove instance for update: {}", e).into())
     }
 }
 
 #[delete("/instances/<id>")]
-pub async fn delete_instance(id: String, app_manager: &State<AppManager>) -> Result<String, String> {
+pub async fn delete_instance(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<String, String> {
     // Remove container
     let options = Some(RemoveContainerOptions {
         force: true,
@@ -316,15 +1210,19 @@ pub async fn delete_instance(id: String, app_manager: &State<AppManager>) -> Res
         Ok(_) => {
             // Remove from our local state
             app_manager.instances.lock().unwrap().remove(&id);
+            app_manager.forget(&id).await;
+            app_manager.audit("delete", &id).await;
             Ok(format!("Instance {} deleted successfully", id))
         },
         Err(e) => Err(format!("Failed to delete instance: {}", e))
     }
 }
 
+/// Image tags; the CSV export adds each tag's image ID, size and creation time
 #[get("/images")]
-pub async fn list_images(app_manager: &State<AppManager>) -> Json<Vec<String>> {
+pub async fn list_images(app_manager: &State<AppManager>, format: ExportFormat) -> Export<Vec<String>> {
     let mut images = Vec::new();
+    let mut rows = Vec::new();
     
     // List images via Docker API
     let options = Some(ListImagesOptions::<String> {
@@ -337,6 +1235,7 @@ pub async fn list_images(app_manager: &State<AppManager>) -> Json<Vec<String>> {
             for image in image_list {
                 for tag in &image.repo_tags {
                     images.push(tag.clone());
+                    rows.push(ImageRow { tag: tag.clone(), id: image.id.clone(), size: image.size, created: image.created });
                 }
             }
         },
@@ -345,93 +1244,174 @@ pub async fn list_images(app_manager: &State<AppManager>) -> Json<Vec<String>> {
         }
     }
     
-    Json(images)
+    match format {
+        ExportFormat::Json => Export::Json(Json(images)),
+        ExportFormat::Csv => Export::Csv(export::to_csv(&rows)),
+    }
 }
 
-#[get("/events")]
-pub async fn stream_events(app_manager: &State<AppManager>) -> String {
-    // This would typically be implemented with Server-Sent Events or WebSockets
-    // For this example, we'll just demonstrate the Docker events API
-    
-    let options = Some(EventsOptions::<String> {
-        ..Default::default()
-    });
-    
-    let mut event_stream = app_manager.docker.events(options);
-    
-    // In a real implementation, you'd stream these to the client
-    // Here we'll just return a message
-    while let Some(event) = event_stream.next().await {
-        match event {
-            Ok(event) => {
-                println!("Event: {:?}", event);
-                // In a real implementation, send this to the client
-            },
-            Err(e) => {
-                eprintln!("Error receiving event: {}", e);
-                break;
-            }
+/// Forwards Docker events as server-sent events named by object type (`container`,
+/// `image`, ...), each carrying Docker's event message as JSON. Filter with comma-separated
+/// `event` actions, e.g. `start,die`, and `container` names or IDs.
+#[get("/events?<event>&<container>")]
+pub async fn stream_events(event: Option<String>, container: Option<String>, app_manager: &State<AppManager>) -> EventStream<BoxStream<'static, Event>> {
+    let mut filters = HashMap::new();
+    for (key, values) in [("event", event), ("container", container)] {
+        let values: Vec<String> = values.iter()
+            .flat_map(|values| values.split(','))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+            .collect();
+        if !values.is_empty() {
+            filters.insert(key.to_string(), values);
         }
     }
-    
-    "Event streaming would happen here".to_string()
+
+    let events = app_manager.docker.events(Some(EventsOptions::<String> {
+        filters,
+        ..Default::default()
+    }))
+        .scan(false, |ended, event| {
+            if *ended {
+                return futures::future::ready(None);
+            }
+            let event = match event {
+                Ok(event) => {
+                    let kind = event.typ.map(|typ| typ.to_string()).unwrap_or_else(|| "event".to_string());
+                    Event::json(&event).event(kind)
+                },
+                Err(e) => {
+                    *ended = true;
+                    Event::data(format!("Docker event stream ended: {}", e)).event("error")
+                },
+            };
+            futures::future::ready(Some(event))
+        })
+        .boxed();
+
+    EventStream::from(events)
 }
 
 #[get("/health")]
-pub fn health_check() -> String {
-    "App Manager is healthy".to_string()
-}
-
-#[get("/instances/<id>/logs")]
-pub async fn get_instance_logs(id: String, app_manager: &State<AppManager>) -> Result<String, String> {
-    let options = Some(bollard::container::LogsOptions::<String> {
-        stdout: true,
-        stderr: true,
-        follow: false,
-        timestamps: true,
-        tail: "100".to_string(),
-        ..Default::default()
-    });
+pub fn health_check(app_manager: &State<AppManager>) -> status::Custom<Json<HealthStatus>> {
+    let docker = app_manager.daemon_state();
+    let (code, status) = match docker {
+        DaemonState::Ready { .. } => (Status::Ok, "healthy"),
+        DaemonState::Connecting => (Status::ServiceUnavailable, "starting"),
+        DaemonState::Unavailable { .. } => (Status::ServiceUnavailable, "degraded"),
+    };
 
-    match app_manager.docker.logs(&id, options).try_collect::<Vec<_>>().await {
-        Ok(logs) => {
-            let log_content = logs.iter()
-                .map(|chunk| {
-                    match chunk {
-                        bollard::container::LogOutput::StdOut { message: bytes } | 
-                        bollard::container::LogOutput::StdErr { message: bytes } => {
-                            String::from_utf8_lossy(bytes).to_string()
-                        },
-                        bollard::container::LogOutput::StdIn { message: bytes } => {
-                            String::from_utf8_lossy(bytes).to_string()
-                        },
-                        bollard::container::LogOutput::Console { message: bytes } => {
-                            String::from_utf8_lossy(bytes).to_string()
-                        }
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join("");
-            Ok(log_content)
-        },
-        Err(e) => Err(format!("Failed to fetch logs: {}", e))
+    status::Custom(code, Json(HealthStatus {
+        status: status.to_string(),
+        docker,
+    }))
+}
+
+#[get("/instances/<id>/logs?<stream>&<tail>&<since>")]
+pub async fn get_instance_logs(id: String, stream: Option<String>, tail: Option<usize>, since: Option<i64>, app_manager: &State<AppManager>, slot: Slot<Logs>, _key: ApiKey) -> Result<Json<InstanceLogs>, String> {
+    instance_logs(id, stream, tail, since, app_manager, slot).await
+}
+
+/// The last `tail` log lines, for callers that have already been authorized
+pub async fn instance_logs(id: String, stream: Option<String>, tail: Option<usize>, since: Option<i64>, app_manager: &State<AppManager>, _slot: Slot<Logs>) -> Result<Json<InstanceLogs>, String> {
+    let stream = stream.unwrap_or_else(|| "all".to_string());
+    let (stdout, stderr) = logs::stream_selection(&stream)?;
+    let tail = tail.unwrap_or(100);
+
+    let parser = logs::parser_for(app_manager, &id).await?;
+
+    // Read the whole selection so lines outside the window can be counted rather than
+    // silently cut by Docker's own tail
+    let mut lines: std::collections::VecDeque<LogLine> = std::collections::VecDeque::with_capacity(tail + 1);
+    let mut dropped_lines = 0u64;
+    let rejoined_lines = logs::read_lines(&app_manager.docker, &id, stdout, stderr, since.unwrap_or(0), |mut line| {
+        if let Some(parser) = &parser {
+            parser.apply(&mut line);
+        }
+        lines.push_back(line);
+        if lines.len() > tail {
+            lines.pop_front();
+            dropped_lines += 1;
+        }
+    }).await?;
+
+    // Docker interleaves the streams; RFC 3339 timestamps with fixed precision sort lexically
+    let mut lines: Vec<LogLine> = lines.into_iter().collect();
+    lines.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+
+    Ok(Json(InstanceLogs {
+        stream,
+        lines,
+        dropped_lines,
+        rejoined_lines,
+    }))
+}
+
+/// Stats responses: one sample, or a stream of them as server-sent events
+pub enum StatsResponse {
+    Sample(Json<Box<bollard::container::Stats>>),
+    Stream(EventStream<BoxStream<'static, Event>>),
+}
+
+// Derived responders need one lifetime for every variant, which `EventStream` can't offer
+impl<'r> rocket::response::Responder<'r, 'r> for StatsResponse {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'r> {
+        match self {
+            StatsResponse::Sample(stats) => stats.respond_to(request),
+            StatsResponse::Stream(events) => events.respond_to(request),
+        }
     }
 }
 
-#[get("/instances/<id>/stats")]
-pub async fn get_instance_stats(id: String, app_manager: &State<AppManager>) -> Result<Json<bollard::container::Stats>, String> {
+/// One-shot stats; `memory_stats.usage` excludes reclaimable page cache, like `docker stats`.
+/// With `stream=true`, sends a `stats` event every `interval` seconds (default 1) until the
+/// client disconnects or the instance stops.
+#[get("/instances/<id>/stats?<stream>&<interval>")]
+pub async fn get_instance_stats(id: String, stream: Option<bool>, interval: Option<u64>, app_manager: &State<AppManager>, _slot: Slot<Stats>) -> Result<StatsResponse, String> {
+    if stream.unwrap_or(false) {
+        app_manager.docker.inspect_container(&id, None).await
+            .map_err(|e| format!("Failed to get stats: {}", e))?;
+
+        // Docker samples about once a second; keep one sample per interval
+        let interval = std::time::Duration::from_secs(interval.unwrap_or(1).max(1));
+        let mut next = std::time::Instant::now();
+        let samples = app_manager.docker.stats(&id, Some(bollard::container::StatsOptions {
+            stream: true,
+            one_shot: false,
+        }))
+            .take_while(|stats| futures::future::ready(stats.is_ok()))
+            .filter_map(move |stats| {
+                let now = std::time::Instant::now();
+                let event = match stats {
+                    Ok(mut stats) if now >= next => {
+                        next = now + interval - std::time::Duration::from_millis(100);
+                        stats.memory_stats.usage = cgroup::working_set(&stats.memory_stats);
+                        Some(Event::json(&stats).event("stats"))
+                    },
+                    _ => None,
+                };
+                futures::future::ready(event)
+            })
+            .boxed();
+        return Ok(StatsResponse::Stream(EventStream::from(samples)));
+    }
+
     match app_manager.docker.stats(&id, Some(bollard::container::StatsOptions { 
         stream: false,
         one_shot: true,
     })).try_next().await {
-        Ok(Some(stats)) => Ok(Json(stats)),
+        Ok(Some(mut stats)) => {
+            stats.memory_stats.usage = cgroup::working_set(&stats.memory_stats);
+            Ok(StatsResponse::Sample(Json(Box::new(stats))))
+        },
         Ok(None) => Err("No stats available".to_string()),
         Err(e) => Err(format!("Failed to get stats: {}", e))
     }
 }
 
 #[put("/instances/<id>/pause")]
-pub async fn pause_instance(id: String, app_manager: &State<AppManager>) -> Result<String, String> {
+pub async fn pause_instance(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<String, String> {
     match app_manager.docker.pause_container(&id).await {
         Ok(_) => Ok(format!("Instance {} paused", id)),
         Err(e) => Err(format!("Failed to pause instance: {}", e))
@@ -439,7 +1419,7 @@ pub async fn pause_instance(id: String, app_manager: &State<AppManager>) -> Resu
 }
 
 #[put("/instances/<id>/unpause")]
-pub async fn unpause_instance(id: String, app_manager: &State<AppManager>) -> Result<String, String> {
+pub async fn unpause_instance(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<String, String> {
     match app_manager.docker.unpause_container(&id).await {
         Ok(_) => Ok(format!("Instance {} unpaused", id)),
         Err(e) => Err(format!("Failed to unpause instance: {}", e))
@@ -454,16 +1434,190 @@ pub async fn inspect_instance(id: String, app_manager: &State<AppManager>) -> Re
     }
 }
 
-// Volume Management
+#[get("/instances/<id>/port-forward/<port>")]
+pub async fn port_forward_instance(id: String, port: u16, ws: WebSocket, app_manager: &State<AppManager>, _key: ApiKey) -> Result<Channel, String> {
+    let container = match app_manager.docker.inspect_container(&id, None).await {
+        Ok(container) => container,
+        Err(e) => return Err(format!("Failed to inspect instance: {}", e))
+    };
+
+    // Reach the container over whichever Docker network gives it an address
+    let ip = container.network_settings
+        .and_then(|settings| settings.networks)
+        .and_then(|networks| networks.into_values()
+            .filter_map(|endpoint| endpoint.ip_address)
+            .find(|ip| !ip.is_empty()))
+        .ok_or_else(|| format!("Instance {} has no reachable network address", id))?;
+
+    let tcp = match tokio::net::TcpStream::connect((ip.as_str(), port)).await {
+        Ok(tcp) => tcp,
+        Err(e) => return Err(format!("Failed to connect to {}:{}: {}", ip, port, e))
+    };
+
+    Ok(ws.channel(move |stream| Box::pin(async move {
+        let (mut ws_tx, mut ws_rx) = stream.split();
+        let (mut tcp_rx, mut tcp_tx) = tcp.into_split();
+
+        let upstream = async {
+            while let Some(message) = ws_rx.next().await {
+                match message.map_err(to_io_error)? {
+                    Message::Binary(data) => tcp_tx.write_all(&data).await?,
+                    Message::Text(text) => tcp_tx.write_all(text.as_bytes()).await?,
+                    Message::Close(_) => break,
+                    _ => {}
+                }
+            }
+            tcp_tx.shutdown().await
+        };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VolumeInfo {
-    name: String,
-    mountpoint: String,
-    labels: HashMap<String, String>,
-    created_at: String,
+        let downstream = async {
+            let mut buf = vec![0u8; 16 * 1024];
+            loop {
+                let n = tcp_rx.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                ws_tx.send(Message::Binary(buf[..n].to_vec())).await.map_err(to_io_error)?;
+            }
+            ws_tx.send(Message::Close(None)).await.map_err(to_io_error)
+        };
+
+        // Either side closing ends the tunnel
+        tokio::select! {
+            result = upstream => result,
+            result = downstream => result,
+        }
+    })))
 }
 
+/// Creates an exec in a running instance. Attach to it with a WebSocket to
+/// `/instances/<id>/exec/<exec_id>` to start it.
+#[post("/instances/<id>/exec", format = "json", data = "<exec_req>")]
+pub async fn create_exec(id: String, exec_req: Json<ExecRequest>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<ExecSession>, String> {
+    let container = app_manager.docker.inspect_container(&id, None).await
+        .map_err(|e| format!("Failed to inspect instance: {}", e))?;
+    if !container.state.and_then(|state| state.running).unwrap_or(false) {
+        return Err(format!("Instance {} is not running", id));
+    }
+
+    let exec_req = exec_req.into_inner();
+    let cmd = if exec_req.cmd.is_empty() { vec!["/bin/sh".to_string()] } else { exec_req.cmd };
+    let options = bollard::exec::CreateExecOptions {
+        attach_stdin: Some(true),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        tty: Some(exec_req.tty),
+        cmd: Some(cmd),
+        env: (!exec_req.env.is_empty()).then_some(exec_req.env),
+        working_dir: exec_req.working_dir,
+        user: exec_req.user,
+        ..Default::default()
+    };
+    let exec = app_manager.docker.create_exec(&id, options).await
+        .map_err(|e| format!("Failed to create exec: {}", e))?;
+
+    Ok(Json(ExecSession {
+        id: exec.id,
+        instance_id: container.id.unwrap_or(id),
+        tty: exec_req.tty,
+    }))
+}
+
+/// Checks that `exec_id` was created in instance `id`, returning whether it has a TTY
+pub(crate) async fn exec_of(app_manager: &AppManager, id: &str, exec_id: &str) -> Result<bool, String> {
+    let container = app_manager.docker.inspect_container(id, None).await
+        .map_err(|e| format!("Failed to inspect instance: {}", e))?;
+    let exec = app_manager.docker.inspect_exec(exec_id).await
+        .map_err(|e| format!("Failed to inspect exec: {}", e))?;
+    if exec.container_id.is_none() || exec.container_id != container.id {
+        return Err(format!("Exec {} does not belong to instance {}", exec_id, id));
+    }
+    Ok(exec.process_config.and_then(|process| process.tty).unwrap_or(false))
+}
+
+/// Starts an exec and bridges it to the WebSocket: incoming frames are its stdin and its
+/// output arrives as binary frames. A text frame holding an `ExecResize` resizes the TTY
+/// instead. The close frame's reason carries the exit code. Like `create_exec` it needs an
+/// API key whose roles may update the instance.
+#[get("/instances/<id>/exec/<exec_id>")]
+pub async fn attach_exec(id: String, exec_id: String, ws: WebSocket, app_manager: &State<AppManager>, _key: ApiKey) -> Result<Channel, String> {
+    exec_channel(id, exec_id, ws, app_manager).await
+}
+
+/// Bridges an exec session to a WebSocket for callers that have already been authorized
+pub async fn exec_channel(id: String, exec_id: String, ws: WebSocket, app_manager: &State<AppManager>) -> Result<Channel, String> {
+    let tty = exec_of(app_manager, &id, &exec_id).await?;
+
+    let started = app_manager.docker.start_exec(&exec_id, Some(bollard::exec::StartExecOptions {
+        detach: false,
+        tty,
+        output_capacity: None,
+    })).await
+        .map_err(|e| format!("Failed to start exec: {}", e))?;
+    let bollard::exec::StartExecResults::Attached { mut output, mut input } = started else {
+        return Err(format!("Exec {} started detached", exec_id));
+    };
+
+    let docker = app_manager.docker.clone();
+    Ok(ws.channel(move |stream| Box::pin(async move {
+        let (mut ws_tx, mut ws_rx) = stream.split();
+
+        let upstream = async {
+            while let Some(message) = ws_rx.next().await {
+                match message.map_err(to_io_error)? {
+                    Message::Text(text) => match rocket::serde::json::from_str::<ExecResize>(&text) {
+                        Ok(size) => {
+                            let resize = bollard::exec::ResizeExecOptions { height: size.rows, width: size.cols };
+                            if let Err(e) = docker.resize_exec(&exec_id, resize).await {
+                                eprintln!("Failed to resize exec {}: {}", exec_id, e);
+                            }
+                        },
+                        Err(_) => input.write_all(text.as_bytes()).await?,
+                    },
+                    Message::Binary(data) => input.write_all(&data).await?,
+                    Message::Close(_) => break,
+                    _ => {}
+                }
+                input.flush().await?;
+            }
+            input.shutdown().await?;
+            // Keep the session open for the rest of the output
+            std::future::pending::<()>().await;
+            Ok(())
+        };
+
+        let downstream = async {
+            while let Some(chunk) = output.next().await {
+                let chunk = chunk.map_err(std::io::Error::other)?;
+                ws_tx.send(Message::Binary(chunk.into_bytes().to_vec())).await.map_err(to_io_error)?;
+            }
+            let exit_code = docker.inspect_exec(&exec_id).await.ok().and_then(|exec| exec.exit_code);
+            let reason = exit_code.map(|code| format!("exit {}", code)).unwrap_or_default();
+            ws_tx.send(Message::Close(Some(tokio_tungstenite::tungstenite::protocol::CloseFrame {
+                code: tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode::Normal,
+                reason: reason.into(),
+            }))).await.map_err(to_io_error)
+        };
+
+        // The exec exiting ends the session; the client closing only ends its stdin
+        tokio::select! {
+            result = upstream => result,
+            result = downstream => result,
+        }
+    })))
+}
+
+#[put("/instances/<id>/exec/<exec_id>/resize", format = "json", data = "<size>")]
+pub async fn resize_exec(id: String, exec_id: String, size: Json<ExecResize>, app_manager: &State<AppManager>, _key: ApiKey) -> Result<String, String> {
+    exec_of(app_manager, &id, &exec_id).await?;
+    match app_manager.docker.resize_exec(&exec_id, bollard::exec::ResizeExecOptions { height: size.rows, width: size.cols }).await {
+        Ok(_) => Ok(format!("Exec {} resized to {}x{}", exec_id, size.cols, size.rows)),
+        Err(e) => Err(format!("Failed to resize exec: {}", e))
+    }
+}
+
+// Volume Management
+
 #[get("/volumes")]
 pub async fn list_volumes(app_manager: &State<AppManager>) -> Result<Json<Vec<VolumeInfo>>, String> {
     match app_manager.docker.list_volumes::<String>(None).await {
@@ -490,14 +1644,9 @@ pub async fn list_volumes(app_manager: &State<AppManager>) -> Result<Json<Vec<Vo
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VolumeCreateRequest {
-    name: String,
-    labels: Option<HashMap<String, String>>,
-}
-
 #[post("/volumes", format = "json", data = "<volume_req>")]
-pub async fn create_volume(volume_req: Json<VolumeCreateRequest>, app_manager: &State<AppManager>) -> Result<Json<VolumeInfo>, String> {
+pub async fn create_volume(volume_req: Json<VolumeCreateRequest>, app_manager: &State<AppManager>, _mutation: Mutation, key: ApiKey) -> Result<Json<VolumeInfo>, String> {
+    key.authorize("create", "volumes", rbac::label_namespace(volume_req.labels.as_ref()))?;
     let options = bollard::volume::CreateVolumeOptions {
         name: volume_req.name.clone(),
         labels: volume_req.labels.clone().unwrap_or_default(),
@@ -520,7 +1669,7 @@ pub async fn create_volume(volume_req: Json<VolumeCreateRequest>, app_manager: &
 }
 
 #[delete("/volumes/<name>")]
-pub async fn delete_volume(name: String, app_manager: &State<AppManager>) -> Result<String, String> {
+pub async fn delete_volume(name: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<String, String> {
     match app_manager.docker.remove_volume(&name, None).await {
         Ok(_) => Ok(format!("Volume {} deleted successfully", name)),
         Err(e) => Err(format!("Failed to delete volume: {}", e))
@@ -529,22 +1678,6 @@ pub async fn delete_volume(name: String, app_manager: &State<AppManager>) -> Res
 
 // Network Management
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NetworkInfo {
-    id: String,
-    name: String,
-    driver: String,
-    scope: String,
-    containers: HashMap<String, NetworkContainerInfo>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NetworkContainerInfo {
-    name: String,
-    endpoint_id: String,
-    ipv4_address: String,
-}
-
 #[get("/networks")]
 pub async fn list_networks(app_manager: &State<AppManager>) -> Result<Json<Vec<NetworkInfo>>, String> {
     match app_manager.docker.list_networks::<String>(None).await {
@@ -586,15 +1719,9 @@ pub async fn list_networks(app_manager: &State<AppManager>) -> Result<Json<Vec<N
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NetworkCreateRequest {
-    name: String,
-    driver: Option<String>,
-    labels: Option<HashMap<String, String>>,
-}
-
 #[post("/networks", format = "json", data = "<network_req>")]
-pub async fn create_network(network_req: Json<NetworkCreateRequest>, app_manager: &State<AppManager>) -> Result<Json<NetworkInfo>, String> {
+pub async fn create_network(network_req: Json<NetworkCreateRequest>, app_manager: &State<AppManager>, _mutation: Mutation, key: ApiKey) -> Result<Json<NetworkInfo>, String> {
+    key.authorize("create", "networks", rbac::label_namespace(network_req.labels.as_ref()))?;
     let options = bollard::network::CreateNetworkOptions {
         name: network_req.name.clone(),
         driver: network_req.driver.clone().unwrap_or_default(),
@@ -639,28 +1766,42 @@ pub async fn create_network(network_req: Json<NetworkCreateRequest>, app_manager
 }
 
 #[delete("/networks/<id>")]
-pub async fn delete_network(id: String, app_manager: &State<AppManager>) -> Result<String, String> {
+pub async fn delete_network(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<String, String> {
     match app_manager.docker.remove_network(&id).await {
         Ok(_) => Ok(format!("Network {} deleted successfully", id)),
         Err(e) => Err(format!("Failed to delete network: {}", e))
     }
 }
 
-#[put("/instances/<id>/connect/<network_id>")]
-pub async fn connect_instance_to_network(id: String, network_id: String, app_manager: &State<AppManager>) -> Result<String, String> {
+#[put("/instances/<id>/connect/<network_id>", data = "<endpoint_req>")]
+pub async fn connect_instance_to_network(id: String, network_id: String, endpoint_req: Option<Json<NetworkEndpointConfig>>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<bollard::models::EndpointSettings>, String> {
+    let endpoint = endpoint_req.map(|req| req.into_inner()).unwrap_or_default();
     let options = bollard::network::ConnectNetworkOptions {
         container: id.clone(),
-        ..Default::default()
+        endpoint_config: endpoint_settings(&endpoint),
     };
     
-    match app_manager.docker.connect_network(&network_id, options).await {
-        Ok(_) => Ok(format!("Instance {} connected to network {}", id, network_id)),
-        Err(e) => Err(format!("Failed to connect instance to network: {}", e))
+    if let Err(e) = app_manager.docker.connect_network(&network_id, options).await {
+        return Err(format!("Failed to connect instance to network: {}", e));
     }
+
+    // Read back the endpoint Docker actually configured
+    let container = match app_manager.docker.inspect_container(&id, None).await {
+        Ok(container) => container,
+        Err(e) => return Err(format!("Failed to inspect instance after connecting: {}", e))
+    };
+
+    container.network_settings
+        .and_then(|settings| settings.networks)
+        .and_then(|networks| networks.into_iter().find(|(name, endpoint)| {
+            *name == network_id || endpoint.network_id.as_deref().is_some_and(|net_id| net_id.starts_with(&network_id))
+        }))
+        .map(|(_, endpoint)| Json(endpoint))
+        .ok_or_else(|| format!("Instance {} is not attached to network {} after connecting", id, network_id))
 }
 
 #[put("/instances/<id>/disconnect/<network_id>")]
-pub async fn disconnect_instance_from_network(id: String, network_id: String, app_manager: &State<AppManager>) -> Result<String, String> {
+pub async fn disconnect_instance_from_network(id: String, network_id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<String, String> {
     let options = bollard::network::DisconnectNetworkOptions {
         container: id.clone(),
         force: false,
@@ -674,24 +1815,123 @@ pub async fn disconnect_instance_from_network(id: String, network_id: String, ap
 
 // Agent Management Routes
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AgentInfo {
-    id: String,
-    name: String,
-    version: String,
-    platform: String,
-    instance_count: usize,
-    status: String,
-    resources: SystemResources,
+/// Version of the capability schema; bump when fields change meaning
+const CAPABILITIES_SCHEMA_VERSION: u32 = 1;
+
+/// Optional agent features orchestrators can detect
+const AGENT_FEATURES: &[&str] = &[
+    "deferred_start",
+    "network_attachments",
+    "image_preload",
+    "registry_cache",
+    "port_forward",
+    "capacity_reservations",
+    "node_taints",
+    "cpu_pinning",
+    "device_passthrough",
+    "disk_pressure",
+    "bandwidth_limits",
+    "wireguard_mesh",
+    "userns_remap",
+    "seccomp_profiles",
+    "scheduling_constraints",
+    "preemption",
+];
+
+/// Detects NVIDIA GPUs through the kernel driver's procfs entries
+fn detect_gpu() -> Option<GpuInfo> {
+    let count = std::fs::read_dir("/proc/driver/nvidia/gpus").ok()?.count();
+    if count == 0 {
+        return None;
+    }
+
+    // e.g. "NVRM version: NVIDIA UNIX x86_64 Kernel Module  535.104.05  Sat Aug 19 ..."
+    let driver_version = std::fs::read_to_string("/proc/driver/nvidia/version").ok()
+        .and_then(|version| version.lines().next().map(str::to_string))
+        .and_then(|line| line.split("Kernel Module").nth(1).map(str::to_string))
+        .and_then(|rest| rest.split_whitespace().next().map(str::to_string));
+
+    Some(GpuInfo {
+        vendor: "nvidia".to_string(),
+        count,
+        driver_version,
+    })
+}
+
+/// Reads CPU and NUMA layout from sysfs; empty on hosts without it
+fn detect_topology() -> HostTopology {
+    let read = |path: &str| std::fs::read_to_string(path).ok().map(|s| s.trim().to_string());
+
+    let mut numa_nodes: Vec<NumaNode> = std::fs::read_dir("/sys/devices/system/node")
+        .map(|entries| entries.filter_map(|entry| entry.ok()).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|entry| {
+            let id = entry.file_name().to_str()?.strip_prefix("node")?.parse().ok()?;
+            let dir = entry.path();
+            let cpus = read(&format!("{}/cpulist", dir.display()))?;
+            // e.g. "Node 0 MemTotal:       32823716 kB"
+            let memory_total = read(&format!("{}/meminfo", dir.display()))
+                .and_then(|meminfo| meminfo.lines()
+                    .find(|line| line.contains("MemTotal:"))
+                    .and_then(|line| line.split_whitespace().rev().nth(1))
+                    .and_then(|kb| kb.parse::<u64>().ok()))
+                .unwrap_or(0) * 1024;
+            Some(NumaNode { id, cpus, memory_total })
+        })
+        .collect();
+    numa_nodes.sort_by_key(|node| node.id);
+
+    HostTopology {
+        cpus_online: read("/sys/devices/system/cpu/online"),
+        numa_nodes,
+    }
+}
+
+fn system_resources() -> SystemResources {
+    let (memory_total, memory_available) = host_stats::memory();
+    let disks = host_stats::provider().disks();
+    SystemResources {
+        cpu_count: num_cpus::get(),
+        memory_total,
+        memory_available,
+        disk_total: disks.iter().map(|disk| disk.total).sum(),
+        disk_available: disks.iter().map(|disk| disk.available).sum(),
+        disks,
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SystemResources {
-    cpu_count: usize,
-    memory_total: u64,
-    memory_available: u64,
-    disk_total: u64,
-    disk_available: u64,
+fn agent_capabilities(info: Option<&bollard::models::SystemInfo>, cpis: &CpiRegistry) -> AgentCapabilities {
+    let mut runtimes: Vec<String> = info
+        .and_then(|info| info.runtimes.as_ref())
+        .map(|runtimes| runtimes.keys().cloned().collect())
+        .unwrap_or_default();
+    runtimes.sort();
+
+    AgentCapabilities {
+        schema_version: CAPABILITIES_SCHEMA_VERSION,
+        architecture: std::env::consts::ARCH.to_string(),
+        runtimes,
+        default_runtime: info.and_then(|info| info.default_runtime.clone()),
+        gpu: detect_gpu(),
+        instance_kinds: vec!["container".to_string()],
+        cpi_providers: cpis.names(),
+        features: AGENT_FEATURES.iter().map(|f| f.to_string()).collect(),
+    }
+}
+
+/// Detects Docker Desktop and its WSL2 backend from the daemon's reported kernel and OS
+fn docker_backend(info: &bollard::models::SystemInfo) -> String {
+    let kernel = info.kernel_version.as_deref().unwrap_or_default().to_lowercase();
+    let os = info.operating_system.as_deref().unwrap_or_default();
+
+    if kernel.contains("wsl2") {
+        "wsl2".to_string()
+    } else if os.contains("Docker Desktop") {
+        "docker-desktop".to_string()
+    } else {
+        "native".to_string()
+    }
 }
 
 #[get("/agent/info")]
@@ -706,50 +1946,84 @@ pub async fn get_agent_info(app_manager: &State<AppManager>) -> Json<AgentInfo>
                 name: hostname::get().unwrap_or_default().to_string_lossy().to_string(),
                 version: "unknown".to_string(),
                 platform: "unknown".to_string(),
+                container_mode: "unknown".to_string(),
+                docker_backend: "unknown".to_string(),
                 instance_count: app_manager.instances.lock().unwrap().len(),
                 status: "degraded".to_string(),
-                resources: SystemResources {
-                    cpu_count: num_cpus::get(),
-                    memory_total: 0,
-                    memory_available: 0,
-                    disk_total: 0,
-                    disk_available: 0,
-                },
+                resources: system_resources(),
+                capacity: None,
+                capabilities: agent_capabilities(None, app_manager.cpis()),
+                labels: app_manager.node.labels(),
+                taints: app_manager.node.taints(),
+                topology: detect_topology(),
+                userns: userns::detect(None),
+                cgroup: cgroup::detect(None),
+                crypto: crypto::info(),
+                feature_flags: app_manager.flags.list(),
             });
         }
     };
     
-    // Get system resources
-    let memory_info = sys_info::mem_info().unwrap_or(sys_info::MemInfo {
-        total: 0,
-        free: 0,
-        avail: 0,
-        buffers: 0,
-        cached: 0,
-        swap_total: 0,
-        swap_free: 0,
-    });
-    
-    let disk_info = sys_info::disk_info().unwrap_or(sys_info::DiskInfo {
-        total: 0,
-        free: 0,
-    });
-    
+    let docker_backend = docker_backend(&info);
+    let capabilities = agent_capabilities(Some(&info), app_manager.cpis());
+    let userns = userns::detect(Some(&info));
+    let cgroup = cgroup::detect(Some(&info));
+    let capacity = match app_manager.capacity().await {
+        Ok(capacity) => Some(capacity),
+        Err(e) => {
+            eprintln!("Failed to compute resource capacity: {}", e);
+            None
+        }
+    };
+
     Json(AgentInfo {
         id: uuid::Uuid::new_v4().to_string(),
         name: hostname::get().unwrap_or_default().to_string_lossy().to_string(),
+        container_mode: info.os_type.clone().unwrap_or_else(|| "unknown".to_string()),
+        docker_backend,
         version: info.server_version.unwrap_or_default(),
         platform: format!("{} / {}", 
             info.operating_system.unwrap_or_default(),
             info.architecture.unwrap_or_default()),
         instance_count: app_manager.instances.lock().unwrap().len(),
-        status: "healthy".to_string(),
-        resources: SystemResources {
-            cpu_count: num_cpus::get(),
-            memory_total: memory_info.total * 1024,
-            memory_available: memory_info.avail * 1024,
-            disk_total: disk_info.total * 1024,
-            disk_available: disk_info.free * 1024,
-        },
+        status: if app_manager.maintenance.in_maintenance() { "maintenance" } else { "healthy" }.to_string(),
+        resources: system_resources(),
+        capacity,
+        capabilities,
+        labels: app_manager.node.labels(),
+        taints: app_manager.node.taints(),
+        topology: detect_topology(),
+        userns,
+        cgroup,
+        crypto: crypto::info(),
+        feature_flags: app_manager.flags.list(),
     })
 }
+
+#[get("/agent/docker")]
+pub async fn get_docker_info(app_manager: &State<AppManager>) -> Result<Json<DockerDaemonInfo>, String> {
+    let info = match app_manager.docker.info().await {
+        Ok(info) => info,
+        Err(e) => return Err(format!("Failed to get Docker info: {}", e))
+    };
+    let version = match app_manager.docker.version().await {
+        Ok(version) => version,
+        Err(e) => return Err(format!("Failed to get Docker version: {}", e))
+    };
+
+    Ok(Json(DockerDaemonInfo {
+        connection: app_manager.daemon_state(),
+        version: version.version.unwrap_or_default(),
+        api_version: version.api_version.unwrap_or_default(),
+        operating_system: info.operating_system.unwrap_or_default(),
+        architecture: info.architecture.unwrap_or_default(),
+        kernel_version: info.kernel_version.unwrap_or_default(),
+        storage_driver: info.driver.unwrap_or_default(),
+        docker_root_dir: info.docker_root_dir.unwrap_or_default(),
+        cgroup_driver: info.cgroup_driver.map(|d| d.to_string()).unwrap_or_default(),
+        cgroup_version: info.cgroup_version.map(|v| v.to_string()).unwrap_or_default(),
+        logging_driver: info.logging_driver.unwrap_or_default(),
+        default_runtime: info.default_runtime.unwrap_or_default(),
+        security_options: info.security_options.unwrap_or_default(),
+    }))
+}
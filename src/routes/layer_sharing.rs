@@ -0,0 +1,325 @@
+use rocket::get;
+use rocket::fs::NamedFile;
+use rocket::State;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use bollard::Docker;
+use bollard::image::ImportImageOptions;
+use futures::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::crypto;
+use super::access::{ApiKey, API_KEY_HEADER};
+use super::images::{self, ImageManager};
+
+/// Longest wait for the next chunk of a blob download
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where a blob came from, with the bytes transferred
+enum Source {
+    Cache,
+    Peer(u64),
+    Registry(u64),
+}
+
+/// Content-addressed cache of the compressed blobs of images pulled through it, which peer
+/// agents fetch instead of going to the registry. Every blob is checked against its digest
+/// whatever its source, so a peer can't serve altered layers.
+#[derive(Clone)]
+pub struct LayerSharing {
+    peers: Vec<String>,
+    /// Sent as `X-API-Key` to peers, whose blob endpoint needs one once they have keys
+    peer_key: Option<String>,
+    dir: PathBuf,
+    max_bytes: u64,
+    http: reqwest::Client,
+}
+
+impl LayerSharing {
+    /// Peers are the agent URLs in `OMNI_IMAGE_PEERS`, comma-separated, fetched from with the
+    /// API key in `OMNI_IMAGE_PEER_KEY`. Blobs are kept under `OMNI_STATE_DIR` up to
+    /// `OMNI_BLOB_CACHE_MAX_MB` (default 10240), oldest dropped first.
+    pub fn from_env() -> Self {
+        let state_dir = std::env::var("OMNI_STATE_DIR").unwrap_or_else(|_| "./state".to_string());
+        let max_mb = std::env::var("OMNI_BLOB_CACHE_MAX_MB").ok()
+            .and_then(|mb| mb.parse::<u64>().ok())
+            .filter(|mb| *mb > 0)
+            .unwrap_or(10240);
+
+        LayerSharing {
+            peers: std::env::var("OMNI_IMAGE_PEERS").unwrap_or_default()
+                .split(',')
+                .map(|peer| peer.trim().trim_end_matches('/').to_string())
+                .filter(|peer| !peer.is_empty())
+                .collect(),
+            peer_key: std::env::var("OMNI_IMAGE_PEER_KEY").ok().filter(|key| !key.is_empty()),
+            dir: Path::new(&state_dir).join("blobs").join("sha256"),
+            max_bytes: max_mb * 1024 * 1024,
+            http: crypto::http_builder()
+                .connect_timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.peers.is_empty()
+    }
+
+    /// Cache path of a `sha256:<hex>` digest; None for anything else, so requests can't
+    /// name other files
+    fn blob_path(&self, digest: &str) -> Option<PathBuf> {
+        let hex = digest.strip_prefix("sha256:")?;
+        let valid = hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        valid.then(|| self.dir.join(hex))
+    }
+
+    /// Downloads a blob into the cache, keeping it only if its content matches `digest`.
+    /// Ok(None) when the server doesn't have it.
+    async fn download(&self, request: reqwest::RequestBuilder, digest: &str, path: &Path) -> Result<Option<u64>, String> {
+        let mut response = request.send().await.map_err(|e| e.to_string())?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+
+        let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        let mut file = tokio::fs::File::create(&tmp).await
+            .map_err(|e| format!("Failed to create {}: {}", tmp.display(), e))?;
+        let result = async move {
+            let mut hasher = crypto::Sha256::default();
+            let mut size = 0;
+            while let Some(chunk) = tokio::time::timeout(READ_TIMEOUT, response.chunk()).await
+                .map_err(|_| "download stalled".to_string())?
+                .map_err(|e| e.to_string())?
+            {
+                hasher.update(&chunk);
+                size += chunk.len() as u64;
+                file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            }
+            file.flush().await.map_err(|e| e.to_string())?;
+
+            let actual = format!("sha256:{}", hasher.finish());
+            if actual != digest {
+                return Err(format!("content digest is {}", actual));
+            }
+            Ok(size)
+        }.await;
+
+        match result {
+            Ok(size) => {
+                tokio::fs::rename(&tmp, path).await
+                    .map_err(|e| format!("Failed to store blob {}: {}", digest, e))?;
+                Ok(Some(size))
+            },
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&tmp).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Puts a blob in the cache from the first peer that has it, else from the registry
+    async fn fetch(&self, base: &str, token: &Option<String>, digest: &str) -> Result<Source, String> {
+        let path = self.blob_path(digest).ok_or_else(|| format!("Unsupported digest {}", digest))?;
+        if tokio::fs::metadata(&path).await.is_ok() {
+            return Ok(Source::Cache);
+        }
+
+        for peer in &self.peers {
+            let mut request = self.http.get(format!("{}/blobs/{}", peer, digest));
+            if let Some(key) = &self.peer_key {
+                request = request.header(API_KEY_HEADER, key);
+            }
+            match self.download(request, digest, &path).await {
+                Ok(Some(size)) => return Ok(Source::Peer(size)),
+                Ok(None) => {},
+                Err(e) => log::error!("Failed to fetch blob {} from peer {}: {}", digest, peer, e),
+            }
+        }
+
+        let mut request = self.http.get(format!("{}/blobs/{}", base, digest));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        match self.download(request, digest, &path).await {
+            Ok(Some(size)) => Ok(Source::Registry(size)),
+            Ok(None) => Err(format!("Registry has no blob {}", digest)),
+            Err(e) => Err(format!("Failed to fetch blob {} from the registry: {}", digest, e)),
+        }
+    }
+
+    /// Pulls a tagged `image` blob by blob through the cache and peers, falling back to the
+    /// registry per blob, then loads it into Docker. Digest references aren't supported, as
+    /// loaded images carry no repository digest.
+    pub async fn pull(&self, docker: &Docker, image: &str, on_progress: impl Fn(f64)) -> Result<(), String> {
+        if image.contains('@') {
+            return Err("Digest references are not shared between peers".to_string());
+        }
+        let client = crypto::http_client();
+        let (base, manifest, token, _) = images::platform_manifest(&client, image).await?;
+        tokio::fs::create_dir_all(&self.dir).await
+            .map_err(|e| format!("Failed to create blob cache at {}: {}", self.dir.display(), e))?;
+
+        let config = manifest["config"]["digest"].as_str()
+            .ok_or_else(|| format!("Manifest for {} has no config", image))?
+            .to_string();
+        let layers: Vec<String> = manifest["layers"].as_array().into_iter().flatten()
+            .filter_map(|layer| layer["digest"].as_str())
+            .map(|digest| digest.to_string())
+            .collect();
+
+        let blobs: Vec<&String> = std::iter::once(&config).chain(&layers).collect();
+        let (mut from_peers, mut from_registry) = (0, 0);
+        for (i, digest) in blobs.iter().enumerate() {
+            match self.fetch(&base, &token, digest).await? {
+                Source::Peer(size) => from_peers += size,
+                Source::Registry(size) => from_registry += size,
+                Source::Cache => {},
+            }
+            on_progress((i + 1) as f64 / blobs.len() as f64 * 100.0);
+        }
+        log::info!("Fetched blobs of {}: {} bytes from peers, {} bytes from the registry", image, from_peers, from_registry);
+
+        let archive = self.dir.with_file_name(format!("load-{}.tar", uuid::Uuid::new_v4()));
+        let layout = ImageLayout {
+            image: image.to_string(),
+            manifest,
+            config,
+            layers,
+        };
+        let (sharing, path) = (self.clone(), archive.clone());
+        let written = tokio::task::spawn_blocking(move || sharing.write_layout(&layout, &path)).await
+            .map_err(|e| format!("Layout task failed: {}", e))?;
+        let result = match written {
+            Ok(()) => load(docker, image, &archive).await,
+            Err(e) => Err(format!("Failed to write image layout for {}: {}", image, e)),
+        };
+        let _ = tokio::fs::remove_file(&archive).await;
+
+        self.prune();
+        result
+    }
+
+    /// Writes an image tarball in both the OCI layout and Docker's `manifest.json` format, so
+    /// either image store can load it
+    fn write_layout(&self, layout: &ImageLayout, path: &Path) -> std::io::Result<()> {
+        let (registry, repo, tag) = images::parse_image_ref(&layout.image);
+        let registry = if registry == "registry-1.docker.io" { "docker.io".to_string() } else { registry };
+
+        let manifest = layout.manifest.to_string().into_bytes();
+        let manifest_digest = format!("sha256:{}", crypto::sha256_hex(&manifest));
+        let media_type = layout.manifest["mediaType"].as_str().unwrap_or("application/vnd.oci.image.manifest.v1+json");
+        let blob_name = |digest: &str| format!("blobs/sha256/{}", digest.trim_start_matches("sha256:"));
+
+        let index = rocket::serde::json::json!({
+            "schemaVersion": 2,
+            "manifests": [{
+                "mediaType": media_type,
+                "digest": manifest_digest,
+                "size": manifest.len(),
+                "annotations": {
+                    "io.containerd.image.name": format!("{}/{}:{}", registry, repo, tag),
+                    "org.opencontainers.image.ref.name": tag,
+                },
+            }],
+        });
+        let docker_manifest = rocket::serde::json::json!([{
+            "Config": blob_name(&layout.config),
+            "RepoTags": [layout.image],
+            "Layers": layout.layers.iter().map(|digest| blob_name(digest)).collect::<Vec<_>>(),
+        }]);
+
+        let mut archive = tar::Builder::new(std::fs::File::create(path)?);
+        append_file(&mut archive, "oci-layout", br#"{"imageLayoutVersion":"1.0.0"}"#)?;
+        append_file(&mut archive, "index.json", index.to_string().as_bytes())?;
+        append_file(&mut archive, "manifest.json", docker_manifest.to_string().as_bytes())?;
+        append_file(&mut archive, &blob_name(&manifest_digest), &manifest)?;
+
+        let mut written = HashSet::new();
+        for digest in std::iter::once(&layout.config).chain(&layout.layers) {
+            if written.insert(digest) {
+                let blob = self.blob_path(digest).unwrap_or_default();
+                archive.append_path_with_name(blob, blob_name(digest))?;
+            }
+        }
+        archive.into_inner()?.sync_all()
+    }
+
+    /// Drops the oldest blobs while the cache is over its size limit
+    fn prune(&self) {
+        let mut blobs: Vec<(std::time::SystemTime, u64, PathBuf)> = std::fs::read_dir(&self.dir).into_iter().flatten()
+            .flatten()
+            .filter(|entry| entry.path().extension().is_none())
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect();
+        blobs.sort();
+
+        let mut size: u64 = blobs.iter().map(|(_, len, _)| len).sum();
+        for (_, len, path) in blobs {
+            if size <= self.max_bytes {
+                break;
+            }
+            if std::fs::remove_file(&path).is_ok() {
+                size -= len;
+            }
+        }
+    }
+}
+
+/// What goes into the tarball loaded into Docker
+struct ImageLayout {
+    image: String,
+    manifest: rocket::serde::json::Value,
+    config: String,
+    layers: Vec<String>,
+}
+
+fn append_file(archive: &mut tar::Builder<std::fs::File>, name: &str, contents: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, name, contents)
+}
+
+/// Streams an image tarball into `docker load`
+async fn load(docker: &Docker, image: &str, archive: &Path) -> Result<(), String> {
+    let file = tokio::fs::File::open(archive).await
+        .map_err(|e| format!("Failed to open image layout for {}: {}", image, e))?;
+    let body = futures::stream::unfold(file, |mut file| async move {
+        let mut buffer = vec![0u8; 1024 * 1024];
+        match file.read(&mut buffer).await {
+            Ok(0) | Err(_) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((bytes::Bytes::from(buffer), file))
+            }
+        }
+    });
+
+    let mut stream = docker.import_image_stream(ImportImageOptions { quiet: true }, body, None);
+    while let Some(info) = stream.next().await {
+        let info = info.map_err(|e| format!("Failed to load {}: {}", image, e))?;
+        if let Some(message) = info.error {
+            return Err(format!("Failed to load {}: {}", image, message));
+        }
+    }
+    Ok(())
+}
+
+/// A cached image blob, for peer agents pulling the same image. Layers may come from
+/// private registries, so peers need an API key.
+#[get("/blobs/<digest>")]
+pub async fn get_blob(digest: String, image_manager: &State<Arc<ImageManager>>, _key: ApiKey) -> Option<NamedFile> {
+    let path = image_manager.layers().blob_path(&digest)?;
+    NamedFile::open(path).await.ok()
+}
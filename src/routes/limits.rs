@@ -0,0 +1,157 @@
+use rocket::get;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::State;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::access::AccessError;
+pub use omniagent_client::models::limits::LimitSaturation;
+
+/// One class of expensive endpoints sharing a pool of slots
+#[derive(Clone)]
+pub struct Limit {
+    max: usize,
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+    rejected: Arc<AtomicU64>,
+}
+
+impl Limit {
+    fn from_env(name: &str, default: usize) -> Self {
+        let max = std::env::var(name).ok()
+            .and_then(|max| max.parse().ok())
+            .filter(|max: &usize| *max > 0)
+            .unwrap_or(default);
+
+        Limit {
+            max,
+            semaphore: Arc::new(Semaphore::new(max)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            rejected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn saturation(&self, class: &str) -> LimitSaturation {
+        LimitSaturation {
+            class: class.to_string(),
+            max: self.max,
+            in_use: self.max - self.semaphore.available_permits(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Caps stats, log and create requests separately (`OMNI_LIMIT_STATS`, `OMNI_LIMIT_LOGS`,
+/// `OMNI_LIMIT_CREATE`) so a burst of one can't starve the others or the cheap endpoints.
+/// Requests wait up to `OMNI_LIMIT_WAIT_SECS` (default 10) for a slot before a 429.
+#[derive(Clone)]
+pub struct ConcurrencyLimits {
+    stats: Limit,
+    logs: Limit,
+    create: Limit,
+    wait: Duration,
+}
+
+impl ConcurrencyLimits {
+    pub fn from_env() -> Self {
+        let wait_secs = std::env::var("OMNI_LIMIT_WAIT_SECS").ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(10);
+
+        ConcurrencyLimits {
+            stats: Limit::from_env("OMNI_LIMIT_STATS", 8),
+            logs: Limit::from_env("OMNI_LIMIT_LOGS", 8),
+            create: Limit::from_env("OMNI_LIMIT_CREATE", 4),
+            wait: Duration::from_secs(wait_secs),
+        }
+    }
+
+    pub fn saturation(&self) -> Vec<LimitSaturation> {
+        vec![
+            self.stats.saturation(Stats::NAME),
+            self.logs.saturation(Logs::NAME),
+            self.create.saturation(Create::NAME),
+        ]
+    }
+}
+
+pub trait LimitClass: Send + Sync + 'static {
+    const NAME: &'static str;
+    fn limit(limits: &ConcurrencyLimits) -> &Limit;
+}
+
+pub struct Stats;
+pub struct Logs;
+pub struct Create;
+
+impl LimitClass for Stats {
+    const NAME: &'static str = "stats";
+    fn limit(limits: &ConcurrencyLimits) -> &Limit {
+        &limits.stats
+    }
+}
+
+impl LimitClass for Logs {
+    const NAME: &'static str = "logs";
+    fn limit(limits: &ConcurrencyLimits) -> &Limit {
+        &limits.logs
+    }
+}
+
+impl LimitClass for Create {
+    const NAME: &'static str = "create";
+    fn limit(limits: &ConcurrencyLimits) -> &Limit {
+        &limits.create
+    }
+}
+
+/// Request guard holding one slot of class `C` until the response is built
+pub struct Slot<C: LimitClass> {
+    _permit: Option<OwnedSemaphorePermit>,
+    _class: PhantomData<C>,
+}
+
+impl<C: LimitClass> Slot<C> {
+    /// A slot holding no permit, for work done on behalf of a request that already holds one
+    pub fn nested() -> Self {
+        Slot { _permit: None, _class: PhantomData }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, C: LimitClass> FromRequest<'r> for Slot<C> {
+    type Error = AccessError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(limits) = req.rocket().state::<ConcurrencyLimits>() else {
+            return Outcome::Success(Slot { _permit: None, _class: PhantomData });
+        };
+        let limit = C::limit(limits);
+
+        limit.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = tokio::time::timeout(limits.wait, limit.semaphore.clone().acquire_owned()).await;
+        limit.waiting.fetch_sub(1, Ordering::Relaxed);
+
+        match permit {
+            Ok(Ok(permit)) => Outcome::Success(Slot { _permit: Some(permit), _class: PhantomData }),
+            _ => {
+                limit.rejected.fetch_add(1, Ordering::Relaxed);
+                let error = AccessError::new("too_many_requests", &format!("Too many concurrent {} requests; retry shortly", C::NAME));
+                req.local_cache(|| Some(error.clone()));
+                Outcome::Error((Status::TooManyRequests, error))
+            }
+        }
+    }
+}
+
+// API Endpoints
+#[get("/agent/concurrency")]
+pub fn get_concurrency(limits: &State<ConcurrencyLimits>) -> Json<Vec<LimitSaturation>> {
+    Json(limits.saturation())
+}
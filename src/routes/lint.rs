@@ -0,0 +1,114 @@
+use rocket::post;
+use rocket::serde::json::Json;
+use rocket::State;
+use bollard::models::ImageConfig;
+
+use super::images;
+use super::instances::{check_resource_limits, AppInstanceRequest, AppManager};
+pub use omniagent_client::models::lint::{LintFinding, LintReport, LintSeverity};
+
+fn finding(rule: &str, severity: LintSeverity, field: Option<&str>, message: impl Into<String>) -> LintFinding {
+    LintFinding {
+        rule: rule.to_string(),
+        severity,
+        field: field.map(str::to_string),
+        message: message.into(),
+    }
+}
+
+/// `root`, `0`, or either with a group
+fn is_root(user: &str) -> bool {
+    let user = user.split(':').next().unwrap_or_default();
+    user.is_empty() || user == "root" || user == "0"
+}
+
+/// Best-practice checks on a spec. `image` is the image's config when it is present locally;
+/// without it the healthcheck rule is skipped and the user rule assumes the image runs as root.
+pub fn lint(app_req: &AppInstanceRequest, image: Option<&ImageConfig>) -> Vec<LintFinding> {
+    use LintSeverity::{Error, Info, Warning};
+    let mut findings = Vec::new();
+
+    if app_req.name.is_empty() {
+        findings.push(finding("name_required", Error, Some("name"), "name is empty"));
+    }
+    if app_req.image.is_empty() {
+        findings.push(finding("image_required", Error, Some("image"), "image is empty"));
+    }
+    if let Err(e) = check_resource_limits(app_req) {
+        findings.push(finding("resource_limits", Error, None, e));
+    }
+
+    let (_, _, reference) = images::parse_image_ref(&app_req.image);
+    if !app_req.image.is_empty() && reference == "latest" {
+        findings.push(finding("image_latest_tag", Warning, Some("image"),
+            format!("Image {} uses the latest tag; pin a tag or digest for repeatable deploys", app_req.image)));
+    }
+    if app_req.memory_limit.is_none() {
+        findings.push(finding("memory_limit", Warning, Some("memory_limit"), "No memory limit is set, so the instance can use all host memory"));
+    }
+    if app_req.cpu_limit.is_none() {
+        findings.push(finding("cpu_limit", Warning, Some("cpu_limit"), "No CPU limit is set, so the instance can use every core"));
+    }
+
+    let image_healthcheck = image.and_then(|config| config.healthcheck.as_ref())
+        .and_then(|healthcheck| healthcheck.test.as_ref())
+        .is_some_and(|test| test.first().is_some_and(|kind| kind != "NONE"));
+    if image.is_some() && !image_healthcheck && app_req.log_health.is_none() {
+        findings.push(finding("healthcheck", Warning, None, "Neither the image nor the spec defines a health check; set log_health or add a HEALTHCHECK to the image"));
+    }
+
+    match app_req.user.as_deref() {
+        Some(user) if is_root(user) => {
+            findings.push(finding("non_root_user", Warning, Some("user"), format!("user {} runs the instance as root", user)));
+        },
+        Some(_) => {},
+        None => match image.and_then(|config| config.user.as_deref()) {
+            Some(user) if !is_root(user) => {},
+            Some(_) => findings.push(finding("non_root_user", Warning, Some("user"), "The image runs as root and the spec doesn't set user")),
+            None => findings.push(finding("non_root_user", Warning, Some("user"), "user isn't set, so the instance runs as the image's user, which is often root")),
+        },
+    }
+
+    if app_req.read_only != Some(true) {
+        findings.push(finding("read_only_rootfs", Info, Some("read_only"), "The root filesystem is writable; set read_only if the app only writes to volumes"));
+    }
+    for port in app_req.ports.iter().flatten().filter(|port| port.host_port != 0 && port.host_port < 1024) {
+        findings.push(finding("privileged_port", Info, Some("ports"),
+            format!("Host port {} is below 1024 and needs elevated privileges on most hosts", port.host_port)));
+    }
+    if app_req.userns_mode.as_deref() == Some("host") {
+        findings.push(finding("userns_host", Warning, Some("userns_mode"), "userns_mode host runs the instance without user namespace remapping"));
+    }
+
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    findings
+}
+
+fn parse_severity(severity: &str) -> Result<LintSeverity, String> {
+    match severity {
+        "info" => Ok(LintSeverity::Info),
+        "warning" => Ok(LintSeverity::Warning),
+        "error" => Ok(LintSeverity::Error),
+        other => Err(format!("Unknown severity {}; expected info, warning or error", other)),
+    }
+}
+
+/// Lints an instance spec without deploying it, for CI to gate manifests. `passed` is false
+/// when a finding is at or above `fail_on` (default `error`). The image is inspected if it is
+/// already on this agent; nothing is pulled.
+#[post("/lint?<fail_on>", format = "json", data = "<app_req>")]
+pub async fn lint_spec(app_req: Json<AppInstanceRequest>, fail_on: Option<String>, app_manager: &State<AppManager>) -> Result<Json<LintReport>, String> {
+    let fail_on = parse_severity(fail_on.as_deref().unwrap_or("error"))?;
+    let image = if app_req.image.is_empty() {
+        None
+    } else {
+        app_manager.docker().inspect_image(&app_req.image).await.ok().and_then(|image| image.config)
+    };
+
+    let findings = lint(&app_req, image.as_ref());
+    Ok(Json(LintReport {
+        passed: findings.iter().all(|finding| finding.severity < fail_on),
+        image_inspected: image.is_some(),
+        findings,
+    }))
+}
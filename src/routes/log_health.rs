@@ -0,0 +1,201 @@
+use rocket::get;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use bollard::container::{ListContainersOptions, LogOutput, LogsOptions};
+use futures::StreamExt;
+use regex::Regex;
+
+use crate::event_bus::{AgentEvent, EventBus};
+use super::instances::{AppManager, LogLine};
+use super::logs::{self, Parser};
+pub use omniagent_client::models::logs::{LogHealth, LogHealthStatus};
+
+const DEFAULT_PATTERN: &str = r"(?i)\b(error|exception|fatal|panic)\b";
+/// Parsed levels counted as errors
+const ERROR_LEVELS: &[&str] = &["error", "err", "fatal", "critical", "crit", "alert", "emergency", "panic"];
+const WINDOW: Duration = Duration::from_secs(60);
+
+pub fn compile(health: &LogHealth) -> Result<Vec<Regex>, String> {
+    if health.threshold_per_minute == 0 {
+        return Err("log_health.threshold_per_minute must be at least 1".to_string());
+    }
+    let patterns: Vec<&str> = match health.patterns.is_empty() {
+        true => vec![DEFAULT_PATTERN],
+        false => health.patterns.iter().map(String::as_str).collect(),
+    };
+    patterns.into_iter()
+        .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid log_health pattern {}: {}", pattern, e)))
+        .collect()
+}
+
+struct Watch {
+    matches: VecDeque<Instant>,
+    status: LogHealthStatus,
+}
+
+/// Follows the logs of running instances that set `log_health` and alerts when their
+/// error rate crosses the threshold or falls back under it
+#[derive(Clone, Default)]
+pub struct LogHealthMonitor {
+    watches: Arc<Mutex<HashMap<String, Watch>>>,
+    /// Instances with a log follower running
+    following: Arc<Mutex<HashSet<String>>>,
+}
+
+impl LogHealthMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self, id: &str) -> Option<LogHealthStatus> {
+        self.watches.lock().unwrap().get(id).map(|watch| watch.status.clone())
+    }
+
+    fn record(&self, id: &str, message: &str) {
+        if let Some(watch) = self.watches.lock().unwrap().get_mut(id) {
+            watch.matches.push_back(Instant::now());
+            watch.status.last_error = Some(message.chars().take(500).collect());
+        }
+    }
+
+    fn follow(&self, app_manager: &AppManager, id: String, patterns: Vec<Regex>, parser: Option<Parser>) {
+        let monitor = self.clone();
+        let docker = app_manager.docker().clone();
+
+        tokio::spawn(async move {
+            let mut logs = docker.logs(&id, Some(LogsOptions::<String> {
+                stdout: true,
+                stderr: true,
+                follow: true,
+                since: chrono::Utc::now().timestamp(),
+                ..Default::default()
+            }));
+            while let Some(Ok(chunk)) = logs.next().await {
+                let (LogOutput::StdOut { message } | LogOutput::StdErr { message } | LogOutput::Console { message }) = chunk else {
+                    continue;
+                };
+                for text in String::from_utf8_lossy(&message).lines() {
+                    let mut line = LogLine {
+                        stream: String::new(),
+                        timestamp: String::new(),
+                        message: text.to_string(),
+                        level: None,
+                        fields: Default::default(),
+                    };
+                    if let Some(parser) = &parser {
+                        parser.apply(&mut line);
+                    }
+                    let is_error = line.level.as_deref().is_some_and(|level| ERROR_LEVELS.contains(&level))
+                        || patterns.iter().any(|pattern| pattern.is_match(text));
+                    if is_error {
+                        monitor.record(&id, text);
+                    }
+                }
+            }
+            monitor.following.lock().unwrap().remove(&id);
+        });
+    }
+
+    /// Every 15 seconds starts followers for newly running instances, re-evaluates each
+    /// instance's rate over the last minute and forgets instances that are gone
+    pub fn start(&self, app_manager: AppManager, bus: EventBus) {
+        let monitor = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(15)).await;
+
+                let running: HashSet<String> = match app_manager.docker().list_containers(Some(ListContainersOptions::<String> {
+                    filters: HashMap::from([("status".to_string(), vec!["running".to_string()])]),
+                    ..Default::default()
+                })).await {
+                    Ok(containers) => containers.into_iter().filter_map(|container| container.id).collect(),
+                    Err(_) => continue,
+                };
+                let specs = match app_manager.managed_specs().await {
+                    Ok(specs) => specs,
+                    Err(e) => {
+                        log::error!("Failed to read specs for log health: {}", e);
+                        continue;
+                    }
+                };
+
+                let mut watched = HashSet::new();
+                for (id, spec) in specs {
+                    let Some(health) = spec.log_health.filter(|_| running.contains(&id)) else {
+                        continue;
+                    };
+                    watched.insert(id.clone());
+                    if !monitor.following.lock().unwrap().insert(id.clone()) {
+                        continue;
+                    }
+
+                    let patterns = match compile(&health) {
+                        Ok(patterns) => patterns,
+                        Err(e) => {
+                            log::warn!("Log health for {} disabled: {}", id, e);
+                            continue;
+                        }
+                    };
+                    let parser = spec.log_parsing.as_ref().and_then(|parsing| logs::compile(parsing).ok());
+                    monitor.watches.lock().unwrap().entry(id.clone()).or_insert_with(|| Watch {
+                        matches: VecDeque::new(),
+                        status: LogHealthStatus {
+                            instance_id: id.clone(),
+                            healthy: true,
+                            errors_last_minute: 0,
+                            threshold_per_minute: health.threshold_per_minute,
+                            last_error: None,
+                            since: chrono::Utc::now().to_rfc3339(),
+                        },
+                    });
+                    monitor.follow(&app_manager, id, patterns, parser);
+                }
+
+                let mut alerts = Vec::new();
+                {
+                    let mut watches = monitor.watches.lock().unwrap();
+                    watches.retain(|id, _| watched.contains(id));
+                    for (id, watch) in watches.iter_mut() {
+                        while watch.matches.front().is_some_and(|at| at.elapsed() > WINDOW) {
+                            watch.matches.pop_front();
+                        }
+                        let status = &mut watch.status;
+                        status.errors_last_minute = watch.matches.len() as u32;
+                        let healthy = status.errors_last_minute < status.threshold_per_minute;
+                        if healthy == status.healthy {
+                            continue;
+                        }
+                        status.healthy = healthy;
+                        status.since = chrono::Utc::now().to_rfc3339();
+                        alerts.push(match healthy {
+                            true => ("info", format!("Instance {} error rate back to {} per minute", id, status.errors_last_minute)),
+                            false => ("warning", format!("Instance {} logged {} errors in the last minute, over its threshold of {}", id, status.errors_last_minute, status.threshold_per_minute)),
+                        });
+                    }
+                }
+                for (severity, message) in alerts {
+                    match severity {
+                        "warning" => log::warn!("{}", message),
+                        _ => log::info!("{}", message),
+                    }
+                    bus.publish(AgentEvent::Alert {
+                        severity: severity.to_string(),
+                        source: "log_health".to_string(),
+                        message,
+                        timestamp: chrono::Utc::now().to_rfc3339(),
+                    });
+                }
+            }
+        });
+    }
+}
+
+// API Endpoints
+#[get("/instances/<id>/log-health")]
+pub fn get_log_health(id: String, monitor: &State<LogHealthMonitor>) -> Option<Json<LogHealthStatus>> {
+    monitor.status(&id).map(Json)
+}
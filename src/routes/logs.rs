@@ -0,0 +1,354 @@
+use rocket::{get, put, FromForm};
+use rocket::response::stream::{Event, EventStream, TextStream};
+use rocket::serde::json::{Json, Value};
+use rocket::State;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use bollard::Docker;
+use bollard::container::{ListContainersOptions, LogOutput, LogsOptions};
+use futures::stream::{self, BoxStream, StreamExt};
+use regex::Regex;
+
+use super::access::{ApiKey, Mutation};
+use super::instances::{AppManager, InstanceLogs, LogLine};
+use super::limits::{Logs, Slot};
+pub use omniagent_client::models::logs::LogParsing;
+
+/// ANSI colours cycled through per instance, in the order `docker compose` uses
+const COLORS: &[&str] = &["36", "33", "32", "35", "34", "31", "96", "93", "92", "95", "94", "91"];
+
+/// Field names that set a parsed line's level
+const LEVEL_FIELDS: &[&str] = &["level", "severity", "lvl"];
+
+/// Log parsing rules ready to apply
+pub enum Parser {
+    Json,
+    Regex(Regex),
+}
+
+pub fn compile(parsing: &LogParsing) -> Result<Parser, String> {
+    match parsing {
+        LogParsing::Json => Ok(Parser::Json),
+        LogParsing::Regex { pattern } => {
+            let regex = Regex::new(pattern).map_err(|e| format!("Invalid log pattern: {}", e))?;
+            if regex.capture_names().flatten().next().is_none() {
+                return Err("Log pattern needs at least one named capture, e.g. (?P<level>\\w+)".to_string());
+            }
+            Ok(Parser::Regex(regex))
+        },
+    }
+}
+
+impl Parser {
+    /// Fills in the line's fields and level; lines that don't match are left as they are
+    pub fn apply(&self, line: &mut LogLine) {
+        match self {
+            Parser::Json => {
+                let Ok(Value::Object(object)) = rocket::serde::json::from_str::<Value>(&line.message) else {
+                    return;
+                };
+                line.fields = object.into_iter()
+                    .map(|(key, value)| match value {
+                        Value::String(value) => (key, value),
+                        value => (key, value.to_string()),
+                    })
+                    .collect();
+            },
+            Parser::Regex(regex) => {
+                let Some(captures) = regex.captures(&line.message) else {
+                    return;
+                };
+                line.fields = regex.capture_names().flatten()
+                    .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().to_string())))
+                    .collect();
+            },
+        }
+        line.level = LEVEL_FIELDS.iter()
+            .find_map(|field| line.fields.get(*field))
+            .map(|level| level.to_lowercase());
+    }
+}
+
+/// The compiled parsing rules of a managed instance, if it has any
+pub async fn parser_for(app_manager: &AppManager, id: &str) -> Result<Option<Parser>, String> {
+    match app_manager.spec(id).await?.and_then(|spec| spec.log_parsing) {
+        Some(parsing) => compile(&parsing).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Which streams a `stream` query parameter selects, as `(stdout, stderr)`
+pub fn stream_selection(stream: &str) -> Result<(bool, bool), String> {
+    match stream {
+        "stdout" => Ok((true, false)),
+        "stderr" => Ok((false, true)),
+        "all" => Ok((true, true)),
+        other => Err(format!("Unknown log stream {}; expected stdout, stderr or all", other)),
+    }
+}
+
+/// Rebuilds lines from timestamped Docker log frames, joining lines Docker split at its
+/// 16 KiB buffer
+#[derive(Default)]
+struct Rejoiner {
+    /// Per-stream fragment of a line still waiting for its end
+    partial: HashMap<&'static str, LogLine>,
+    rejoined: u64,
+}
+
+impl Rejoiner {
+    /// The line this frame completes, if any
+    fn push(&mut self, output: LogOutput) -> Option<LogLine> {
+        let (stream_name, bytes) = match output {
+            LogOutput::StdOut { message } => ("stdout", message),
+            LogOutput::StdErr { message } => ("stderr", message),
+            LogOutput::Console { message } => ("stdout", message),
+            LogOutput::StdIn { .. } => return None,
+        };
+
+        let text = String::from_utf8_lossy(&bytes);
+        let (timestamp, message) = text.split_once(' ').unwrap_or(("", &text));
+        let complete = message.ends_with('\n');
+        let message = message.trim_end_matches('\n');
+
+        let line = match self.partial.remove(stream_name) {
+            Some(mut line) => {
+                self.rejoined += 1;
+                line.message.push_str(message);
+                line
+            },
+            None => LogLine {
+                stream: stream_name.to_string(),
+                timestamp: timestamp.to_string(),
+                message: message.to_string(),
+                level: None,
+                fields: BTreeMap::new(),
+            },
+        };
+        if !complete {
+            self.partial.insert(stream_name, line);
+            return None;
+        }
+        Some(line)
+    }
+}
+
+/// Reads an instance's logs line by line, joining lines Docker split at its 16 KiB buffer.
+/// Returns how many lines were rejoined.
+pub async fn read_lines(docker: &Docker, id: &str, stdout: bool, stderr: bool, since: i64, mut on_line: impl FnMut(LogLine)) -> Result<u64, String> {
+    let options = Some(LogsOptions::<String> {
+        stdout,
+        stderr,
+        follow: false,
+        timestamps: true,
+        since,
+        tail: "all".to_string(),
+        ..Default::default()
+    });
+
+    let mut rejoiner = Rejoiner::default();
+    let mut logs = docker.logs(id, options);
+    while let Some(chunk) = logs.next().await {
+        if let Some(line) = rejoiner.push(chunk.map_err(|e| format!("Failed to fetch logs: {}", e))?) {
+            on_line(line);
+        }
+    }
+    let rejoined = rejoiner.rejoined;
+    rejoiner.partial.into_values().for_each(on_line);
+    Ok(rejoined)
+}
+
+/// Resolves `instances` (names, IDs or ID prefixes) and `label` (`key` or `key=value`) to
+/// `(id, name)` pairs
+async fn select(app_manager: &AppManager, instances: Option<&str>, label: Option<&str>) -> Result<Vec<(String, String)>, String> {
+    let mut filters = HashMap::new();
+    if let Some(label) = label {
+        filters.insert("label".to_string(), vec![label.to_string()]);
+    }
+    let containers = app_manager.docker().list_containers(Some(ListContainersOptions::<String> {
+        all: true,
+        filters,
+        ..Default::default()
+    })).await.map_err(|e| format!("Failed to list containers: {}", e))?;
+
+    let containers: Vec<(String, String)> = containers.into_iter()
+        .filter_map(|container| {
+            let name = container.names?.first()?.trim_start_matches('/').to_string();
+            Some((container.id?, name))
+        })
+        .collect();
+
+    let Some(instances) = instances else {
+        return Ok(containers);
+    };
+    let mut selected = Vec::new();
+    for wanted in instances.split(',').map(str::trim).filter(|wanted| !wanted.is_empty()) {
+        let found = containers.iter()
+            .find(|(id, name)| name == wanted || id.starts_with(wanted))
+            .ok_or_else(|| format!("Instance {} not found", wanted))?;
+        if !selected.contains(found) {
+            selected.push(found.clone());
+        }
+    }
+    Ok(selected)
+}
+
+// API Endpoints
+
+/// Follows the logs of several instances at once, like `docker compose logs -f`. Each line is
+/// prefixed with its instance name, padded to align and coloured unless `color=false`.
+/// Select instances with `instances=a,b,c`, a `label` selector, or both.
+#[get("/logs/stream?<instances>&<label>&<tail>&<color>")]
+pub async fn stream_logs(instances: Option<String>, label: Option<String>, tail: Option<usize>, color: Option<bool>, app_manager: &State<AppManager>, _slot: Slot<Logs>, _key: ApiKey) -> Result<TextStream<BoxStream<'static, String>>, String> {
+    if instances.is_none() && label.is_none() {
+        return Err("Select instances with instances=a,b,c or label=key=value".to_string());
+    }
+    let selected = select(app_manager, instances.as_deref(), label.as_deref()).await?;
+    if selected.is_empty() {
+        return Err("No instances match the selection".to_string());
+    }
+
+    let width = selected.iter().map(|(_, name)| name.len()).max().unwrap_or(0);
+    let color = color.unwrap_or(true);
+    let tail = tail.unwrap_or(10).to_string();
+
+    let streams = selected.into_iter().enumerate().map(|(i, (id, name))| {
+        let prefix = match color {
+            true => format!("\x1b[{}m{:<width$} |\x1b[0m ", COLORS[i % COLORS.len()], name, width = width),
+            false => format!("{:<width$} | ", name, width = width),
+        };
+        app_manager.docker().logs(&id, Some(LogsOptions::<String> {
+            stdout: true,
+            stderr: true,
+            follow: true,
+            tail: tail.clone(),
+            ..Default::default()
+        }))
+        .map(move |chunk| match chunk {
+            Ok(LogOutput::StdOut { message } | LogOutput::StdErr { message } | LogOutput::Console { message }) => {
+                String::from_utf8_lossy(&message).lines()
+                    .map(|line| format!("{}{}\n", prefix, line))
+                    .collect::<String>()
+            },
+            Ok(LogOutput::StdIn { .. }) => String::new(),
+            Err(e) => format!("{}log stream ended: {}\n", prefix, e),
+        })
+        .boxed()
+    });
+
+    Ok(TextStream::from(stream::select_all(streams).boxed()))
+}
+
+/// Follows one instance's logs as server-sent events: a `stdout` or `stderr` event per line
+/// carrying the `LogLine` as JSON, parsed by the instance's log parsing rules. Starts with
+/// the last `tail` lines (default 100) or those since the `since` Unix timestamp.
+#[get("/instances/<id>/logs/stream?<stream>&<tail>&<since>")]
+pub async fn follow_instance_logs(id: String, stream: Option<String>, tail: Option<usize>, since: Option<i64>, app_manager: &State<AppManager>, _slot: Slot<Logs>, _key: ApiKey) -> Result<EventStream<BoxStream<'static, Event>>, String> {
+    let (stdout, stderr) = stream_selection(stream.as_deref().unwrap_or("all"))?;
+    app_manager.docker().inspect_container(&id, None).await
+        .map_err(|e| format!("Failed to inspect instance: {}", e))?;
+    let parser = parser_for(app_manager, &id).await?;
+
+    let mut rejoiner = Rejoiner::default();
+    let events = app_manager.docker().logs(&id, Some(LogsOptions::<String> {
+        stdout,
+        stderr,
+        follow: true,
+        timestamps: true,
+        since: since.unwrap_or(0),
+        tail: if since.is_some() { "all".to_string() } else { tail.unwrap_or(100).to_string() },
+        ..Default::default()
+    }))
+        .filter_map(move |chunk| {
+            let event = match chunk {
+                Ok(output) => rejoiner.push(output).map(|mut line| {
+                    if let Some(parser) = &parser {
+                        parser.apply(&mut line);
+                    }
+                    Event::json(&line).event(line.stream.clone())
+                }),
+                Err(e) => Some(Event::data(format!("Log stream ended: {}", e)).event("error")),
+            };
+            futures::future::ready(event)
+        })
+        .boxed();
+
+    Ok(EventStream::from(events))
+}
+
+/// Sets or, with `null`, clears the instance's log parsing rules
+#[put("/instances/<id>/log-parsing", format = "json", data = "<parsing>")]
+pub async fn set_log_parsing(id: String, parsing: Json<Option<LogParsing>>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<Option<LogParsing>>, String> {
+    let mut spec = app_manager.spec(&id).await?
+        .ok_or_else(|| format!("Instance {} is not managed by this agent", id))?;
+
+    let parsing = parsing.into_inner();
+    if let Some(parsing) = &parsing {
+        compile(parsing)?;
+    }
+    spec.log_parsing = parsing.clone();
+    app_manager.save_spec(&id, &spec).await?;
+    Ok(Json(parsing))
+}
+
+/// Query parameters of a log search
+#[derive(FromForm)]
+pub struct LogSearch {
+    q: Option<String>,
+    level: Option<String>,
+    /// `key` or `key=value`
+    field: Vec<String>,
+    since: Option<i64>,
+    limit: Option<usize>,
+}
+
+/// Searches an instance's logs, keeping the last `limit` matches (default 100). `q` matches a
+/// substring of the message; `level` and each `field` (`key` or `key=value`) match parsed
+/// fields, so they need the instance to have log parsing rules.
+#[get("/instances/<id>/logs/search?<search..>")]
+pub async fn search_instance_logs(id: String, search: LogSearch, app_manager: &State<AppManager>, _slot: Slot<Logs>, _key: ApiKey) -> Result<Json<InstanceLogs>, String> {
+    let LogSearch { q, level, field, since, limit } = search;
+    let parser = parser_for(app_manager, &id).await?;
+    if parser.is_none() && (level.is_some() || !field.is_empty()) {
+        return Err(format!("Instance {} has no log parsing rules; set them with PUT /instances/{}/log-parsing", id, id));
+    }
+    let level = level.map(|level| level.to_lowercase());
+    let fields: Vec<(&str, Option<&str>)> = field.iter()
+        .map(|field| match field.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (field.as_str(), None),
+        })
+        .collect();
+    let limit = limit.unwrap_or(100);
+
+    let mut lines: VecDeque<LogLine> = VecDeque::with_capacity(limit + 1);
+    let mut dropped_lines = 0u64;
+    let rejoined_lines = read_lines(app_manager.docker(), &id, true, true, since.unwrap_or(0), |mut line| {
+        if let Some(parser) = &parser {
+            parser.apply(&mut line);
+        }
+        let matches = q.as_ref().is_none_or(|q| line.message.contains(q.as_str()))
+            && level.as_ref().is_none_or(|level| line.level.as_ref() == Some(level))
+            && fields.iter().all(|(key, value)| match (line.fields.get(*key), value) {
+                (Some(actual), Some(value)) => actual == value,
+                (found, None) => found.is_some(),
+                (None, Some(_)) => false,
+            });
+        if !matches {
+            return;
+        }
+        lines.push_back(line);
+        if lines.len() > limit {
+            lines.pop_front();
+            dropped_lines += 1;
+        }
+    }).await?;
+
+    let mut lines: Vec<LogLine> = lines.into_iter().collect();
+    lines.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(Json(InstanceLogs {
+        stream: "all".to_string(),
+        lines,
+        dropped_lines,
+        rejoined_lines,
+    }))
+}
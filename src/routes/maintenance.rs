@@ -0,0 +1,171 @@
+use rocket::{delete, get, post, put};
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::{Arc, Mutex};
+use chrono::Utc;
+
+use super::instances::AppManager;
+use super::access::{ApiKey, Mutation};
+pub use omniagent_client::models::maintenance::{WindowState, MaintenanceWindow, MaintenanceWindowRequest};
+
+/// Scheduled windows during which background housekeeping pauses
+#[derive(Clone, Default)]
+pub struct MaintenanceWindows {
+    windows: Arc<Mutex<Vec<MaintenanceWindow>>>,
+}
+
+impl MaintenanceWindows {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True while any window is open; GC, auto-updates and non-critical alerts should hold off
+    pub fn in_maintenance(&self) -> bool {
+        let now = Utc::now();
+        self.windows.lock().unwrap().iter().any(|w| w.starts_at <= now && now < w.ends_at)
+    }
+
+    /// Moves windows through scheduled -> active -> completed, running their hooks
+    pub fn start_scheduler(&self) {
+        let windows = self.windows.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let now = Utc::now();
+                let mut hooks = Vec::new();
+                {
+                    let mut windows = windows.lock().unwrap();
+                    for window in windows.iter_mut() {
+                        let next = if now >= window.ends_at {
+                            WindowState::Completed
+                        } else if now >= window.starts_at {
+                            WindowState::Active
+                        } else {
+                            WindowState::Scheduled
+                        };
+                        if next == window.state {
+                            continue;
+                        }
+
+                        if window.state == WindowState::Scheduled && next != WindowState::Scheduled {
+                            hooks.extend(window.pre_hook.clone().map(|hook| (window.id.clone(), hook)));
+                        }
+                        if next == WindowState::Completed {
+                            hooks.extend(window.post_hook.clone().map(|hook| (window.id.clone(), hook)));
+                        }
+                        log::info!("Maintenance window {} is now {:?}", window.name, next);
+                        window.state = next;
+                    }
+                }
+
+                for (id, hook) in hooks {
+                    let output = run_hook(&hook).await;
+                    if let Some(window) = windows.lock().unwrap().iter_mut().find(|w| w.id == id) {
+                        window.last_hook_output = Some(output);
+                    }
+                }
+
+                tokio::time::sleep(std::time::Duration::from_secs(15)).await;
+            }
+        });
+    }
+}
+
+async fn run_hook(hook: &str) -> String {
+    #[cfg(windows)]
+    let mut command = tokio::process::Command::new("cmd");
+    #[cfg(windows)]
+    command.args(["/C", hook]);
+    #[cfg(not(windows))]
+    let mut command = tokio::process::Command::new("sh");
+    #[cfg(not(windows))]
+    command.args(["-c", hook]);
+
+    match tokio::time::timeout(std::time::Duration::from_secs(300), command.output()).await {
+        Ok(Ok(output)) => {
+            let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+            if !output.status.success() {
+                log::warn!("Maintenance hook `{}` exited with {}", hook, output.status);
+            }
+            text
+        },
+        Ok(Err(e)) => {
+            log::error!("Failed to run maintenance hook `{}`: {}", hook, e);
+            e.to_string()
+        },
+        Err(_) => {
+            log::warn!("Maintenance hook `{}` timed out", hook);
+            "timed out".to_string()
+        }
+    }
+}
+
+fn validate(window_req: &MaintenanceWindowRequest) -> Result<(), String> {
+    if window_req.ends_at <= window_req.starts_at {
+        return Err("Maintenance window must end after it starts".to_string());
+    }
+    Ok(())
+}
+
+// API Endpoints
+#[get("/agent/maintenance-windows")]
+pub fn list_maintenance_windows(app_manager: &State<AppManager>) -> Json<Vec<MaintenanceWindow>> {
+    let mut windows = app_manager.maintenance().windows.lock().unwrap().clone();
+    windows.sort_by_key(|w| w.starts_at);
+    Json(windows)
+}
+
+#[get("/agent/maintenance-windows/<id>")]
+pub fn get_maintenance_window(id: String, app_manager: &State<AppManager>) -> Option<Json<MaintenanceWindow>> {
+    app_manager.maintenance().windows.lock().unwrap().iter().find(|w| w.id == id).cloned().map(Json)
+}
+
+#[post("/agent/maintenance-windows", format = "json", data = "<window_req>")]
+pub fn create_maintenance_window(window_req: Json<MaintenanceWindowRequest>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<MaintenanceWindow>, String> {
+    validate(&window_req)?;
+    let window_req = window_req.into_inner();
+
+    let window = MaintenanceWindow {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: window_req.name,
+        starts_at: window_req.starts_at,
+        ends_at: window_req.ends_at,
+        pre_hook: window_req.pre_hook,
+        post_hook: window_req.post_hook,
+        state: WindowState::Scheduled,
+        last_hook_output: None,
+    };
+    app_manager.maintenance().windows.lock().unwrap().push(window.clone());
+    Ok(Json(window))
+}
+
+#[put("/agent/maintenance-windows/<id>", format = "json", data = "<window_req>")]
+pub fn update_maintenance_window(id: String, window_req: Json<MaintenanceWindowRequest>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<MaintenanceWindow>, String> {
+    validate(&window_req)?;
+    let window_req = window_req.into_inner();
+
+    let mut windows = app_manager.maintenance().windows.lock().unwrap();
+    let window = windows.iter_mut().find(|w| w.id == id)
+        .ok_or_else(|| format!("Maintenance window {} not found", id))?;
+    if window.state != WindowState::Scheduled {
+        return Err(format!("Maintenance window {} has already started", id));
+    }
+
+    window.name = window_req.name;
+    window.starts_at = window_req.starts_at;
+    window.ends_at = window_req.ends_at;
+    window.pre_hook = window_req.pre_hook;
+    window.post_hook = window_req.post_hook;
+    Ok(Json(window.clone()))
+}
+
+#[delete("/agent/maintenance-windows/<id>")]
+pub fn delete_maintenance_window(id: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<String, String> {
+    let mut windows = app_manager.maintenance().windows.lock().unwrap();
+    let before = windows.len();
+    windows.retain(|w| w.id != id);
+    if windows.len() == before {
+        return Err(format!("Maintenance window {} not found", id));
+    }
+    Ok(format!("Maintenance window {} deleted successfully", id))
+}
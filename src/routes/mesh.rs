@@ -0,0 +1,327 @@
+use rocket::{get, put};
+use rocket::serde::{Serialize, json::Json};
+use rocket::State;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use bollard::Docker;
+use bollard::network::CreateNetworkOptions;
+use tokio::io::AsyncWriteExt;
+
+use super::access::{ApiKey, Mutation};
+pub use omniagent_client::models::mesh::{MeshPeer, PeerStatus, MeshStatus};
+
+/// Docker network containers join to be reachable across the mesh
+pub const MESH_NETWORK: &str = "omni-mesh";
+
+/// What this agent announces to the orchestrator; the response is the full peer list
+#[derive(Debug, Serialize)]
+struct MeshRegistration<'a> {
+    public_key: &'a str,
+    endpoint: Option<&'a str>,
+    address: &'a str,
+    subnet: &'a str,
+}
+
+#[derive(Debug, Clone)]
+struct MeshConfig {
+    interface: String,
+    address: String,
+    subnet: String,
+    listen_port: u16,
+    endpoint: Option<String>,
+    key_path: PathBuf,
+}
+
+impl MeshConfig {
+    /// The mesh is enabled by `OMNI_MESH_ADDRESS` (this agent's overlay address, e.g.
+    /// `10.90.0.1/16`) together with `OMNI_MESH_SUBNET` (the container subnet it routes,
+    /// e.g. `10.91.1.0/24`). `OMNI_MESH_INTERFACE`, `OMNI_MESH_LISTEN_PORT` and
+    /// `OMNI_MESH_ENDPOINT` are optional.
+    fn from_env() -> Option<Self> {
+        let address = std::env::var("OMNI_MESH_ADDRESS").ok()?;
+        let subnet = std::env::var("OMNI_MESH_SUBNET").ok()?;
+        let state_dir = std::env::var("OMNI_STATE_DIR").unwrap_or_else(|_| "./state".to_string());
+
+        Some(MeshConfig {
+            interface: std::env::var("OMNI_MESH_INTERFACE").unwrap_or_else(|_| "omni-mesh0".to_string()),
+            address,
+            subnet,
+            listen_port: std::env::var("OMNI_MESH_LISTEN_PORT").ok()
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(51820),
+            endpoint: std::env::var("OMNI_MESH_ENDPOINT").ok(),
+            key_path: PathBuf::from(state_dir).join("wireguard.key"),
+        })
+    }
+}
+
+fn valid_cidr(cidr: &str) -> bool {
+    let Some((ip, prefix)) = cidr.split_once('/') else {
+        return false;
+    };
+    match (ip.parse::<IpAddr>(), prefix.parse::<u8>()) {
+        (Ok(IpAddr::V4(_)), Ok(prefix)) => prefix <= 32,
+        (Ok(IpAddr::V6(_)), Ok(prefix)) => prefix <= 128,
+        _ => false,
+    }
+}
+
+fn validate_peer(peer: &MeshPeer) -> Result<(), String> {
+    // WireGuard keys are 32 bytes, base64 encoded
+    if peer.public_key.len() != 44 || !peer.public_key.ends_with('=') {
+        return Err(format!("Invalid public key for peer {}", peer.agent_id));
+    }
+    if let Some(cidr) = peer.allowed_ips.iter().find(|cidr| !valid_cidr(cidr)) {
+        return Err(format!("Invalid allowed IP {} for peer {}", cidr, peer.agent_id));
+    }
+    Ok(())
+}
+
+async fn run(program: &str, args: &[&str], input: Option<&str>) -> Result<String, String> {
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).await.map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("{} {} failed: {}", program, args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Loads the agent's WireGuard private key, generating it on first start
+async fn private_key(path: &PathBuf) -> Result<String, String> {
+    if let Ok(key) = tokio::fs::read_to_string(path).await {
+        return Ok(key.trim().to_string());
+    }
+
+    let key = run("wg", &["genkey"], None).await?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    std::io::Write::write_all(&mut file, key.as_bytes()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(key)
+}
+
+/// WireGuard overlay between federated agents. Each agent owns a container subnet; peers
+/// (exchanged through the orchestrator) route each other's subnets over the tunnel, so
+/// containers on the `omni-mesh` network reach each other without NAT.
+#[derive(Clone)]
+pub struct Mesh {
+    config: Option<MeshConfig>,
+    public_key: Arc<Mutex<Option<String>>>,
+    peers: Arc<Mutex<Vec<MeshPeer>>>,
+}
+
+impl Mesh {
+    pub fn from_env() -> Self {
+        Mesh {
+            config: MeshConfig::from_env(),
+            public_key: Arc::new(Mutex::new(None)),
+            peers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    /// Creates the interface and the mesh Docker network
+    async fn bring_up(&self, config: &MeshConfig, docker: &Docker) -> Result<(), String> {
+        let key = private_key(&config.key_path).await?;
+        let public_key = run("wg", &["pubkey"], Some(&key)).await?;
+        *self.public_key.lock().unwrap() = Some(public_key);
+
+        let interface = config.interface.as_str();
+        if run("ip", &["link", "show", interface], None).await.is_err() {
+            run("ip", &["link", "add", interface, "type", "wireguard"], None).await?;
+        }
+        run("ip", &["address", "replace", &config.address, "dev", interface], None).await?;
+        let key_path = config.key_path.to_string_lossy().to_string();
+        run("wg", &["set", interface, "listen-port", &config.listen_port.to_string(), "private-key", &key_path], None).await?;
+        run("ip", &["link", "set", interface, "up"], None).await?;
+
+        if docker.inspect_network::<String>(MESH_NETWORK, None).await.is_err() {
+            let options = CreateNetworkOptions {
+                name: MESH_NETWORK.to_string(),
+                driver: "bridge".to_string(),
+                ipam: bollard::models::Ipam {
+                    config: Some(vec![bollard::models::IpamConfig {
+                        subnet: Some(config.subnet.clone()),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                },
+                // Peers must see real container addresses
+                options: HashMap::from([
+                    ("com.docker.network.bridge.enable_ip_masquerade".to_string(), "false".to_string()),
+                ]),
+                ..Default::default()
+            };
+            docker.create_network(options).await
+                .map_err(|e| format!("Failed to create {} network: {}", MESH_NETWORK, e))?;
+        }
+        Ok(())
+    }
+
+    /// Reconciles WireGuard peers and routes with the desired list
+    async fn apply_peers(&self, peers: Vec<MeshPeer>) -> Result<(), String> {
+        let Some(config) = &self.config else {
+            return Err("The mesh is not enabled on this agent".to_string());
+        };
+        for peer in &peers {
+            validate_peer(peer)?;
+        }
+        let interface = config.interface.as_str();
+        let previous = self.peers.lock().unwrap().clone();
+
+        let desired: HashSet<&str> = peers.iter().map(|peer| peer.public_key.as_str()).collect();
+        for peer in previous.iter().filter(|peer| !desired.contains(peer.public_key.as_str())) {
+            run("wg", &["set", interface, "peer", &peer.public_key, "remove"], None).await?;
+        }
+
+        let desired_routes: HashSet<&str> = peers.iter().flat_map(|peer| peer.allowed_ips.iter().map(String::as_str)).collect();
+        for cidr in previous.iter().flat_map(|peer| peer.allowed_ips.iter()).filter(|cidr| !desired_routes.contains(cidr.as_str())) {
+            let _ = run("ip", &["route", "del", cidr, "dev", interface], None).await;
+        }
+
+        for peer in &peers {
+            let allowed_ips = peer.allowed_ips.join(",");
+            let mut args = vec!["set", interface, "peer", &peer.public_key, "allowed-ips", &allowed_ips, "persistent-keepalive", "25"];
+            if let Some(endpoint) = &peer.endpoint {
+                args.extend(["endpoint", endpoint.as_str()]);
+            }
+            run("wg", &args, None).await?;
+            for cidr in &peer.allowed_ips {
+                run("ip", &["route", "replace", cidr, "dev", interface], None).await?;
+            }
+        }
+
+        *self.peers.lock().unwrap() = peers;
+        Ok(())
+    }
+
+    /// Brings the mesh up, then registers with `OMNI_ORCHESTRATOR_URL` every 30 seconds
+    /// and applies the peer list it returns
+    pub fn start(&self, agent_id: &str, docker: Docker) {
+        let Some(config) = self.config.clone() else {
+            return;
+        };
+        let mesh = self.clone();
+        let orchestrator = std::env::var("OMNI_ORCHESTRATOR_URL").ok()
+            .map(|url| format!("{}/agents/{}/mesh", url.trim_end_matches('/'), agent_id));
+
+        tokio::spawn(async move {
+            if let Err(e) = mesh.bring_up(&config, &docker).await {
+                log::error!("Failed to bring up mesh interface {}: {}", config.interface, e);
+                return;
+            }
+            log::info!("Mesh interface {} up at {}", config.interface, config.address);
+
+            let Some(url) = orchestrator else {
+                return;
+            };
+            let client = crate::crypto::http_client();
+            loop {
+                let public_key = mesh.public_key.lock().unwrap().clone().unwrap_or_default();
+                let registration = MeshRegistration {
+                    public_key: &public_key,
+                    endpoint: config.endpoint.as_deref(),
+                    address: &config.address,
+                    subnet: &config.subnet,
+                };
+
+                match client.post(&url).json(&registration).timeout(Duration::from_secs(10)).send().await {
+                    Ok(response) if response.status().is_success() => match response.json::<Vec<MeshPeer>>().await {
+                        Ok(peers) => {
+                            let peers = peers.into_iter().filter(|peer| peer.public_key != public_key).collect();
+                            if let Err(e) = mesh.apply_peers(peers).await {
+                                eprintln!("Failed to apply mesh peers: {}", e);
+                            }
+                        },
+                        Err(e) => eprintln!("Failed to read mesh peers: {}", e),
+                    },
+                    Ok(response) => eprintln!("Orchestrator rejected mesh registration: {}", response.status()),
+                    Err(e) => eprintln!("Failed to register with mesh: {}", e),
+                }
+
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+        });
+    }
+
+    async fn status(&self) -> MeshStatus {
+        let peers = self.peers.lock().unwrap().clone();
+        let Some(config) = &self.config else {
+            return MeshStatus {
+                enabled: false,
+                interface: None,
+                public_key: None,
+                address: None,
+                subnet: None,
+                endpoint: None,
+                peers: Vec::new(),
+            };
+        };
+
+        // `wg show dump` peer lines: key, psk, endpoint, allowed ips, handshake, rx, tx, keepalive
+        let dump = run("wg", &["show", &config.interface, "dump"], None).await.unwrap_or_default();
+        let counters: HashMap<&str, (i64, u64, u64)> = dump.lines()
+            .skip(1)
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                let parse = |i: usize| fields.get(i).and_then(|f| f.parse::<u64>().ok()).unwrap_or(0);
+                fields.first().map(|key| (*key, (parse(4) as i64, parse(5), parse(6))))
+            })
+            .collect();
+
+        MeshStatus {
+            enabled: true,
+            interface: Some(config.interface.clone()),
+            public_key: self.public_key.lock().unwrap().clone(),
+            address: Some(config.address.clone()),
+            subnet: Some(config.subnet.clone()),
+            endpoint: config.endpoint.clone(),
+            peers: peers.into_iter()
+                .map(|peer| {
+                    let (handshake, rx_bytes, tx_bytes) = counters.get(peer.public_key.as_str()).copied().unwrap_or_default();
+                    PeerStatus {
+                        peer,
+                        latest_handshake: (handshake > 0).then_some(handshake),
+                        rx_bytes,
+                        tx_bytes,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+// API Endpoints
+#[get("/mesh")]
+pub async fn get_mesh_status(mesh: &State<Mesh>) -> Json<MeshStatus> {
+    Json(mesh.status().await)
+}
+
+/// Replaces the peer list. With an orchestrator configured, its list wins on the next sync.
+#[put("/mesh/peers", format = "json", data = "<peers_req>")]
+pub async fn set_mesh_peers(peers_req: Json<Vec<MeshPeer>>, mesh: &State<Mesh>, _mutation: Mutation, _key: ApiKey) -> Result<Json<MeshStatus>, String> {
+    mesh.apply_peers(peers_req.into_inner()).await
+        .map_err(|e| format!("Failed to apply mesh peers: {}", e))?;
+    Ok(Json(mesh.status().await))
+}
@@ -0,0 +1,304 @@
+use rocket::get;
+use rocket::http::ContentType;
+use rocket::State;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use bollard::container::{ListContainersOptions, StatsOptions};
+use futures::TryStreamExt;
+
+use crate::config::Config;
+use crate::host_stats;
+use super::cgroup;
+use super::instances::AppManager;
+use super::limits::{ConcurrencyLimits, LimitSaturation};
+pub use omniagent_client::models::metrics::MetricsScrape;
+
+/// One metric family: its `# HELP`/`# TYPE` lines, then its samples
+#[derive(Default)]
+struct Family {
+    metadata: Vec<String>,
+    samples: Vec<String>,
+}
+
+struct Scrape {
+    name: String,
+    /// Exposition text from the last successful scrape
+    body: Option<String>,
+}
+
+/// Resource counters of a running managed container, from its last stats sample
+struct ContainerUsage {
+    name: String,
+    cpu_seconds: f64,
+    memory_bytes: u64,
+}
+
+/// One sample of the `/metrics` exposition, for exporters that need it structured
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Adds `labels` (already formatted as `key="value",...`) to a sample line
+fn relabel(sample: &str, labels: &str) -> String {
+    match sample.find(['{', ' ']) {
+        Some(i) if sample[i..].starts_with("{}") => format!("{}{{{}}}{}", &sample[..i], labels, &sample[i + 2..]),
+        Some(i) if sample[i..].starts_with('{') => format!("{}{{{},{}", &sample[..i], labels, &sample[i + 1..]),
+        Some(i) => format!("{}{{{}}}{}", &sample[..i], labels, &sample[i..]),
+        None => sample.to_string(),
+    }
+}
+
+/// Parses a sample line of the text exposition; any timestamp is dropped
+fn parse_sample(line: &str) -> Option<Sample> {
+    let end = line.find(['{', ' '])?;
+    let name = line[..end].to_string();
+    let mut labels = Vec::new();
+    let mut rest = &line[end..];
+
+    if let Some(mut inner) = rest.strip_prefix('{') {
+        loop {
+            inner = inner.trim_start_matches([',', ' ']);
+            if let Some(after) = inner.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+            let (key, after) = inner.split_once("=\"")?;
+            let mut value = String::new();
+            let mut chars = after.char_indices();
+            let close = loop {
+                match chars.next()? {
+                    (i, '"') => break i,
+                    (_, '\\') => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        escaped => value.push(escaped),
+                    },
+                    (_, c) => value.push(c),
+                }
+            };
+            labels.push((key.trim().to_string(), value));
+            inner = &after[close + 1..];
+        }
+    }
+
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+    Some(Sample { name, labels, value })
+}
+
+/// Merges instance expositions into one, grouping samples by family since a family may
+/// appear only once per exposition
+fn merge(scrapes: &BTreeMap<String, Scrape>) -> String {
+    let mut families: BTreeMap<String, Family> = BTreeMap::new();
+    for (id, scrape) in scrapes {
+        let Some(body) = &scrape.body else {
+            continue;
+        };
+        let labels = format!("instance_id=\"{}\",instance_name=\"{}\"", escape_label(id), escape_label(&scrape.name));
+        let mut current = String::new();
+
+        for line in body.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(comment) = line.strip_prefix('#') {
+                let mut words = comment.split_whitespace();
+                if let (Some(kind @ ("HELP" | "TYPE")), Some(name)) = (words.next(), words.next()) {
+                    current = name.to_string();
+                    let family = families.entry(current.clone()).or_default();
+                    if !family.metadata.iter().any(|existing| existing.split_whitespace().nth(1) == Some(kind)) {
+                        family.metadata.push(line.to_string());
+                    }
+                }
+                continue;
+            }
+
+            // Histogram and summary samples carry suffixes of their family's name
+            let name = line.split(['{', ' ']).next().unwrap_or_default();
+            let family = if !current.is_empty() && name.starts_with(&current) { current.clone() } else { name.to_string() };
+            families.entry(family).or_default().samples.push(relabel(line, &labels));
+        }
+    }
+
+    let mut exposition = String::new();
+    for family in families.values() {
+        for line in family.metadata.iter().chain(&family.samples) {
+            exposition.push_str(line);
+            exposition.push('\n');
+        }
+    }
+    exposition
+}
+
+/// Scrapes the Prometheus endpoint of every running instance that sets `metrics`, every
+/// `metrics_scrape_interval_secs` (default 30), for `/metrics` to re-export with
+/// `instance_id` and `instance_name` labels. The agent reaches each container on its own
+/// network address, so instances on isolated networks don't need published ports.
+///
+/// The same loop samples CPU and memory of every managed container, exported next to the
+/// agent's own host and concurrency metrics.
+#[derive(Clone)]
+pub struct MetricsScraper {
+    scrapes: Arc<Mutex<BTreeMap<String, Scrape>>>,
+    containers: Arc<Mutex<BTreeMap<String, ContainerUsage>>>,
+    limits: ConcurrencyLimits,
+    interval: Duration,
+}
+
+impl MetricsScraper {
+    pub fn new(limits: ConcurrencyLimits, config: &Config) -> Self {
+        MetricsScraper {
+            scrapes: Arc::new(Mutex::new(BTreeMap::new())),
+            containers: Arc::new(Mutex::new(BTreeMap::new())),
+            limits,
+            interval: Duration::from_secs(config.metrics_scrape_interval_secs),
+        }
+    }
+
+    pub fn start(&self, app_manager: AppManager) {
+        let scraper = self.clone();
+        let interval = self.interval;
+        let http = crate::crypto::http_builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+
+        tokio::spawn(async move {
+            loop {
+                let managed = app_manager.managed_specs().await.unwrap_or_default();
+                let specs: HashMap<String, MetricsScrape> = managed.iter()
+                    .filter_map(|(id, spec)| Some((id.clone(), spec.metrics.clone()?)))
+                    .collect();
+                let containers = app_manager.docker().list_containers(Some(ListContainersOptions::<String> {
+                    filters: HashMap::from([("status".to_string(), vec!["running".to_string()])]),
+                    ..Default::default()
+                })).await.unwrap_or_default();
+
+                let mut scrapes = BTreeMap::new();
+                let mut usage = BTreeMap::new();
+                for container in containers {
+                    let Some(id) = container.id.clone() else {
+                        continue;
+                    };
+                    let name = container.names.iter().flatten().next()
+                        .map(|name| name.trim_start_matches('/').to_string())
+                        .unwrap_or_default();
+
+                    if managed.iter().any(|(managed_id, _)| *managed_id == id) {
+                        let stats = app_manager.docker()
+                            .stats(&id, Some(StatsOptions { stream: false, one_shot: true }))
+                            .try_next().await;
+                        if let Ok(Some(stats)) = stats {
+                            usage.insert(id.clone(), ContainerUsage {
+                                name: name.clone(),
+                                cpu_seconds: stats.cpu_stats.cpu_usage.total_usage as f64 / 1e9,
+                                memory_bytes: cgroup::working_set(&stats.memory_stats).unwrap_or(0),
+                            });
+                        }
+                    }
+
+                    let Some(target) = specs.get(&id) else {
+                        continue;
+                    };
+                    let ip = container.network_settings
+                        .and_then(|settings| settings.networks)
+                        .and_then(|networks| networks.into_values()
+                            .filter_map(|endpoint| endpoint.ip_address)
+                            .find(|ip| !ip.is_empty()));
+
+                    let url = ip.map(|ip| format!("http://{}:{}{}", ip, target.port, target.path.as_deref().unwrap_or("/metrics")));
+                    let body = match url {
+                        Some(url) => match http.get(&url).send().await.and_then(|response| response.error_for_status()) {
+                            Ok(response) => response.text().await.ok(),
+                            Err(e) => {
+                                eprintln!("Failed to scrape metrics from {}: {}", url, e);
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                    scrapes.insert(id, Scrape { name, body });
+                }
+                *scraper.scrapes.lock().unwrap() = scrapes;
+                *scraper.containers.lock().unwrap() = usage;
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    /// The agent's own metrics: host memory and disks, endpoint concurrency, and managed
+    /// container usage
+    fn agent_metrics(&self) -> String {
+        let mut exposition = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            exposition.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+            for (labels, value) in samples {
+                let labels = if labels.is_empty() { labels } else { format!("{{{}}}", labels) };
+                exposition.push_str(&format!("{}{} {}\n", name, labels, value));
+            }
+        };
+
+        let (memory_total, memory_available) = host_stats::memory();
+        family("omni_host_memory_total_bytes", "gauge", "Physical memory of the host", vec![(String::new(), memory_total.to_string())]);
+        family("omni_host_memory_available_bytes", "gauge", "Memory available to new processes", vec![(String::new(), memory_available.to_string())]);
+
+        let disks = host_stats::provider().disks();
+        let by_mount = |value: fn(&host_stats::DiskUsage) -> u64| disks.iter()
+            .map(|disk| (format!("mount=\"{}\"", escape_label(&disk.mount)), value(disk).to_string()))
+            .collect();
+        family("omni_host_disk_total_bytes", "gauge", "Size of each local volume", by_mount(|disk| disk.total));
+        family("omni_host_disk_available_bytes", "gauge", "Free space on each local volume", by_mount(|disk| disk.available));
+
+        let saturation = self.limits.saturation();
+        let by_class = |value: fn(&LimitSaturation) -> String| saturation.iter()
+            .map(|limit| (format!("class=\"{}\"", limit.class), value(limit)))
+            .collect();
+        family("omni_concurrency_limit", "gauge", "Slots of each endpoint class", by_class(|limit| limit.max.to_string()));
+        family("omni_concurrency_in_use", "gauge", "Slots of each endpoint class held by requests", by_class(|limit| limit.in_use.to_string()));
+        family("omni_concurrency_waiting", "gauge", "Requests waiting for a slot", by_class(|limit| limit.waiting.to_string()));
+        family("omni_concurrency_rejected_total", "counter", "Requests rejected after waiting for a slot", by_class(|limit| limit.rejected.to_string()));
+
+        let containers = self.containers.lock().unwrap();
+        let by_container = |value: fn(&ContainerUsage) -> String| containers.iter()
+            .map(|(id, usage)| (format!("instance_id=\"{}\",instance_name=\"{}\"", escape_label(id), escape_label(&usage.name)), value(usage)))
+            .collect();
+        family("omni_container_cpu_seconds_total", "counter", "CPU time used by the instance", by_container(|usage| usage.cpu_seconds.to_string()));
+        family("omni_container_memory_working_set_bytes", "gauge", "Memory in use by the instance, excluding inactive page cache", by_container(|usage| usage.memory_bytes.to_string()));
+        exposition
+    }
+
+    /// Prometheus text exposition of the last scrapes, plus `omni_instance_scrape_up` and the
+    /// agent's own metrics
+    pub fn render(&self) -> String {
+        let scrapes = self.scrapes.lock().unwrap();
+        let mut exposition = merge(&scrapes);
+        exposition.push_str("# HELP omni_instance_scrape_up Whether the last scrape of the instance's metrics succeeded\n");
+        exposition.push_str("# TYPE omni_instance_scrape_up gauge\n");
+        for (id, scrape) in scrapes.iter() {
+            exposition.push_str(&format!(
+                "omni_instance_scrape_up{{instance_id=\"{}\",instance_name=\"{}\"}} {}\n",
+                escape_label(id), escape_label(&scrape.name), u8::from(scrape.body.is_some()),
+            ));
+        }
+        drop(scrapes);
+        exposition.push_str(&self.agent_metrics());
+        exposition
+    }
+
+    /// Every sample currently exported on `/metrics`
+    pub fn samples(&self) -> Vec<Sample> {
+        self.render().lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(parse_sample)
+            .collect()
+    }
+}
+
+// API Endpoints
+#[get("/metrics")]
+pub fn get_metrics(scraper: &State<MetricsScraper>) -> (ContentType, String) {
+    (ContentType::new("text", "plain").with_params(("version", "0.0.4")), scraper.render())
+}
@@ -1,2 +1,305 @@
+use rocket::{Catcher, Route};
+
 pub mod index;
-pub mod instances;
\ No newline at end of file
+pub mod instances;
+pub mod images;
+pub mod registry_cache;
+pub mod node;
+pub mod maintenance;
+pub mod state;
+pub mod ha;
+pub mod host;
+pub mod access;
+pub mod disk;
+pub mod diagnostics;
+pub mod bandwidth;
+pub mod mesh;
+pub mod userns;
+pub mod seccomp;
+pub mod limits;
+pub mod preemption;
+pub mod cgroup;
+pub mod housekeeping;
+pub mod apply;
+pub mod usage;
+pub mod export;
+pub mod logs;
+pub mod log_health;
+pub mod metrics;
+pub mod capture;
+pub mod netns;
+pub mod nettest;
+pub mod layer_sharing;
+pub mod plugins;
+pub mod search;
+pub mod views;
+pub mod rbac;
+pub mod share;
+pub mod cpi;
+pub mod lint;
+pub mod checks;
+pub mod flags;
+
+/// Every route the agent serves
+pub fn all() -> Vec<Route> {
+    rocket::routes![
+        index::     index,
+        index::     ui,
+        index::     ui_script,
+        index::     ui_styles,
+        instances:: list_instances,
+        instances:: get_instance,
+        instances:: create_instance,
+        instances:: start_instance,
+        instances:: activate_instance,
+        instances:: stop_instance,
+        instances:: restart_instance,
+        instances:: write_instance_stdin,
+        instances:: update_instance,
+        instances:: delete_instance,
+        instances:: list_images,
+        instances:: stream_events,
+        instances:: health_check,
+        instances:: get_instance_logs,
+        instances:: get_instance_stats,
+        instances:: pause_instance,
+        instances:: unpause_instance,
+        instances:: inspect_instance,
+        instances:: port_forward_instance,
+        instances:: create_exec,
+        instances:: attach_exec,
+        instances:: resize_exec,
+        instances:: list_volumes,
+        instances:: create_volume,
+        instances:: delete_volume,
+        instances:: list_networks,
+        instances:: create_network,
+        instances:: delete_network,
+        instances:: connect_instance_to_network,
+        instances:: disconnect_instance_from_network,
+        instances:: get_agent_info,
+        instances:: get_docker_info,
+        images::    preload_images,
+        images::    list_preload_jobs,
+        images::    get_preload_job,
+        images::    list_pinned_images,
+        images::    set_pinned_images,
+        images::    get_image_metadata,
+        images::    build_image,
+        registry_cache:: get_registry_cache_status,
+        node::      get_node_labels,
+        node::      set_node_labels,
+        node::      get_node_taints,
+        node::      set_node_taints,
+        maintenance:: list_maintenance_windows,
+        maintenance:: get_maintenance_window,
+        maintenance:: create_maintenance_window,
+        maintenance:: update_maintenance_window,
+        maintenance:: delete_maintenance_window,
+        state::     get_state_delta,
+        state::     get_state_digest,
+        search::    search_instances,
+        views::     list_views,
+        views::     get_view,
+        views::     put_view,
+        views::     delete_view,
+        ha::        get_leader_status,
+        host::      shutdown_host,
+        access::    get_read_only,
+        access::    set_read_only,
+        access::    list_api_keys,
+        access::    mint_api_key,
+        access::    rotate_api_key,
+        access::    revoke_api_key,
+        rbac::      list_roles,
+        rbac::      put_role,
+        rbac::      delete_role,
+        rbac::      list_role_bindings,
+        rbac::      put_role_binding,
+        rbac::      delete_role_binding,
+        share::     share_instance,
+        share::     get_shared_logs,
+        share::     attach_shared_exec,
+        cpi::       list_cpis,
+        cpi::       get_cpi,
+        cpi::       reload_cpis,
+        cpi::       execute_cpi_action,
+        cpi::       list_cpi_executions,
+        cpi::       cancel_cpi_execution,
+        lint::      lint_spec,
+        checks::    list_checks,
+        checks::    list_check_statuses,
+        checks::    get_check,
+        checks::    put_check,
+        checks::    delete_check,
+        checks::    get_check_history,
+        flags::     list_flags,
+        flags::     get_flag,
+        flags::     evaluate_flag,
+        flags::     put_flag,
+        flags::     delete_flag,
+        disk::      get_disk_status,
+        housekeeping:: get_housekeeping_status,
+        apply::     apply_bundle,
+        usage::     get_usage,
+        logs::      stream_logs,
+        logs::      follow_instance_logs,
+        logs::      set_log_parsing,
+        logs::      search_instance_logs,
+        log_health:: get_log_health,
+        metrics::   get_metrics,
+        capture::   capture_instance,
+        nettest::   test_instance_network,
+        layer_sharing::get_blob,
+        plugins::   list_plugins,
+        plugins::   plugin_get,
+        plugins::   plugin_post,
+        plugins::   plugin_put,
+        plugins::   plugin_delete,
+        diagnostics:: get_diagnostics,
+        diagnostics:: get_diagnostics_bundle,
+        bandwidth:: set_instance_bandwidth,
+        mesh::      get_mesh_status,
+        mesh::      set_mesh_peers,
+        seccomp::   list_seccomp_profiles,
+        seccomp::   get_seccomp_profile,
+        seccomp::   put_seccomp_profile,
+        seccomp::   delete_seccomp_profile,
+        limits::    get_concurrency,
+    ]
+}
+
+/// Catchers giving access-guard rejections a JSON body
+pub fn catchers() -> Vec<Catcher> {
+    rocket::catchers![access::unauthorized, access::forbidden, access::too_many_requests, access::service_unavailable, access::insufficient_storage]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::config::LogLevel;
+    use rocket::http::{Accept, ContentType, Header, Status};
+    use rocket::local::asynchronous::Client;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use crate::agent::Agent;
+    use crate::config::Config;
+    use crate::event_bus::EventBus;
+    use crate::state_store::{FileStore, StateStore};
+    use super::access::{AdminToken, ApiKeys, ReadOnlyMode};
+    use super::*;
+
+    /// Routes anyone who can reach the agent may call. Every other route must turn away a
+    /// request without an API key with 401.
+    const PUBLIC: &[&str] = &[
+        // The UI and agent status
+        "index", "ui", "ui_script", "ui_styles", "health_check", "get_agent_info", "get_docker_info",
+        "get_leader_status", "get_read_only", "get_disk_status", "get_housekeeping_status",
+        "get_diagnostics", "get_concurrency", "get_metrics", "get_mesh_status", "get_registry_cache_status",
+        "get_node_labels", "get_node_taints", "get_state_delta", "get_state_digest", "stream_events",
+        // Instances, images and other resources
+        "list_instances", "get_instance", "search_instances", "get_instance_stats", "inspect_instance",
+        "get_log_health", "get_usage", "list_images", "get_image_metadata", "list_preload_jobs",
+        "get_preload_job", "list_pinned_images", "list_volumes", "list_networks",
+        "list_maintenance_windows", "get_maintenance_window", "list_views", "get_view",
+        "list_checks", "list_check_statuses", "get_check", "get_check_history", "list_flags",
+        "get_flag", "evaluate_flag", "list_plugins", "list_seccomp_profiles", "get_seccomp_profile",
+        // Validates a spec without creating anything
+        "lint_spec",
+        // Checked against a signed share token instead
+        "get_shared_logs", "attach_shared_exec",
+    ];
+
+    const ADMIN_TOKEN: &str = "test-admin-token";
+
+    /// The agent's routes with all their state, and an API key minted so keys are enforced
+    async fn client() -> (Client, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("omni-routes-{}", uuid::Uuid::new_v4()));
+        let store: Arc<dyn StateStore> = Arc::new(FileStore::open(dir.clone()).await.unwrap());
+        // Nothing should reach Docker without a key, so it needn't be running
+        let config = Config { docker_host: Some("tcp://127.0.0.1:9".to_string()), ..Config::default() };
+        let bus = EventBus::new();
+        let agent = Agent::new("test".to_string(), "0.0.0".to_string());
+        let limits = limits::ConcurrencyLimits::from_env();
+        let rocket = rocket::custom(rocket::Config { log_level: LogLevel::Off, ..rocket::Config::debug_default() })
+            .mount("/", all())
+            .register("/", catchers())
+            .manage(all())
+            .manage(instances::AppManager::new(store.clone(), &config).unwrap())
+            .manage(Arc::new(images::ImageManager::new()))
+            .manage(Arc::new(registry_cache::RegistryCache::from_env()))
+            .manage(state::StateTracker::new(bus.clone()))
+            .manage(ha::LeaderElection::from_env(agent.id().to_string()))
+            .manage(ReadOnlyMode::from_env())
+            .manage(ApiKeys::load(store.clone()).await.unwrap())
+            .manage(AdminToken::new(ADMIN_TOKEN))
+            .manage(rbac::Authorizer::load(store.clone()).await.unwrap())
+            .manage(share::ShareSigner::from_env())
+            .manage(disk::DiskMonitor::from_env())
+            .manage(housekeeping::Housekeeping::from_env())
+            .manage(log_health::LogHealthMonitor::new())
+            .manage(metrics::MetricsScraper::new(limits.clone(), &config))
+            .manage(diagnostics::RecentEvents::start(&bus))
+            .manage(mesh::Mesh::from_env())
+            .manage(limits)
+            .manage(checks::SyntheticChecks::new())
+            .manage(bus)
+            .manage(agent);
+        let client = Client::untracked(rocket).await.unwrap();
+
+        let minted = client.post("/agent/api-keys")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", ADMIN_TOKEN)))
+            .body(r#"{"name": "test"}"#)
+            .dispatch().await
+            .status();
+        assert_eq!(minted, Status::Ok);
+        (client, dir)
+    }
+
+    /// A URI matching `route`, with every dynamic segment and query parameter filled in
+    fn uri(route: &Route) -> String {
+        let fill = |segment: &str| match segment.strip_prefix('<') {
+            Some(name) => format!("{}=x", name.trim_end_matches('>').trim_end_matches("..")),
+            None => segment.to_string(),
+        };
+        let path: Vec<String> = route.uri.path().split('/')
+            .map(|segment| if segment.starts_with('<') { "x".to_string() } else { segment.to_string() })
+            .collect();
+        match route.uri.query() {
+            Some(query) => format!("{}?{}", path.join("/"), query.split('&').map(fill).collect::<Vec<_>>().join("&")),
+            None => path.join("/"),
+        }
+    }
+
+    #[rocket::async_test]
+    async fn routes_need_an_api_key() {
+        let (client, dir) = client().await;
+        let names: Vec<&str> = client.rocket().routes().filter_map(|route| route.name.as_deref()).collect();
+        assert!(PUBLIC.iter().all(|name| names.contains(name)), "PUBLIC names a route that doesn't exist");
+
+        let mut open = Vec::new();
+        for route in client.rocket().routes() {
+            let name = route.name.as_deref().unwrap_or_default();
+            if PUBLIC.contains(&name) {
+                continue;
+            }
+            // Upgrade headers too, so WebSocket routes get as far as their other guards
+            let mut request = client.req(route.method, uri(route))
+                .header(Accept::JSON)
+                .header(Header::new("Connection", "Upgrade"))
+                .header(Header::new("Upgrade", "websocket"))
+                .header(Header::new("Sec-WebSocket-Version", "13"))
+                .header(Header::new("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="));
+            if let Some(format) = &route.format {
+                request = request.header(ContentType(format.clone()));
+            }
+            let status = request.dispatch().await.status();
+            if status != Status::Unauthorized {
+                open.push(format!("{} {} {}: {}", route.method, route.uri, name, status));
+            }
+        }
+        assert!(open.is_empty(), "Routes that answer without an API key:\n{}", open.join("\n"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+use bollard::Docker;
+use bollard::container::{Config, CreateContainerOptions, LogOutput, LogsOptions, RemoveContainerOptions, StartContainerOptions};
+use bollard::image::CreateImageOptions;
+use futures::{StreamExt, TryStreamExt};
+
+const DEFAULT_IMAGE: &str = "nicolaka/netshoot";
+
+/// What a helper container printed before it exited or was cut off
+pub struct HelperOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: String,
+    /// None when the helper was stopped by the time or size limit
+    pub exit_code: Option<i64>,
+}
+
+/// Full ID of a running container, or why it can't host a helper
+pub async fn running_container(docker: &Docker, id: &str) -> Result<String, String> {
+    let container = docker.inspect_container(id, None).await
+        .map_err(|e| format!("Failed to inspect instance: {}", e))?;
+    if !container.state.and_then(|state| state.running).unwrap_or(false) {
+        return Err(format!("Instance {} is not running", id));
+    }
+    Ok(container.id.unwrap_or_else(|| id.to_string()))
+}
+
+/// Reads the helper's output until it exits, `duration` passes or stdout reaches `max_bytes`
+async fn collect(docker: &Docker, helper: &str, duration: Duration, max_bytes: usize) -> Result<HelperOutput, String> {
+    let mut logs = docker.logs(helper, Some(LogsOptions::<String> {
+        follow: true,
+        stdout: true,
+        stderr: true,
+        ..Default::default()
+    }));
+
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let read = async {
+        while let Some(chunk) = logs.next().await {
+            match chunk.map_err(|e| format!("Failed to read helper output: {}", e))? {
+                LogOutput::StdOut { message } => {
+                    stdout.extend_from_slice(&message);
+                    if stdout.len() >= max_bytes {
+                        return Ok(false);
+                    }
+                },
+                LogOutput::StdErr { message } => stderr.extend_from_slice(&message),
+                _ => {},
+            }
+        }
+        Ok::<_, String>(true)
+    };
+    let exited = match tokio::time::timeout(duration, read).await {
+        Ok(exited) => exited?,
+        Err(_) => false,
+    };
+
+    let exit_code = if exited {
+        docker.inspect_container(helper, None).await.ok()
+            .and_then(|container| container.state)
+            .and_then(|state| state.exit_code)
+    } else {
+        None
+    };
+    stdout.truncate(max_bytes);
+    Ok(HelperOutput { stdout, stderr: String::from_utf8_lossy(&stderr).into_owned(), exit_code })
+}
+
+/// Runs `command` in a helper container sharing the network namespace (and so the DNS
+/// configuration) of container `id`, so the instance image needs no network tools. The
+/// helper image is `OMNI_NETNS_IMAGE` (default `nicolaka/netshoot`) and is removed afterwards.
+pub async fn run(docker: &Docker, id: &str, command: Vec<String>, cap_add: &[&str], duration: Duration, max_bytes: usize) -> Result<HelperOutput, String> {
+    let image = std::env::var("OMNI_NETNS_IMAGE").unwrap_or_else(|_| DEFAULT_IMAGE.to_string());
+    if docker.inspect_image(&image).await.is_err() {
+        docker.create_image(Some(CreateImageOptions {
+            from_image: image.clone(),
+            ..Default::default()
+        }), None, None).try_collect::<Vec<_>>().await
+            .map_err(|e| format!("Failed to pull {}: {}", image, e))?;
+    }
+
+    let helper = format!("omni-netns-{}-{}", &id[..12.min(id.len())], chrono::Utc::now().timestamp_millis());
+    let config = Config {
+        image: Some(image),
+        cmd: Some(command),
+        host_config: Some(bollard::models::HostConfig {
+            network_mode: Some(format!("container:{}", id)),
+            cap_add: Some(cap_add.iter().map(|capability| capability.to_string()).collect()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    docker.create_container(Some(CreateContainerOptions {
+        name: helper.as_str(),
+        platform: None,
+    }), config).await
+        .map_err(|e| format!("Failed to create helper container: {}", e))?;
+
+    let output = match docker.start_container(&helper, None::<StartContainerOptions<String>>).await {
+        Ok(()) => collect(docker, &helper, duration, max_bytes).await,
+        Err(e) => Err(format!("Failed to start helper container: {}", e)),
+    };
+    if let Err(e) = docker.remove_container(&helper, Some(RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+    })).await {
+        eprintln!("Failed to remove helper container {}: {}", helper, e);
+    }
+    output
+}
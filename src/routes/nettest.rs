@@ -0,0 +1,102 @@
+use rocket::post;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::time::Duration;
+
+use super::access::{ApiKey, Mutation};
+use super::instances::AppManager;
+use super::netns;
+pub use omniagent_client::models::nettest::{NetCheck, NetTestReport, NetTestRequest};
+
+/// curl's `-w` format: phase timings in seconds, then the status code and peer address
+const WRITE_OUT: &str = "%{time_namelookup} %{time_connect} %{time_appconnect} %{time_total} %{http_code} %{remote_ip}";
+
+/// curl exit codes for failed name resolution and failed connects
+const CURL_RESOLVE_FAILED: i64 = 6;
+const CURL_CONNECT_FAILED: i64 = 7;
+
+fn check(name: &str, success: bool, seconds: Option<f64>, detail: Option<String>, error: Option<String>) -> NetCheck {
+    NetCheck {
+        check: name.to_string(),
+        success,
+        duration_ms: seconds.filter(|_| success).map(|seconds| seconds * 1000.0),
+        detail,
+        error: if success { None } else { error },
+    }
+}
+
+/// Builds the checks from one curl run: its timings show how far the request got, and its
+/// error message belongs to the first phase that failed
+fn report(target: &str, http: bool, output: &netns::HelperOutput) -> Vec<NetCheck> {
+    let text = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let time = |i: usize| fields.get(i).and_then(|field| field.parse::<f64>().ok()).unwrap_or(0.0);
+    let (lookup, connect, tls, total) = (time(0), time(1), time(2), time(3));
+    let status = fields.get(4).copied().unwrap_or("000");
+    let remote_ip = fields.get(5).map(|ip| ip.to_string());
+
+    let error = match output.exit_code {
+        None => Some(format!("Timed out testing {}", target)),
+        Some(0) => None,
+        Some(code) => Some(output.stderr.trim().trim_start_matches("curl: ").to_string())
+            .filter(|message| !message.is_empty())
+            .or_else(|| Some(format!("curl exited with {}", code))),
+    };
+
+    // A telnet:// check that connected may still end in curl's time limit; that's a success
+    let resolved = output.exit_code.is_some_and(|code| code != CURL_RESOLVE_FAILED) && (remote_ip.is_some() || connect > 0.0);
+    let connected = resolved && connect > 0.0 && output.exit_code != Some(CURL_CONNECT_FAILED);
+    let mut checks = vec![
+        check("dns", resolved, Some(lookup), remote_ip, error.clone()),
+        check("tcp", connected, Some(connect - lookup), None, if resolved { error.clone() } else { Some("Skipped: name did not resolve".to_string()) }),
+    ];
+
+    if http {
+        let responded = connected && output.exit_code == Some(0) && status != "000";
+        let detail = format!("HTTP {}", status);
+        let detail = if tls > 0.0 { format!("{} (TLS handshake {:.1} ms)", detail, (tls - connect) * 1000.0) } else { detail };
+        let error = if connected { error } else { Some("Skipped: could not connect".to_string()) };
+        checks.push(check("http", responded, Some(total), responded.then_some(detail), error));
+    }
+    checks
+}
+
+/// Runs DNS, TCP and, for URLs, HTTP GET checks from inside the instance's network
+/// namespace, with the instance's own DNS configuration. Any HTTP status counts as a
+/// response; the status is in `detail`.
+#[post("/instances/<id>/nettest", format = "json", data = "<nettest_req>")]
+pub async fn test_instance_network(id: String, nettest_req: Json<NetTestRequest>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<NetTestReport>, String> {
+    let docker = app_manager.docker();
+    let id = netns::running_container(docker, &id).await?;
+
+    let target = nettest_req.target.trim();
+    let http = target.starts_with("http://") || target.starts_with("https://");
+    let url = if http {
+        target.to_string()
+    } else {
+        match target.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => format!("telnet://{}", target),
+            _ => return Err(format!("Invalid target {}: expected host:port or an http(s) URL", target)),
+        }
+    };
+
+    let timeout = nettest_req.timeout_secs.unwrap_or(5).clamp(1, 30);
+    let command = vec![
+        "curl".to_string(),
+        "-sS".to_string(),
+        "-o".to_string(),
+        "/dev/null".to_string(),
+        "-m".to_string(),
+        timeout.to_string(),
+        "-w".to_string(),
+        WRITE_OUT.to_string(),
+        url,
+    ];
+
+    let output = netns::run(docker, &id, command, &[], Duration::from_secs(timeout + 10), 64 * 1024).await?;
+
+    Ok(Json(NetTestReport {
+        target: target.to_string(),
+        checks: report(target, http, &output),
+    }))
+}
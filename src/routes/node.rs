@@ -0,0 +1,179 @@
+use rocket::{get, put};
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+
+use super::instances::AppManager;
+use super::access::{ApiKey, Mutation};
+pub use omniagent_client::models::node::{TaintEffect, Taint, TolerationOperator, Toleration, NodeLabels, NodeTaints, Constraints, Unschedulable};
+
+/// Agent-level placement metadata: labels describe the node, taints repel instances
+/// that don't tolerate them
+#[derive(Clone)]
+pub struct NodeConfig {
+    labels: Arc<Mutex<HashMap<String, String>>>,
+    taints: Arc<Mutex<Vec<Taint>>>,
+    /// Sysctl names or `prefix.*` patterns instances may set
+    allowed_sysctls: Vec<String>,
+    /// Ulimit names instances may set
+    allowed_ulimits: Vec<String>,
+    /// Host device paths or `prefix*` patterns instances may map; empty denies all
+    allowed_devices: Vec<String>,
+    /// Whether instances may opt out of daemon user namespace remapping
+    allow_userns_host: bool,
+}
+
+/// Namespaced sysctls Docker can set per container without affecting the host
+const DEFAULT_ALLOWED_SYSCTLS: &str = "net.*,kernel.shm*,kernel.msg*,kernel.sem,fs.mqueue.*";
+const DEFAULT_ALLOWED_ULIMITS: &str = "nofile,nproc,memlock";
+
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+impl NodeConfig {
+    /// Loads initial values from `OMNI_NODE_LABELS` (`key=value,...`) and
+    /// `OMNI_NODE_TAINTS` (`key[=value]:Effect,...`), and the sysctl/ulimit/device allowlists
+    /// from `OMNI_ALLOWED_SYSCTLS`, `OMNI_ALLOWED_ULIMITS` and `OMNI_ALLOWED_DEVICES`.
+    /// `OMNI_ALLOW_USERNS_HOST=true` lets instances use `userns_mode: host`.
+    pub fn from_env() -> Self {
+        let labels = std::env::var("OMNI_NODE_LABELS").unwrap_or_default()
+            .split(',')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        let taints = std::env::var("OMNI_NODE_TAINTS").unwrap_or_default()
+            .split(',')
+            .filter_map(|spec| parse_taint(spec.trim()))
+            .collect();
+
+        NodeConfig {
+            labels: Arc::new(Mutex::new(labels)),
+            taints: Arc::new(Mutex::new(taints)),
+            allowed_sysctls: parse_list(&std::env::var("OMNI_ALLOWED_SYSCTLS").unwrap_or_else(|_| DEFAULT_ALLOWED_SYSCTLS.to_string())),
+            allowed_ulimits: parse_list(&std::env::var("OMNI_ALLOWED_ULIMITS").unwrap_or_else(|_| DEFAULT_ALLOWED_ULIMITS.to_string())),
+            allowed_devices: parse_list(&std::env::var("OMNI_ALLOWED_DEVICES").unwrap_or_default()),
+            allow_userns_host: std::env::var("OMNI_ALLOW_USERNS_HOST").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+        }
+    }
+
+    pub fn labels(&self) -> HashMap<String, String> {
+        self.labels.lock().unwrap().clone()
+    }
+
+    pub fn taints(&self) -> Vec<Taint> {
+        self.taints.lock().unwrap().clone()
+    }
+
+    /// Returns an error naming the first NoSchedule taint the tolerations don't cover
+    pub fn check_tolerations(&self, tolerations: &[Toleration]) -> Result<(), String> {
+        for taint in self.taints.lock().unwrap().iter() {
+            if taint.effect == TaintEffect::NoSchedule && !tolerations.iter().any(|t| t.tolerates(taint)) {
+                return Err(format!("Instance does not tolerate node taint {}{}:{:?}",
+                    taint.key,
+                    taint.value.as_ref().map(|v| format!("={}", v)).unwrap_or_default(),
+                    taint.effect));
+            }
+        }
+        Ok(())
+    }
+
+    /// Required labels that are missing or have a different value
+    pub fn unmatched_labels(&self, required: &HashMap<String, String>) -> Vec<String> {
+        let labels = self.labels.lock().unwrap();
+        let mut unmatched: Vec<String> = required.iter()
+            .filter(|(key, value)| labels.get(*key) != Some(*value))
+            .map(|(key, value)| match labels.get(key) {
+                Some(actual) => format!("Node label {} is {}, not {}", key, actual, value),
+                None => format!("Node label {}={} is not set", key, value),
+            })
+            .collect();
+        unmatched.sort();
+        unmatched
+    }
+
+    /// Rejects sysctls and ulimits outside the agent's allowlists
+    pub fn check_host_options<'a>(&self, sysctls: impl Iterator<Item = &'a String>, ulimits: impl Iterator<Item = &'a String>) -> Result<(), String> {
+        for sysctl in sysctls {
+            let allowed = self.allowed_sysctls.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => sysctl.starts_with(prefix),
+                None => pattern == sysctl,
+            });
+            if !allowed {
+                return Err(format!("Sysctl {} is not allowed on this agent", sysctl));
+            }
+        }
+        for ulimit in ulimits {
+            if !self.allowed_ulimits.contains(ulimit) {
+                return Err(format!("Ulimit {} is not allowed on this agent", ulimit));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects host devices outside the agent's allowlist
+    pub fn check_devices<'a>(&self, devices: impl Iterator<Item = &'a String>) -> Result<(), String> {
+        for device in devices {
+            let allowed = self.allowed_devices.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => device.starts_with(prefix),
+                None => pattern == device,
+            });
+            if !allowed || device.contains("..") {
+                return Err(format!("Device {} is not allowed on this agent", device));
+            }
+        }
+        Ok(())
+    }
+
+    /// Only `host` is a valid user namespace mode, and only where the agent allows it
+    pub fn check_userns_mode(&self, mode: Option<&str>) -> Result<(), String> {
+        match mode {
+            None => Ok(()),
+            Some("host") if self.allow_userns_host => Ok(()),
+            Some("host") => Err("userns_mode host is not allowed on this agent".to_string()),
+            Some(mode) => Err(format!("Unsupported userns_mode {}; only host is supported", mode)),
+        }
+    }
+}
+
+fn parse_taint(spec: &str) -> Option<Taint> {
+    let (key_value, effect) = spec.rsplit_once(':')?;
+    let effect = match effect {
+        "NoSchedule" => TaintEffect::NoSchedule,
+        "PreferNoSchedule" => TaintEffect::PreferNoSchedule,
+        _ => return None,
+    };
+    let (key, value) = match key_value.split_once('=') {
+        Some((key, value)) => (key.to_string(), Some(value.to_string())),
+        None => (key_value.to_string(), None),
+    };
+    Some(Taint { key, value, effect })
+}
+
+// API Endpoints
+#[get("/agent/labels")]
+pub fn get_node_labels(app_manager: &State<AppManager>) -> Json<NodeLabels> {
+    Json(NodeLabels { labels: app_manager.node().labels() })
+}
+
+#[put("/agent/labels", format = "json", data = "<labels_req>")]
+pub fn set_node_labels(labels_req: Json<NodeLabels>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Json<NodeLabels> {
+    *app_manager.node().labels.lock().unwrap() = labels_req.labels.clone();
+    Json(labels_req.into_inner())
+}
+
+#[get("/agent/taints")]
+pub fn get_node_taints(app_manager: &State<AppManager>) -> Json<NodeTaints> {
+    Json(NodeTaints { taints: app_manager.node().taints() })
+}
+
+#[put("/agent/taints", format = "json", data = "<taints_req>")]
+pub fn set_node_taints(taints_req: Json<NodeTaints>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Json<NodeTaints> {
+    *app_manager.node().taints.lock().unwrap() = taints_req.taints.clone();
+    Json(taints_req.into_inner())
+}
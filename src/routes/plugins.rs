@@ -0,0 +1,373 @@
+use rocket::{delete, get, post, put};
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::http::uri::Origin;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::serde::json::{self, Json};
+use rocket::State;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::event_bus::{AgentEvent, EventBus};
+use super::access::{ApiKey, Mutation};
+use super::instances::{AppInstanceRequest, AppManager};
+pub use omniagent_client::models::plugins::PluginInfo;
+
+/// A `pre_create` hook's answer. An empty answer leaves the request as it was.
+#[derive(Debug, Deserialize)]
+struct PreCreate {
+    /// Replaces the request
+    request: Option<AppInstanceRequest>,
+    /// Rejects the create with this message
+    error: Option<String>,
+}
+
+/// A request to one of a plugin's routes, as its `handle` export receives it
+#[derive(Debug, Serialize)]
+pub struct PluginRequest {
+    pub method: String,
+    /// The path below `/plugins/<name>/`
+    pub path: String,
+    pub query: Option<String>,
+    pub body: String,
+}
+
+/// A plugin's answer to a route request
+#[derive(Debug, Deserialize)]
+pub struct PluginResponse {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub body: String,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+/// Sandboxed WASM plugins loaded from `OMNI_PLUGIN_DIR`, one per `<name>.wasm` (requires the
+/// `plugins` feature). Plugins hook into instance creation and starts and can serve routes
+/// under `/plugins/<name>/`. They get no host access beyond logging, and every call runs in
+/// a fresh instance limited to `OMNI_PLUGIN_FUEL` units of fuel (default 100 million, roughly
+/// one per instruction) and `OMNI_PLUGIN_MEMORY_MB` of memory (default 64).
+///
+/// Plugins export `memory`, `alloc(len: i32) -> i32` and any of the hooks below, each
+/// `fn(ptr: i32, len: i32) -> i64` taking JSON in its memory and returning the location of
+/// its JSON answer as `ptr << 32 | len` (0 for no answer):
+///
+/// - `pre_create` gets the `AppInstanceRequest` and may answer `{"request": ...}` to replace
+///   it or `{"error": "..."}` to reject it
+/// - `post_start` gets the lifecycle event of every instance start; its answer is ignored
+/// - `handle` gets a `PluginRequest` and answers a `PluginResponse`
+///
+/// A `log(ptr: i32, len: i32)` import in module `env` writes to the agent's log.
+#[derive(Clone, Default)]
+pub struct Plugins {
+    loaded: Arc<Vec<Arc<imp::Plugin>>>,
+}
+
+impl Plugins {
+    pub fn load() -> Self {
+        match std::env::var("OMNI_PLUGIN_DIR") {
+            Ok(dir) => Plugins { loaded: Arc::new(imp::load_dir(&dir).into_iter().map(Arc::new).collect()) },
+            Err(_) => Plugins::default(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<PluginInfo> {
+        self.loaded.iter()
+            .map(|plugin| PluginInfo {
+                name: plugin.name.clone(),
+                hooks: plugin.hooks.clone(),
+            })
+            .collect()
+    }
+
+    fn with_hook<'a>(&'a self, hook: &'a str) -> impl Iterator<Item = &'a Arc<imp::Plugin>> + 'a {
+        self.loaded.iter().filter(move |plugin| plugin.hooks.iter().any(|name| name == hook))
+    }
+
+    /// Runs one hook of a plugin on a blocking thread, as the interpreter doesn't yield
+    async fn call(plugin: &Arc<imp::Plugin>, hook: &'static str, input: String) -> Result<Vec<u8>, String> {
+        let plugin = plugin.clone();
+        tokio::task::spawn_blocking(move || plugin.call(hook, input.as_bytes())).await
+            .map_err(|e| format!("Plugin task failed: {}", e))?
+    }
+
+    /// Passes a create request through every plugin's `pre_create` hook in name order. A
+    /// plugin that fails blocks the create, since it may be enforcing policy.
+    pub async fn pre_create(&self, request: &mut AppInstanceRequest) -> Result<(), String> {
+        for plugin in self.with_hook("pre_create") {
+            let input = json::to_string(request).map_err(|e| e.to_string())?;
+            let output = Self::call(plugin, "pre_create", input).await
+                .map_err(|e| format!("Plugin {} failed: {}", plugin.name, e))?;
+            if output.is_empty() {
+                continue;
+            }
+
+            let answer: PreCreate = json::from_slice(&output)
+                .map_err(|e| format!("Plugin {} answered with invalid JSON: {}", plugin.name, e))?;
+            if let Some(error) = answer.error {
+                return Err(format!("Plugin {} rejected instance {}: {}", plugin.name, request.name, error));
+            }
+            if let Some(replacement) = answer.request {
+                *request = replacement;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a route request to the plugin called `name`; None when no such plugin serves routes
+    pub async fn handle(&self, name: &str, request: &PluginRequest) -> Option<Result<PluginResponse, String>> {
+        let plugin = self.with_hook("handle").find(|plugin| plugin.name == name)?;
+        let result = async {
+            let input = json::to_string(request).map_err(|e| e.to_string())?;
+            let output = Self::call(plugin, "handle", input).await?;
+            json::from_slice(&output).map_err(|e| format!("invalid response: {}", e))
+        }.await;
+        Some(result.map_err(|e| format!("Plugin {} failed: {}", name, e)))
+    }
+
+    /// Notifies `post_start` hooks of instance starts
+    pub fn start(&self, bus: &EventBus) {
+        if self.with_hook("post_start").next().is_none() {
+            return;
+        }
+
+        let plugins = self.clone();
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("Plugin notifications dropped {} events", skipped);
+                        continue;
+                    },
+                    Err(RecvError::Closed) => return,
+                };
+                if !matches!(&event, AgentEvent::Lifecycle { action, .. } if action == "start") {
+                    continue;
+                }
+
+                let input = json::to_string(&event).unwrap_or_default();
+                for plugin in plugins.with_hook("post_start") {
+                    if let Err(e) = Self::call(plugin, "post_start", input.clone()).await {
+                        log::warn!("Plugin {} failed in post_start: {}", plugin.name, e);
+                    }
+                }
+            }
+        });
+    }
+}
+
+type PluginReply = Result<Option<(Status, (ContentType, String))>, String>;
+
+/// Forwards a request to the plugin's `handle` hook; 404 when no loaded plugin by that name
+/// serves routes
+async fn forward(app_manager: &AppManager, name: &str, method: &str, path: PathBuf, origin: &Origin<'_>, body: Option<Data<'_>>) -> PluginReply {
+    let body = match body {
+        Some(body) => {
+            let body = body.open(1.mebibytes()).into_string().await
+                .map_err(|e| format!("Failed to read request body: {}", e))?;
+            if !body.is_complete() {
+                return Err("Request body exceeds 1 MiB".to_string());
+            }
+            body.into_inner()
+        },
+        None => String::new(),
+    };
+    let request = PluginRequest {
+        method: method.to_string(),
+        path: path.to_string_lossy().into_owned(),
+        query: origin.query().map(|query| query.as_str().to_string()),
+        body,
+    };
+
+    let Some(response) = app_manager.plugins().handle(name, &request).await else {
+        return Ok(None);
+    };
+    let response = response?;
+    let status = Status::from_code(response.status).unwrap_or(Status::InternalServerError);
+    let content_type = response.content_type.as_deref()
+        .and_then(ContentType::parse_flexible)
+        .unwrap_or(ContentType::Plain);
+    Ok(Some((status, (content_type, response.body))))
+}
+
+#[get("/plugins")]
+pub fn list_plugins(app_manager: &State<AppManager>) -> Json<Vec<PluginInfo>> {
+    Json(app_manager.plugins().list())
+}
+
+/// Runs plugin code, which decides what a GET does, so it needs an API key like the others
+#[get("/plugins/<name>/<path..>")]
+pub async fn plugin_get(name: &str, path: PathBuf, origin: &Origin<'_>, app_manager: &State<AppManager>, _key: ApiKey) -> PluginReply {
+    forward(app_manager, name, "GET", path, origin, None).await
+}
+
+#[post("/plugins/<name>/<path..>", data = "<body>")]
+pub async fn plugin_post(name: &str, path: PathBuf, origin: &Origin<'_>, body: Data<'_>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> PluginReply {
+    forward(app_manager, name, "POST", path, origin, Some(body)).await
+}
+
+#[put("/plugins/<name>/<path..>", data = "<body>")]
+pub async fn plugin_put(name: &str, path: PathBuf, origin: &Origin<'_>, body: Data<'_>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> PluginReply {
+    forward(app_manager, name, "PUT", path, origin, Some(body)).await
+}
+
+#[delete("/plugins/<name>/<path..>")]
+pub async fn plugin_delete(name: &str, path: PathBuf, origin: &Origin<'_>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> PluginReply {
+    forward(app_manager, name, "DELETE", path, origin, None).await
+}
+
+#[cfg(feature = "plugins")]
+mod imp {
+    use wasmi::{Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+    const HOOKS: &[&str] = &["pre_create", "post_start", "handle"];
+
+    /// Longest message a plugin can log in one call
+    const MAX_LOG_BYTES: usize = 4096;
+
+    pub struct Plugin {
+        pub name: String,
+        pub hooks: Vec<String>,
+        engine: Engine,
+        module: Module,
+        fuel: u64,
+        memory_bytes: usize,
+    }
+
+    struct Host {
+        name: String,
+        limits: StoreLimits,
+    }
+
+    fn env_number(name: &str, default: u64) -> u64 {
+        std::env::var(name).ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(default)
+    }
+
+    /// Compiles every `.wasm` file in `dir`, skipping (and logging) those that don't load
+    pub fn load_dir(dir: &str) -> Vec<Plugin> {
+        let mut paths: Vec<std::path::PathBuf> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries.flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "wasm"))
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to read plugin directory {}: {}", dir, e);
+                return Vec::new();
+            }
+        };
+        paths.sort();
+
+        let fuel = env_number("OMNI_PLUGIN_FUEL", 100_000_000);
+        let memory_bytes = env_number("OMNI_PLUGIN_MEMORY_MB", 64) as usize * 1024 * 1024;
+        paths.iter()
+            .filter_map(|path| match load(path, fuel, memory_bytes) {
+                Ok(plugin) => {
+                    log::info!("Loaded plugin {} with hooks: {}", plugin.name, plugin.hooks.join(", "));
+                    Some(plugin)
+                },
+                Err(e) => {
+                    log::warn!("Failed to load plugin {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn load(path: &std::path::Path, fuel: u64, memory_bytes: usize) -> Result<Plugin, String> {
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let wasm = std::fs::read(path).map_err(|e| e.to_string())?;
+        let module = Module::new(&engine, &wasm).map_err(|e| e.to_string())?;
+
+        let exports: Vec<&str> = module.exports().map(|export| export.name()).collect();
+        for required in ["memory", "alloc"] {
+            if !exports.contains(&required) {
+                return Err(format!("missing `{}` export", required));
+            }
+        }
+        let hooks = HOOKS.iter()
+            .filter(|hook| exports.contains(hook))
+            .map(|hook| hook.to_string())
+            .collect();
+
+        Ok(Plugin { name, hooks, engine, module, fuel, memory_bytes })
+    }
+
+    impl Plugin {
+        /// Calls `hook` with `input` in a fresh instance and returns its answer
+        pub fn call(&self, hook: &str, input: &[u8]) -> Result<Vec<u8>, String> {
+            let host = Host {
+                name: self.name.clone(),
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.memory_bytes)
+                    .instances(1)
+                    .build(),
+            };
+            let mut store = Store::new(&self.engine, host);
+            store.limiter(|host| &mut host.limits);
+            store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+
+            let mut linker = Linker::<Host>::new(&self.engine);
+            linker.func_wrap("env", "log", |caller: Caller<'_, Host>, ptr: i32, len: i32| {
+                let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+                    return;
+                };
+                let mut message = vec![0; (len.max(0) as usize).min(MAX_LOG_BYTES)];
+                if memory.read(&caller, ptr as u32 as usize, &mut message).is_ok() {
+                    log::info!("Plugin {}: {}", caller.data().name, String::from_utf8_lossy(&message));
+                }
+            }).map_err(|e| e.to_string())?;
+
+            let instance = linker.instantiate(&mut store, &self.module)
+                .and_then(|instance| instance.start(&mut store))
+                .map_err(|e| format!("failed to instantiate: {}", e))?;
+            let memory = instance.get_memory(&store, "memory").ok_or("`memory` is not a memory")?;
+            let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc").map_err(|e| e.to_string())?;
+            let entry = instance.get_typed_func::<(i32, i32), i64>(&store, hook).map_err(|e| e.to_string())?;
+
+            let len = i32::try_from(input.len()).map_err(|_| "input too large".to_string())?;
+            let ptr = alloc.call(&mut store, len).map_err(|e| format!("alloc failed: {}", e))?;
+            memory.write(&mut store, ptr as u32 as usize, input).map_err(|e| e.to_string())?;
+            let answer = entry.call(&mut store, (ptr, len)).map_err(|e| format!("{} failed: {}", hook, e))?;
+
+            let output_len = (answer as u64 & 0xffff_ffff) as usize;
+            if output_len > self.memory_bytes {
+                return Err(format!("{} answered beyond its memory", hook));
+            }
+            let mut output = vec![0; output_len];
+            memory.read(&store, (answer as u64 >> 32) as usize, &mut output).map_err(|e| e.to_string())?;
+            Ok(output)
+        }
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+mod imp {
+    pub struct Plugin {
+        pub name: String,
+        pub hooks: Vec<String>,
+    }
+
+    pub fn load_dir(_dir: &str) -> Vec<Plugin> {
+        log::warn!("OMNI_PLUGIN_DIR is set but this build lacks the `plugins` feature; plugins are disabled");
+        Vec::new()
+    }
+
+    impl Plugin {
+        pub fn call(&self, _hook: &str, _input: &[u8]) -> Result<Vec<u8>, String> {
+            Err("plugins are disabled".to_string())
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+use bollard::Docker;
+use bollard::container::{ListContainersOptions, StopContainerOptions};
+
+use crate::event_bus::{AgentEvent, EventBus};
+use crate::host_stats;
+use crate::state_store::{self, StateStore};
+use super::instances::{self, AppInstanceRequest, AppManager};
+
+/// Running, unprotected managed instances below `priority`, lowest priority first
+async fn candidates(docker: &Docker, store: &dyn StateStore, priority: i32) -> Result<Vec<(String, AppInstanceRequest)>, String> {
+    let running: Vec<String> = docker.list_containers(Some(ListContainersOptions::<String> {
+        all: false,
+        ..Default::default()
+    })).await
+        .map_err(|e| format!("Failed to list containers: {}", e))?
+        .into_iter()
+        .filter_map(|container| container.id)
+        .collect();
+
+    let mut candidates: Vec<(String, AppInstanceRequest)> = store.list(state_store::SPECS).await?
+        .into_iter()
+        .filter(|(id, _)| running.contains(id))
+        .filter_map(|(id, record)| rocket::serde::json::from_value::<AppInstanceRequest>(record).ok().map(|spec| (id, spec)))
+        .filter(|(_, spec)| !spec.protected.unwrap_or(false) && spec.priority.unwrap_or(0) < priority)
+        .collect();
+    candidates.sort_by_key(|(_, spec)| spec.priority.unwrap_or(0));
+    Ok(candidates)
+}
+
+async fn preempt(docker: &Docker, store: &dyn StateStore, bus: &EventBus, id: &str, spec: &AppInstanceRequest, reason: String) -> Result<(), String> {
+    let t = spec.stop_grace_period().unwrap_or(30);
+    docker.stop_container(id, Some(StopContainerOptions { t })).await
+        .map_err(|e| format!("Failed to preempt {}: {}", spec.name(), e))?;
+
+    log::warn!("Preempted {} (priority {}): {}", spec.name(), spec.priority.unwrap_or(0), reason);
+    let record = rocket::serde::json::json!({
+        "action": "preempt",
+        "instance_id": id,
+        "reason": reason,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    if let Err(e) = state_store::append(store, state_store::AUDIT, &record).await {
+        log::error!("Failed to record audit entry: {}", e);
+    }
+    bus.publish(AgentEvent::Preemption {
+        instance_id: id.to_string(),
+        name: spec.name().to_string(),
+        priority: spec.priority.unwrap_or(0),
+        reason,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    });
+    Ok(())
+}
+
+/// Stops lower-priority instances holding reservations, lowest first, until the create fits.
+/// Returns false if it still doesn't fit once every candidate is stopped.
+pub async fn make_room(app_manager: &AppManager, bus: &EventBus, host_config: &bollard::models::HostConfig, priority: i32, name: &str) -> bool {
+    let docker = app_manager.docker();
+    let candidates = match candidates(docker, app_manager.store(), priority).await {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            log::error!("Failed to find preemption candidates: {}", e);
+            return false;
+        }
+    };
+
+    for (id, spec) in candidates {
+        let reserves = match docker.inspect_container(&id, None).await {
+            Ok(container) => container.host_config.is_some_and(|host_config| instances::requested_resources(&host_config) != (0.0, 0)),
+            Err(_) => false,
+        };
+        if !reserves {
+            continue;
+        }
+
+        let reason = format!("Making room for {} (priority {})", name, priority);
+        if let Err(e) = preempt(docker, app_manager.store(), bus, &id, &spec, reason).await {
+            log::error!("{}", e);
+            continue;
+        }
+        if app_manager.check_capacity(host_config).await.is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Stops the lowest-priority unprotected instance, one per check, while available memory is
+/// below `OMNI_PREEMPT_MEMORY_PERCENT` of total. Disabled when unset.
+pub fn start(docker: Docker, store: std::sync::Arc<dyn StateStore>, bus: EventBus) {
+    let Some(threshold_percent) = std::env::var("OMNI_PREEMPT_MEMORY_PERCENT").ok()
+        .and_then(|percent| percent.parse::<f64>().ok())
+        .filter(|percent| *percent > 0.0 && *percent < 100.0)
+    else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+
+            let (total, available) = host_stats::memory();
+            if total == 0 {
+                continue;
+            }
+            let available_percent = available as f64 / total as f64 * 100.0;
+            if available_percent >= threshold_percent {
+                continue;
+            }
+
+            match candidates(&docker, store.as_ref(), i32::MAX).await {
+                Ok(candidates) => {
+                    let Some((id, spec)) = candidates.first() else {
+                        continue;
+                    };
+                    let reason = format!("Available memory at {:.1}%, below {:.1}%", available_percent, threshold_percent);
+                    if let Err(e) = preempt(&docker, store.as_ref(), &bus, id, spec, reason).await {
+                        log::error!("{}", e);
+                    }
+                },
+                Err(e) => log::error!("Failed to find preemption candidates: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,192 @@
+use rocket::{delete, get, put};
+use rocket::http::Method;
+use rocket::request::Request;
+use rocket::serde::json::{self, Json};
+use rocket::serde::DeserializeOwned;
+use rocket::State;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use bollard::Docker;
+
+use crate::state_store::{self, StateStore};
+use super::access::{AccessError, Admin};
+pub use omniagent_client::models::rbac::{PolicyRule, Role, RoleRequest, RoleBinding, RoleBindingRequest};
+
+/// Label holding a container's, volume's or network's namespace
+pub const NAMESPACE_LABEL: &str = "omni.namespace";
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+const VERBS: &[&str] = &["create", "update", "delete"];
+const RESOURCES: &[&str] = &["instances", "volumes", "networks"];
+
+/// Namespace from a container's, volume's or network's labels
+pub fn label_namespace(labels: Option<&HashMap<String, String>>) -> &str {
+    labels.and_then(|labels| labels.get(NAMESPACE_LABEL))
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_NAMESPACE)
+}
+
+fn grants(values: &[String], wanted: &str) -> bool {
+    values.iter().any(|value| value == "*" || value == wanted)
+}
+
+/// Roles and their bindings, cached from the state store. Enforcement starts with the first
+/// binding; until then every valid API key may do anything.
+#[derive(Clone)]
+pub struct Authorizer {
+    store: Arc<dyn StateStore>,
+    roles: Arc<Mutex<BTreeMap<String, Role>>>,
+    bindings: Arc<Mutex<BTreeMap<String, RoleBinding>>>,
+}
+
+async fn load_collection<T: DeserializeOwned>(store: &dyn StateStore, collection: &str) -> Result<BTreeMap<String, T>, String> {
+    let mut items = BTreeMap::new();
+    for (name, value) in store.list(collection).await? {
+        match json::from_value(value) {
+            Ok(item) => {
+                items.insert(name, item);
+            },
+            Err(e) => eprintln!("Skipping unreadable {} entry {}: {}", collection, name, e),
+        }
+    }
+    Ok(items)
+}
+
+impl Authorizer {
+    pub async fn load(store: Arc<dyn StateStore>) -> Result<Self, String> {
+        let roles = load_collection(store.as_ref(), state_store::ROLES).await?;
+        let bindings = load_collection(store.as_ref(), state_store::ROLE_BINDINGS).await?;
+        Ok(Authorizer {
+            store,
+            roles: Arc::new(Mutex::new(roles)),
+            bindings: Arc::new(Mutex::new(bindings)),
+        })
+    }
+
+    pub fn enforced(&self) -> bool {
+        !self.bindings.lock().unwrap().is_empty()
+    }
+
+    /// Whether `identity`, a minted API key ID, may `verb` `resource` in `namespace`. Callers
+    /// without an identity used an `OMNI_API_KEYS` key, or no keys exist, and are unrestricted.
+    pub fn authorize(&self, identity: Option<&str>, verb: &str, resource: &str, namespace: &str) -> Result<(), AccessError> {
+        let Some(identity) = identity else {
+            return Ok(());
+        };
+        let bindings = self.bindings.lock().unwrap();
+        if bindings.is_empty() {
+            return Ok(());
+        }
+        let roles = self.roles.lock().unwrap();
+        let allowed = bindings.values()
+            .filter(|binding| grants(&binding.subjects, identity) && grants(&binding.namespaces, namespace))
+            .filter_map(|binding| roles.get(&binding.role))
+            .flat_map(|role| &role.rules)
+            .any(|rule| grants(&rule.verbs, verb) && grants(&rule.resources, resource));
+        if allowed {
+            return Ok(());
+        }
+        Err(AccessError::new("rbac_denied", &format!("API key {} may not {} {} in namespace {}", identity, verb, resource, namespace)))
+    }
+}
+
+/// The verb, resource type and namespace a request acts on, for requests on an existing
+/// instance, volume or network. Creations aren't covered: their namespace is in the body,
+/// so their handlers authorize them.
+pub async fn target(req: &Request<'_>, docker: &Docker) -> Option<(&'static str, &'static str, String)> {
+    let resource = RESOURCES.iter().copied().find(|resource| req.routed_segment(0) == Some(*resource))?;
+    let target = req.routed_segment(1)?;
+    let verb = if req.method() == Method::Delete && req.routed_segment(2).is_none() { "delete" } else { "update" };
+    let labels = match resource {
+        "instances" => docker.inspect_container(target, None).await.ok()
+            .and_then(|container| container.config)
+            .and_then(|config| config.labels),
+        "volumes" => docker.inspect_volume(target).await.ok().map(|volume| volume.labels),
+        _ => docker.inspect_network::<String>(target, None).await.ok()
+            .and_then(|network| network.labels),
+    };
+    Some((verb, resource, label_namespace(labels.as_ref()).to_string()))
+}
+
+fn check_rules(rules: &[PolicyRule]) -> Result<(), String> {
+    for rule in rules {
+        if let Some(verb) = rule.verbs.iter().find(|verb| *verb != "*" && !VERBS.contains(&verb.as_str())) {
+            return Err(format!("Unknown verb {}; expected one of {} or *", verb, VERBS.join(", ")));
+        }
+        if let Some(resource) = rule.resources.iter().find(|resource| *resource != "*" && !RESOURCES.contains(&resource.as_str())) {
+            return Err(format!("Unknown resource {}; expected one of {} or *", resource, RESOURCES.join(", ")));
+        }
+    }
+    Ok(())
+}
+
+async fn save<T: rocket::serde::Serialize>(store: &dyn StateStore, collection: &str, name: &str, item: &T) -> Result<(), String> {
+    let value = json::to_value(item).map_err(|e| format!("Failed to serialize {}: {}", name, e))?;
+    store.put(collection, name, &value).await
+        .map_err(|e| format!("Failed to save {}: {}", name, e))
+}
+
+// API Endpoints
+#[get("/rbac/roles")]
+pub fn list_roles(authorizer: &State<Authorizer>, _admin: Admin) -> Json<Vec<Role>> {
+    Json(authorizer.roles.lock().unwrap().values().cloned().collect())
+}
+
+#[put("/rbac/roles/<name>", format = "json", data = "<role_req>")]
+pub async fn put_role(name: String, role_req: Json<RoleRequest>, authorizer: &State<Authorizer>, _admin: Admin) -> Result<Json<Role>, String> {
+    check_rules(&role_req.rules)?;
+    let role = Role { name: name.clone(), rules: role_req.into_inner().rules };
+    save(authorizer.store.as_ref(), state_store::ROLES, &name, &role).await?;
+    authorizer.roles.lock().unwrap().insert(name, role.clone());
+    Ok(Json(role))
+}
+
+/// Roles still referenced by a binding can't be removed
+#[delete("/rbac/roles/<name>")]
+pub async fn delete_role(name: String, authorizer: &State<Authorizer>, _admin: Admin) -> Result<Json<Role>, String> {
+    let role = authorizer.roles.lock().unwrap().get(&name).cloned()
+        .ok_or_else(|| format!("Role {} does not exist", name))?;
+    let users: Vec<String> = authorizer.bindings.lock().unwrap().values()
+        .filter(|binding| binding.role == name)
+        .map(|binding| binding.name.clone())
+        .collect();
+    if !users.is_empty() {
+        return Err(format!("Role {} is used by bindings {}", name, users.join(", ")));
+    }
+    authorizer.store.delete(state_store::ROLES, &name).await
+        .map_err(|e| format!("Failed to delete role: {}", e))?;
+    authorizer.roles.lock().unwrap().remove(&name);
+    Ok(Json(role))
+}
+
+#[get("/rbac/bindings")]
+pub fn list_role_bindings(authorizer: &State<Authorizer>, _admin: Admin) -> Json<Vec<RoleBinding>> {
+    Json(authorizer.bindings.lock().unwrap().values().cloned().collect())
+}
+
+#[put("/rbac/bindings/<name>", format = "json", data = "<binding_req>")]
+pub async fn put_role_binding(name: String, binding_req: Json<RoleBindingRequest>, authorizer: &State<Authorizer>, _admin: Admin) -> Result<Json<RoleBinding>, String> {
+    if !authorizer.roles.lock().unwrap().contains_key(&binding_req.role) {
+        return Err(format!("Role {} does not exist", binding_req.role));
+    }
+    let binding_req = binding_req.into_inner();
+    let binding = RoleBinding {
+        name: name.clone(),
+        role: binding_req.role,
+        subjects: binding_req.subjects,
+        namespaces: binding_req.namespaces,
+    };
+    save(authorizer.store.as_ref(), state_store::ROLE_BINDINGS, &name, &binding).await?;
+    authorizer.bindings.lock().unwrap().insert(name, binding.clone());
+    Ok(Json(binding))
+}
+
+#[delete("/rbac/bindings/<name>")]
+pub async fn delete_role_binding(name: String, authorizer: &State<Authorizer>, _admin: Admin) -> Result<Json<RoleBinding>, String> {
+    let binding = authorizer.bindings.lock().unwrap().get(&name).cloned()
+        .ok_or_else(|| format!("Role binding {} does not exist", name))?;
+    authorizer.store.delete(state_store::ROLE_BINDINGS, &name).await
+        .map_err(|e| format!("Failed to delete role binding: {}", e))?;
+    authorizer.bindings.lock().unwrap().remove(&name);
+    Ok(Json(binding))
+}
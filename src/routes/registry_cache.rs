@@ -0,0 +1,154 @@
+use rocket::get;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::collections::HashMap;
+use std::sync::Arc;
+use bollard::Docker;
+use bollard::container::{Config, CreateContainerOptions, StartContainerOptions};
+use bollard::image::CreateImageOptions;
+use futures::stream::TryStreamExt;
+
+use super::instances::AppManager;
+pub use omniagent_client::models::registry_cache::RegistryCacheStatus;
+
+const CACHE_CONTAINER_NAME: &str = "omni-registry-cache";
+const CACHE_VOLUME_NAME: &str = "omni-registry-cache-data";
+const CACHE_IMAGE: &str = "registry:2";
+const DOCKER_HUB_HOSTS: [&str; 3] = ["docker.io", "index.docker.io", "registry-1.docker.io"];
+
+/// Optional pull-through cache for a single upstream registry.
+///
+/// Configured with `OMNI_REGISTRY_CACHE_UPSTREAM` (e.g. `https://registry-1.docker.io`)
+/// and `OMNI_REGISTRY_CACHE_PORT` (defaults to 5000). When enabled the agent runs a
+/// `registry:2` proxy container bound to localhost and pulls matching images through it.
+pub struct RegistryCache {
+    upstream: Option<String>,
+    port: u16,
+}
+
+impl RegistryCache {
+    pub fn from_env() -> Self {
+        RegistryCache {
+            upstream: std::env::var("OMNI_REGISTRY_CACHE_UPSTREAM").ok().filter(|u| !u.is_empty()),
+            port: std::env::var("OMNI_REGISTRY_CACHE_PORT").ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(5000),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.upstream.is_some()
+    }
+
+    fn endpoint(&self) -> String {
+        format!("localhost:{}", self.port)
+    }
+
+    fn upstream_host(&self) -> Option<String> {
+        let upstream = self.upstream.as_ref()?;
+        let host = upstream
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_end_matches('/');
+        Some(host.to_string())
+    }
+
+    /// Rewrites an image reference served by the upstream registry to go through the cache.
+    /// Returns `None` when the image comes from another registry or is pinned by digest.
+    pub fn rewrite(&self, image: &str) -> Option<String> {
+        let upstream_host = self.upstream_host()?;
+        if image.contains('@') {
+            return None;
+        }
+
+        // The first path component is a registry host if it looks like one
+        let (host, path) = match image.split_once('/') {
+            Some((first, rest)) if first.contains('.') || first.contains(':') || first == "localhost" => {
+                (first.to_string(), rest.to_string())
+            },
+            _ => ("docker.io".to_string(), image.to_string()),
+        };
+
+        let upstream_is_hub = DOCKER_HUB_HOSTS.contains(&upstream_host.as_str());
+        if upstream_is_hub && DOCKER_HUB_HOSTS.contains(&host.as_str()) {
+            let path = if path.contains('/') { path } else { format!("library/{}", path) };
+            Some(format!("{}/{}", self.endpoint(), path))
+        } else if host == upstream_host {
+            Some(format!("{}/{}", self.endpoint(), path))
+        } else {
+            None
+        }
+    }
+
+    /// Creates and starts the cache container if it isn't already running
+    pub async fn ensure_running(&self, docker: &Docker) -> Result<(), String> {
+        let upstream = match &self.upstream {
+            Some(upstream) => upstream.clone(),
+            None => return Ok(()),
+        };
+
+        if let Ok(container) = docker.inspect_container(CACHE_CONTAINER_NAME, None).await {
+            let running = container.state.and_then(|s| s.running).unwrap_or(false);
+            if !running {
+                docker.start_container(CACHE_CONTAINER_NAME, None::<StartContainerOptions<String>>).await
+                    .map_err(|e| format!("Failed to start registry cache: {}", e))?;
+            }
+            return Ok(());
+        }
+
+        docker.create_image(Some(CreateImageOptions {
+            from_image: CACHE_IMAGE,
+            ..Default::default()
+        }), None, None).try_collect::<Vec<_>>().await
+            .map_err(|e| format!("Failed to pull {}: {}", CACHE_IMAGE, e))?;
+
+        let config = Config {
+            image: Some(CACHE_IMAGE.to_string()),
+            env: Some(vec![format!("REGISTRY_PROXY_REMOTEURL={}", upstream)]),
+            host_config: Some(bollard::models::HostConfig {
+                port_bindings: Some(HashMap::from([(
+                    "5000/tcp".to_string(),
+                    Some(vec![bollard::models::PortBinding {
+                        host_ip: Some("127.0.0.1".to_string()),
+                        host_port: Some(self.port.to_string()),
+                    }]),
+                )])),
+                binds: Some(vec![format!("{}:/var/lib/registry", CACHE_VOLUME_NAME)]),
+                restart_policy: Some(bollard::models::RestartPolicy {
+                    name: Some(bollard::models::RestartPolicyNameEnum::UNLESS_STOPPED),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        docker.create_container(Some(CreateContainerOptions {
+            name: CACHE_CONTAINER_NAME,
+            platform: None,
+        }), config).await
+            .map_err(|e| format!("Failed to create registry cache: {}", e))?;
+
+        docker.start_container(CACHE_CONTAINER_NAME, None::<StartContainerOptions<String>>).await
+            .map_err(|e| format!("Failed to start registry cache: {}", e))
+    }
+}
+
+#[get("/registry-cache")]
+pub async fn get_registry_cache_status(app_manager: &State<AppManager>, registry_cache: &State<Arc<RegistryCache>>) -> Json<RegistryCacheStatus> {
+    let container_status = if registry_cache.is_enabled() {
+        app_manager.docker().inspect_container(CACHE_CONTAINER_NAME, None).await.ok()
+            .and_then(|container| container.state)
+            .and_then(|state| state.status)
+            .map(|status| status.to_string())
+    } else {
+        None
+    };
+
+    Json(RegistryCacheStatus {
+        enabled: registry_cache.is_enabled(),
+        upstream: registry_cache.upstream.clone(),
+        endpoint: registry_cache.is_enabled().then(|| registry_cache.endpoint()),
+        container_status,
+    })
+}
@@ -0,0 +1,263 @@
+use rocket::get;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::instances::{instance_status, AppManager, IMAGE_LABEL};
+use super::state::{InstanceSummary, StateTracker};
+use super::views;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Contains,
+    Prefix,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+struct Term {
+    field: String,
+    op: Op,
+    value: String,
+}
+
+const FIELDS: &[&str] = &["id", "name", "image", "status", "restarts", "created", "started"];
+
+fn is_time_field(field: &str) -> bool {
+    field == "created" || field == "started"
+}
+
+/// RFC 3339 timestamps, or plain dates taken as midnight UTC
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok()
+        .map(|time| time.with_timezone(&Utc))
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|time| time.and_utc()))
+}
+
+/// Parses whitespace-separated `field<op>value` terms, all of which must match. Values may
+/// be double-quoted, with `\` escaping the next character.
+fn parse_terms(query: &str) -> Result<Vec<Term>, String> {
+    let mut terms = Vec::new();
+    let mut chars = query.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut field = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | '/')) {
+            field.push(c);
+        }
+        let mut op = String::new();
+        while let Some(c) = chars.next_if(|c| "=!~^<>".contains(*c)) {
+            op.push(c);
+        }
+        let op = match op.as_str() {
+            "=" | "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "~" => Op::Contains,
+            "^" => Op::Prefix,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            "" => return Err(format!("Expected an operator after `{}`", field)),
+            other => return Err(format!("Unknown operator `{}`", other)),
+        };
+        if !FIELDS.contains(&field.as_str()) && field.strip_prefix("label.").is_none_or(|key| key.is_empty()) {
+            return Err(format!("Unknown field `{}`; expected one of {} or label.<key>", field, FIELDS.join(", ")));
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => value.extend(chars.next()),
+                    Some(c) => value.push(c),
+                    None => return Err(format!("Unterminated quote in the value for `{}`", field)),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+
+        if is_time_field(&field) && parse_time(&value).is_none() {
+            return Err(format!("Invalid time `{}` for `{}`: expected YYYY-MM-DD or RFC 3339", value, field));
+        }
+        if field == "restarts" && value.parse::<i64>().is_err() {
+            return Err(format!("Invalid number `{}` for `restarts`", value));
+        }
+        terms.push(Term { field, op, value });
+    }
+    Ok(terms)
+}
+
+fn field_value(instance: &InstanceSummary, field: &str) -> Option<String> {
+    match field {
+        "id" => Some(instance.id.clone()),
+        "name" => Some(instance.name.clone()),
+        "image" => Some(instance.labels.get(IMAGE_LABEL).unwrap_or(&instance.image).clone()),
+        "status" => Some(instance_status(instance.status.clone(), &instance.labels)),
+        "restarts" => Some(instance.restart_count.to_string()),
+        "created" => Some(instance.created_at.clone()),
+        "started" => Some(instance.started_at.clone()),
+        _ => field.strip_prefix("label.").and_then(|key| instance.labels.get(key)).cloned(),
+    }
+}
+
+/// Orders an instance's value against a term's, numerically or by time where the field calls
+/// for it. Docker reports never-started containers with a zero time, which orders first.
+fn compare(field: &str, actual: &str, value: &str) -> Option<Ordering> {
+    if is_time_field(field) {
+        Some(parse_time(actual)?.cmp(&parse_time(value)?))
+    } else if field == "restarts" {
+        Some(actual.parse::<i64>().ok()?.cmp(&value.parse::<i64>().ok()?))
+    } else {
+        Some(actual.cmp(value))
+    }
+}
+
+impl Term {
+    /// Missing labels only match `!=`
+    fn matches(&self, instance: &InstanceSummary) -> bool {
+        let Some(actual) = field_value(instance, &self.field) else {
+            return self.op == Op::Ne;
+        };
+        let ordering = compare(&self.field, &actual, &self.value);
+        match self.op {
+            Op::Eq => ordering == Some(Ordering::Equal),
+            Op::Ne => ordering != Some(Ordering::Equal),
+            Op::Contains => actual.to_lowercase().contains(&self.value.to_lowercase()),
+            Op::Prefix => actual.starts_with(&self.value),
+            Op::Gt => ordering == Some(Ordering::Greater),
+            Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            Op::Lt => ordering == Some(Ordering::Less),
+            Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        }
+    }
+}
+
+/// A parsed instance query
+pub struct Query(Vec<Term>);
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self, String> {
+        parse_terms(query).map(Query)
+    }
+
+    pub fn matches(&self, instance: &InstanceSummary) -> bool {
+        self.0.iter().all(|term| term.matches(instance))
+    }
+
+    /// IDs of the tracked instances matching every query
+    pub fn matching_ids(queries: &[Query], tracker: &StateTracker) -> HashSet<String> {
+        tracker.instances().into_iter()
+            .filter(|instance| queries.iter().all(|query| query.matches(instance)))
+            .map(|instance| instance.id)
+            .collect()
+    }
+}
+
+/// Searches tracked instances with terms like `name~"api" status=running image^"ghcr.io/"
+/// label.team=payments created>2024-01-01`, all of which must match. Fields are id, name,
+/// image, status, restarts, created, started and `label.<key>`; operators are `=`, `!=`, `~`
+/// (case-insensitive substring), `^` (prefix), `>`, `>=`, `<` and `<=`. `view` adds a
+/// saved view's query.
+#[get("/instances/search?<q>&<view>")]
+pub async fn search_instances(q: Option<String>, view: Option<String>, tracker: &State<StateTracker>, app_manager: &State<AppManager>) -> Result<Json<Vec<InstanceSummary>>, String> {
+    let mut queries = vec![Query::parse(q.as_deref().unwrap_or_default())?];
+    if let Some(view) = view {
+        queries.push(views::query(app_manager.store(), &view).await?);
+    }
+    let mut instances: Vec<InstanceSummary> = tracker.instances().into_iter()
+        .filter(|instance| queries.iter().all(|query| query.matches(instance)))
+        .collect();
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(instances))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn terms(query: &str) -> Vec<(String, Op, String)> {
+        parse_terms(query).unwrap().into_iter().map(|term| (term.field, term.op, term.value)).collect()
+    }
+
+    fn error(query: &str) -> String {
+        parse_terms(query).err().unwrap()
+    }
+
+    fn instance() -> InstanceSummary {
+        InstanceSummary {
+            id: "abc123".to_string(),
+            name: "payments-api".to_string(),
+            image: "ghcr.io/acme/payments:2".to_string(),
+            status: "running".to_string(),
+            restart_count: 3,
+            started_at: "2024-03-01T12:00:00Z".to_string(),
+            created_at: "2024-02-01T08:00:00Z".to_string(),
+            labels: HashMap::from([("team".to_string(), "payments".to_string())]),
+        }
+    }
+
+    fn matches(query: &str) -> bool {
+        Query::parse(query).unwrap().matches(&instance())
+    }
+
+    #[test]
+    fn parses_every_operator() {
+        let parsed = terms("name=a name==b name!=c name~d name^e restarts>1 restarts>=2 restarts<3 restarts<=4");
+        let ops: Vec<Op> = parsed.iter().map(|(_, op, _)| *op).collect();
+        assert_eq!(ops, [Op::Eq, Op::Eq, Op::Ne, Op::Contains, Op::Prefix, Op::Gt, Op::Ge, Op::Lt, Op::Le]);
+        assert_eq!(parsed[0], ("name".to_string(), Op::Eq, "a".to_string()));
+        assert!(terms("  ").is_empty());
+    }
+
+    #[test]
+    fn parses_quoted_values() {
+        assert_eq!(terms(r#"label.note="two words" name~"say \"hi\"" image^"ghcr.io/""#), [
+            ("label.note".to_string(), Op::Eq, "two words".to_string()),
+            ("name".to_string(), Op::Contains, "say \"hi\"".to_string()),
+            ("image".to_string(), Op::Prefix, "ghcr.io/".to_string()),
+        ]);
+        assert_eq!(terms(r#"name="""#)[0].2, "");
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(error("name").contains("Expected an operator"));
+        assert!(error("name=>x").contains("Unknown operator `=>`"));
+        assert!(error("owner=me").contains("Unknown field `owner`"));
+        assert!(error("label.=x").contains("Unknown field `label.`"));
+        assert!(error(r#"name="open"#).contains("Unterminated quote"));
+        assert!(error("created>yesterday").contains("Invalid time"));
+        assert!(error("restarts>many").contains("Invalid number"));
+    }
+
+    #[test]
+    fn matches_instances() {
+        assert!(matches(r#"name~"API" status=running image^"ghcr.io/" label.team=payments"#));
+        assert!(!matches("name^api"));
+        // Numbers and times compare by value, not as text
+        assert!(matches("restarts>=3 restarts<10"));
+        assert!(matches("created>2024-01-15 started<=2024-03-01T12:00:00+00:00"));
+        assert!(!matches("created>2024-02-01T08:00:00Z"));
+        // A missing label only matches !=
+        assert!(matches("label.tier!=gold"));
+        assert!(!matches("label.tier~gold"));
+    }
+}
@@ -0,0 +1,144 @@
+use rocket::{delete, get, put};
+use rocket::serde::{json::{Json, Value}};
+use rocket::State;
+
+use crate::state_store::{self, StateStore};
+use super::access::{ApiKey, Mutation};
+use super::instances::AppManager;
+pub use omniagent_client::models::seccomp::SeccompProfileSummary;
+
+const ACTIONS: &[&str] = &[
+    "SCMP_ACT_KILL", "SCMP_ACT_KILL_PROCESS", "SCMP_ACT_KILL_THREAD", "SCMP_ACT_TRAP",
+    "SCMP_ACT_ERRNO", "SCMP_ACT_TRACE", "SCMP_ACT_ALLOW", "SCMP_ACT_LOG", "SCMP_ACT_NOTIFY",
+];
+const OPERATORS: &[&str] = &[
+    "SCMP_CMP_NE", "SCMP_CMP_LT", "SCMP_CMP_LE", "SCMP_CMP_EQ",
+    "SCMP_CMP_GE", "SCMP_CMP_GT", "SCMP_CMP_MASKED_EQ",
+];
+
+fn string_list<'a>(value: &'a Value, field: &str, context: &str) -> Result<Vec<&'a str>, String> {
+    match value.get(field) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(items)) => items.iter()
+            .map(|item| item.as_str().ok_or_else(|| format!("{}.{} must contain only strings", context, field)))
+            .collect(),
+        Some(_) => Err(format!("{}.{} must be an array", context, field)),
+    }
+}
+
+fn check_action(value: &Value, field: &str, context: &str) -> Result<(), String> {
+    match value.get(field).and_then(|action| action.as_str()) {
+        Some(action) if ACTIONS.contains(&action) => Ok(()),
+        Some(action) => Err(format!("{}.{} has unknown action {}", context, field, action)),
+        None => Err(format!("{}.{} is required", context, field)),
+    }
+}
+
+/// Checks a profile against the runtime's seccomp schema, as runc would parse it
+fn validate(profile: &Value) -> Result<(), String> {
+    if !profile.is_object() {
+        return Err("Profile must be a JSON object".to_string());
+    }
+    check_action(profile, "defaultAction", "profile")?;
+    if let Some(architecture) = string_list(profile, "architectures", "profile")?.iter().find(|arch| !arch.starts_with("SCMP_ARCH_")) {
+        return Err(format!("profile.architectures has unknown architecture {}", architecture));
+    }
+
+    let syscalls = match profile.get("syscalls") {
+        None | Some(Value::Null) => return Ok(()),
+        Some(Value::Array(syscalls)) => syscalls,
+        Some(_) => return Err("profile.syscalls must be an array".to_string()),
+    };
+    for (i, rule) in syscalls.iter().enumerate() {
+        let context = format!("syscalls[{}]", i);
+        let names = string_list(rule, "names", &context)?;
+        if names.is_empty() && rule.get("name").and_then(|name| name.as_str()).is_none() {
+            return Err(format!("{} needs names", context));
+        }
+        check_action(rule, "action", &context)?;
+
+        if let Some(args) = rule.get("args").filter(|args| !args.is_null()) {
+            let args = args.as_array().ok_or_else(|| format!("{}.args must be an array", context))?;
+            for (j, arg) in args.iter().enumerate() {
+                let context = format!("{}.args[{}]", context, j);
+                if arg.get("index").and_then(|index| index.as_u64()).is_none_or(|index| index > 5) {
+                    return Err(format!("{}.index must be between 0 and 5", context));
+                }
+                if arg.get("value").and_then(|value| value.as_u64()).is_none() {
+                    return Err(format!("{}.value must be an unsigned integer", context));
+                }
+                match arg.get("op").and_then(|op| op.as_str()) {
+                    Some(op) if OPERATORS.contains(&op) => {},
+                    _ => return Err(format!("{}.op must be one of {}", context, OPERATORS.join(", "))),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Resolves a profile name from an instance spec to the `security_opt` Docker expects
+pub async fn security_opt(store: &dyn StateStore, name: &str) -> Result<String, String> {
+    let profile = store.get(state_store::SECCOMP_PROFILES, name).await?
+        .ok_or_else(|| format!("Seccomp profile {} does not exist", name))?;
+    Ok(format!("seccomp={}", profile))
+}
+
+fn summary(name: String, profile: &Value) -> SeccompProfileSummary {
+    SeccompProfileSummary {
+        name,
+        default_action: profile["defaultAction"].as_str().unwrap_or_default().to_string(),
+        syscall_rules: profile["syscalls"].as_array().map(|rules| rules.len()).unwrap_or(0),
+    }
+}
+
+// API Endpoints
+#[get("/profiles/seccomp")]
+pub async fn list_seccomp_profiles(app_manager: &State<AppManager>) -> Result<Json<Vec<SeccompProfileSummary>>, String> {
+    let profiles = app_manager.store().list(state_store::SECCOMP_PROFILES).await
+        .map_err(|e| format!("Failed to list seccomp profiles: {}", e))?;
+    Ok(Json(profiles.into_iter().map(|(name, profile)| summary(name, &profile)).collect()))
+}
+
+#[get("/profiles/seccomp/<name>")]
+pub async fn get_seccomp_profile(name: String, app_manager: &State<AppManager>) -> Result<Option<Json<Value>>, String> {
+    app_manager.store().get(state_store::SECCOMP_PROFILES, &name).await
+        .map(|profile| profile.map(Json))
+        .map_err(|e| format!("Failed to read seccomp profile: {}", e))
+}
+
+#[put("/profiles/seccomp/<name>", format = "json", data = "<profile>")]
+pub async fn put_seccomp_profile(name: String, profile: Json<Value>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<SeccompProfileSummary>, String> {
+    if !valid_name(&name) {
+        return Err(format!("Invalid profile name {}", name));
+    }
+    validate(&profile).map_err(|e| format!("Invalid seccomp profile: {}", e))?;
+
+    app_manager.store().put(state_store::SECCOMP_PROFILES, &name, &profile).await
+        .map_err(|e| format!("Failed to save seccomp profile: {}", e))?;
+    Ok(Json(summary(name, &profile)))
+}
+
+/// Profiles still referenced by a managed instance can't be removed
+#[delete("/profiles/seccomp/<name>")]
+pub async fn delete_seccomp_profile(name: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<SeccompProfileSummary>, String> {
+    let profile = app_manager.store().get(state_store::SECCOMP_PROFILES, &name).await?
+        .ok_or_else(|| format!("Seccomp profile {} does not exist", name))?;
+
+    let users: Vec<String> = app_manager.managed_specs().await?
+        .into_iter()
+        .filter(|(_, spec)| spec.seccomp_profile() == Some(name.as_str()))
+        .map(|(_, spec)| spec.name().to_string())
+        .collect();
+    if !users.is_empty() {
+        return Err(format!("Seccomp profile {} is used by {}", name, users.join(", ")));
+    }
+
+    app_manager.store().delete(state_store::SECCOMP_PROFILES, &name).await
+        .map_err(|e| format!("Failed to delete seccomp profile: {}", e))?;
+    Ok(Json(summary(name, &profile)))
+}
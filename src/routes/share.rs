@@ -0,0 +1,236 @@
+use rocket::{get, post};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::State;
+
+use crate::crypto;
+use crate::state_store;
+use crate::websocket::{Channel, WebSocket};
+use super::access::{authenticate, AccessError, ApiKey};
+use super::instances::{self, AppManager, InstanceLogs};
+use super::limits::{Logs, Slot};
+pub use omniagent_client::models::share::{ShareRequest, SharedLink};
+
+/// Signs capability tokens with `OMNI_SHARE_SECRET`. Without it a random secret is used, so
+/// links stop working when the agent restarts.
+pub struct ShareSigner {
+    secret: Vec<u8>,
+}
+
+impl ShareSigner {
+    pub fn from_env() -> Self {
+        let secret = std::env::var("OMNI_SHARE_SECRET").ok()
+            .filter(|secret| !secret.is_empty())
+            .map(String::into_bytes)
+            .unwrap_or_else(|| {
+                log::info!("OMNI_SHARE_SECRET is unset; shared links will not survive a restart");
+                uuid::Uuid::new_v4().as_bytes().iter().chain(uuid::Uuid::new_v4().as_bytes()).copied().collect()
+            });
+        ShareSigner { secret }
+    }
+
+    /// `<expires>.<action>.<instance id>.<exec id or ->.<signature>`; container and exec IDs
+    /// are hex, so the token needs no escaping
+    fn sign(&self, grant: &Grant) -> String {
+        let payload = format!("{}.{}.{}.{}", grant.expires, grant.action, grant.instance_id, grant.exec_id.as_deref().unwrap_or("-"));
+        let signature: String = crypto::hmac_sha256(&self.secret, payload.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{}.{}", payload, signature)
+    }
+
+    fn verify(&self, token: &str) -> Option<Grant> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature: Vec<u8> = (0..signature.len()).step_by(2)
+            .map(|i| signature.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<_>>()?;
+        if !crypto::verify_hmac_sha256(&self.secret, payload.as_bytes(), &signature) {
+            return None;
+        }
+
+        let mut fields = payload.splitn(4, '.');
+        let expires = fields.next()?.parse().ok()?;
+        let action = fields.next()?.to_string();
+        let instance_id = fields.next()?.to_string();
+        let exec_id = Some(fields.next()?.to_string()).filter(|exec_id| exec_id != "-");
+        Some(Grant { expires, action, instance_id, exec_id })
+    }
+
+    /// The grant in `token` if it is genuine, unexpired at `now` and allows `action`
+    fn check(&self, token: Option<&str>, action: Option<&str>, now: i64) -> Result<Grant, AccessError> {
+        match token.and_then(|token| self.verify(token)) {
+            None => Err(AccessError::new("invalid_share_token", "The shared link is not valid")),
+            Some(grant) if grant.expires < now => Err(AccessError::new("share_expired", "The shared link has expired")),
+            Some(grant) if action != Some(grant.action.as_str()) => Err(AccessError::new("share_not_granted", "The shared link does not grant this action")),
+            Some(grant) => Ok(grant),
+        }
+    }
+}
+
+/// What a capability token allows, checked as a request guard on `/shared/<token>/<action>`
+pub struct Grant {
+    /// Unix timestamp
+    expires: i64,
+    action: String,
+    instance_id: String,
+    exec_id: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Grant {
+    type Error = AccessError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let result = match req.rocket().state::<ShareSigner>() {
+            Some(signer) => signer.check(req.routed_segment(1), req.routed_segment(2), chrono::Utc::now().timestamp()),
+            None => Err(AccessError::new("invalid_share_token", "The shared link is not valid")),
+        };
+        authenticate(req, result.map_err(|error| (Status::Forbidden, error))).await
+    }
+}
+
+/// Mints a link to one instance's logs, or to attach to one of its exec sessions, that works
+/// without credentials until it expires. `ttl_secs` is capped at `OMNI_SHARE_MAX_TTL`
+/// seconds (default 86400).
+#[post("/instances/<id>/share", format = "json", data = "<share_req>")]
+pub async fn share_instance(id: String, share_req: Json<ShareRequest>, app_manager: &State<AppManager>, signer: &State<ShareSigner>, _key: ApiKey) -> Result<Json<SharedLink>, String> {
+    let container = app_manager.docker().inspect_container(&id, None).await
+        .map_err(|e| format!("Failed to inspect instance: {}", e))?;
+    let instance_id = container.id.unwrap_or(id);
+
+    let exec_id = match share_req.action.as_str() {
+        "logs" => None,
+        "exec" => {
+            let exec_id = share_req.exec_id.clone().ok_or("Sharing an exec session needs exec_id")?;
+            instances::exec_of(app_manager, &instance_id, &exec_id).await?;
+            Some(exec_id)
+        },
+        other => return Err(format!("Unknown action {}; expected logs or exec", other)),
+    };
+
+    let max_ttl = std::env::var("OMNI_SHARE_MAX_TTL").ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(86400u64);
+    let ttl = share_req.ttl_secs.unwrap_or(900).clamp(1, max_ttl);
+    let grant = Grant {
+        expires: chrono::Utc::now().timestamp() + ttl as i64,
+        action: share_req.action.clone(),
+        instance_id,
+        exec_id,
+    };
+    let token = signer.sign(&grant);
+    let path = format!("/shared/{}/{}", token, grant.action);
+    let url = match std::env::var("OMNI_PUBLIC_URL") {
+        Ok(base) if !base.is_empty() => format!("{}{}", base.trim_end_matches('/'), path),
+        _ => path,
+    };
+    let expires_at = chrono::DateTime::from_timestamp(grant.expires, 0).unwrap_or_default().to_rfc3339();
+
+    let record = rocket::serde::json::json!({
+        "action": "share",
+        "instance_id": grant.instance_id,
+        "shared_action": grant.action,
+        "exec_id": grant.exec_id,
+        "expires_at": expires_at,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    if let Err(e) = state_store::append(app_manager.store(), state_store::AUDIT, &record).await {
+        eprintln!("Failed to record audit entry: {}", e);
+    }
+
+    Ok(Json(SharedLink {
+        url,
+        token,
+        action: grant.action,
+        instance_id: grant.instance_id,
+        exec_id: grant.exec_id,
+        expires_at,
+    }))
+}
+
+#[get("/shared/<_token>/logs?<stream>&<tail>&<since>")]
+pub async fn get_shared_logs(_token: String, grant: Grant, stream: Option<String>, tail: Option<usize>, since: Option<i64>, app_manager: &State<AppManager>, slot: Slot<Logs>) -> Result<Json<InstanceLogs>, String> {
+    instances::instance_logs(grant.instance_id, stream, tail, since, app_manager, slot).await
+}
+
+#[get("/shared/<_token>/exec")]
+pub async fn attach_shared_exec(_token: String, grant: Grant, ws: WebSocket, app_manager: &State<AppManager>) -> Result<Channel, String> {
+    let exec_id = grant.exec_id.ok_or("The shared link names no exec session")?;
+    instances::exec_channel(grant.instance_id, exec_id, ws, app_manager).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSTANCE: &str = "0123456789abcdef";
+    const NOW: i64 = 1_700_000_000;
+
+    fn signer() -> ShareSigner {
+        ShareSigner { secret: b"test secret".to_vec() }
+    }
+
+    fn token(signer: &ShareSigner, instance_id: &str, expires: i64) -> String {
+        signer.sign(&Grant { expires, action: "logs".to_string(), instance_id: instance_id.to_string(), exec_id: None })
+    }
+
+    fn code(result: Result<Grant, AccessError>) -> String {
+        result.err().map(|error| error.code).unwrap_or_default()
+    }
+
+    #[test]
+    fn accepts_a_valid_token() {
+        let signer = signer();
+        let grant = signer.check(Some(&token(&signer, INSTANCE, NOW + 60)), Some("logs"), NOW).unwrap();
+        assert_eq!(grant.instance_id, INSTANCE);
+        assert_eq!(grant.exec_id, None);
+    }
+
+    #[test]
+    fn rejects_a_tampered_signature() {
+        let signer = signer();
+        let mut token = token(&signer, INSTANCE, NOW + 60);
+        let last = if token.ends_with('0') { '1' } else { '0' };
+        token.pop();
+        token.push(last);
+        assert_eq!(code(signer.check(Some(&token), Some("logs"), NOW)), "invalid_share_token");
+    }
+
+    #[test]
+    fn rejects_a_token_moved_to_another_instance() {
+        let signer = signer();
+        let token = token(&signer, INSTANCE, NOW + 60).replace(INSTANCE, "fedcba9876543210");
+        assert_eq!(code(signer.check(Some(&token), Some("logs"), NOW)), "invalid_share_token");
+    }
+
+    #[test]
+    fn rejects_an_extended_expiry() {
+        let signer = signer();
+        let token = token(&signer, INSTANCE, NOW + 60).replacen(&(NOW + 60).to_string(), &(NOW + 6000).to_string(), 1);
+        assert_eq!(code(signer.check(Some(&token), Some("logs"), NOW)), "invalid_share_token");
+    }
+
+    #[test]
+    fn rejects_another_agents_token() {
+        let other = ShareSigner { secret: b"other secret".to_vec() };
+        assert_eq!(code(signer().check(Some(&token(&other, INSTANCE, NOW + 60)), Some("logs"), NOW)), "invalid_share_token");
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        let signer = signer();
+        assert_eq!(code(signer.check(Some(&token(&signer, INSTANCE, NOW - 1)), Some("logs"), NOW)), "share_expired");
+    }
+
+    #[test]
+    fn rejects_another_action() {
+        let signer = signer();
+        assert_eq!(code(signer.check(Some(&token(&signer, INSTANCE, NOW + 60)), Some("exec"), NOW)), "share_not_granted");
+    }
+
+    #[test]
+    fn rejects_a_missing_or_malformed_token() {
+        let signer = signer();
+        assert_eq!(code(signer.check(None, Some("logs"), NOW)), "invalid_share_token");
+        assert_eq!(code(signer.check(Some("garbage"), Some("logs"), NOW)), "invalid_share_token");
+    }
+}
@@ -0,0 +1,282 @@
+use rocket::get;
+use rocket::serde::json::Json;
+use rocket::State;
+use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use bollard::Docker;
+use bollard::container::ListContainersOptions;
+use bollard::system::EventsOptions;
+use futures::stream::StreamExt;
+
+use crate::event_bus::{AgentEvent, EventBus};
+use crate::host_stats;
+pub use omniagent_client::models::state::{InstanceSummary, TrackedInstance, StateDelta, StateDigest};
+
+/// Tombstones kept for deleted instances before clients must fully resync
+const MAX_TOMBSTONES: usize = 1024;
+
+/// Last lines of a container's stdout and stderr, for crash reports
+async fn log_tail(docker: &Docker, id: &str, lines: usize) -> Vec<String> {
+    let options = Some(bollard::container::LogsOptions::<String> {
+        stdout: true,
+        stderr: true,
+        tail: lines.to_string(),
+        ..Default::default()
+    });
+
+    let collect = docker.logs(id, options)
+        .filter_map(|chunk| async move { chunk.ok() })
+        .map(|chunk| chunk.to_string().trim_end_matches(['\r', '\n']).to_string())
+        .collect::<Vec<_>>();
+    tokio::time::timeout(std::time::Duration::from_secs(5), collect).await.unwrap_or_default()
+}
+
+#[derive(Default)]
+struct TrackerState {
+    version: u64,
+    instances: HashMap<String, TrackedInstance>,
+    /// (version, instance id) of removed instances
+    tombstones: Vec<(u64, String)>,
+    /// Highest version whose tombstones have been discarded
+    compacted_through: u64,
+}
+
+/// Versioned view of all containers, kept current from the Docker event stream so
+/// orchestrators can sync only what changed
+#[derive(Clone)]
+pub struct StateTracker {
+    state: Arc<Mutex<TrackerState>>,
+    bus: EventBus,
+}
+
+/// FNV-1a, stable across builds so hashes stay comparable between agent restarts
+fn content_hash(instance: &InstanceSummary) -> String {
+    let serialized = rocket::serde::json::to_string(instance).unwrap_or_default();
+    let hash = serialized.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+async fn summarize(docker: &Docker, id: &str) -> Option<InstanceSummary> {
+    let container = docker.inspect_container(id, None).await.ok()?;
+    let config = container.config.unwrap_or_default();
+    let state = container.state.unwrap_or_default();
+
+    Some(InstanceSummary {
+        id: container.id.unwrap_or_else(|| id.to_string()),
+        name: container.name.unwrap_or_default().trim_start_matches('/').to_string(),
+        image: config.image.unwrap_or_default(),
+        status: state.status.map(|s| s.to_string()).unwrap_or_else(|| "unknown".to_string()),
+        restart_count: container.restart_count.unwrap_or(0),
+        started_at: state.started_at.unwrap_or_default(),
+        created_at: container.created.unwrap_or_default(),
+        labels: config.labels.unwrap_or_default(),
+    })
+}
+
+impl StateTracker {
+    pub fn new(bus: EventBus) -> Self {
+        StateTracker {
+            state: Arc::new(Mutex::new(TrackerState::default())),
+            bus,
+        }
+    }
+
+    fn upsert(&self, instance: InstanceSummary) {
+        let hash = content_hash(&instance);
+        let mut state = self.state.lock().unwrap();
+        if state.instances.get(&instance.id).is_some_and(|tracked| tracked.hash == hash) {
+            return;
+        }
+        state.version += 1;
+        let version = state.version;
+        state.instances.insert(instance.id.clone(), TrackedInstance { version, hash, instance });
+    }
+
+    fn remove(&self, id: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.instances.remove(id).is_none() {
+            return;
+        }
+        state.version += 1;
+        let version = state.version;
+        state.tombstones.push((version, id.to_string()));
+        if state.tombstones.len() > MAX_TOMBSTONES {
+            let (dropped_version, _) = state.tombstones.remove(0);
+            state.compacted_through = dropped_version;
+        }
+    }
+
+    /// Reconciles the tracked set against every container Docker knows about
+    async fn resync(&self, docker: &Docker) {
+        let containers = match docker.list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            ..Default::default()
+        })).await {
+            Ok(containers) => containers,
+            Err(e) => {
+                eprintln!("Failed to list containers for state sync: {}", e);
+                return;
+            }
+        };
+
+        let ids: Vec<String> = containers.into_iter().filter_map(|c| c.id).collect();
+        for id in &ids {
+            if let Some(instance) = summarize(docker, id).await {
+                self.upsert(instance);
+            }
+        }
+
+        let stale: Vec<String> = self.state.lock().unwrap().instances.keys()
+            .filter(|id| !ids.contains(id))
+            .cloned()
+            .collect();
+        for id in stale {
+            self.remove(&id);
+        }
+    }
+
+    /// Follows container events, resyncing whenever the event stream is re-established, and
+    /// publishes lifecycle events and periodic summaries on the event bus. Non-zero exits and
+    /// OOM kills carry the last `OMNI_CRASH_LOG_LINES` (default 20) lines of output.
+    pub fn start(&self, docker: Docker) {
+        let tracker = self.clone();
+        let crash_log_lines = std::env::var("OMNI_CRASH_LOG_LINES").ok()
+            .and_then(|lines| lines.parse().ok())
+            .unwrap_or(20usize);
+
+        tokio::spawn(async move {
+            loop {
+                tracker.resync(&docker).await;
+
+                let options = Some(EventsOptions::<String> {
+                    filters: HashMap::from([("type".to_string(), vec!["container".to_string()])]),
+                    ..Default::default()
+                });
+                let mut events = docker.events(options);
+
+                while let Some(event) = events.next().await {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(e) => {
+                            eprintln!("Container event stream interrupted: {}", e);
+                            break;
+                        }
+                    };
+                    let Some(actor) = event.actor else {
+                        continue;
+                    };
+                    let Some(id) = actor.id else {
+                        continue;
+                    };
+                    let attributes = actor.attributes.unwrap_or_default();
+                    let exit_code = attributes.get("exitCode").and_then(|code| code.parse().ok());
+                    let crashed = match event.action.as_deref() {
+                        Some("die") => exit_code.is_some_and(|code: i64| code != 0),
+                        Some("oom") => true,
+                        _ => false,
+                    };
+                    let log_tail = if crashed && crash_log_lines > 0 {
+                        Some(log_tail(&docker, &id, crash_log_lines).await)
+                    } else {
+                        None
+                    };
+
+                    tracker.bus.publish(AgentEvent::Lifecycle {
+                        instance_id: id.clone(),
+                        name: attributes.get("name").cloned().unwrap_or_default(),
+                        action: event.action.clone().unwrap_or_default(),
+                        exit_code,
+                        log_tail,
+                        timestamp: chrono::Utc::now().to_rfc3339(),
+                    });
+
+                    if event.action.as_deref() == Some("destroy") {
+                        tracker.remove(&id);
+                    } else if let Some(instance) = summarize(&docker, &id).await {
+                        tracker.upsert(instance);
+                    }
+                }
+
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            }
+        });
+
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                let (instances_total, instances_running) = {
+                    let state = tracker.state.lock().unwrap();
+                    let running = state.instances.values()
+                        .filter(|tracked| tracked.instance.status == "running")
+                        .count();
+                    (state.instances.len(), running)
+                };
+                tracker.bus.publish(AgentEvent::MetricsSummary {
+                    instances_total,
+                    instances_running,
+                    memory_available: host_stats::memory().1,
+                    load_average: sys_info::loadavg().map(|l| l.one).unwrap_or(0.0),
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                });
+            }
+        });
+    }
+
+    pub fn delta(&self, since: u64) -> StateDelta {
+        let state = self.state.lock().unwrap();
+        let full_resync = since < state.compacted_through;
+
+        let changed = state.instances.values()
+            .filter(|tracked| full_resync || tracked.version > since)
+            .cloned()
+            .collect();
+        let removed = if full_resync {
+            Vec::new()
+        } else {
+            state.tombstones.iter()
+                .filter(|(version, _)| *version > since)
+                .map(|(_, id)| id.clone())
+                .collect()
+        };
+
+        StateDelta {
+            version: state.version,
+            full_resync,
+            changed,
+            removed,
+        }
+    }
+
+    /// Every tracked instance, unordered
+    pub fn instances(&self) -> Vec<InstanceSummary> {
+        self.state.lock().unwrap().instances.values()
+            .map(|tracked| tracked.instance.clone())
+            .collect()
+    }
+
+    pub fn digest(&self) -> StateDigest {
+        let state = self.state.lock().unwrap();
+        StateDigest {
+            version: state.version,
+            instances: state.instances.iter()
+                .map(|(id, tracked)| (id.clone(), tracked.hash.clone()))
+                .collect(),
+            memory_available: host_stats::memory().1,
+            load_average: sys_info::loadavg().map(|l| l.one).unwrap_or(0.0),
+        }
+    }
+}
+
+// API Endpoints
+#[get("/state/delta?<since>")]
+pub fn get_state_delta(since: Option<u64>, tracker: &State<StateTracker>) -> Json<StateDelta> {
+    Json(tracker.delta(since.unwrap_or(0)))
+}
+
+#[get("/state/digest")]
+pub fn get_state_digest(tracker: &State<StateTracker>) -> Json<StateDigest> {
+    Json(tracker.digest())
+}
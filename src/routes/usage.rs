@@ -0,0 +1,248 @@
+use rocket::get;
+use rocket::serde::json;
+use rocket::State;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use bollard::Docker;
+use bollard::container::{ListContainersOptions, StatsOptions};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::TryStreamExt;
+
+use crate::state_store::{self, StateStore};
+use super::cgroup;
+use super::export::{Export, ExportFormat};
+use super::instances::AppManager;
+pub use omniagent_client::models::usage::UsageRecord;
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Cumulative counters from the previous sample of a container
+struct Sample {
+    at: DateTime<Utc>,
+    cpu_nanos: u64,
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+/// Counters reset when a container restarts; the new value is then the whole delta
+fn delta(current: u64, previous: u64) -> u64 {
+    if current >= previous { current - previous } else { current }
+}
+
+async fn sample(docker: &Docker, id: &str) -> Option<(Sample, u64)> {
+    let stats = docker.stats(id, Some(StatsOptions { stream: false, one_shot: true })).try_next().await.ok()??;
+    let (rx_bytes, tx_bytes) = stats.networks.iter().flatten()
+        .fold((0, 0), |(rx, tx), (_, network)| (rx + network.rx_bytes, tx + network.tx_bytes));
+    let sample = Sample {
+        at: Utc::now(),
+        cpu_nanos: stats.cpu_stats.cpu_usage.total_usage,
+        rx_bytes,
+        tx_bytes,
+    };
+    Some((sample, cgroup::working_set(&stats.memory_stats).unwrap_or(0)))
+}
+
+/// Samples every running container every `OMNI_USAGE_INTERVAL` seconds (default 300) and
+/// appends one usage record per container and period. A container's first sample only sets
+/// its baseline.
+pub fn start(docker: Docker, store: Arc<dyn StateStore>) {
+    let interval = std::env::var("OMNI_USAGE_INTERVAL").ok()
+        .and_then(|secs| secs.parse().ok())
+        .filter(|secs| *secs >= 10)
+        .unwrap_or(300);
+
+    tokio::spawn(async move {
+        let mut previous: HashMap<String, Sample> = HashMap::new();
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            let containers = match docker.list_containers(Some(ListContainersOptions::<String> {
+                filters: HashMap::from([("status".to_string(), vec!["running".to_string()])]),
+                ..Default::default()
+            })).await {
+                Ok(containers) => containers,
+                Err(e) => {
+                    eprintln!("Failed to list containers for usage metering: {}", e);
+                    continue;
+                }
+            };
+
+            let mut current = HashMap::new();
+            for container in containers {
+                let Some(id) = container.id else {
+                    continue;
+                };
+                let Some((sample, memory)) = sample(&docker, &id).await else {
+                    continue;
+                };
+
+                if let Some(last) = previous.get(&id) {
+                    let seconds = (sample.at - last.at).num_milliseconds() as f64 / 1000.0;
+                    let record = UsageRecord {
+                        instance_id: id.clone(),
+                        name: container.names.iter().flatten().next().map(|name| name.trim_start_matches('/').to_string()).unwrap_or_default(),
+                        period_start: last.at.to_rfc3339(),
+                        period_end: sample.at.to_rfc3339(),
+                        runtime_seconds: seconds,
+                        cpu_seconds: delta(sample.cpu_nanos, last.cpu_nanos) as f64 / 1e9,
+                        memory_gb_hours: memory as f64 / GIB * seconds / 3600.0,
+                        network_rx_bytes: delta(sample.rx_bytes, last.rx_bytes),
+                        network_tx_bytes: delta(sample.tx_bytes, last.tx_bytes),
+                    };
+                    let result = match json::to_value(&record) {
+                        Ok(record) => state_store::append(store.as_ref(), state_store::USAGE, &record).await,
+                        Err(e) => Err(e.to_string()),
+                    };
+                    if let Err(e) = result {
+                        eprintln!("Failed to record usage for {}: {}", id, e);
+                    }
+                }
+                current.insert(id, sample);
+            }
+            previous = current;
+        }
+    });
+}
+
+/// Sums one instance's records, in time order, into a single record spanning them all
+fn roll_up(records: &[UsageRecord]) -> UsageRecord {
+    let mut total = records[0].clone();
+    for record in &records[1..] {
+        total.name = record.name.clone();
+        total.period_end = record.period_end.clone();
+        total.runtime_seconds += record.runtime_seconds;
+        total.cpu_seconds += record.cpu_seconds;
+        total.memory_gb_hours += record.memory_gb_hours;
+        total.network_rx_bytes += record.network_rx_bytes;
+        total.network_tx_bytes += record.network_tx_bytes;
+    }
+    total
+}
+
+/// Removes usage records that ended more than `OMNI_USAGE_RETENTION_DAYS` ago (default 90),
+/// and rolls each instance's records from before today (UTC) up into one per day, so the
+/// collection grows with instances and days rather than with samples. Returns how many
+/// records were removed.
+pub async fn prune(store: &dyn StateStore) -> Result<usize, String> {
+    let days = std::env::var("OMNI_USAGE_RETENTION_DAYS").ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(90);
+    let now = Utc::now();
+    let cutoff = now - chrono::Duration::days(days);
+
+    let mut expired = Vec::new();
+    let mut by_day: BTreeMap<(String, NaiveDate), Vec<(String, UsageRecord)>> = BTreeMap::new();
+    for (key, record) in store.list(state_store::USAGE).await? {
+        let Ok(record) = json::from_value::<UsageRecord>(record) else {
+            continue;
+        };
+        let (Ok(start), Ok(end)) = (DateTime::parse_from_rfc3339(&record.period_start), DateTime::parse_from_rfc3339(&record.period_end)) else {
+            continue;
+        };
+        if end < cutoff {
+            expired.push(key);
+            continue;
+        }
+        let day = start.with_timezone(&Utc).date_naive();
+        if day < now.date_naive() {
+            by_day.entry((record.instance_id.clone(), day)).or_default().push((key, record));
+        }
+    }
+
+    let mut removed = expired.len();
+    store.delete_many(state_store::USAGE, &expired).await?;
+    for records in by_day.into_values().filter(|records| records.len() > 1) {
+        let (keys, records): (Vec<String>, Vec<UsageRecord>) = records.into_iter().unzip();
+        // Keys are time-ordered, so the day's total takes the first one's place
+        let total = json::to_value(roll_up(&records)).map_err(|e| e.to_string())?;
+        store.put(state_store::USAGE, &keys[0], &total).await?;
+        store.delete_many(state_store::USAGE, &keys[1..]).await?;
+        removed += keys.len() - 1;
+    }
+    Ok(removed)
+}
+
+fn parse_time(value: Option<&str>, name: &str) -> Result<Option<DateTime<Utc>>, String> {
+    value.map(|value| DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("Invalid {} timestamp {}: {}", name, value, e)))
+        .transpose()
+}
+
+// API Endpoints
+
+/// Usage records overlapping `from`..`to` (RFC 3339), as JSON or CSV
+#[get("/usage?<from>&<to>")]
+pub async fn get_usage(from: Option<&str>, to: Option<&str>, format: ExportFormat, app_manager: &State<AppManager>) -> Result<Export<Vec<UsageRecord>>, String> {
+    let from = parse_time(from, "from")?;
+    let to = parse_time(to, "to")?;
+
+    let records: Vec<UsageRecord> = app_manager.store().list(state_store::USAGE).await
+        .map_err(|e| format!("Failed to read usage records: {}", e))?
+        .into_iter()
+        .filter_map(|(_, record)| json::from_value::<UsageRecord>(record).ok())
+        .filter(|record| {
+            let start = DateTime::parse_from_rfc3339(&record.period_start).ok();
+            let end = DateTime::parse_from_rfc3339(&record.period_end).ok();
+            from.is_none_or(|from| end.is_some_and(|end| end > from))
+                && to.is_none_or(|to| start.is_some_and(|start| start < to))
+        })
+        .collect();
+    Ok(format.respond(records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use state_store::FileStore;
+
+    fn record(instance_id: &str, start: DateTime<Utc>, cpu_seconds: f64) -> json::Value {
+        json::to_value(UsageRecord {
+            instance_id: instance_id.to_string(),
+            name: instance_id.to_string(),
+            period_start: start.to_rfc3339(),
+            period_end: (start + chrono::Duration::minutes(5)).to_rfc3339(),
+            runtime_seconds: 300.0,
+            cpu_seconds,
+            memory_gb_hours: 0.5,
+            network_rx_bytes: 10,
+            network_tx_bytes: 20,
+        }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn prune_rolls_up_earlier_days_and_drops_expired_records() {
+        let dir = std::env::temp_dir().join(format!("omni-usage-{}", uuid::Uuid::new_v4()));
+        let store = FileStore::open(dir.clone()).await.unwrap();
+        let today = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let yesterday = today - chrono::Duration::days(1);
+        let records = [
+            ("1", record("a", today - chrono::Duration::days(100), 1.0)),
+            ("2", record("a", yesterday + chrono::Duration::hours(1), 1.0)),
+            ("3", record("b", yesterday + chrono::Duration::hours(1), 4.0)),
+            ("4", record("a", yesterday + chrono::Duration::hours(2), 2.0)),
+            ("5", record("a", today, 8.0)),
+            ("6", record("a", today + chrono::Duration::minutes(5), 16.0)),
+        ];
+        for (key, value) in &records {
+            store.put(state_store::USAGE, key, value).await.unwrap();
+        }
+
+        assert_eq!(prune(&store).await.unwrap(), 2);
+        let left: Vec<(String, UsageRecord)> = store.list(state_store::USAGE).await.unwrap().into_iter()
+            .map(|(key, value)| (key, json::from_value(value).unwrap()))
+            .collect();
+        let keys: Vec<&str> = left.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["2", "3", "5", "6"]);
+        let (_, rolled) = &left[0];
+        assert_eq!(rolled.cpu_seconds, 3.0);
+        assert_eq!(rolled.runtime_seconds, 600.0);
+        assert_eq!(rolled.network_tx_bytes, 40);
+        assert_eq!(rolled.period_end, (yesterday + chrono::Duration::minutes(125)).to_rfc3339());
+
+        // Already rolled up, so a second run changes nothing
+        assert_eq!(prune(&store).await.unwrap(), 0);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,69 @@
+use rocket::serde::json;
+pub use omniagent_client::models::userns::{IdRange, UsernsInfo};
+
+/// Where dockerd reads `userns-remap` from
+const DAEMON_CONFIG: &str = "/etc/docker/daemon.json";
+
+fn remap_user() -> Option<String> {
+    let config: json::Value = json::from_str(&std::fs::read_to_string(DAEMON_CONFIG).ok()?).ok()?;
+    let user = config.get("userns-remap")?.as_str()?;
+    let user = user.split(':').next().unwrap_or(user);
+    Some(if user == "default" { "dockremap" } else { user }.to_string())
+}
+
+/// First `user:start:count` entry for the user in a subordinate ID file
+fn subordinate_range(path: &str, user: &str) -> Option<IdRange> {
+    std::fs::read_to_string(path).ok()?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().split(':');
+            let (name, start, count) = (fields.next()?, fields.next()?, fields.next()?);
+            (name == user).then(|| Some(IdRange { start: start.parse().ok()?, count: count.parse().ok()? }))?
+        })
+        .next()
+}
+
+pub fn detect(info: Option<&bollard::models::SystemInfo>) -> UsernsInfo {
+    let enabled = info
+        .and_then(|info| info.security_options.as_ref())
+        .is_some_and(|options| options.iter().any(|option| option.contains("name=userns")));
+    if !enabled {
+        return UsernsInfo { enabled, remap_user: None, uid_range: None, gid_range: None };
+    }
+
+    let remap_user = remap_user();
+    let uid_range = remap_user.as_deref().and_then(|user| subordinate_range("/etc/subuid", user));
+    let gid_range = remap_user.as_deref().and_then(|user| subordinate_range("/etc/subgid", user));
+    UsernsInfo { enabled, remap_user, uid_range, gid_range }
+}
+
+/// Checks that remapped containers can still write to bind-mounted host paths: each must be
+/// owned by an ID inside the remapped range or be world-writable. Named volumes and paths
+/// that don't exist yet are created by the daemon with the right owner and are skipped.
+pub fn check_bind_ownership<'a>(userns: &UsernsInfo, host_paths: impl Iterator<Item = &'a String>) -> Result<(), String> {
+    let (true, Some(uid_range), Some(gid_range)) = (userns.enabled, &userns.uid_range, &userns.gid_range) else {
+        return Ok(());
+    };
+
+    #[cfg(unix)]
+    for path in host_paths.filter(|path| path.starts_with('/')) {
+        use std::os::unix::fs::MetadataExt;
+
+        let Ok(metadata) = std::fs::metadata(path) else {
+            continue;
+        };
+        let writable = uid_range.contains(metadata.uid())
+            || gid_range.contains(metadata.gid()) && metadata.mode() & 0o020 != 0
+            || metadata.mode() & 0o002 != 0;
+        if !writable {
+            return Err(format!(
+                "Volume {} is owned by {}:{}, outside the remapped range starting at {}:{}; chown it into the range or set userns_mode to host",
+                path, metadata.uid(), metadata.gid(), uid_range.start, gid_range.start,
+            ));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = host_paths;
+
+    Ok(())
+}
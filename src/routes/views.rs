@@ -0,0 +1,72 @@
+use rocket::{delete, get, put};
+use rocket::serde::json::{self, Json};
+use rocket::State;
+
+use crate::state_store::{self, StateStore};
+use super::access::{ApiKey, Mutation};
+use super::instances::AppManager;
+use super::search::Query;
+pub use omniagent_client::models::views::{SavedView, SavedViewRequest};
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+async fn load(store: &dyn StateStore, name: &str) -> Result<Option<SavedView>, String> {
+    let Some(value) = store.get(state_store::VIEWS, name).await? else {
+        return Ok(None);
+    };
+    json::from_value(value).map(Some).map_err(|e| format!("Failed to read view {}: {}", name, e))
+}
+
+/// The parsed query of a saved view, for list routes taking `?view=`
+pub async fn query(store: &dyn StateStore, name: &str) -> Result<Query, String> {
+    let view = load(store, name).await?
+        .ok_or_else(|| format!("View {} does not exist", name))?;
+    Query::parse(&view.query).map_err(|e| format!("View {} has an invalid query: {}", name, e))
+}
+
+// API Endpoints
+#[get("/views")]
+pub async fn list_views(app_manager: &State<AppManager>) -> Result<Json<Vec<SavedView>>, String> {
+    let views = app_manager.store().list(state_store::VIEWS).await
+        .map_err(|e| format!("Failed to list views: {}", e))?;
+    Ok(Json(views.into_iter()
+        .filter_map(|(_, value)| json::from_value(value).ok())
+        .collect()))
+}
+
+#[get("/views/<name>")]
+pub async fn get_view(name: String, app_manager: &State<AppManager>) -> Result<Option<Json<SavedView>>, String> {
+    load(app_manager.store(), &name).await.map(|view| view.map(Json))
+}
+
+/// Creates or replaces a view. The query is checked here so a broken view can't be saved.
+#[put("/views/<name>", format = "json", data = "<view_req>")]
+pub async fn put_view(name: String, view_req: Json<SavedViewRequest>, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<SavedView>, String> {
+    if !valid_name(&name) {
+        return Err(format!("Invalid view name {}", name));
+    }
+    Query::parse(&view_req.query).map_err(|e| format!("Invalid query: {}", e))?;
+
+    let view_req = view_req.into_inner();
+    let view = SavedView {
+        name: name.clone(),
+        query: view_req.query,
+        description: view_req.description,
+        updated_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let value = json::to_value(&view).map_err(|e| format!("Failed to serialize view: {}", e))?;
+    app_manager.store().put(state_store::VIEWS, &name, &value).await
+        .map_err(|e| format!("Failed to save view: {}", e))?;
+    Ok(Json(view))
+}
+
+#[delete("/views/<name>")]
+pub async fn delete_view(name: String, app_manager: &State<AppManager>, _mutation: Mutation, _key: ApiKey) -> Result<Json<SavedView>, String> {
+    let view = load(app_manager.store(), &name).await?
+        .ok_or_else(|| format!("View {} does not exist", name))?;
+    app_manager.store().delete(state_store::VIEWS, &name).await
+        .map_err(|e| format!("Failed to delete view: {}", e))?;
+    Ok(Json(view))
+}
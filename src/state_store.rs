@@ -0,0 +1,843 @@
+use rocket::serde::json::{self, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Every collection in the store, each named by one of the constants below
+const COLLECTIONS: &[&str] = &[
+    "instances", "specs", "audit", "leases", "seccomp_profiles", "usage", "api_keys", "views",
+    "roles", "role_bindings", "checks", "check_history", "flags", "pinned_images",
+];
+
+/// Collection holding `AppInstance` records by container ID
+pub const INSTANCES: &str = COLLECTIONS[0];
+/// Collection holding the `AppInstanceRequest` each instance was created from
+pub const SPECS: &str = COLLECTIONS[1];
+/// Append-only collection of audit records, kept for `OMNI_AUDIT_RETENTION_DAYS`
+pub const AUDIT: &str = COLLECTIONS[2];
+/// Collection holding leader-election leases by name
+pub const LEASES: &str = COLLECTIONS[3];
+/// Collection holding uploaded seccomp profiles by name
+pub const SECCOMP_PROFILES: &str = COLLECTIONS[4];
+/// Append-only collection of per-instance usage records
+pub const USAGE: &str = COLLECTIONS[5];
+/// Collection holding minted API keys, hashed, by key ID
+pub const API_KEYS: &str = COLLECTIONS[6];
+/// Collection holding saved instance views by name
+pub const VIEWS: &str = COLLECTIONS[7];
+/// Collections holding RBAC roles and role bindings by name
+pub const ROLES: &str = COLLECTIONS[8];
+pub const ROLE_BINDINGS: &str = COLLECTIONS[9];
+/// Collection holding synthetic check definitions by name
+pub const CHECKS: &str = COLLECTIONS[10];
+/// Append-only collection of synthetic checks going up or down
+pub const CHECK_HISTORY: &str = COLLECTIONS[11];
+/// Collection holding feature flags set through the API by name
+pub const FLAGS: &str = COLLECTIONS[12];
+/// Collection holding images pinned against cleanup by normalized reference
+pub const PINNED_IMAGES: &str = COLLECTIONS[13];
+
+/// Every collection, for checks that cover the whole store
+pub fn all_collections() -> &'static [&'static str] {
+    COLLECTIONS
+}
+
+/// Persistence for agent state, organised as collections of JSON documents by key
+#[rocket::async_trait]
+pub trait StateStore: Send + Sync {
+    /// Short backend name for diagnostics, e.g. `file`
+    fn backend(&self) -> &'static str;
+    async fn put(&self, collection: &str, key: &str, value: &Value) -> Result<(), String>;
+    async fn get(&self, collection: &str, key: &str) -> Result<Option<Value>, String>;
+    async fn delete(&self, collection: &str, key: &str) -> Result<(), String>;
+    /// Deletes several documents of a collection at once; backends that can do it in one
+    /// write override this
+    async fn delete_many(&self, collection: &str, keys: &[String]) -> Result<(), String> {
+        for key in keys {
+            self.delete(collection, key).await?;
+        }
+        Ok(())
+    }
+    /// All documents in a collection, ordered by key
+    async fn list(&self, collection: &str) -> Result<Vec<(String, Value)>, String>;
+    /// Atomically takes or renews lease `name` for `holder`. Returns false while another
+    /// holder's lease is unexpired.
+    async fn acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool, String>;
+    /// Reclaims space left behind by deleted or rewritten documents; returns what was done
+    async fn compact(&self) -> Result<String, String> {
+        Ok("Nothing to compact".to_string())
+    }
+}
+
+/// Lease document as stored by the file and PostgreSQL backends
+fn lease_record(holder: &str, ttl: Duration) -> Value {
+    let expires_at = chrono::Utc::now() + chrono::Duration::from_std(ttl).unwrap_or_default();
+    json::json!({ "holder": holder, "expires_at": expires_at.to_rfc3339() })
+}
+
+fn lease_available(record: Option<&Value>, holder: &str) -> bool {
+    let Some(record) = record else {
+        return true;
+    };
+    if record["holder"].as_str() == Some(holder) {
+        return true;
+    }
+    record["expires_at"].as_str()
+        .and_then(|expires_at| chrono::DateTime::parse_from_rfc3339(expires_at).ok())
+        .is_none_or(|expires_at| expires_at < chrono::Utc::now())
+}
+
+/// Collections only ever added to with `append`, apart from pruning
+const APPEND_ONLY: &[&str] = &[AUDIT, USAGE, CHECK_HISTORY];
+
+const APPEND_KEY_FORMAT: &str = "%Y%m%dT%H%M%S%.6fZ";
+
+/// Appends a record to a collection under a time-ordered key
+pub async fn append(store: &dyn StateStore, collection: &str, value: &Value) -> Result<(), String> {
+    let key = format!("{}-{}", chrono::Utc::now().format(APPEND_KEY_FORMAT), uuid::Uuid::new_v4());
+    store.put(collection, &key, value).await
+}
+
+/// Deletes records `append` added before `cutoff`; returns how many there were
+pub async fn prune_appended(store: &dyn StateStore, collection: &str, cutoff: chrono::DateTime<chrono::Utc>) -> Result<usize, String> {
+    let cutoff = cutoff.format(APPEND_KEY_FORMAT).to_string();
+    let expired: Vec<String> = store.list(collection).await?.into_iter()
+        .map(|(key, _)| key)
+        .take_while(|key| *key < cutoff)
+        .collect();
+    store.delete_many(collection, &expired).await?;
+    Ok(expired.len())
+}
+
+/// Selects a backend from `OMNI_STATE_STORE`: a `postgres://`, `redis://` or `sqlite:` URL,
+/// or unset/`file` for the embedded store in `OMNI_STATE_DIR` (default `./state`)
+pub async fn connect_from_env() -> Result<Arc<dyn StateStore>, String> {
+    let target = std::env::var("OMNI_STATE_STORE").unwrap_or_else(|_| "file".to_string());
+
+    if target.starts_with("postgres://") || target.starts_with("postgresql://") {
+        return connect_postgres(&target).await;
+    }
+    if target.starts_with("redis://") || target.starts_with("rediss://") {
+        return connect_redis(&target).await;
+    }
+    if target.starts_with("sqlite:") {
+        return connect_sqlite(&target).await;
+    }
+    if target != "file" {
+        return Err(format!("Unsupported state store {}", target));
+    }
+
+    let dir = std::env::var("OMNI_STATE_DIR").unwrap_or_else(|_| "./state".to_string());
+    Ok(Arc::new(FileStore::open(PathBuf::from(dir)).await?))
+}
+
+/// Embedded store keeping one JSON file per collection. Append-only collections such as the
+/// audit log are JSON Lines files instead, so adding a record writes one line rather than
+/// the whole collection; a rewritten key is appended again and its last line wins until
+/// compaction or a delete rewrites the file.
+pub struct FileStore {
+    dir: PathBuf,
+    // Serialises read-modify-write cycles on the collection files
+    lock: Mutex<()>,
+}
+
+impl FileStore {
+    pub async fn open(dir: PathBuf) -> Result<Self, String> {
+        tokio::fs::create_dir_all(&dir).await
+            .map_err(|e| format!("Failed to create state directory {}: {}", dir.display(), e))?;
+        let store = FileStore { dir, lock: Mutex::new(()) };
+        store.convert_append_only().await?;
+        Ok(store)
+    }
+
+    fn path(&self, collection: &str) -> PathBuf {
+        if APPEND_ONLY.contains(&collection) {
+            self.dir.join(format!("{}.jsonl", collection))
+        } else {
+            self.dir.join(format!("{}.json", collection))
+        }
+    }
+
+    /// Moves append-only collections kept as one JSON file by earlier versions into their
+    /// JSON Lines files
+    async fn convert_append_only(&self) -> Result<(), String> {
+        for collection in APPEND_ONLY {
+            let old = self.dir.join(format!("{}.json", collection));
+            let bytes = match tokio::fs::read(&old).await {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to read state for {}: {}", collection, e)),
+            };
+            let mut documents: BTreeMap<String, Value> = json::from_slice(&bytes)
+                .map_err(|e| format!("Corrupt state file for {}: {}", collection, e))?;
+            documents.extend(self.read(collection).await?);
+            self.write(collection, &documents).await?;
+            tokio::fs::remove_file(&old).await
+                .map_err(|e| format!("Failed to remove {}: {}", old.display(), e))?;
+        }
+        Ok(())
+    }
+
+    async fn read(&self, collection: &str) -> Result<BTreeMap<String, Value>, String> {
+        Ok(self.read_lines(collection).await?.0)
+    }
+
+    /// A collection's documents, and for JSON Lines files how many lines they took
+    async fn read_lines(&self, collection: &str) -> Result<(BTreeMap<String, Value>, usize), String> {
+        let bytes = match tokio::fs::read(self.path(collection)).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((BTreeMap::new(), 0)),
+            Err(e) => return Err(format!("Failed to read state for {}: {}", collection, e)),
+        };
+        if !APPEND_ONLY.contains(&collection) {
+            let documents: BTreeMap<String, Value> = json::from_slice(&bytes)
+                .map_err(|e| format!("Corrupt state file for {}: {}", collection, e))?;
+            let count = documents.len();
+            return Ok((documents, count));
+        }
+
+        let mut documents = BTreeMap::new();
+        let mut count = 0;
+        for line in bytes.split(|byte| *byte == b'\n').filter(|line| !line.is_empty()) {
+            count += 1;
+            // Only a crash mid-append tears a line, losing just that record
+            match json::from_slice::<(String, Value)>(line) {
+                Ok((key, value)) => {
+                    documents.insert(key, value);
+                },
+                Err(e) => log::warn!("Skipping torn line in state file for {}: {}", collection, e),
+            }
+        }
+        Ok((documents, count))
+    }
+
+    /// Writes via a temporary file and rename so a crash never leaves a truncated file
+    async fn write(&self, collection: &str, documents: &BTreeMap<String, Value>) -> Result<(), String> {
+        let path = self.path(collection);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let bytes = if APPEND_ONLY.contains(&collection) {
+            let mut bytes = String::new();
+            for document in documents {
+                bytes.push_str(&json::to_string(&document).map_err(|e| e.to_string())?);
+                bytes.push('\n');
+            }
+            bytes
+        } else {
+            json::to_string(documents).map_err(|e| e.to_string())?
+        };
+        tokio::fs::write(&tmp, bytes).await
+            .map_err(|e| format!("Failed to write state for {}: {}", collection, e))?;
+        tokio::fs::rename(&tmp, &path).await
+            .map_err(|e| format!("Failed to write state for {}: {}", collection, e))
+    }
+
+    async fn append_line(&self, collection: &str, key: &str, value: &Value) -> Result<(), String> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+        let mut line = json::to_string(&(key, value)).map_err(|e| e.to_string())?;
+        line.push('\n');
+        let result: std::io::Result<()> = async {
+            let mut file = tokio::fs::OpenOptions::new().create(true).read(true).append(true).open(self.path(collection)).await?;
+            // Start past a line torn by a crash rather than joining it
+            if file.metadata().await?.len() > 0 {
+                file.seek(std::io::SeekFrom::End(-1)).await?;
+                if file.read_u8().await? != b'\n' {
+                    line.insert(0, '\n');
+                }
+            }
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await?;
+            file.sync_data().await
+        }.await;
+        result.map_err(|e| format!("Failed to write state for {}: {}", collection, e))
+    }
+
+    /// Cross-process lock for agents sharing one state directory. Locks older than
+    /// `STALE_LOCK` are assumed to belong to a crashed process and broken.
+    async fn lock_directory(&self) -> Result<DirectoryLock, String> {
+        const STALE_LOCK: Duration = Duration::from_secs(10);
+        let path = self.dir.join(".lock");
+
+        for _ in 0..100 {
+            match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&path).await {
+                Ok(_) => return Ok(DirectoryLock { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let stale = tokio::fs::metadata(&path).await.ok()
+                        .and_then(|meta| meta.modified().ok())
+                        .and_then(|modified| modified.elapsed().ok())
+                        .is_some_and(|age| age > STALE_LOCK);
+                    if stale {
+                        let _ = tokio::fs::remove_file(&path).await;
+                    } else {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                },
+                Err(e) => return Err(format!("Failed to lock state directory: {}", e)),
+            }
+        }
+        Err("Timed out waiting for state directory lock".to_string())
+    }
+}
+
+struct DirectoryLock {
+    path: PathBuf,
+}
+
+impl Drop for DirectoryLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[rocket::async_trait]
+impl StateStore for FileStore {
+    fn backend(&self) -> &'static str {
+        "file"
+    }
+
+    async fn put(&self, collection: &str, key: &str, value: &Value) -> Result<(), String> {
+        let _guard = self.lock.lock().await;
+        if APPEND_ONLY.contains(&collection) {
+            return self.append_line(collection, key, value).await;
+        }
+        let mut documents = self.read(collection).await?;
+        documents.insert(key.to_string(), value.clone());
+        self.write(collection, &documents).await
+    }
+
+    async fn get(&self, collection: &str, key: &str) -> Result<Option<Value>, String> {
+        let _guard = self.lock.lock().await;
+        Ok(self.read(collection).await?.remove(key))
+    }
+
+    async fn delete(&self, collection: &str, key: &str) -> Result<(), String> {
+        let _guard = self.lock.lock().await;
+        let mut documents = self.read(collection).await?;
+        if documents.remove(key).is_some() {
+            self.write(collection, &documents).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_many(&self, collection: &str, keys: &[String]) -> Result<(), String> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let _guard = self.lock.lock().await;
+        let mut documents = self.read(collection).await?;
+        let before = documents.len();
+        for key in keys {
+            documents.remove(key);
+        }
+        if documents.len() != before {
+            self.write(collection, &documents).await?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, collection: &str) -> Result<Vec<(String, Value)>, String> {
+        let _guard = self.lock.lock().await;
+        Ok(self.read(collection).await?.into_iter().collect())
+    }
+
+    async fn acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool, String> {
+        let _guard = self.lock.lock().await;
+        let _lock = self.lock_directory().await?;
+        let mut leases = self.read(LEASES).await?;
+        if !lease_available(leases.get(name), holder) {
+            return Ok(false);
+        }
+        leases.insert(name.to_string(), lease_record(holder, ttl));
+        self.write(LEASES, &leases).await?;
+        Ok(true)
+    }
+
+    /// Removes temporary files left by writes interrupted mid-rename and files of
+    /// collections that are now empty, and rewrites JSON Lines files without the lines of
+    /// rewritten keys
+    async fn compact(&self) -> Result<String, String> {
+        let _guard = self.lock.lock().await;
+        let _lock = self.lock_directory().await?;
+        let mut entries = tokio::fs::read_dir(&self.dir).await
+            .map_err(|e| format!("Failed to read state directory: {}", e))?;
+
+        let (mut temporary, mut empty, mut rewritten) = (0, 0, 0);
+        while let Some(entry) = entries.next_entry().await.map_err(|e| format!("Failed to read state directory: {}", e))? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".json.tmp") || name.ends_with(".jsonl.tmp") {
+                tokio::fs::remove_file(entry.path()).await
+                    .map_err(|e| format!("Failed to remove {}: {}", name, e))?;
+                temporary += 1;
+            } else if let Some(collection) = name.strip_suffix(".json").or_else(|| name.strip_suffix(".jsonl")) {
+                let (documents, lines) = self.read_lines(collection).await?;
+                if documents.is_empty() {
+                    tokio::fs::remove_file(entry.path()).await
+                        .map_err(|e| format!("Failed to remove {}: {}", name, e))?;
+                    empty += 1;
+                } else if lines > documents.len() {
+                    self.write(collection, &documents).await?;
+                    rewritten += 1;
+                }
+            }
+        }
+        Ok(format!("Removed {} temporary files and {} empty collections, and rewrote {} logs", temporary, empty, rewritten))
+    }
+}
+
+#[cfg(feature = "postgres")]
+async fn connect_postgres(url: &str) -> Result<Arc<dyn StateStore>, String> {
+    Ok(Arc::new(postgres::PostgresStore::connect(url).await?))
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn connect_postgres(_url: &str) -> Result<Arc<dyn StateStore>, String> {
+    Err("PostgreSQL state store requires building with the `postgres` feature".to_string())
+}
+
+#[cfg(feature = "redis")]
+async fn connect_redis(url: &str) -> Result<Arc<dyn StateStore>, String> {
+    Ok(Arc::new(redis_store::RedisStore::connect(url).await?))
+}
+
+#[cfg(not(feature = "redis"))]
+async fn connect_redis(_url: &str) -> Result<Arc<dyn StateStore>, String> {
+    Err("Redis state store requires building with the `redis` feature".to_string())
+}
+
+#[cfg(feature = "sqlite")]
+async fn connect_sqlite(url: &str) -> Result<Arc<dyn StateStore>, String> {
+    Ok(Arc::new(sqlite::SqliteStore::connect(url).await?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+async fn connect_sqlite(_url: &str) -> Result<Arc<dyn StateStore>, String> {
+    Err("SQLite state store requires building with the `sqlite` feature".to_string())
+}
+
+#[cfg(feature = "postgres")]
+mod postgres {
+    use rocket::serde::json::Value;
+    use sqlx::postgres::{PgPool, PgPoolOptions};
+    use std::time::Duration;
+
+    use super::{lease_record, StateStore, LEASES};
+
+    /// Shared store for large or HA installations; all documents live in one JSONB table
+    pub struct PostgresStore {
+        pool: PgPool,
+    }
+
+    impl PostgresStore {
+        pub async fn connect(url: &str) -> Result<Self, String> {
+            let pool = PgPoolOptions::new()
+                .max_connections(5)
+                .connect(url).await
+                .map_err(|e| format!("Failed to connect to PostgreSQL: {}", e))?;
+
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS omni_agent_state (
+                    collection TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    value JSONB NOT NULL,
+                    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                    PRIMARY KEY (collection, key)
+                )"
+            ).execute(&pool).await
+                .map_err(|e| format!("Failed to prepare PostgreSQL schema: {}", e))?;
+
+            Ok(PostgresStore { pool })
+        }
+    }
+
+    #[rocket::async_trait]
+    impl StateStore for PostgresStore {
+        fn backend(&self) -> &'static str {
+            "postgres"
+        }
+
+        async fn put(&self, collection: &str, key: &str, value: &Value) -> Result<(), String> {
+            sqlx::query(
+                "INSERT INTO omni_agent_state (collection, key, value) VALUES ($1, $2, $3)
+                 ON CONFLICT (collection, key) DO UPDATE SET value = EXCLUDED.value, updated_at = now()"
+            )
+                .bind(collection)
+                .bind(key)
+                .bind(sqlx::types::Json(value))
+                .execute(&self.pool).await
+                .map(|_| ())
+                .map_err(|e| format!("Failed to write state: {}", e))
+        }
+
+        async fn get(&self, collection: &str, key: &str) -> Result<Option<Value>, String> {
+            sqlx::query_scalar::<_, sqlx::types::Json<Value>>(
+                "SELECT value FROM omni_agent_state WHERE collection = $1 AND key = $2"
+            )
+                .bind(collection)
+                .bind(key)
+                .fetch_optional(&self.pool).await
+                .map(|value| value.map(|v| v.0))
+                .map_err(|e| format!("Failed to read state: {}", e))
+        }
+
+        async fn delete(&self, collection: &str, key: &str) -> Result<(), String> {
+            sqlx::query("DELETE FROM omni_agent_state WHERE collection = $1 AND key = $2")
+                .bind(collection)
+                .bind(key)
+                .execute(&self.pool).await
+                .map(|_| ())
+                .map_err(|e| format!("Failed to delete state: {}", e))
+        }
+
+        async fn delete_many(&self, collection: &str, keys: &[String]) -> Result<(), String> {
+            sqlx::query("DELETE FROM omni_agent_state WHERE collection = $1 AND key = ANY($2)")
+                .bind(collection)
+                .bind(keys)
+                .execute(&self.pool).await
+                .map(|_| ())
+                .map_err(|e| format!("Failed to delete state: {}", e))
+        }
+
+        async fn list(&self, collection: &str) -> Result<Vec<(String, Value)>, String> {
+            sqlx::query_as::<_, (String, sqlx::types::Json<Value>)>(
+                "SELECT key, value FROM omni_agent_state WHERE collection = $1 ORDER BY key"
+            )
+                .bind(collection)
+                .fetch_all(&self.pool).await
+                .map(|rows| rows.into_iter().map(|(key, value)| (key, value.0)).collect())
+                .map_err(|e| format!("Failed to list state: {}", e))
+        }
+
+        async fn acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool, String> {
+            // The conditional upsert only touches a row we already hold or whose lease has lapsed
+            sqlx::query(
+                "INSERT INTO omni_agent_state (collection, key, value) VALUES ($1, $2, $3)
+                 ON CONFLICT (collection, key) DO UPDATE SET value = EXCLUDED.value, updated_at = now()
+                 WHERE omni_agent_state.value->>'holder' = $4
+                    OR (omni_agent_state.value->>'expires_at')::timestamptz < now()"
+            )
+                .bind(LEASES)
+                .bind(name)
+                .bind(sqlx::types::Json(lease_record(holder, ttl)))
+                .bind(holder)
+                .execute(&self.pool).await
+                .map(|result| result.rows_affected() == 1)
+                .map_err(|e| format!("Failed to acquire lease {}: {}", name, e))
+        }
+
+        async fn compact(&self) -> Result<String, String> {
+            sqlx::query("VACUUM ANALYZE omni_agent_state")
+                .execute(&self.pool).await
+                .map(|_| "Vacuumed omni_agent_state".to_string())
+                .map_err(|e| format!("Failed to vacuum state table: {}", e))
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use rocket::serde::json::Value;
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use super::{lease_record, StateStore, LEASES};
+
+    /// Embedded store in one SQLite file, e.g. `sqlite:///var/lib/omni-agent/state.db`. Unlike
+    /// the JSON files, a write only touches its own row and survives a crash mid-write.
+    pub struct SqliteStore {
+        pool: SqlitePool,
+    }
+
+    impl SqliteStore {
+        pub async fn connect(url: &str) -> Result<Self, String> {
+            let options = SqliteConnectOptions::from_str(url)
+                .map_err(|e| format!("Invalid SQLite URL: {}", e))?
+                .create_if_missing(true)
+                .journal_mode(SqliteJournalMode::Wal)
+                .busy_timeout(Duration::from_secs(5));
+            let pool = SqlitePoolOptions::new()
+                .max_connections(5)
+                .connect_with(options).await
+                .map_err(|e| format!("Failed to open SQLite database: {}", e))?;
+
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS omni_agent_state (
+                    collection TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    value TEXT NOT NULL,
+                    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY (collection, key)
+                )"
+            ).execute(&pool).await
+                .map_err(|e| format!("Failed to prepare SQLite schema: {}", e))?;
+
+            Ok(SqliteStore { pool })
+        }
+    }
+
+    #[rocket::async_trait]
+    impl StateStore for SqliteStore {
+        fn backend(&self) -> &'static str {
+            "sqlite"
+        }
+
+        async fn put(&self, collection: &str, key: &str, value: &Value) -> Result<(), String> {
+            sqlx::query(
+                "INSERT INTO omni_agent_state (collection, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (collection, key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP"
+            )
+                .bind(collection)
+                .bind(key)
+                .bind(sqlx::types::Json(value))
+                .execute(&self.pool).await
+                .map(|_| ())
+                .map_err(|e| format!("Failed to write state: {}", e))
+        }
+
+        async fn get(&self, collection: &str, key: &str) -> Result<Option<Value>, String> {
+            sqlx::query_scalar::<_, sqlx::types::Json<Value>>(
+                "SELECT value FROM omni_agent_state WHERE collection = ?1 AND key = ?2"
+            )
+                .bind(collection)
+                .bind(key)
+                .fetch_optional(&self.pool).await
+                .map(|value| value.map(|v| v.0))
+                .map_err(|e| format!("Failed to read state: {}", e))
+        }
+
+        async fn delete(&self, collection: &str, key: &str) -> Result<(), String> {
+            sqlx::query("DELETE FROM omni_agent_state WHERE collection = ?1 AND key = ?2")
+                .bind(collection)
+                .bind(key)
+                .execute(&self.pool).await
+                .map(|_| ())
+                .map_err(|e| format!("Failed to delete state: {}", e))
+        }
+
+        async fn delete_many(&self, collection: &str, keys: &[String]) -> Result<(), String> {
+            sqlx::query("DELETE FROM omni_agent_state WHERE collection = ?1 AND key IN (SELECT value FROM json_each(?2))")
+                .bind(collection)
+                .bind(sqlx::types::Json(keys))
+                .execute(&self.pool).await
+                .map(|_| ())
+                .map_err(|e| format!("Failed to delete state: {}", e))
+        }
+
+        async fn list(&self, collection: &str) -> Result<Vec<(String, Value)>, String> {
+            sqlx::query_as::<_, (String, sqlx::types::Json<Value>)>(
+                "SELECT key, value FROM omni_agent_state WHERE collection = ?1 ORDER BY key"
+            )
+                .bind(collection)
+                .fetch_all(&self.pool).await
+                .map(|rows| rows.into_iter().map(|(key, value)| (key, value.0)).collect())
+                .map_err(|e| format!("Failed to list state: {}", e))
+        }
+
+        async fn acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool, String> {
+            // Agents sharing the file race on the same row; the conditional upsert settles it
+            sqlx::query(
+                "INSERT INTO omni_agent_state (collection, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (collection, key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP
+                 WHERE json_extract(omni_agent_state.value, '$.holder') = ?4
+                    OR julianday(json_extract(omni_agent_state.value, '$.expires_at')) < julianday('now')"
+            )
+                .bind(LEASES)
+                .bind(name)
+                .bind(sqlx::types::Json(lease_record(holder, ttl)))
+                .bind(holder)
+                .execute(&self.pool).await
+                .map(|result| result.rows_affected() == 1)
+                .map_err(|e| format!("Failed to acquire lease {}: {}", name, e))
+        }
+
+        async fn compact(&self) -> Result<String, String> {
+            sqlx::query("VACUUM")
+                .execute(&self.pool).await
+                .map(|_| "Vacuumed the SQLite database".to_string())
+                .map_err(|e| format!("Failed to vacuum SQLite database: {}", e))
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+mod redis_store {
+    use redis::AsyncCommands;
+    use redis::aio::MultiplexedConnection;
+    use rocket::serde::json::{self, Value};
+    use std::time::Duration;
+
+    use super::{StateStore, LEASES};
+
+    /// Takes the lease when it is free or already ours, letting Redis expire it
+    const ACQUIRE_LEASE: &str = r"
+        local current = redis.call('GET', KEYS[1])
+        if not current or current == ARGV[1] then
+            redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+            return 1
+        end
+        return 0
+    ";
+
+    /// Shared store keeping each collection in a Redis hash named `omni-agent:<collection>`
+    pub struct RedisStore {
+        connection: MultiplexedConnection,
+    }
+
+    impl RedisStore {
+        pub async fn connect(url: &str) -> Result<Self, String> {
+            let client = redis::Client::open(url)
+                .map_err(|e| format!("Invalid Redis URL: {}", e))?;
+            let connection = client.get_multiplexed_async_connection().await
+                .map_err(|e| format!("Failed to connect to Redis: {}", e))?;
+            Ok(RedisStore { connection })
+        }
+
+        fn hash(collection: &str) -> String {
+            format!("omni-agent:{}", collection)
+        }
+    }
+
+    #[rocket::async_trait]
+    impl StateStore for RedisStore {
+        fn backend(&self) -> &'static str {
+            "redis"
+        }
+
+        async fn put(&self, collection: &str, key: &str, value: &Value) -> Result<(), String> {
+            let payload = json::to_string(value).map_err(|e| e.to_string())?;
+            self.connection.clone().hset::<_, _, _, ()>(Self::hash(collection), key, payload).await
+                .map_err(|e| format!("Failed to write state: {}", e))
+        }
+
+        async fn get(&self, collection: &str, key: &str) -> Result<Option<Value>, String> {
+            let payload: Option<String> = self.connection.clone().hget(Self::hash(collection), key).await
+                .map_err(|e| format!("Failed to read state: {}", e))?;
+            payload.map(|p| json::from_str(&p).map_err(|e| e.to_string())).transpose()
+        }
+
+        async fn delete(&self, collection: &str, key: &str) -> Result<(), String> {
+            self.connection.clone().hdel::<_, _, ()>(Self::hash(collection), key).await
+                .map_err(|e| format!("Failed to delete state: {}", e))
+        }
+
+        async fn delete_many(&self, collection: &str, keys: &[String]) -> Result<(), String> {
+            // HDEL needs at least one field
+            if keys.is_empty() {
+                return Ok(());
+            }
+            self.connection.clone().hdel::<_, _, ()>(Self::hash(collection), keys).await
+                .map_err(|e| format!("Failed to delete state: {}", e))
+        }
+
+        async fn list(&self, collection: &str) -> Result<Vec<(String, Value)>, String> {
+            let entries: std::collections::BTreeMap<String, String> = self.connection.clone()
+                .hgetall(Self::hash(collection)).await
+                .map_err(|e| format!("Failed to list state: {}", e))?;
+            entries.into_iter()
+                .map(|(key, payload)| json::from_str(&payload).map(|value| (key, value)).map_err(|e| e.to_string()))
+                .collect()
+        }
+
+        async fn acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool, String> {
+            let acquired: i64 = redis::Script::new(ACQUIRE_LEASE)
+                .key(format!("{}:{}", Self::hash(LEASES), name))
+                .arg(holder)
+                .arg(ttl.as_millis() as u64)
+                .invoke_async(&mut self.connection.clone()).await
+                .map_err(|e| format!("Failed to acquire lease {}: {}", name, e))?;
+            Ok(acquired == 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn open() -> (FileStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("omni-state-{}", uuid::Uuid::new_v4()));
+        (FileStore::open(dir.clone()).await.unwrap(), dir)
+    }
+
+    fn lines(dir: &std::path::Path, collection: &str) -> usize {
+        std::fs::read_to_string(dir.join(format!("{}.jsonl", collection))).unwrap().lines().count()
+    }
+
+    #[tokio::test]
+    async fn appends_one_line_per_record() {
+        let (store, dir) = open().await;
+        for i in 0..3 {
+            append(&store, AUDIT, &json::json!({ "n": i })).await.unwrap();
+        }
+        let records = store.list(AUDIT).await.unwrap();
+        assert_eq!(records.iter().map(|(_, value)| value["n"].as_i64().unwrap()).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(lines(&dir, AUDIT), 3);
+
+        // A rewritten key is appended and wins until compaction drops the old line
+        store.put(AUDIT, &records[0].0, &json::json!({ "n": 9 })).await.unwrap();
+        assert_eq!(store.get(AUDIT, &records[0].0).await.unwrap(), Some(json::json!({ "n": 9 })));
+        assert_eq!(lines(&dir, AUDIT), 4);
+        store.compact().await.unwrap();
+        assert_eq!(lines(&dir, AUDIT), 3);
+
+        store.delete_many(AUDIT, &[records[1].0.clone(), records[2].0.clone()]).await.unwrap();
+        assert_eq!(store.list(AUDIT).await.unwrap().len(), 1);
+        assert_eq!(lines(&dir, AUDIT), 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn survives_a_torn_line() {
+        let (store, dir) = open().await;
+        store.put(USAGE, "a", &json::json!(1)).await.unwrap();
+        let path = dir.join("usage.jsonl");
+        let mut text = std::fs::read_to_string(&path).unwrap();
+        text.push_str("[\"b\", ");
+        std::fs::write(&path, text).unwrap();
+
+        store.put(USAGE, "c", &json::json!(3)).await.unwrap();
+        let keys: Vec<String> = store.list(USAGE).await.unwrap().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["a", "c"]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn converts_collections_from_earlier_versions() {
+        let dir = std::env::temp_dir().join(format!("omni-state-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("audit.json"), r#"{"a": 1, "b": 2}"#).unwrap();
+
+        let store = FileStore::open(dir.clone()).await.unwrap();
+        assert!(!dir.join("audit.json").exists());
+        assert_eq!(store.list(AUDIT).await.unwrap(), [("a".to_string(), json::json!(1)), ("b".to_string(), json::json!(2))]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn all_collections_lists_every_collection() {
+        let named = [
+            INSTANCES, SPECS, AUDIT, LEASES, SECCOMP_PROFILES, USAGE, API_KEYS, VIEWS, ROLES, ROLE_BINDINGS,
+            CHECKS, CHECK_HISTORY, FLAGS, PINNED_IMAGES,
+        ];
+        // Each collection has its own constant, in order, and no two share a name
+        assert_eq!(all_collections(), COLLECTIONS);
+        assert_eq!(all_collections(), named);
+        let distinct: std::collections::BTreeSet<_> = all_collections().iter().collect();
+        assert_eq!(distinct.len(), all_collections().len());
+    }
+
+    #[tokio::test]
+    async fn prunes_appended_records_before_the_cutoff() {
+        let (store, dir) = open().await;
+        store.put(AUDIT, "20200101T000000.000000Z-old", &json::json!(1)).await.unwrap();
+        append(&store, AUDIT, &json::json!(2)).await.unwrap();
+
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(1);
+        assert_eq!(prune_appended(&store, AUDIT, cutoff).await.unwrap(), 1);
+        assert_eq!(store.list(AUDIT).await.unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
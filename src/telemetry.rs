@@ -0,0 +1,154 @@
+use futures::sink::SinkExt;
+use futures::stream::StreamExt;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::serde::json;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::event_bus::{AgentEvent, EventBus};
+
+/// Version of the envelope below; bump when fields change meaning
+const TELEMETRY_SCHEMA_VERSION: u32 = 1;
+
+/// Unacknowledged messages kept for resending after a reconnect
+const MAX_PENDING: usize = 4096;
+
+/// Every message on the uplink. The event's own `kind` tag says which type it is.
+#[derive(Debug, Serialize)]
+struct Envelope<'a> {
+    schema_version: u32,
+    /// Monotonic per agent run; acknowledgments refer to it
+    seq: u64,
+    agent_id: &'a str,
+    #[serde(flatten)]
+    event: &'a AgentEvent,
+}
+
+/// Cumulative acknowledgment from the orchestrator: everything up to `ack` was received
+#[derive(Debug, Deserialize)]
+struct Ack {
+    ack: u64,
+}
+
+struct Uplink {
+    agent_id: String,
+    events: broadcast::Receiver<AgentEvent>,
+    seq: u64,
+    pending: VecDeque<(u64, String)>,
+}
+
+impl Uplink {
+    /// Frames the next bus event and queues it until acknowledged. Returns None once the bus closes.
+    async fn next(&mut self) -> Option<(u64, String)> {
+        loop {
+            match self.events.recv().await {
+                Ok(event) => {
+                    self.seq += 1;
+                    let envelope = Envelope {
+                        schema_version: TELEMETRY_SCHEMA_VERSION,
+                        seq: self.seq,
+                        agent_id: &self.agent_id,
+                        event: &event,
+                    };
+                    let frame = json::to_string(&envelope).unwrap_or_default();
+                    if self.pending.len() == MAX_PENDING {
+                        self.pending.pop_front();
+                    }
+                    self.pending.push_back((self.seq, frame.clone()));
+                    return Some((self.seq, frame));
+                },
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("Telemetry uplink dropped {} events", skipped);
+                },
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    fn acknowledge(&mut self, ack: u64) {
+        while self.pending.front().is_some_and(|(seq, _)| *seq <= ack) {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Runs one connection until it fails, replaying anything still unacknowledged first
+    async fn session(&mut self, url: &str, token: Option<&str>) -> Result<(), String> {
+        let mut request = url.into_client_request().map_err(|e| format!("Invalid telemetry URL: {}", e))?;
+        if let Some(token) = token {
+            let value = format!("Bearer {}", token).parse().map_err(|_| "Invalid telemetry token".to_string())?;
+            request.headers_mut().insert("Authorization", value);
+        }
+        request.headers_mut().insert("X-Telemetry-Schema", TELEMETRY_SCHEMA_VERSION.into());
+
+        let (stream, _) = tokio_tungstenite::connect_async_tls_with_config(request, None, false, crate::crypto::ws_connector()).await.map_err(|e| e.to_string())?;
+        let (mut sink, mut stream) = stream.split();
+        log::info!("Telemetry uplink connected to {}", url);
+
+        for (_, frame) in self.pending.clone() {
+            sink.send(Message::Text(frame)).await.map_err(|e| e.to_string())?;
+        }
+
+        loop {
+            tokio::select! {
+                next = self.next() => {
+                    let Some((_, frame)) = next else {
+                        return Ok(());
+                    };
+                    sink.send(Message::Text(frame)).await.map_err(|e| e.to_string())?;
+                },
+                message = stream.next() => match message {
+                    Some(Ok(Message::Text(text))) => match json::from_str::<Ack>(&text) {
+                        Ok(ack) => self.acknowledge(ack.ack),
+                        Err(e) => log::warn!("Ignoring unexpected telemetry message: {}", e),
+                    },
+                    Some(Ok(Message::Close(_))) | None => return Err("connection closed".to_string()),
+                    Some(Err(e)) => return Err(e.to_string()),
+                    // Pings are answered by tungstenite on the next write
+                    Some(Ok(_)) => {},
+                },
+            }
+        }
+    }
+
+    /// Keeps queueing events while disconnected so nothing is lost to broadcast lag
+    async fn buffer_for(&mut self, delay: Duration) {
+        let deadline = tokio::time::Instant::now() + delay;
+        while tokio::time::timeout_at(deadline, self.next()).await.is_ok() {}
+    }
+}
+
+/// Streams metrics, lifecycle, health and alert events as versioned envelopes over one
+/// WebSocket to `OMNI_TELEMETRY_URL` (ws:// or wss://), authenticating with
+/// `OMNI_TELEMETRY_TOKEN` as a bearer token. Messages stay queued until the orchestrator
+/// acknowledges them with `{"ack": <seq>}` and are replayed after reconnects.
+pub fn start(bus: &EventBus, agent_id: &str) {
+    let Ok(url) = std::env::var("OMNI_TELEMETRY_URL") else {
+        return;
+    };
+    let token = std::env::var("OMNI_TELEMETRY_TOKEN").ok();
+
+    let mut uplink = Uplink {
+        agent_id: agent_id.to_string(),
+        events: bus.subscribe(),
+        seq: 0,
+        pending: VecDeque::new(),
+    };
+
+    tokio::spawn(async move {
+        let mut attempt: u32 = 0;
+        loop {
+            match uplink.session(&url, token.as_deref()).await {
+                Ok(()) => return,
+                Err(e) => {
+                    attempt += 1;
+                    let retry_in_secs = 2u64.saturating_pow(attempt.min(6)).min(60);
+                    log::warn!("Telemetry uplink disconnected, retrying in {}s: {}", retry_in_secs, e);
+                    uplink.buffer_for(Duration::from_secs(retry_in_secs)).await;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,240 @@
+// Management UI for a single agent, served from /ui. Talks to the same API as any client.
+
+const REFRESH_MS = 5000;
+const HISTORY = 60;
+const MAX_LOG_LINES = 1000;
+const SVG = 'http://www.w3.org/2000/svg';
+
+const keyInput = document.getElementById('apiKey');
+keyInput.value = localStorage.getItem('omniagent-api-key') || '';
+keyInput.addEventListener('change', () => localStorage.setItem('omniagent-api-key', keyInput.value));
+
+// Per instance: the previous CPU sample and recent CPU (cores) and memory (bytes) points
+const usage = new Map();
+let logStream = null;
+
+function showError(message) {
+    const error = document.getElementById('error');
+    error.textContent = message;
+    error.hidden = !message;
+}
+
+function authHeaders() {
+    return keyInput.value ? { 'X-API-Key': keyInput.value } : {};
+}
+
+async function check(method, path, response) {
+    if (!response.ok) {
+        const body = await response.text();
+        let message = body;
+        try {
+            message = JSON.parse(body).error || body;
+        } catch (e) {}
+        throw new Error(`${method} ${path}: ${response.status} ${message}`);
+    }
+    return response;
+}
+
+async function api(method, path) {
+    const response = await fetch(path, { method, headers: authHeaders() });
+    return (await check(method, path, response)).json();
+}
+
+function formatBytes(bytes) {
+    const units = ['B', 'KiB', 'MiB', 'GiB', 'TiB'];
+    let i = 0;
+    while (bytes >= 1024 && i < units.length - 1) {
+        bytes /= 1024;
+        i++;
+    }
+    return `${bytes.toFixed(i ? 1 : 0)} ${units[i]}`;
+}
+
+function sparkline(points, label) {
+    const cell = document.createElement('div');
+    cell.className = 'spark';
+    const svg = document.createElementNS(SVG, 'svg');
+    svg.setAttribute('width', '100');
+    svg.setAttribute('height', '24');
+    if (points.length > 1) {
+        const max = Math.max(...points) || 1;
+        const line = document.createElementNS(SVG, 'polyline');
+        line.setAttribute('points', points
+            .map((value, i) => `${(i * 100 / (HISTORY - 1)).toFixed(1)},${(22 - value / max * 20).toFixed(1)}`)
+            .join(' '));
+        svg.appendChild(line);
+    }
+    cell.appendChild(svg);
+    cell.appendChild(document.createTextNode(label));
+    return cell;
+}
+
+async function sample(instance) {
+    const stats = await api('GET', `/instances/${encodeURIComponent(instance.id)}/stats`);
+    const entry = usage.get(instance.id) || { cpu: [], memory: [] };
+    const now = Date.now();
+    const total = stats.cpu_stats.cpu_usage.total_usage;
+    if (entry.last && total >= entry.last.total) {
+        entry.cpu.push((total - entry.last.total) / 1e6 / (now - entry.last.at));
+    }
+    entry.last = { total, at: now };
+    entry.memory.push(stats.memory_stats.usage || 0);
+    entry.cpu.splice(0, entry.cpu.length - HISTORY);
+    entry.memory.splice(0, entry.memory.length - HISTORY);
+    usage.set(instance.id, entry);
+}
+
+function button(text, enabled, onClick) {
+    const element = document.createElement('button');
+    element.type = 'button';
+    element.textContent = text;
+    element.disabled = !enabled;
+    element.addEventListener('click', onClick);
+    return element;
+}
+
+async function act(instance, action) {
+    try {
+        await api('PUT', `/instances/${encodeURIComponent(instance.id)}/${action}`);
+        showError('');
+    } catch (e) {
+        showError(e.message);
+    }
+    refresh();
+}
+
+// Server-sent events read through fetch rather than EventSource, which can't send the API key
+async function followLogs(path, signal, onEvent) {
+    const response = await check('GET', path, await fetch(path, { headers: authHeaders(), signal }));
+    const reader = response.body.pipeThrough(new TextDecoderStream()).getReader();
+    let buffer = '';
+    for (;;) {
+        const { value, done } = await reader.read();
+        if (done) {
+            return;
+        }
+        buffer += value;
+        let end;
+        while ((end = buffer.indexOf('\n\n')) >= 0) {
+            const event = { name: 'message', data: [] };
+            for (const line of buffer.slice(0, end).split('\n')) {
+                if (line.startsWith('event:')) {
+                    event.name = line.slice(6).trim();
+                } else if (line.startsWith('data:')) {
+                    event.data.push(line.slice(5).replace(/^ /, ''));
+                }
+            }
+            buffer = buffer.slice(end + 2);
+            onEvent(event.name, event.data.join('\n'));
+        }
+    }
+}
+
+function openLogs(instance) {
+    closeLogs();
+    const log = document.getElementById('log');
+    log.replaceChildren();
+    document.getElementById('logTitle').textContent = instance.name;
+    document.getElementById('logPanel').hidden = false;
+
+    logStream = new AbortController();
+    const path = `/instances/${encodeURIComponent(instance.id)}/logs/stream?tail=200`;
+    followLogs(path, logStream.signal, (name, data) => {
+        if (name === 'error') {
+            showError(data);
+            return;
+        }
+        if (name !== 'stdout' && name !== 'stderr') {
+            return;
+        }
+        const line = JSON.parse(data);
+        const element = document.createElement('div');
+        element.className = line.stream;
+        element.textContent = `${line.timestamp} ${line.message}`;
+        const follow = log.scrollTop + log.clientHeight >= log.scrollHeight - 5;
+        log.appendChild(element);
+        while (log.childElementCount > MAX_LOG_LINES) {
+            log.firstElementChild.remove();
+        }
+        if (follow) {
+            log.scrollTop = log.scrollHeight;
+        }
+    }).catch((e) => {
+        if (e.name !== 'AbortError') {
+            showError(e.message);
+        }
+    });
+}
+
+function closeLogs() {
+    if (logStream) {
+        logStream.abort();
+        logStream = null;
+    }
+    document.getElementById('logPanel').hidden = true;
+}
+
+function render(instances) {
+    const rows = instances.map((instance) => {
+        const row = document.createElement('tr');
+        const running = instance.status === 'running';
+        const entry = usage.get(instance.id) || { cpu: [], memory: [] };
+
+        const status = document.createElement('span');
+        status.className = `status ${instance.status}`;
+        status.textContent = instance.status;
+
+        const cpu = entry.cpu.length ? `${(entry.cpu[entry.cpu.length - 1] * 100).toFixed(1)}%` : '';
+        const memory = entry.memory.length ? formatBytes(entry.memory[entry.memory.length - 1]) : '';
+        const actions = [
+            button('Start', !running, () => act(instance, 'start')),
+            button('Stop', running, () => act(instance, 'stop')),
+            button('Logs', true, () => openLogs(instance)),
+        ];
+
+        const cells = [instance.name, instance.image, status, sparkline(entry.cpu, cpu), sparkline(entry.memory, memory), actions];
+        for (const content of cells) {
+            const cell = document.createElement('td');
+            cell.append(...[].concat(content));
+            row.appendChild(cell);
+        }
+        return row;
+    });
+    document.getElementById('instances').replaceChildren(...rows);
+    document.getElementById('empty').hidden = instances.length > 0;
+}
+
+async function refresh() {
+    try {
+        const instances = await api('GET', '/instances');
+        instances.sort((a, b) => a.name.localeCompare(b.name));
+        for (const id of usage.keys()) {
+            if (!instances.some((instance) => instance.id === id && instance.status === 'running')) {
+                usage.delete(id);
+            }
+        }
+        // One at a time, so a large agent doesn't hit its stats concurrency limit
+        for (const instance of instances.filter((instance) => instance.status === 'running')) {
+            await sample(instance).catch(() => {});
+        }
+        render(instances);
+    } catch (e) {
+        showError(e.message);
+    }
+}
+
+async function loadAgent() {
+    try {
+        const agent = await api('GET', '/agent/info');
+        document.getElementById('agent').textContent = `${agent.name} ${agent.version} (${agent.id})`;
+    } catch (e) {
+        showError(e.message);
+    }
+}
+
+document.getElementById('logClose').addEventListener('click', closeLogs);
+loadAgent();
+(async function poll() {
+    await refresh();
+    setTimeout(poll, REFRESH_MS);
+})();
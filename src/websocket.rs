@@ -0,0 +1,79 @@
+use std::io;
+use std::pin::Pin;
+use futures::future::BoxFuture;
+use rocket::data::{IoHandler, IoStream};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+
+pub use tokio_tungstenite::tungstenite::Message;
+
+type Handler = Box<dyn FnOnce(WebSocketStream<IoStream>) -> BoxFuture<'static, io::Result<()>> + Send>;
+
+/// Request guard for routes that upgrade the connection to a WebSocket
+pub struct WebSocket {
+    accept_key: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebSocket {
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let is_upgrade = req.headers().get("Upgrade")
+            .any(|value| value.eq_ignore_ascii_case("websocket"));
+
+        match req.headers().get_one("Sec-WebSocket-Key") {
+            Some(key) if is_upgrade => Outcome::Success(WebSocket {
+                accept_key: derive_accept_key(key.as_bytes()),
+            }),
+            _ => Outcome::Error((Status::UpgradeRequired, "Expected a WebSocket upgrade request")),
+        }
+    }
+}
+
+impl WebSocket {
+    /// Completes the upgrade and hands the resulting stream to `handler`
+    pub fn channel<F>(self, handler: F) -> Channel
+    where
+        F: FnOnce(WebSocketStream<IoStream>) -> BoxFuture<'static, io::Result<()>> + Send + 'static,
+    {
+        Channel {
+            accept_key: self.accept_key,
+            handler: Box::new(handler),
+        }
+    }
+}
+
+/// Responder that switches protocols and runs the WebSocket handler on the upgraded connection
+pub struct Channel {
+    accept_key: String,
+    handler: Handler,
+}
+
+impl<'r> Responder<'r, 'static> for Channel {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .raw_header("Sec-WebSocket-Version", "13")
+            .raw_header("Sec-WebSocket-Accept", self.accept_key.clone())
+            .upgrade("websocket", self)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for Channel {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> io::Result<()> {
+        let channel = Pin::into_inner(self);
+        let stream = WebSocketStream::from_raw_socket(io, Role::Server, None).await;
+        (channel.handler)(stream).await
+    }
+}
+
+/// Maps WebSocket protocol errors onto I/O errors for handler results
+pub fn to_io_error(e: tokio_tungstenite::tungstenite::Error) -> io::Error {
+    io::Error::other(e)
+}